@@ -0,0 +1,784 @@
+//! Attributing each line of a file to the commit that introduced it.
+//!
+//! Like git's scoreboard, every line starts out blamed on the final version
+//! of the file. Versions are visited newest first; each passes the lines it
+//! shares with a parent's version on to that parent and keeps the rest.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::ops::Range;
+
+use crate::diff::patch::{FilePair, Side};
+use crate::diff::{self, rename, tree, Algorithm, Edit};
+use crate::error::{Error, Result};
+use crate::object::tree::GITLINK_MODE;
+use crate::object::Kind;
+use crate::odb::ObjectDatabase;
+use crate::Repository;
+
+/// The object name standing for changes that are not committed yet.
+pub const NOT_COMMITTED: &str = "0000000000000000000000000000000000000000";
+
+/// Default of `-M<n>`: alphanumeric characters a moved block must exceed.
+pub const MOVE_SCORE: usize = 20;
+/// Default of `-C<n>`: alphanumeric characters a copied block must exceed.
+pub const COPY_SCORE: usize = 40;
+
+// --ignore-revで、似た行を探す範囲(対応する位置からの行数)
+const SEARCH_DISTANCE: usize = 10;
+
+/// How lines are traced through history.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Lines of the final file to attribute, from 0; all when empty.
+    pub ranges: Vec<Range<usize>>,
+    /// `-w`: lines that differ only in whitespace are the same.
+    pub ignore_whitespace: bool,
+    /// `-M`: also find lines moved within the file, in blocks of more than
+    /// this many alphanumeric characters.
+    pub moves: Option<usize>,
+    /// `-C`: also find lines copied from other files: how many times `-C`
+    /// was given, and the score of `-C<n>`.
+    pub copies: Option<(usize, usize)>,
+    /// Commits whose changes are looked through, as if they never happened.
+    pub ignore_revs: HashSet<String>,
+}
+
+/// The version of the file to start from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Start<'a> {
+    /// The file as committed in a commit.
+    Commit(&'a str),
+    /// The file in the working tree, on top of the commit `HEAD` points to.
+    WorkTree(Option<&'a str>),
+}
+
+/// A version of the file that lines were found in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    /// The commit, or [`NOT_COMMITTED`].
+    pub commit: String,
+    pub path: String,
+    /// The version in the first parent the commit changed, as
+    /// `(commit, path)`.
+    pub previous: Option<(String, String)>,
+    /// Whether the commit has no parents to look further in.
+    pub boundary: bool,
+}
+
+/// Where one line of the final file comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line {
+    /// Line number in the final file, from 0.
+    pub number: usize,
+    /// Index into [`Blame::origins`].
+    pub origin: usize,
+    /// Line number in the origin's version, from 0.
+    pub source: usize,
+    /// Whether an ignored commit changed the line and it was attributed to
+    /// a similar line of its parent.
+    pub ignored: bool,
+    /// Whether an ignored commit changed the line and no similar line was
+    /// found, so that it stays blamed on the ignored commit.
+    pub unblamable: bool,
+}
+
+/// The result of [`blame`], lines in order of the final file.
+#[derive(Debug, Clone, Default)]
+pub struct Blame {
+    pub origins: Vec<Origin>,
+    pub lines: Vec<Line>,
+}
+
+/// Attribute the lines of `data`, the content of `path` at `start`.
+pub fn blame(
+    repo: &Repository,
+    start: Start<'_>,
+    path: &str,
+    data: &[u8],
+    options: &Options,
+) -> Result<Blame> {
+    let mut board = Scoreboard {
+        repo,
+        options,
+        final_lines: diff::lines(data),
+        versions: Vec::new(),
+        origins: Vec::new(),
+        ids: HashMap::new(),
+        commits: HashMap::new(),
+        lines: Vec::new(),
+        queue: BinaryHeap::new(),
+        queued: Vec::new(),
+        sequence: 0,
+    };
+    let commit = match start {
+        Start::Commit(commit) => commit,
+        Start::WorkTree(head) => {
+            let parents = head.map(ToString::to_string).into_iter().collect();
+            board
+                .commits
+                .insert(NOT_COMMITTED.to_string(), (i64::MAX, parents));
+            NOT_COMMITTED
+        }
+    };
+    let blob = ObjectDatabase::hash(Kind::Blob, data);
+    let id = board.add_version(commit, path, blob, data.to_vec());
+
+    let count = board.final_lines.len();
+    let numbers: Vec<usize> = if options.ranges.is_empty() {
+        (0..count).collect()
+    } else {
+        (0..count)
+            .filter(|n| options.ranges.iter().any(|range| range.contains(n)))
+            .collect()
+    };
+    board.lines = numbers
+        .into_iter()
+        .map(|number| Line {
+            number,
+            origin: id,
+            source: number,
+            ignored: false,
+            unblamable: false,
+        })
+        .collect();
+    board.enqueue(id)?;
+    while let Some((_, _, id)) = board.queue.pop() {
+        board.queued[id] = false;
+        board.pass_blame(id)?;
+    }
+
+    // 行の残ったものだけを、現れた順に番号を振り直して返す
+    let mut renumber = HashMap::new();
+    let mut origins = Vec::new();
+    let lines = board
+        .lines
+        .iter()
+        .map(|line| {
+            let origin = *renumber.entry(line.origin).or_insert_with(|| {
+                origins.push(board.origins[line.origin].clone());
+                origins.len() - 1
+            });
+            Line { origin, ..*line }
+        })
+        .collect();
+    Ok(Blame { origins, lines })
+}
+
+// 各versionの内容
+struct Version {
+    commit: String,
+    path: String,
+    blob: String,
+    data: Vec<u8>,
+}
+
+struct Scoreboard<'a> {
+    repo: &'a Repository,
+    options: &'a Options,
+    final_lines: Vec<&'a [u8]>,
+    versions: Vec<Version>,
+    origins: Vec<Origin>,
+    ids: HashMap<(String, String), usize>,
+    // commit -> (committer time, parents)
+    commits: HashMap<String, (i64, Vec<String>)>,
+    lines: Vec<Line>,
+    // 新しいcommitから、同じ時刻なら先に見つけたものから調べる
+    queue: BinaryHeap<(i64, Reverse<usize>, usize)>,
+    queued: Vec<bool>,
+    sequence: usize,
+}
+
+impl Scoreboard<'_> {
+    fn add_version(&mut self, commit: &str, path: &str, blob: String, data: Vec<u8>) -> usize {
+        let key = (commit.to_string(), path.to_string());
+        if let Some(&id) = self.ids.get(&key) {
+            return id;
+        }
+        let id = self.versions.len();
+        self.versions.push(Version {
+            commit: commit.to_string(),
+            path: path.to_string(),
+            blob,
+            data,
+        });
+        self.origins.push(Origin {
+            commit: commit.to_string(),
+            path: path.to_string(),
+            previous: None,
+            boundary: false,
+        });
+        self.queued.push(false);
+        self.ids.insert(key, id);
+        id
+    }
+
+    fn version(&mut self, commit: &str, path: &str, blob: &str) -> Result<usize> {
+        if let Some(&id) = self.ids.get(&(commit.to_string(), path.to_string())) {
+            return Ok(id);
+        }
+        let data = self.repo.find_blob(blob)?;
+        Ok(self.add_version(commit, path, blob.to_string(), data))
+    }
+
+    fn commit(&mut self, oid: &str) -> Result<&(i64, Vec<String>)> {
+        if !self.commits.contains_key(oid) {
+            let commit = self.repo.find_commit(oid)?;
+            let time = commit.committer.time_stamp.timestamp();
+            self.commits.insert(oid.to_string(), (time, commit.parents));
+        }
+        Ok(&self.commits[oid])
+    }
+
+    fn enqueue(&mut self, id: usize) -> Result<()> {
+        if self.queued[id] {
+            return Ok(());
+        }
+        let commit = self.versions[id].commit.clone();
+        let time = self.commit(&commit)?.0;
+        self.queued[id] = true;
+        self.sequence += 1;
+        self.queue.push((time, Reverse(self.sequence), id));
+        Ok(())
+    }
+
+    // `id`のせいになっている行の、最終的なファイルでの位置
+    fn suspects(&self, id: usize) -> Vec<usize> {
+        (0..self.lines.len())
+            .filter(|&i| self.lines[i].origin == id)
+            .collect()
+    }
+
+    // 連続していて元の版でも連続している行のまとまり
+    fn blocks(&self, id: usize) -> Vec<Vec<usize>> {
+        let mut blocks: Vec<Vec<usize>> = Vec::new();
+        for i in self.suspects(id) {
+            let line = self.lines[i];
+            match blocks.last_mut() {
+                Some(block)
+                    if block.last().is_some_and(|&last| {
+                        last + 1 == i
+                            && self.lines[last].number + 1 == line.number
+                            && self.lines[last].source + 1 == line.source
+                    }) =>
+                {
+                    block.push(i);
+                }
+                _ => blocks.push(vec![i]),
+            }
+        }
+        blocks
+    }
+
+    fn keys(&self, id: usize) -> Vec<Vec<u8>> {
+        diff::lines(&self.versions[id].data)
+            .into_iter()
+            .map(|line| self.key(line))
+            .collect()
+    }
+
+    // -wなら空白を除いて比べる
+    fn key(&self, line: &[u8]) -> Vec<u8> {
+        if self.options.ignore_whitespace {
+            line.iter()
+                .copied()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect()
+        } else {
+            line.to_vec()
+        }
+    }
+
+    fn score(&self, block: &[usize]) -> usize {
+        block
+            .iter()
+            .map(|&i| alphanumerics(self.final_lines[self.lines[i].number]))
+            .sum()
+    }
+
+    fn pass_blame(&mut self, id: usize) -> Result<()> {
+        if self.suspects(id).is_empty() {
+            return Ok(());
+        }
+        let commit = self.versions[id].commit.clone();
+        let parents = self.commit(&commit)?.1.clone();
+        if parents.is_empty() {
+            self.origins[id].boundary = true;
+            return Ok(());
+        }
+
+        let mut found = Vec::new();
+        for parent in &parents {
+            let parent_id = self.find_in_parent(id, parent)?;
+            // 親と同じ内容なら、すべての行をその親のせいにする
+            if let Some(parent_id) = parent_id {
+                if self.versions[parent_id].blob == self.versions[id].blob {
+                    for i in self.suspects(id) {
+                        self.lines[i].origin = parent_id;
+                    }
+                    return self.enqueue(parent_id);
+                }
+            }
+            found.push(parent_id);
+        }
+        for &parent_id in found.iter().flatten() {
+            if self.origins[id].previous.is_none() {
+                let version = &self.versions[parent_id];
+                self.origins[id].previous = Some((version.commit.clone(), version.path.clone()));
+            }
+            self.pass_unchanged(id, parent_id)?;
+        }
+        if let Some(score) = self
+            .options
+            .moves
+            .or_else(|| self.options.copies.map(|_| MOVE_SCORE))
+        {
+            for &parent_id in found.iter().flatten() {
+                self.find_copies(id, &[parent_id], score)?;
+            }
+        }
+        if let Some((level, score)) = self.options.copies {
+            for (parent, &parent_id) in parents.iter().zip(&found) {
+                let candidates = self.copy_candidates(id, parent, parent_id, level)?;
+                self.find_copies(id, &candidates, score)?;
+            }
+        }
+        if self.options.ignore_revs.contains(&commit) {
+            for &parent_id in found.iter().flatten() {
+                self.pass_similar(id, parent_id)?;
+            }
+            for i in self.suspects(id) {
+                self.lines[i].unblamable = true;
+            }
+        }
+        Ok(())
+    }
+
+    // 親でのファイル。同じパスになければ、名前が変わる前のものを探す
+    fn find_in_parent(&mut self, id: usize, parent: &str) -> Result<Option<usize>> {
+        let path = self.versions[id].path.clone();
+        let parent_tree = self.repo.find_commit(parent)?.tree;
+        if let Some((mode, blob)) = find_file(self.repo, &parent_tree, &path)? {
+            if mode == GITLINK_MODE {
+                return Ok(None);
+            }
+            return self.version(parent, &path, &blob).map(Some);
+        }
+        let commit = self.versions[id].commit.clone();
+        if commit == NOT_COMMITTED {
+            return Ok(None);
+        }
+        let tree = self.repo.find_commit(&commit)?.tree;
+        let mut pairs = Vec::new();
+        for change in tree::changes(self.repo, Some(&parent_tree), Some(&tree))? {
+            let side = |(mode, oid): (u32, String), data| Side::new(&change.path, mode, oid, data);
+            let pair = match (change.old, change.new) {
+                (Some(old), None) => {
+                    let data = self.repo.find_blob(&old.1)?;
+                    FilePair::new(Some(side(old, data)), None)
+                }
+                (None, Some(new)) if change.path == path => {
+                    FilePair::new(None, Some(side(new, self.versions[id].data.clone())))
+                }
+                _ => continue,
+            };
+            pairs.push(pair);
+        }
+        let renamed = rename::detect(pairs, rename::Options::default())
+            .into_iter()
+            .find_map(|pair| match (pair.old, pair.new) {
+                (Some(old), Some(new)) if new.path == path => Some(old),
+                _ => None,
+            });
+        match renamed {
+            Some(old) => self.version(parent, &old.path, &old.oid).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // 親と変わらない行を親のせいにする
+    fn pass_unchanged(&mut self, id: usize, parent_id: usize) -> Result<()> {
+        let edits = diff::diff(Algorithm::Myers, &self.keys(parent_id), &self.keys(id));
+        let unchanged: HashMap<usize, usize> = edits
+            .into_iter()
+            .filter_map(|edit| match edit {
+                Edit::Equal(old, new) => Some((new, old)),
+                _ => None,
+            })
+            .collect();
+        let mut passed = false;
+        for i in self.suspects(id) {
+            if let Some(&source) = unchanged.get(&self.lines[i].source) {
+                self.lines[i].origin = parent_id;
+                self.lines[i].source = source;
+                passed = true;
+            }
+        }
+        if passed {
+            self.enqueue(parent_id)?;
+        }
+        Ok(())
+    }
+
+    // -Mや-Cで、まとまった行が移されたもの(写されたもの)を`candidates`から探す
+    fn find_copies(&mut self, id: usize, candidates: &[usize], score: usize) -> Result<()> {
+        let candidate_keys: Vec<_> = candidates.iter().map(|&c| self.keys(c)).collect();
+        let keys = self.keys(id);
+        loop {
+            let mut progress = false;
+            for block in self.blocks(id) {
+                if self.score(&block) <= score {
+                    continue;
+                }
+                let block_keys: Vec<_> = block
+                    .iter()
+                    .map(|&i| keys[self.lines[i].source].clone())
+                    .collect();
+                // 最も文字の多い一致を選ぶ
+                let mut best: Option<(usize, usize, Range<usize>, usize)> = None;
+                for (c, keys) in candidate_keys.iter().enumerate() {
+                    for (range, source) in common_runs(keys, &block_keys) {
+                        let found = self.score(&block[range.clone()]);
+                        if best.as_ref().map_or(true, |best| found > best.0) {
+                            best = Some((found, c, range, source));
+                        }
+                    }
+                }
+                let Some((found, c, range, source)) = best else {
+                    continue;
+                };
+                if found <= score {
+                    continue;
+                }
+                for (k, &i) in block[range].iter().enumerate() {
+                    self.lines[i].origin = candidates[c];
+                    self.lines[i].source = source + k;
+                }
+                self.enqueue(candidates[c])?;
+                progress = true;
+            }
+            if !progress {
+                return Ok(());
+            }
+        }
+    }
+
+    // -Cで写した元として探すファイル。同じcommitで変更されたもの、
+    // -C -Cでファイルが作られたときと-C -C -Cではすべてのファイル
+    fn copy_candidates(
+        &mut self,
+        id: usize,
+        parent: &str,
+        parent_id: Option<usize>,
+        level: usize,
+    ) -> Result<Vec<usize>> {
+        let path = self.versions[id].path.clone();
+        let commit = self.versions[id].commit.clone();
+        let parent_tree = self.repo.find_commit(parent)?.tree;
+        let created = parent_id.map_or(true, |p| self.versions[p].path != path);
+        let files: Vec<(String, String)> = if level >= 3 || (level >= 2 && created) {
+            self.repo
+                .tree_files(&parent_tree)?
+                .into_iter()
+                .filter(|entry| entry.mode != GITLINK_MODE)
+                .map(|entry| (entry.name, entry.oid))
+                .collect()
+        } else if commit == NOT_COMMITTED {
+            Vec::new()
+        } else {
+            let tree = self.repo.find_commit(&commit)?.tree;
+            tree::changes(self.repo, Some(&parent_tree), Some(&tree))?
+                .into_iter()
+                .filter_map(|change| {
+                    let (mode, oid) = change.old?;
+                    (mode != GITLINK_MODE).then_some((change.path, oid))
+                })
+                .collect()
+        };
+        let skip = parent_id.map(|p| self.versions[p].path.clone());
+        let mut candidates = Vec::new();
+        for (file, blob) in files {
+            if Some(&file) != skip.as_ref() {
+                candidates.push(self.version(parent, &file, &blob)?);
+            }
+        }
+        Ok(candidates)
+    }
+
+    // 無視するcommitで変わった行を、親の似た行のせいにする
+    fn pass_similar(&mut self, id: usize, parent_id: usize) -> Result<()> {
+        let edits = diff::diff(Algorithm::Myers, &self.keys(parent_id), &self.keys(id));
+        let parent_lines = diff::lines(&self.versions[parent_id].data);
+        let lines = diff::lines(&self.versions[id].data);
+        let suspects: HashMap<usize, usize> = self
+            .suspects(id)
+            .into_iter()
+            .map(|i| (self.lines[i].source, i))
+            .collect();
+        let mut passed = false;
+        for (old, new) in changed_chunks(&edits) {
+            if !new.clone().any(|n| suspects.contains_key(&n)) {
+                continue;
+            }
+            let matches = similar_lines(&parent_lines[old.clone()], &lines[new.clone()]);
+            for (n, matched) in new.zip(matches) {
+                if let (Some(&i), Some(m)) = (suspects.get(&n), matched) {
+                    self.lines[i].origin = parent_id;
+                    self.lines[i].source = old.start + m;
+                    self.lines[i].ignored = true;
+                    passed = true;
+                }
+            }
+        }
+        if passed {
+            self.enqueue(parent_id)?;
+        }
+        Ok(())
+    }
+}
+
+// treeの中のパスのファイルの(mode, oid)
+fn find_file(repo: &Repository, tree: &str, path: &str) -> Result<Option<(u32, String)>> {
+    let mut tree = tree.to_string();
+    let mut components = path.split('/').peekable();
+    while let Some(name) = components.next() {
+        let Some(entry) = repo
+            .find_tree(&tree)?
+            .entries
+            .into_iter()
+            .find(|entry| entry.name == name)
+        else {
+            return Ok(None);
+        };
+        if components.peek().is_none() {
+            return Ok((!entry.is_tree()).then_some((entry.mode, entry.oid)));
+        }
+        if !entry.is_tree() {
+            return Ok(None);
+        }
+        tree = entry.oid;
+    }
+    Err(Error::InvalidObjectName(path.to_string()))
+}
+
+fn alphanumerics(line: &[u8]) -> usize {
+    line.iter().filter(|b| b.is_ascii_alphanumeric()).count()
+}
+
+// `old`と`new`に共通する連続した行: (`new`での範囲, `old`での開始位置)
+fn common_runs<T: Eq + std::hash::Hash>(old: &[T], new: &[T]) -> Vec<(Range<usize>, usize)> {
+    let mut runs: Vec<(Range<usize>, usize)> = Vec::new();
+    for edit in diff::diff(Algorithm::Myers, old, new) {
+        let Edit::Equal(o, n) = edit else {
+            continue;
+        };
+        match runs.last_mut() {
+            Some((range, start)) if range.end == n && *start + range.len() == o => range.end += 1,
+            _ => runs.push((
+                Range {
+                    start: n,
+                    end: n + 1,
+                },
+                o,
+            )),
+        }
+    }
+    runs
+}
+
+// 編集の変更された部分: (古い側の範囲, 新しい側の範囲)
+fn changed_chunks(edits: &[Edit]) -> Vec<(Range<usize>, Range<usize>)> {
+    let mut chunks = Vec::new();
+    let (mut old, mut new) = (0, 0);
+    let mut current: Option<(Range<usize>, Range<usize>)> = None;
+    for edit in edits {
+        match *edit {
+            Edit::Equal(o, n) => {
+                chunks.extend(current.take());
+                old = o + 1;
+                new = n + 1;
+            }
+            Edit::Delete(o) => {
+                let chunk = current.get_or_insert((old..old, new..new));
+                chunk.0.end = o + 1;
+            }
+            Edit::Insert(n) => {
+                let chunk = current.get_or_insert((old..old, new..new));
+                chunk.1.end = n + 1;
+            }
+        }
+    }
+    chunks.extend(current);
+    chunks
+}
+
+// 行の特徴: 小文字にした隣り合う2文字の組(空白は0)ごとの数
+fn fingerprint(line: &[u8]) -> HashMap<u16, usize> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let mut counts = HashMap::new();
+    let mut previous = 0u8;
+    for i in 0..=line.len() {
+        let c = match line.get(i) {
+            Some(b) if !b.is_ascii_whitespace() => b.to_ascii_lowercase(),
+            _ => 0,
+        };
+        let pair = u16::from(previous) | (u16::from(c) << 8);
+        previous = c;
+        if pair != 0 {
+            *counts.entry(pair).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+fn fingerprint_similarity(a: &HashMap<u16, usize>, b: &HashMap<u16, usize>) -> usize {
+    a.iter()
+        .map(|(pair, &count)| b.get(pair).map_or(0, |&other| count.min(other)))
+        .sum()
+}
+
+/// For each line of `new`, the most similar line of `old` (by pairs of
+/// characters), keeping the matches in order like git's `--ignore-rev`.
+fn similar_lines(old: &[&[u8]], new: &[&[u8]]) -> Vec<Option<usize>> {
+    let mut result = vec![None; new.len()];
+    if old.is_empty() {
+        return result;
+    }
+    let old_prints: Vec<_> = old.iter().map(|line| fingerprint(line)).collect();
+    let new_prints: Vec<_> = new.iter().map(|line| fingerprint(line)).collect();
+    let matcher = Matcher {
+        old: &old_prints,
+        new: &new_prints,
+    };
+    matcher.find(0..old.len(), 0..new.len(), &mut result);
+    result
+}
+
+struct Matcher<'a> {
+    old: &'a [HashMap<u16, usize>],
+    new: &'a [HashMap<u16, usize>],
+}
+
+impl Matcher<'_> {
+    // 最も確かな組を決め、その前後で同じことを繰り返す
+    fn find(&self, old: Range<usize>, new: Range<usize>, result: &mut [Option<usize>]) {
+        let mut most_certain: Option<(usize, usize, usize)> = None;
+        for n in new.clone() {
+            if let Some((m, certainty)) = self.best_match(old.clone(), n) {
+                if most_certain.map_or(true, |(_, _, best)| certainty > best) {
+                    most_certain = Some((n, m, certainty));
+                }
+            }
+        }
+        let Some((n, m, _)) = most_certain else {
+            return;
+        };
+        result[n] = Some(m);
+        if n > new.start {
+            let end = m + 1;
+            self.find(old.start..end, new.start..n, result);
+        }
+        if n + 1 < new.end {
+            self.find(m..old.end, n + 1..new.end, result);
+        }
+    }
+
+    // `new`の行`n`に最も似た`old`の行と確かさ。全体で同じ割合の位置に近いほど優先する
+    fn best_match(&self, old: Range<usize>, n: usize) -> Option<(usize, usize)> {
+        let closest =
+            ((n * 2 + 1) * self.old.len() / (self.new.len() * 2)).clamp(old.start, old.end - 1);
+        let start = closest.saturating_sub(SEARCH_DISTANCE).max(old.start);
+        let end = (closest + SEARCH_DISTANCE + 1).min(old.end);
+        let (mut best, mut second) = ((0, 0), 0);
+        for m in start..end {
+            let similarity =
+                fingerprint_similarity(&self.new[n], &self.old[m]) * (1000 - m.abs_diff(closest));
+            if similarity > best.0 {
+                second = best.0;
+                best = (similarity, m);
+            } else if similarity > second {
+                second = similarity;
+            }
+        }
+        (best.0 > 0).then_some((best.1, best.0 * 2 - second))
+    }
+}
+
+/// Parse a `-L` argument, `<start>,<end>` where either may be omitted and
+/// `<end>` may be `+<count>` or `-<count>`, into line numbers from 0 of a
+/// file with `count` lines.
+pub fn parse_range(spec: &str, count: usize) -> std::result::Result<Range<usize>, String> {
+    let (start, end) = spec.split_once(',').unwrap_or((spec, ""));
+    let number = |s: &str| {
+        s.parse::<usize>()
+            .map_err(|_| format!("invalid -L range: '{spec}'"))
+    };
+    let start = if start.is_empty() { 1 } else { number(start)? };
+    if start == 0 {
+        return Err(format!("-L invalid line number: {start}"));
+    }
+    let (low, high) = if let Some(offset) = end.strip_prefix('+') {
+        match number(offset)? {
+            0 => return Err("-L invalid empty range".to_string()),
+            offset => (start, start + offset - 1),
+        }
+    } else if let Some(offset) = end.strip_prefix('-') {
+        match number(offset)? {
+            0 => return Err("-L invalid empty range".to_string()),
+            offset => ((start + 1).saturating_sub(offset).max(1), start),
+        }
+    } else if end.is_empty() {
+        (start, count.max(start))
+    } else {
+        let end = number(end)?;
+        if end == 0 {
+            return Err(format!("-L invalid line number: {end}"));
+        }
+        (start.min(end), start.max(end))
+    };
+    Ok(low - 1..high.min(count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("3,5", 10), Ok(2..5));
+        assert_eq!(parse_range("5,3", 10), Ok(2..5));
+        assert_eq!(parse_range("3", 10), Ok(2..10));
+        assert_eq!(parse_range(",2", 10), Ok(0..2));
+        assert_eq!(parse_range("2,+2", 10), Ok(1..3));
+        assert_eq!(parse_range("10,-2", 10), Ok(8..10));
+        assert_eq!(parse_range("9,-30", 10), Ok(0..9));
+        assert_eq!(parse_range("8,30", 10), Ok(7..10));
+        assert!(parse_range("0", 10).is_err());
+        assert!(parse_range("3,+0", 10).is_err());
+        assert!(parse_range("x", 10).is_err());
+    }
+
+    #[test]
+    fn test_common_runs() {
+        let old = ["a", "b", "c", "d", "e"];
+        let new = ["x", "c", "d", "y", "a"];
+        let runs = common_runs(&old, &new);
+        assert!(runs.contains(&(1..3, 2)));
+    }
+
+    #[test]
+    fn test_changed_chunks() {
+        let edits = diff::myers(&["a", "b", "c", "d"], &["a", "x", "y", "d", "e"]);
+        assert_eq!(changed_chunks(&edits), vec![(1..3, 1..3), (4..4, 4..5)]);
+    }
+
+    #[test]
+    fn test_similar_lines() {
+        let old: Vec<&[u8]> = vec![b"beta two is the second line\n", b"gamma three\n"];
+        let new: Vec<&[u8]> = vec![b"Beta 2 is the second line\n", b"gamma   three\n"];
+        assert_eq!(similar_lines(&old, &new), vec![Some(0), Some(1)]);
+        let new: Vec<&[u8]> = vec![b"xyz\n", b"qqq\n"];
+        assert_eq!(similar_lines(&old, &new), vec![None, None]);
+        assert_eq!(similar_lines(&[], &new), vec![None, None]);
+    }
+}
@@ -0,0 +1,1691 @@
+//! Command line definitions.
+
+use std::ffi::{OsStr, OsString};
+use std::path::PathBuf;
+
+use bpaf::{any, construct, long, positional, pure, short, Args, Parser};
+use git::diff::patch::WordDiff;
+use git::diff::{rename, Algorithm};
+use git::merge::ConflictStyle;
+use git::message::Cleanup;
+use git::repository::{InitOptions, SharedMode};
+
+use crate::command::update_index::{self, CacheInfo};
+use crate::command::{
+    add, blame, branch, checkout, clean, commit, diff, ls_files, ls_tree, merge, merge_base,
+    mergetool, mv, read_tree, rebase, reset, restore, rm, sequencer, show, stash, switch,
+};
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub directory: Vec<PathBuf>,
+    pub git_dir: Option<PathBuf>,
+    pub work_tree: Option<PathBuf>,
+    pub command: Command,
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Init {
+        directory: Option<PathBuf>,
+        options: InitOptions,
+    },
+    Add {
+        options: add::Options,
+        pathspec: Vec<String>,
+    },
+    Rm {
+        options: rm::Options,
+        pathspec: Vec<String>,
+    },
+    Mv {
+        options: mv::Options,
+        paths: Vec<String>,
+    },
+    Diff {
+        options: diff::Options,
+        args: Vec<String>,
+    },
+    Show {
+        options: show::Options,
+        args: Vec<String>,
+    },
+    Commit {
+        options: commit::Options,
+    },
+    Branch {
+        options: branch::Options,
+        args: Vec<String>,
+    },
+    Checkout {
+        options: checkout::Options,
+        args: Vec<String>,
+        paths: Vec<String>,
+    },
+    Switch {
+        options: switch::Options,
+        args: Vec<String>,
+    },
+    Restore {
+        options: restore::Options,
+        paths: Vec<String>,
+    },
+    Merge {
+        options: merge::Options,
+        args: Vec<String>,
+    },
+    CherryPick {
+        options: sequencer::Options,
+        args: Vec<String>,
+    },
+    Revert {
+        options: sequencer::Options,
+        args: Vec<String>,
+    },
+    Rebase {
+        options: rebase::Options,
+        args: Vec<String>,
+    },
+    Clean {
+        options: clean::Options,
+        pathspec: Vec<String>,
+    },
+    Blame {
+        options: blame::Options,
+        args: Vec<String>,
+        paths: Vec<String>,
+    },
+    Stash {
+        subcommand: stash::Subcommand,
+        options: stash::Options,
+        args: Vec<String>,
+    },
+    MergeBase {
+        options: merge_base::Options,
+        args: Vec<String>,
+    },
+    Mergetool {
+        options: mergetool::Options,
+        paths: Vec<String>,
+    },
+    Log,
+    Status {
+        short: bool,
+        ignored: bool,
+    },
+    HashObject {
+        write: bool,
+        stdin: bool,
+        paths: Vec<PathBuf>,
+    },
+    WriteTree,
+    CommitTree {
+        parents: Vec<String>,
+        messages: Vec<String>,
+        tree: String,
+    },
+    LsTree {
+        options: ls_tree::Options,
+        tree: String,
+        paths: Vec<String>,
+    },
+    LsFiles {
+        options: ls_files::Options,
+        paths: Vec<String>,
+    },
+    ReadTree {
+        options: read_tree::Options,
+        trees: Vec<String>,
+    },
+    UpdateIndex {
+        options: update_index::Options,
+        paths: Vec<String>,
+    },
+    Reset {
+        options: reset::Options,
+        args: Vec<String>,
+        paths: Vec<String>,
+    },
+    CheckIgnore {
+        verbose: bool,
+        non_matching: bool,
+        no_index: bool,
+        paths: Vec<PathBuf>,
+    },
+}
+
+pub fn options() -> Options {
+    let directory = short('C')
+        .help("Run as if git was started in PATH")
+        .argument::<PathBuf>("PATH")
+        .many();
+    let git_dir = long("git-dir")
+        .help("Set the path to the repository")
+        .argument::<PathBuf>("PATH")
+        .optional();
+    let work_tree = long("work-tree")
+        .help("Set the path to the working tree")
+        .argument::<PathBuf>("PATH")
+        .optional();
+
+    let init = init();
+    let add = add();
+    let rm = rm();
+    let mv = mv();
+    let diff = diff();
+    let show = show();
+    let commit = commit();
+    let branch = branch();
+    let checkout = checkout();
+    let switch = switch();
+    let restore = restore();
+    let merge = merge();
+    let merge_base = merge_base();
+    let cherry_pick = cherry_pick();
+    let revert = revert();
+    let rebase = rebase();
+    let stash = stash();
+    let clean = clean();
+    let blame = blame();
+    let mergetool = mergetool();
+    let log = pure(Command::Log).to_options().command("log");
+    let status = status();
+    let check_ignore = check_ignore();
+    let hash_object = hash_object();
+    let write_tree = pure(Command::WriteTree)
+        .to_options()
+        .command("write-tree")
+        .help("Create a tree object from the current index");
+    let commit_tree = commit_tree();
+    let ls_tree = ls_tree();
+    let ls_files = ls_files();
+    let read_tree = read_tree();
+    let update_index = update_index();
+    let reset = reset();
+    let command = construct!([
+        init,
+        add,
+        rm,
+        mv,
+        diff,
+        show,
+        commit,
+        branch,
+        checkout,
+        switch,
+        restore,
+        merge,
+        merge_base,
+        cherry_pick,
+        revert,
+        rebase,
+        stash,
+        clean,
+        blame,
+        mergetool,
+        log,
+        status,
+        check_ignore,
+        hash_object,
+        write_tree,
+        commit_tree,
+        ls_tree,
+        ls_files,
+        read_tree,
+        update_index,
+        reset
+    ]);
+
+    let parser = construct!(Options {
+        directory,
+        git_dir,
+        work_tree,
+        command,
+    })
+    .to_options()
+    .version(env!("CARGO_PKG_VERSION"))
+    .fallback_to_usage();
+
+    let args = command_line();
+    let args: Vec<&OsStr> = args.iter().map(OsString::as_os_str).collect();
+    match parser.run_inner(Args::from(args.as_slice()).set_name("git")) {
+        Ok(options) => options,
+        Err(err) => std::process::exit(err.exit_code()),
+    }
+}
+
+// -Cをとるdiff系のコマンド(blameの-Cも同じ形)
+const DIFF_COMMANDS: &[&str] = &["diff", "show", "blame"];
+
+// "--"の前後でrevisionとpathを区別するコマンド
+const SEPARATED_COMMANDS: &[&str] = &["checkout", "reset", "blame"];
+
+// まとめて書ける短いオプションのあるコマンドと、値をとらないもの、値をとるもの
+const CLUSTERED_COMMANDS: &[(&str, &str, &str)] = &[("commit", "ae", "mF")];
+
+// トップレベルの-C <path>はコマンドの後ろの引数にも効いてしまうので、
+// diff系のコマンドの-C[<n>]は--find-copies[=<n>]に、switchの-Cは--force-createに
+// 置き換えておく。
+// bpafは"--"の位置を教えてくれないので、"--"の後の引数は--path=<path>にする
+fn command_line() -> Vec<OsString> {
+    rewrite_args(std::env::args_os().skip(1).collect())
+}
+
+fn rewrite_args(mut args: Vec<OsString>) -> Vec<OsString> {
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].to_str() {
+            Some("-C" | "--git-dir" | "--work-tree") => i += 2,
+            Some(arg) if arg.starts_with('-') => i += 1,
+            _ => break,
+        }
+    }
+    let command = args
+        .get(i)
+        .and_then(|arg| arg.to_str())
+        .unwrap_or_default()
+        .to_string();
+    if DIFF_COMMANDS.contains(&command.as_str()) {
+        for arg in &mut args[i + 1..] {
+            if arg == "--" {
+                break;
+            }
+            if let Some(score) = arg.to_str().and_then(|arg| arg.strip_prefix("-C")) {
+                *arg = match score {
+                    "" => "--find-copies".into(),
+                    score => format!("--find-copies={score}").into(),
+                };
+            }
+        }
+    }
+    if command == "switch" {
+        for arg in &mut args[i + 1..] {
+            if arg == "--" {
+                break;
+            }
+            if arg == "-C" {
+                *arg = "--force-create".into();
+            }
+        }
+    }
+    if let Some((_, flags, values)) = CLUSTERED_COMMANDS.iter().find(|(c, ..)| *c == command) {
+        args = split_clusters(args, i + 1, flags, values);
+    }
+    if SEPARATED_COMMANDS.contains(&command.as_str()) {
+        if let Some(dash) = args.iter().skip(i + 1).position(|arg| arg == "--") {
+            let paths = args.split_off(i + 1 + dash);
+            args.extend(paths.into_iter().skip(1).map(|path| {
+                let mut arg = OsString::from("--path=");
+                arg.push(path);
+                arg
+            }));
+        }
+    }
+    args
+}
+
+// bpafは"-am msg"や"-mmsg"を受け付けないので、gitと同じように"-a -m msg"や
+// "-m msg"に分けておく。知らない文字があればそのままにする
+fn split_clusters(args: Vec<OsString>, start: usize, flags: &str, values: &str) -> Vec<OsString> {
+    let mut result = Vec::with_capacity(args.len());
+    let mut args = args.into_iter();
+    result.extend(args.by_ref().take(start));
+    while let Some(arg) = args.next() {
+        let cluster = match arg.to_str() {
+            Some("--") => {
+                result.push(arg);
+                break;
+            }
+            Some(arg) if arg.len() > 2 && arg.starts_with('-') && !arg.starts_with("--") => {
+                &arg[1..]
+            }
+            // 値をとるオプションの後ろは、"-"で始まっていても値
+            Some(name)
+                if name.len() == 2 && name.starts_with('-') && values.contains(&name[1..]) =>
+            {
+                result.push(arg);
+                result.extend(args.next());
+                continue;
+            }
+            _ => {
+                result.push(arg);
+                continue;
+            }
+        };
+        let mut split = Vec::new();
+        let mut value = None;
+        for (at, c) in cluster.char_indices() {
+            if flags.contains(c) {
+                split.push(format!("-{c}"));
+            } else if values.contains(c) {
+                split.push(format!("-{c}"));
+                value = Some(&cluster[at + c.len_utf8()..]);
+                break;
+            } else {
+                split.clear();
+                break;
+            }
+        }
+        if split.is_empty() {
+            result.push(arg);
+            continue;
+        }
+        result.extend(split.into_iter().map(OsString::from));
+        match value {
+            Some("") => result.extend(args.next()),
+            Some(value) => result.push(value.into()),
+            None => {}
+        }
+    }
+    result.extend(args);
+    result
+}
+
+fn init() -> impl Parser<Command> {
+    let bare = long("bare").help("Create a bare repository").switch();
+    let initial_branch = short('b')
+        .long("initial-branch")
+        .help("Name of the initial branch")
+        .argument("BRANCH")
+        .optional();
+    let template = long("template")
+        .help("Directory from which templates will be used")
+        .argument::<PathBuf>("DIR")
+        .optional();
+    // --sharedは値を省略でき、その場合はgroupになる
+    let shared = optional_value(
+        "--shared",
+        "group",
+        "Share the repository among several users",
+    )
+    .parse(|value| {
+        SharedMode::parse(&value).ok_or_else(|| format!("invalid --shared value: {value}"))
+    })
+    .optional();
+    let object_format = long("object-format")
+        .help("Hash algorithm of the repository")
+        .argument("FORMAT")
+        .optional();
+    let options = construct!(InitOptions {
+        bare,
+        initial_branch,
+        template,
+        shared,
+        object_format,
+    });
+    let directory = positional::<PathBuf>("DIRECTORY")
+        .help("Directory to create the repository in")
+        .optional();
+    construct!(Command::Init { options, directory })
+        .to_options()
+        .command("init")
+        .help("Initialize new git repository")
+}
+
+fn add() -> impl Parser<Command> {
+    let all = short('A')
+        .long("all")
+        .help("Add changes from all tracked and untracked files")
+        .switch();
+    let update = short('u')
+        .long("update")
+        .help("Update tracked files")
+        .switch();
+    let dry_run = short('n').long("dry-run").help("Dry run").switch();
+    let verbose = short('v').long("verbose").help("Be verbose").switch();
+    let force = short('f')
+        .long("force")
+        .help("Allow adding otherwise ignored files")
+        .switch();
+    let intent_to_add = short('N')
+        .long("intent-to-add")
+        .help("Record only the fact that the path will be added later")
+        .switch();
+    let patch = short('p')
+        .long("patch")
+        .help("Select hunks interactively")
+        .switch();
+    let options = construct!(add::Options {
+        all,
+        update,
+        dry_run,
+        verbose,
+        force,
+        intent_to_add,
+        patch,
+    });
+    let pathspec = positional("PATHSPEC").help("Files to add").many();
+    construct!(Command::Add { options, pathspec })
+        .to_options()
+        .command("add")
+        .help("Add file contents to the index")
+}
+
+fn rm() -> impl Parser<Command> {
+    let cached = long("cached").help("Only remove from the index").switch();
+    let recursive = short('r').help("Allow recursive removal").switch();
+    let force = short('f')
+        .long("force")
+        .help("Override the up-to-date check")
+        .switch();
+    let dry_run = short('n').long("dry-run").help("Dry run").switch();
+    let quiet = short('q')
+        .long("quiet")
+        .help("Do not list removed files")
+        .switch();
+    let options = construct!(rm::Options {
+        cached,
+        recursive,
+        force,
+        dry_run,
+        quiet,
+    });
+    let pathspec = positional("PATHSPEC").help("Files to remove").many();
+    construct!(Command::Rm { options, pathspec })
+        .to_options()
+        .command("rm")
+        .help("Remove files from the working tree and from the index")
+}
+
+fn mv() -> impl Parser<Command> {
+    let force = short('f')
+        .long("force")
+        .help("Force move/rename even if target exists")
+        .switch();
+    let skip_errors = short('k').help("Skip move/rename errors").switch();
+    let dry_run = short('n').long("dry-run").help("Dry run").switch();
+    let verbose = short('v').long("verbose").help("Be verbose").switch();
+    let options = construct!(mv::Options {
+        force,
+        skip_errors,
+        dry_run,
+        verbose,
+    });
+    let paths = positional("PATH")
+        .help("Sources followed by the destination")
+        .many();
+    construct!(Command::Mv { options, paths })
+        .to_options()
+        .command("mv")
+        .help("Move or rename a file, a directory, or a symlink")
+}
+
+fn diff() -> impl Parser<Command> {
+    let options = diff_options();
+    let args = positional("ARGS")
+        .help("Commits to compare, followed by paths to limit the diff to")
+        .many();
+    construct!(Command::Diff { options, args })
+        .to_options()
+        .command("diff")
+        .help("Show changes between commits, commit and working tree, etc")
+}
+
+fn show() -> impl Parser<Command> {
+    let no_patch = short('s')
+        .long("no-patch")
+        .help("Suppress the patch")
+        .switch();
+    let diff = diff_options();
+    let options = construct!(show::Options { diff, no_patch });
+    let args = positional("OBJECT")
+        .help("Objects to show, followed by paths to limit the patches to")
+        .many();
+    construct!(Command::Show { options, args })
+        .to_options()
+        .command("show")
+        .help("Show various types of objects")
+}
+
+// diffとshowに共通のオプション
+fn diff_options() -> impl Parser<diff::Options> {
+    let cached = long("cached")
+        .long("staged")
+        .help("Compare the index with HEAD or the given commit")
+        .switch();
+    let stat = long("stat")
+        .help("Show a diffstat")
+        .req_flag(diff::Format::Stat);
+    let numstat = long("numstat")
+        .help("Show added and removed line counts")
+        .req_flag(diff::Format::Numstat);
+    let name_only = long("name-only")
+        .help("Show only names of changed files")
+        .req_flag(diff::Format::NameOnly);
+    let name_status = long("name-status")
+        .help("Show names and status of changed files")
+        .req_flag(diff::Format::NameStatus);
+    let patch = short('p')
+        .long("patch")
+        .help("Generate a patch")
+        .req_flag(diff::Format::Patch);
+    let format = construct!([stat, numstat, name_only, name_status, patch])
+        .last()
+        .fallback(diff::Format::Patch);
+    let context = short('U')
+        .long("unified")
+        .help("Generate diffs with <n> lines of context")
+        .argument::<usize>("N")
+        .optional();
+    let patience = long("patience")
+        .help("Use the patience diff algorithm")
+        .req_flag(Algorithm::Patience);
+    let histogram = long("histogram")
+        .help("Use the histogram diff algorithm")
+        .req_flag(Algorithm::Histogram);
+    let minimal = long("minimal")
+        .help("Use the Myers diff algorithm")
+        .req_flag(Algorithm::Myers);
+    let diff_algorithm = long("diff-algorithm")
+        .help("Choose a diff algorithm")
+        .argument::<String>("ALGORITHM")
+        .parse(|name| Algorithm::parse(&name).ok_or(format!("unknown diff algorithm: {name}")));
+    let algorithm = construct!([patience, histogram, minimal, diff_algorithm])
+        .last()
+        .optional();
+    let word_diff = optional_value("--word-diff", "plain", "Show a word diff")
+        .parse(|mode| match mode.as_str() {
+            "plain" => Ok(WordDiff::Plain),
+            "color" => Ok(WordDiff::Color),
+            _ => Err(format!("bad --word-diff argument: {mode}")),
+        })
+        .optional();
+    let color = optional_value("--color", "always", "Show colored diff").optional();
+    let renames = renames();
+    let no_renames = long("no-renames")
+        .help("Turn off rename detection")
+        .switch();
+    let base = short('1')
+        .long("base")
+        .help("Compare unmerged files with the common ancestor")
+        .req_flag(1);
+    let ours = short('2')
+        .long("ours")
+        .help("Compare unmerged files with our version")
+        .req_flag(2);
+    let theirs = short('3')
+        .long("theirs")
+        .help("Compare unmerged files with their version")
+        .req_flag(3);
+    let stage = construct!([base, ours, theirs]).last().optional();
+    let combined = long("cc")
+        .help("Show unmerged files as combined diffs")
+        .switch();
+    construct!(diff::Options {
+        cached,
+        format,
+        context,
+        algorithm,
+        word_diff,
+        color,
+        renames,
+        no_renames,
+        stage,
+        combined,
+    })
+}
+
+// -M[<n>], -C[<n>], --find-renames[=<n>], --find-copies[=<n>]
+fn renames() -> impl Parser<Option<rename::Options>> {
+    let flags = [
+        ("-M", "", false),
+        ("--find-renames", "=", false),
+        ("--find-copies", "=", true),
+    ];
+    any::<String, _, _>("-M[<n>]", move |arg| {
+        flags.iter().find_map(|(flag, separator, _)| {
+            let value = arg.strip_prefix(flag)?;
+            (value.is_empty() || value.starts_with(separator)).then(|| arg.clone())
+        })
+    })
+    .help("Detect renames (-M) or copies (-C) at least <n> similar")
+    .anywhere()
+    .parse(move |arg| {
+        let (flag, separator, copies) = flags
+            .iter()
+            .find(|(flag, ..)| arg.starts_with(flag))
+            .ok_or_else(|| format!("unknown option {arg}"))?;
+        let value = &arg[flag.len()..];
+        let threshold = match value.strip_prefix(separator) {
+            Some(score) if !value.is_empty() => rename::parse_score(score)
+                .ok_or_else(|| format!("invalid rename score: {score}"))?,
+            _ => rename::Options::default().threshold,
+        };
+        Ok::<_, String>((*copies, threshold))
+    })
+    .many()
+    .map(|detect| {
+        let &(copies, threshold) = detect.last()?;
+        Some(rename::Options {
+            copies,
+            threshold,
+            ..rename::Options::default()
+        })
+    })
+}
+
+// "--name"と"--name=value"の両方を受け付ける。値を省略した場合はdefault
+fn optional_value(
+    name: &'static str,
+    default: &'static str,
+    help: &'static str,
+) -> impl Parser<String> {
+    let prefix = format!("{name}=");
+    any::<String, _, _>(name, move |arg| {
+        if arg == name {
+            Some(default.to_string())
+        } else {
+            arg.strip_prefix(&prefix).map(ToString::to_string)
+        }
+    })
+    .help(help)
+    .anywhere()
+}
+
+fn commit() -> impl Parser<Command> {
+    let messages = short('m')
+        .long("message")
+        .help("Use the given message as a paragraph of the commit message")
+        .argument("MESSAGE")
+        .many();
+    let file = short('F')
+        .long("file")
+        .help("Take the commit message from the given file (- for standard input)")
+        .argument("FILE")
+        .optional();
+    let edit = short('e')
+        .long("edit")
+        .help("Further edit the message in an editor")
+        .req_flag(true);
+    let no_edit = long("no-edit")
+        .help("Use the message without launching an editor")
+        .req_flag(false);
+    let edit = construct!([edit, no_edit]).last().optional();
+    let all = short('a')
+        .long("all")
+        .help("Stage modified and deleted files first")
+        .switch();
+    let amend = long("amend").help("Amend the previous commit").switch();
+    let reset_author = long("reset-author")
+        .help("Make the committer the author of the amended commit")
+        .switch();
+    let allow_empty = long("allow-empty")
+        .help("Allow a commit with the same tree as its parent")
+        .switch();
+    let cleanup = long("cleanup")
+        .help("How to strip spaces and #comments from the message")
+        .argument::<String>("MODE")
+        .parse(|mode| Cleanup::parse(&mode).ok_or(format!("Invalid cleanup mode {mode}")))
+        .optional();
+    let fixup = long("fixup")
+        .help("Create a fixup! commit for the given commit")
+        .argument("COMMIT")
+        .optional();
+    let squash = long("squash")
+        .help("Create a squash! commit for the given commit")
+        .argument("COMMIT")
+        .optional();
+    let trailers = long("trailer")
+        .help("Add a <token>: <value> trailer to the message")
+        .argument("TRAILER")
+        .many();
+    let author = pure(None);
+    let reflog_action = pure(None);
+    let options = construct!(commit::Options {
+        messages,
+        file,
+        edit,
+        all,
+        amend,
+        reset_author,
+        allow_empty,
+        cleanup,
+        fixup,
+        squash,
+        trailers,
+        author,
+        reflog_action,
+    });
+    construct!(Command::Commit { options })
+        .to_options()
+        .command("commit")
+        .help("Record changes to the repository")
+}
+
+fn branch() -> impl Parser<Command> {
+    let delete = short('d')
+        .long("delete")
+        .help("Delete fully merged branches")
+        .switch();
+    let force_delete = short('D')
+        .help("Delete branches even if not merged")
+        .switch();
+    let merged = long("merged")
+        .help("List only branches merged into the commit")
+        .switch();
+    let no_merged = long("no-merged")
+        .help("List only branches not merged into the commit")
+        .switch();
+    let options = construct!(branch::Options {
+        delete,
+        force_delete,
+        merged,
+        no_merged,
+    });
+    let args = positional("BRANCH")
+        .help("Branch to create or delete, or the commit for --merged")
+        .many();
+    construct!(Command::Branch { options, args })
+        .to_options()
+        .command("branch")
+}
+
+fn checkout() -> impl Parser<Command> {
+    let new_branch = short('b').help("Create new branch?").switch();
+    let (stage, merge, conflict) = conflict_options();
+    let options = construct!(checkout::Options {
+        new_branch,
+        stage,
+        merge,
+        conflict,
+    });
+    // command_line()が"--"の後の引数を書き換えたもの
+    let paths = long("path").argument("PATH").many().hide();
+    let args = positional("ARGS")
+        .help("Branch to switch to, or tree-ish and paths to check out")
+        .many();
+    construct!(Command::Checkout {
+        options,
+        paths,
+        args
+    })
+    .to_options()
+    .command("checkout")
+}
+
+// checkoutとrestoreで衝突中のファイルをどう戻すか
+fn conflict_options() -> (
+    impl Parser<Option<u8>>,
+    impl Parser<bool>,
+    impl Parser<Option<ConflictStyle>>,
+) {
+    let ours = long("ours")
+        .help("Check out our version for unmerged files")
+        .req_flag(2);
+    let theirs = long("theirs")
+        .help("Check out their version for unmerged files")
+        .req_flag(3);
+    let stage = construct!([ours, theirs]).last().optional();
+    let merge = short('m')
+        .long("merge")
+        .help("Recreate the conflicted merge in unmerged files")
+        .switch();
+    let conflict = long("conflict")
+        .help("Like --merge, with the given conflict style")
+        .argument::<String>("STYLE")
+        .parse(|style| {
+            ConflictStyle::parse(&style).ok_or(format!("unknown conflict style '{style}'"))
+        })
+        .optional();
+    (stage, merge, conflict)
+}
+
+fn switch() -> impl Parser<Command> {
+    let create = short('c')
+        .long("create")
+        .help("Create a new branch and switch to it")
+        .argument("BRANCH")
+        .optional();
+    let force_create = short('C')
+        .long("force-create")
+        .help("Create or reset a branch and switch to it")
+        .argument("BRANCH")
+        .optional();
+    let detach = long("detach")
+        .help("Switch to a commit for inspection")
+        .switch();
+    let discard_changes = short('f')
+        .long("discard-changes")
+        .long("force")
+        .help("Throw away local changes")
+        .switch();
+    let options = construct!(switch::Options {
+        create,
+        force_create,
+        detach,
+        discard_changes,
+    });
+    let args = positional("BRANCH")
+        .help("Branch to switch to, or the start point of the new branch")
+        .many();
+    construct!(Command::Switch { options, args })
+        .to_options()
+        .command("switch")
+        .help("Switch branches")
+}
+
+fn restore() -> impl Parser<Command> {
+    let source = short('s')
+        .long("source")
+        .help("Restore from the given tree")
+        .argument("TREE")
+        .optional();
+    let staged = short('S').long("staged").help("Restore the index").switch();
+    let worktree = short('W')
+        .long("worktree")
+        .help("Restore the working tree (default)")
+        .switch();
+    let (stage, merge, conflict) = conflict_options();
+    let overlay = long("overlay")
+        .help("Never remove files missing from the source")
+        .switch();
+    let options = construct!(restore::Options {
+        source,
+        staged,
+        worktree,
+        stage,
+        merge,
+        conflict,
+        overlay,
+    });
+    let paths = positional("PATHSPEC").help("Paths to restore").many();
+    construct!(Command::Restore { options, paths })
+        .to_options()
+        .command("restore")
+        .help("Restore working tree files")
+}
+
+fn merge() -> impl Parser<Command> {
+    let ff = long("ff")
+        .help("Fast-forward when possible (default)")
+        .req_flag(merge::FastForward::Allow);
+    let no_ff = long("no-ff")
+        .help("Always create a merge commit")
+        .req_flag(merge::FastForward::Never);
+    let ff_only = long("ff-only")
+        .help("Refuse to merge unless fast-forward is possible")
+        .req_flag(merge::FastForward::Only);
+    let ff = construct!([ff, no_ff, ff_only]).last().optional();
+    let messages = short('m')
+        .long("message")
+        .help("Message of the merge commit")
+        .argument("MESSAGE")
+        .many();
+    let edit = short('e')
+        .long("edit")
+        .help("Edit the message of the merge commit")
+        .req_flag(true);
+    let no_edit = long("no-edit")
+        .help("Use the message without launching an editor")
+        .req_flag(false);
+    let edit = construct!([edit, no_edit]).last().optional();
+    let abort = long("abort").help("Abort the merge in progress").switch();
+    let continue_ = long("continue")
+        .help("Conclude the merge in progress")
+        .switch();
+    let options = construct!(merge::Options {
+        ff,
+        messages,
+        edit,
+        abort,
+        continue_,
+    });
+    let args = positional("COMMIT").help("Commit to merge").many();
+    construct!(Command::Merge { options, args })
+        .to_options()
+        .command("merge")
+        .help("Join two development histories together")
+}
+
+// cherry-pickとrevertに共通のoption
+fn sequencer_options(pick: bool) -> impl Parser<sequencer::Options> {
+    let continue_ = long("continue")
+        .help("Resume after resolving conflicts")
+        .req_flag(sequencer::Control::Continue);
+    let skip = long("skip")
+        .help("Skip the commit that stopped and go on")
+        .req_flag(sequencer::Control::Skip);
+    let abort = long("abort")
+        .help("Cancel the operation and return to the original HEAD")
+        .req_flag(sequencer::Control::Abort);
+    let quit = long("quit")
+        .help("Forget about the operation in progress")
+        .req_flag(sequencer::Control::Quit);
+    let control = construct!([continue_, skip, abort, quit]).last().optional();
+    let no_commit = short('n')
+        .long("no-commit")
+        .help("Apply the changes without committing")
+        .switch();
+    let edit = short('e')
+        .long("edit")
+        .help("Edit the commit message")
+        .req_flag(true);
+    let no_edit = long("no-edit")
+        .help("Use the message without launching an editor")
+        .req_flag(false);
+    let edit = construct!([edit, no_edit]).last().optional();
+    // -xはcherry-pickだけ
+    let record_origin = if pick {
+        short('x')
+            .help("Append a line telling which commit was cherry-picked")
+            .switch()
+            .boxed()
+    } else {
+        pure(false).boxed()
+    };
+    let mainline = short('m')
+        .long("mainline")
+        .help("Parent number of the mainline of a merge commit")
+        .argument::<usize>("PARENT")
+        .guard(
+            |&n| n > 0,
+            "option `mainline' expects a number greater than zero",
+        )
+        .optional();
+    construct!(sequencer::Options {
+        control,
+        no_commit,
+        edit,
+        record_origin,
+        mainline,
+    })
+}
+
+fn cherry_pick() -> impl Parser<Command> {
+    let options = sequencer_options(true);
+    let args = positional("COMMIT").help("Commits to apply").many();
+    construct!(Command::CherryPick { options, args })
+        .to_options()
+        .command("cherry-pick")
+        .help("Apply the changes introduced by some existing commits")
+}
+
+fn revert() -> impl Parser<Command> {
+    let options = sequencer_options(false);
+    let args = positional("COMMIT").help("Commits to revert").many();
+    construct!(Command::Revert { options, args })
+        .to_options()
+        .command("revert")
+        .help("Revert some existing commits")
+}
+
+fn rebase() -> impl Parser<Command> {
+    let continue_ = long("continue")
+        .help("Resume after resolving conflicts or editing")
+        .req_flag(rebase::Control::Continue);
+    let skip = long("skip")
+        .help("Skip the commit that stopped and go on")
+        .req_flag(rebase::Control::Skip);
+    let abort = long("abort")
+        .help("Cancel the rebase and return to the original branch")
+        .req_flag(rebase::Control::Abort);
+    let quit = long("quit")
+        .help("Forget about the rebase in progress")
+        .req_flag(rebase::Control::Quit);
+    let edit_todo = long("edit-todo")
+        .help("Edit the rest of the todo list")
+        .req_flag(rebase::Control::EditTodo);
+    let control = construct!([continue_, skip, abort, quit, edit_todo])
+        .last()
+        .optional();
+    let onto = long("onto")
+        .help("Starting point for the new commits")
+        .argument::<String>("NEWBASE")
+        .optional();
+    let root = long("root")
+        .help("Rebase all commits reachable from the branch")
+        .switch();
+    let interactive = short('i')
+        .long("interactive")
+        .help("Edit the list of commits to rebase")
+        .switch();
+    let autosquash = flag(
+        "autosquash",
+        "no-autosquash",
+        "Move fixup!/squash! commits after their targets",
+        "Keep fixup!/squash! commits where they are",
+    );
+    let update_refs = flag(
+        "update-refs",
+        "no-update-refs",
+        "Update branches pointing at the rebased commits",
+        "Leave other branches alone",
+    );
+    let autostash = flag(
+        "autostash",
+        "no-autostash",
+        "Stash local changes before the rebase and apply them after",
+        "Refuse to rebase with local changes",
+    );
+    let options = construct!(rebase::Options {
+        control,
+        onto,
+        root,
+        interactive,
+        autosquash,
+        update_refs,
+        autostash,
+    });
+    let args = positional("ARG")
+        .help("Upstream and the branch to rebase")
+        .many();
+    construct!(Command::Rebase { options, args })
+        .to_options()
+        .command("rebase")
+        .help("Reapply commits on top of another base tip")
+}
+
+fn clean() -> impl Parser<Command> {
+    let dry_run = short('n')
+        .long("dry-run")
+        .help("Don't actually remove anything, just show what would be done")
+        .switch();
+    let force = short('f')
+        .long("force")
+        .help("Remove files; given twice, also remove nested repositories")
+        .req_flag(())
+        .many()
+        .map(|flags| flags.len());
+    let directories = short('d').help("Remove untracked directories too").switch();
+    let ignored = short('x')
+        .help("Don't use the standard ignore rules")
+        .switch();
+    let ignored_only = short('X').help("Remove only ignored files").switch();
+    let interactive = short('i')
+        .long("interactive")
+        .help("Show what would be done and clean files interactively")
+        .switch();
+    let quiet = short('q').long("quiet").help("Only report errors").switch();
+    let options = construct!(clean::Options {
+        dry_run,
+        force,
+        directories,
+        ignored,
+        ignored_only,
+        interactive,
+        quiet,
+    });
+    let pathspec = positional("PATHSPEC").help("Paths to clean").many();
+    construct!(Command::Clean { options, pathspec })
+        .to_options()
+        .command("clean")
+        .help("Remove untracked files from the working tree")
+}
+
+fn blame() -> impl Parser<Command> {
+    let ranges = short('L')
+        .help("Annotate only the line range <start>,<end>")
+        .argument("RANGE")
+        .many();
+    let ignore_whitespace = short('w')
+        .help("Ignore whitespace when comparing versions")
+        .switch();
+    let moves = blame_score("-M", "-M[<n>]", "Detect lines moved within the file")
+        .many()
+        .map(|scores| {
+            let score = scores.iter().rev().find_map(|&s| s);
+            (!scores.is_empty()).then(|| score.unwrap_or(git::blame::MOVE_SCORE))
+        });
+    let copies = blame_score(
+        "--find-copies",
+        "-C[<n>]",
+        "Detect lines copied from other files",
+    )
+    .many()
+    .map(|scores| {
+        let score = scores.iter().rev().find_map(|&s| s);
+        (!scores.is_empty()).then(|| (scores.len(), score.unwrap_or(git::blame::COPY_SCORE)))
+    });
+    let ignore_revs = long("ignore-rev")
+        .help("Ignore changes made by the revision")
+        .argument("REV")
+        .many();
+    let ignore_revs_files = long("ignore-revs-file")
+        .help("Ignore revisions listed in the file")
+        .argument::<PathBuf>("FILE")
+        .many();
+    let porcelain = short('p')
+        .long("porcelain")
+        .help("Show in a format designed for machine consumption")
+        .switch();
+    let options = construct!(blame::Options {
+        ranges,
+        ignore_whitespace,
+        moves,
+        copies,
+        ignore_revs,
+        ignore_revs_files,
+        porcelain,
+    });
+    // command_line()が"--"の後の引数を書き換えたもの
+    let paths = long("path").argument("PATH").many().hide();
+    let args = positional("ARGS")
+        .help("Revision and file to annotate")
+        .many();
+    construct!(Command::Blame {
+        options,
+        paths,
+        args
+    })
+    .to_options()
+    .command("blame")
+    .help("Show what revision and author last modified each line of a file")
+}
+
+// blameの-M[<n>]と-C[<n>](--find-copies[=<n>]に置き換えたもの)。<n>は文字数
+fn blame_score(
+    flag: &'static str,
+    metavar: &'static str,
+    help: &'static str,
+) -> impl Parser<Option<usize>> {
+    any::<String, _, _>(metavar, move |arg| {
+        let value = arg.strip_prefix(flag)?;
+        let value = value.strip_prefix('=').unwrap_or(value);
+        value
+            .bytes()
+            .all(|b| b.is_ascii_digit())
+            .then(|| value.to_string())
+    })
+    .help(help)
+    .anywhere()
+    .map(|score| score.parse().ok())
+}
+
+fn stash() -> impl Parser<Command> {
+    let push = stash_command(
+        "push",
+        "Save local changes to a new stash entry and revert them",
+        stash::Subcommand::Push,
+        stash_push_options(),
+        positional("PATHSPEC").help("Paths to stash").many(),
+    );
+    let list = stash_command(
+        "list",
+        "List the stash entries",
+        stash::Subcommand::List,
+        pure(stash::Options::default()),
+        pure(Vec::new()),
+    );
+    let patch = short('p')
+        .long("patch")
+        .help("Show the changes as a patch")
+        .req_flag(diff::Format::Patch);
+    let stat = long("stat")
+        .help("Show a diffstat (default)")
+        .req_flag(diff::Format::Stat);
+    let format = construct!([patch, stat])
+        .last()
+        .fallback(diff::Format::Stat)
+        .map(|format| stash::Options {
+            format,
+            ..stash::Options::default()
+        });
+    let show = stash_command(
+        "show",
+        "Show the changes recorded in a stash entry",
+        stash::Subcommand::Show,
+        format,
+        stash_entry(),
+    );
+    let apply = stash_command(
+        "apply",
+        "Apply a stash entry to the working tree",
+        stash::Subcommand::Apply,
+        stash_apply_options(),
+        stash_entry(),
+    );
+    let pop = stash_command(
+        "pop",
+        "Apply a stash entry and remove it from the list",
+        stash::Subcommand::Pop,
+        stash_apply_options(),
+        stash_entry(),
+    );
+    let drop = stash_command(
+        "drop",
+        "Remove a stash entry from the list",
+        stash::Subcommand::Drop,
+        stash_quiet().map(|quiet| stash::Options {
+            quiet,
+            ..stash::Options::default()
+        }),
+        stash_entry(),
+    );
+    let branch = stash_command(
+        "branch",
+        "Create a branch where a stash entry was made and apply it there",
+        stash::Subcommand::Branch,
+        pure(stash::Options::default()),
+        positional("ARG")
+            .help("The new branch, optionally followed by the stash entry")
+            .many(),
+    );
+    // サブコマンドがなければpush
+    let subcommand = pure(stash::Subcommand::Push);
+    let options = stash_push_options();
+    let args = positional("PATHSPEC").help("Paths to stash").many();
+    let bare = construct!(Command::Stash {
+        subcommand,
+        options,
+        args
+    });
+    construct!([push, list, show, apply, pop, drop, branch, bare])
+        .to_options()
+        .command("stash")
+        .help("Stash the changes in a dirty working directory away")
+}
+
+fn stash_command(
+    name: &'static str,
+    help: &'static str,
+    subcommand: stash::Subcommand,
+    options: impl Parser<stash::Options> + 'static,
+    args: impl Parser<Vec<String>> + 'static,
+) -> impl Parser<Command> {
+    let subcommand = pure(subcommand);
+    construct!(Command::Stash {
+        subcommand,
+        options,
+        args
+    })
+    .to_options()
+    .command(name)
+    .help(help)
+}
+
+fn stash_push_options() -> impl Parser<stash::Options> {
+    let keep_index = short('k')
+        .long("keep-index")
+        .help("Keep the staged changes in the index and the working tree")
+        .switch();
+    let include_untracked = short('u')
+        .long("include-untracked")
+        .help("Also stash untracked files")
+        .switch();
+    let message = short('m')
+        .long("message")
+        .help("Describe the stash entry")
+        .argument("MESSAGE")
+        .optional();
+    let quiet = stash_quiet();
+    construct!(keep_index, include_untracked, message, quiet).map(
+        |(keep_index, include_untracked, message, quiet)| stash::Options {
+            keep_index,
+            include_untracked,
+            message,
+            quiet,
+            ..stash::Options::default()
+        },
+    )
+}
+
+fn stash_apply_options() -> impl Parser<stash::Options> {
+    let index = long("index")
+        .help("Also restore the staged changes")
+        .switch();
+    let quiet = stash_quiet();
+    construct!(index, quiet).map(|(index, quiet)| stash::Options {
+        index,
+        quiet,
+        ..stash::Options::default()
+    })
+}
+
+fn stash_quiet() -> impl Parser<bool> {
+    short('q').long("quiet").help("Be quiet").switch()
+}
+
+fn stash_entry() -> impl Parser<Vec<String>> {
+    positional("STASH")
+        .help("The stash entry, the latest one by default")
+        .many()
+}
+
+fn merge_base() -> impl Parser<Command> {
+    let all = short('a')
+        .long("all")
+        .help("Output all common ancestors")
+        .switch();
+    let octopus = long("octopus")
+        .help("Find ancestors for a single n-way merge")
+        .switch();
+    let is_ancestor = long("is-ancestor")
+        .help("Is the first one ancestor of the other?")
+        .switch();
+    let fork_point = long("fork-point")
+        .help("Find where <commit> forked from reflog of <ref>")
+        .switch();
+    let options = construct!(merge_base::Options {
+        all,
+        octopus,
+        is_ancestor,
+        fork_point,
+    });
+    let args = positional("COMMIT")
+        .help("Commits to find the bases of")
+        .many();
+    construct!(Command::MergeBase { options, args })
+        .to_options()
+        .command("merge-base")
+        .help("Find as good common ancestors as possible for a merge")
+}
+
+fn mergetool() -> impl Parser<Command> {
+    let tool = short('t')
+        .long("tool")
+        .help("Use the merge resolution program specified by <tool>")
+        .argument("TOOL")
+        .optional();
+    let prompt = long("prompt")
+        .help("Prompt before each invocation of the merge resolution program")
+        .req_flag(true);
+    let no_prompt = short('y')
+        .long("no-prompt")
+        .help("Don't prompt before each invocation of the merge resolution program")
+        .req_flag(false);
+    let prompt = construct!([prompt, no_prompt]).last().optional();
+    let options = construct!(mergetool::Options { tool, prompt });
+    let paths = positional("FILE").help("Unmerged files to resolve").many();
+    construct!(Command::Mergetool { options, paths })
+        .to_options()
+        .command("mergetool")
+        .help("Run merge conflict resolution tools to resolve merge conflicts")
+}
+
+fn status() -> impl Parser<Command> {
+    let short = short('s')
+        .long("short")
+        .help("Give the output in the short format")
+        .switch();
+    let ignored = long("ignored").help("Show ignored files as well").switch();
+    construct!(Command::Status { short, ignored })
+        .to_options()
+        .command("status")
+        .help("Show the working tree status")
+}
+
+fn check_ignore() -> impl Parser<Command> {
+    let verbose = short('v')
+        .long("verbose")
+        .help("Show the matching pattern")
+        .switch();
+    let non_matching = short('n')
+        .long("non-matching")
+        .help("Show paths that don't match any pattern")
+        .switch();
+    let no_index = long("no-index").help("Don't look in the index").switch();
+    let paths = positional("PATH").many();
+    construct!(Command::CheckIgnore {
+        verbose,
+        non_matching,
+        no_index,
+        paths,
+    })
+    .to_options()
+    .command("check-ignore")
+    .help("Debug gitignore files")
+}
+
+fn hash_object() -> impl Parser<Command> {
+    let write = short('w')
+        .help("Write the object into the object database")
+        .switch();
+    let stdin = long("stdin")
+        .help("Read the object from standard input")
+        .switch();
+    let paths = positional::<PathBuf>("FILE").many();
+    construct!(Command::HashObject {
+        write,
+        stdin,
+        paths,
+    })
+    .to_options()
+    .command("hash-object")
+    .help("Compute object ID and optionally create an object from a file")
+}
+
+fn commit_tree() -> impl Parser<Command> {
+    let parents = short('p')
+        .help("Id of a parent commit object")
+        .argument("PARENT")
+        .many();
+    let messages = short('m')
+        .help("A paragraph in the commit log message")
+        .argument("MESSAGE")
+        .many();
+    let tree = positional("TREE").help("An existing tree object");
+    construct!(Command::CommitTree {
+        parents,
+        messages,
+        tree,
+    })
+    .to_options()
+    .command("commit-tree")
+    .help("Create a new commit object")
+}
+
+fn ls_tree() -> impl Parser<Command> {
+    let recursive = short('r').help("Recurse into sub-trees").switch();
+    let trees = short('t')
+        .help("Show tree entries even when going to recurse them")
+        .switch();
+    let name_only = long("name-only")
+        .long("name-status")
+        .help("List only filenames")
+        .switch();
+    let long = short('l')
+        .long("long")
+        .help("Show object size of blob entries")
+        .switch();
+    let options = construct!(ls_tree::Options {
+        recursive,
+        trees,
+        long,
+        name_only,
+    });
+    let tree = positional("TREE-ISH").help("Tree to list");
+    let paths = positional("PATH").help("Paths to show").many();
+    construct!(Command::LsTree {
+        options,
+        tree,
+        paths,
+    })
+    .to_options()
+    .command("ls-tree")
+    .help("List the contents of a tree object")
+}
+
+fn ls_files() -> impl Parser<Command> {
+    let cached = short('c')
+        .long("cached")
+        .help("Show cached files in the output (default)")
+        .switch();
+    let stage = short('s')
+        .long("stage")
+        .help("Show staged contents' mode bits, object name and stage number")
+        .switch();
+    let unmerged = short('u')
+        .long("unmerged")
+        .help("Show unmerged files in the output (forces --stage)")
+        .switch();
+    let modified = short('m')
+        .long("modified")
+        .help("Show modified files in the output")
+        .switch();
+    let deleted = short('d')
+        .long("deleted")
+        .help("Show deleted files in the output")
+        .switch();
+    let others = short('o')
+        .long("others")
+        .help("Show other (i.e. untracked) files in the output")
+        .switch();
+    let exclude_standard = long("exclude-standard")
+        .help("Add the standard git exclusions")
+        .switch();
+    let options = construct!(ls_files::Options {
+        cached,
+        stage,
+        unmerged,
+        modified,
+        deleted,
+        others,
+        exclude_standard,
+    });
+    let paths = positional("PATH").help("Files to show").many();
+    construct!(Command::LsFiles { options, paths })
+        .to_options()
+        .command("ls-files")
+        .help("Show information about files in the index and the working tree")
+}
+
+fn read_tree() -> impl Parser<Command> {
+    let merge = short('m').help("Perform a merge, not just a read").switch();
+    let prefix = long("prefix")
+        .help("Read the tree into the index under <prefix>/")
+        .argument("PREFIX")
+        .optional();
+    let options = construct!(read_tree::Options { merge, prefix });
+    let trees = positional("TREE-ISH").help("Trees to read").many();
+    construct!(Command::ReadTree { options, trees })
+        .to_options()
+        .command("read-tree")
+        .help("Read tree information into the index")
+}
+
+fn update_index() -> impl Parser<Command> {
+    let add = long("add")
+        .help("Add files that are not in the index yet")
+        .switch();
+    let remove = long("remove")
+        .help("Remove files that are missing from the working tree")
+        .switch();
+    let refresh = long("refresh")
+        .help("Refresh the stat information of the index")
+        .switch();
+    let cacheinfo = long("cacheinfo")
+        .help("Add the specified entry to the index")
+        .argument::<String>("MODE,OBJECT,PATH")
+        .parse(|arg| {
+            CacheInfo::parse(&arg).ok_or("option 'cacheinfo' expects <mode>,<sha1>,<path>")
+        })
+        .many();
+    let index_info = long("index-info")
+        .help("Read index information from standard input")
+        .switch();
+    let chmod = long("chmod")
+        .help("Override the executable bit of the listed files")
+        .argument::<String>("(+|-)x")
+        .parse(|flag| match flag.as_str() {
+            "+x" => Ok(true),
+            "-x" => Ok(false),
+            _ => Err("option 'chmod' expects \"+x\" or \"-x\""),
+        })
+        .optional();
+    let assume_unchanged = flag(
+        "assume-unchanged",
+        "no-assume-unchanged",
+        "Mark files as \"not changing\"",
+        "Clear the assumed-unchanged bit",
+    );
+    let skip_worktree = flag(
+        "skip-worktree",
+        "no-skip-worktree",
+        "Mark files as \"index-only\"",
+        "Clear the skip-worktree bit",
+    );
+    let options = construct!(update_index::Options {
+        add,
+        remove,
+        refresh,
+        cacheinfo,
+        index_info,
+        chmod,
+        assume_unchanged,
+        skip_worktree,
+    });
+    let paths = positional("FILE").help("Files to act on").many();
+    construct!(Command::UpdateIndex { options, paths })
+        .to_options()
+        .command("update-index")
+        .help("Register file contents in the working tree to the index")
+}
+
+fn reset() -> impl Parser<Command> {
+    let soft = long("soft")
+        .help("Only move the current branch")
+        .req_flag(reset::Mode::Soft);
+    let mixed = long("mixed")
+        .help("Reset the index but not the working tree (default)")
+        .req_flag(reset::Mode::Mixed);
+    let hard = long("hard")
+        .help("Reset the index and the working tree")
+        .req_flag(reset::Mode::Hard);
+    let merge = long("merge")
+        .help("Reset the index and the files that differ from it, keeping other changes")
+        .req_flag(reset::Mode::Merge);
+    let keep = long("keep")
+        .help("Reset the index and the files that change, keeping local changes")
+        .req_flag(reset::Mode::Keep);
+    let mode = construct!([soft, mixed, hard, merge, keep])
+        .last()
+        .optional();
+    let quiet = short('q').long("quiet").help("Be quiet").switch();
+    let options = construct!(reset::Options { mode, quiet });
+    // command_line()が"--"の後の引数を書き換えたもの
+    let paths = long("path").argument("PATH").many().hide();
+    let args = positional("COMMIT")
+        .help("Commit to reset to, optionally followed by paths to reset in the index")
+        .many();
+    construct!(Command::Reset {
+        options,
+        paths,
+        args
+    })
+    .to_options()
+    .command("reset")
+    .help("Reset current HEAD to the specified state")
+}
+
+// --<name>と--no-<name>のうち最後に指定されたもの
+fn flag(
+    name: &'static str,
+    negated: &'static str,
+    help: &'static str,
+    negated_help: &'static str,
+) -> impl Parser<Option<bool>> {
+    let set = long(name).help(help).req_flag(true);
+    let unset = long(negated).help(negated_help).req_flag(false);
+    construct!([set, unset]).last().optional()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite(args: &[&str]) -> Vec<String> {
+        rewrite_args(args.iter().map(OsString::from).collect())
+            .into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_split_clusters() {
+        assert_eq!(
+            rewrite(&["commit", "-am", "msg"]),
+            ["commit", "-a", "-m", "msg"]
+        );
+        assert_eq!(rewrite(&["commit", "-mmsg"]), ["commit", "-m", "msg"]);
+        assert_eq!(
+            rewrite(&["commit", "-aemsg", "-m", "-am"]),
+            ["commit", "-a", "-e", "-m", "sg", "-m", "-am"]
+        );
+        assert_eq!(
+            rewrite(&["-C", "dir", "commit", "-aF", "file", "--", "-am"]),
+            ["-C", "dir", "commit", "-a", "-F", "file", "--", "-am"]
+        );
+        assert_eq!(rewrite(&["commit", "-ax"]), ["commit", "-ax"]);
+        assert_eq!(rewrite(&["add", "-am"]), ["add", "-am"]);
+    }
+}
@@ -1,4 +1,87 @@
+use std::io::{self, IsTerminal};
+use std::path::{Component, Path};
+
+use git::Repository;
+
 pub mod add;
+pub mod blame;
 pub mod branch;
+pub mod check_ignore;
+pub mod checkout;
+pub mod clean;
 pub mod commit;
+pub mod commit_tree;
+pub mod diff;
+pub mod hash_object;
 pub mod init;
+pub mod log;
+pub mod ls_files;
+pub mod ls_tree;
+pub mod merge;
+pub mod merge_base;
+pub mod mergetool;
+pub mod mv;
+pub mod read_tree;
+pub mod rebase;
+pub mod reset;
+pub mod restore;
+pub mod rm;
+pub mod sequencer;
+pub mod show;
+pub mod stash;
+pub mod status;
+pub mod switch;
+pub mod update_index;
+pub mod write_tree;
+
+/// Show `path`, relative to the top of the working tree, relative to the
+/// directory `prefix` the command was started in, like `../a` or `./`.
+pub fn relative_path(path: &str, prefix: &Path) -> String {
+    let (path, dir_suffix) = match path.strip_suffix('/') {
+        Some(path) => (path, "/"),
+        None => (path, ""),
+    };
+    let prefix: Vec<_> = prefix
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .collect();
+    let components: Vec<_> = path.split('/').filter(|c| !c.is_empty()).collect();
+    let common = prefix
+        .iter()
+        .zip(&components)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative = "../".repeat(prefix.len() - common);
+    relative.push_str(&components[common..].join("/"));
+    if relative.is_empty() {
+        return "./".to_string();
+    }
+    if relative.ends_with('/') {
+        relative
+    } else {
+        relative + dir_suffix
+    }
+}
+
+/// Whether to color the output of `command`, given the `--color[=<when>]`
+/// option, then `color.<command>` and `color.ui`; `auto` colors only when
+/// standard output is a terminal.
+pub fn use_color(repo: &Repository, when: Option<&str>, command: &str) -> bool {
+    let when = when
+        .map(ToString::to_string)
+        .or_else(|| {
+            repo.config()
+                .get(&format!("color.{command}"))
+                .map(ToString::to_string)
+        })
+        .or_else(|| repo.config().get("color.ui").map(ToString::to_string));
+    match when.as_deref() {
+        Some("always" | "true") => true,
+        Some("never" | "false") => false,
+        _ => io::stdout().is_terminal(),
+    }
+}
@@ -1,227 +1,238 @@
-use core::panic;
-use std::collections;
-use std::ffi::OsStr;
-use std::fs::{self, File};
-use std::io::{self, Read, Write};
-use std::os::linux::fs::MetadataExt;
-use std::os::unix::ffi::OsStrExt;
-use std::path::{Path, PathBuf};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use git::ignore::Ignore;
+use git::index::Index;
+use git::object::tree::GITLINK_MODE;
+use git::object::Kind;
+use git::pathspec::Pathspec;
+use git::status::find_untracked;
+use git::worktree::Worktree;
+use git::{Repository, Result};
+
+mod patch;
+
+/// Flags of `add`.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `-A`: stage every change in the whole tree when no pathspec is given.
+    pub all: bool,
+    /// `-u`: only stage modifications and removals of tracked files.
+    pub update: bool,
+    pub dry_run: bool,
+    pub verbose: bool,
+    /// `-f`: allow adding ignored files.
+    pub force: bool,
+    /// `-N`: record untracked files with empty content.
+    pub intent_to_add: bool,
+    /// `-p`: choose hunks interactively.
+    pub patch: bool,
+}
 
-use byteorder::{BigEndian, ByteOrder};
+enum Action {
+    Add,
+    Remove,
+}
 
-use crate::util;
+/// Stage the paths matched by `args`, given relative to `prefix`.
+///
+/// Tracked files are updated or, when missing from the working tree, removed
+/// from the index; untracked files are added unless ignored. Naming an
+/// ignored path explicitly is reported, and `false` is returned after the
+/// other paths are staged.
+pub fn add(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<bool> {
+    if args.is_empty() && !options.all && !options.update && !options.patch {
+        eprintln!("Nothing specified, nothing added.");
+        eprintln!("hint: Maybe you wanted to say 'git add .'?");
+        return Ok(true);
+    }
+    let pathspec = Pathspec::parse(args, prefix)?;
+    if options.patch {
+        patch::select_hunks(repo, &pathspec)?;
+        return Ok(true);
+    }
 
-fn travel_dir(
-    file_name: impl AsRef<Path>,
-    file_path_list: &mut Vec<PathBuf>,
-    hash_list: &mut Vec<String>,
-) -> io::Result<()> {
-    if !fs::metadata(&file_name)?.is_dir() {
-        let hash = generate_blob_object(&file_name)?;
-        file_path_list.push(file_name.as_ref().to_path_buf());
-        hash_list.push(hash);
-        return Ok(());
+    let work_tree = repo.require_work_tree()?;
+    let mut index = repo.index()?;
+    // -Nは追跡されていないファイルだけを記録する
+    let mut actions = if options.intent_to_add {
+        Vec::new()
+    } else {
+        tracked_changes(work_tree, &index, &pathspec)?
+    };
+
+    let mut untracked = Vec::new();
+    if !options.update {
+        let found = find_untracked(repo, &index, false, options.force)?;
+        untracked = found.untracked;
+        if options.force {
+            untracked.extend(found.ignored);
+        }
+        untracked.retain(|path| pathspec.matches(path));
     }
 
-    // 再帰的にaddする
-    for entry in fs::read_dir(file_name)? {
-        let path = entry?.path();
-        if path.starts_with("./.git") {
+    // 明示的に指定された無視対象のパスは追加せずに報告する
+    let mut ignore = Ignore::new(repo)?;
+    let mut ignored = Vec::new();
+    for item in pathspec.items.iter().filter(|item| !item.exclude) {
+        let path = work_tree.join(&item.pattern);
+        if !path.exists() {
+            let matched = index.entries().iter().any(|e| item.matches(&e.path))
+                || untracked.iter().any(|path| item.matches(path));
+            if !matched {
+                anyhow::bail!("pathspec '{}' did not match any files", item.original);
+            }
             continue;
         }
-
-        if path.is_dir() {
-            travel_dir(&path, file_path_list, hash_list)?;
+        if options.force || options.update || item.pattern.is_empty() {
             continue;
         }
-        let hash = generate_blob_object(&path)?;
-        file_path_list.push(path);
-        hash_list.push(hash);
+        let tracked = index.entries().iter().any(|e| item.matches(&e.path));
+        if !tracked && ignore.is_ignored(&item.pattern, path.is_dir())? {
+            ignored.push(item.pattern.clone());
+        }
     }
-    Ok(())
-}
 
-pub fn add(file_names: &[PathBuf]) -> anyhow::Result<()> {
-    let mut hash_list = Vec::new();
-    let mut file_path_list = Vec::new();
-    for file_name in file_names {
-        travel_dir(file_name, &mut file_path_list, &mut hash_list)?;
+    for path in untracked {
+        actions.push((path, Action::Add));
     }
-    update_index(&file_path_list, &hash_list)
-}
-
-fn generate_blob_object(file_name: impl AsRef<Path>) -> Result<String, io::Error> {
-    let contents = fs::read_to_string(file_name)?;
-    let file_length = contents.len();
+    actions.sort_by(|(a, _), (b, _)| a.cmp(b));
 
-    // データの準備
-    let header = format!("blob {file_length}\0");
-    let hash = util::compress::hash(format!("{header}{contents}").as_bytes());
-
-    // ファイルの準備
-    let file_directory = format!(".git/objects/{}", &hash[0..2]);
-    let file_path = format!("{}/{}", file_directory, &hash[2..]);
-    let mut file = util::path::create_nested_file(file_path)?;
-
-    // zlib圧縮
-    let contents_will_be_compressed = format!("{header}{contents}");
-    let compressed_contents = util::compress::with_zlib(contents_will_be_compressed.as_bytes())?;
-
-    // ファイルに書き込み
-    file.write_all(&compressed_contents)?;
-
-    Ok(hash)
-}
+    apply(repo, &mut index, actions, options)?;
+    if !options.dry_run {
+        repo.write_index(&index)?;
+    }
 
-#[derive(Clone)]
-struct IndexEntrySummary {
-    index_entry: Vec<u8>,
-    path: PathBuf,
+    if !ignored.is_empty() {
+        eprintln!("The following paths are ignored by one of your .gitignore files:");
+        for path in &ignored {
+            eprintln!("{path}");
+        }
+        eprintln!("hint: Use -f if you really want to add them.");
+        return Ok(false);
+    }
+    Ok(true)
 }
 
-// 既存のentriesと新しく追加されるentriesをmergeする
-// 順番を変えるとファイルが削除されて新しく作成されたとみなされてしまうため、順番は変わらないようにする
-fn merge_entries(
-    exists: &[IndexEntrySummary],
-    new_entries: Vec<IndexEntrySummary>,
-) -> Vec<IndexEntrySummary> {
-    let exist_paths: collections::HashSet<_> = exists.iter().map(|x| x.path.clone()).collect();
-    let new_paths: collections::HashSet<_> = new_entries.iter().map(|x| x.path.clone()).collect();
-
-    let common_paths: collections::HashSet<_> = exist_paths.intersection(&new_paths).collect();
-
-    let mut result = Vec::<IndexEntrySummary>::new();
-
-    for entry in exists.iter().cloned() {
-        if !common_paths.contains(&entry.path) {
-            result.push(entry);
+// 追跡済みのファイルのうち、変更されたものと削除されたもの
+fn tracked_changes(
+    work_tree: &Path,
+    index: &Index,
+    pathspec: &Pathspec,
+) -> Result<Vec<(String, Action)>> {
+    let mut actions: Vec<(String, Action)> = Vec::new();
+    for entry in index.entries() {
+        if !pathspec.matches(&entry.path) {
             continue;
         }
-        match new_entries.iter().find(|&x| x.path == entry.path).cloned() {
-            Some(item) => result.push(item),
-            None => panic!("not found"),
-        };
-    }
-    for entry in new_entries {
-        if !common_paths.contains(&entry.path) {
-            result.push(entry);
+        // 衝突しているパスは、worktreeにあれば解決したものとして加え、なければ消す
+        if entry.stage != 0 {
+            if actions.last().map(|(path, _)| path) == Some(&entry.path) {
+                continue;
+            }
+            let path = work_tree.join(&entry.path);
+            let action = match fs::symlink_metadata(&path) {
+                Ok(metadata) if !metadata.is_dir() => Action::Add,
+                Ok(_) => Action::Remove,
+                Err(e) if e.kind() == ErrorKind::NotFound || !path.exists() => Action::Remove,
+                Err(e) => return Err(e.into()),
+            };
+            actions.push((entry.path.clone(), action));
+            continue;
+        }
+        let path = work_tree.join(&entry.path);
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.is_dir() && entry.mode != GITLINK_MODE => {
+                actions.push((entry.path.clone(), Action::Remove));
+            }
+            Ok(metadata) => {
+                // submoduleはディレクトリのstatでは変更がわからない
+                let gitlink = entry.mode == GITLINK_MODE;
+                if entry.intent_to_add || gitlink || !entry.is_stat_clean(&metadata) {
+                    actions.push((entry.path.clone(), Action::Add));
+                }
+            }
+            // 途中がファイルになっている場合(ENOTDIR)も消えたものとする
+            Err(e) if e.kind() == ErrorKind::NotFound || !path.exists() => {
+                actions.push((entry.path.clone(), Action::Remove));
+            }
+            Err(e) => return Err(e.into()),
         }
     }
-
-    result
+    Ok(actions)
 }
 
-fn decode_index_file() -> Option<Vec<IndexEntrySummary>> {
-    let Ok(mut file) = File::open(".git/index") else {
-        return None;
-    };
-    let mut content = Vec::new();
-    let mut index_entry_summaries = Vec::<IndexEntrySummary>::new();
-    file.read_to_end(&mut content).unwrap();
-
-    // entriesを上から1 entryずつ消費していく
-    let entry_count = BigEndian::read_u32(&content[8..12]);
-    let mut entries = &content[12..];
-    for _ in 0..entry_count {
-        let (next_byte, index_entry_summary) = decode_index_entry(entries);
-        index_entry_summaries.push(index_entry_summary);
-        entries = &entries[next_byte..];
+fn apply(
+    repo: &Repository,
+    index: &mut Index,
+    actions: Vec<(String, Action)>,
+    options: &Options,
+) -> Result<()> {
+    let work_tree = repo.require_work_tree()?;
+    let files = Worktree::new(repo)?;
+    for (path, action) in actions {
+        match action {
+            Action::Add if options.intent_to_add => {
+                if !options.dry_run {
+                    stage_intent_to_add(&files, index, &path)?;
+                }
+            }
+            Action::Add => {
+                let metadata = fs::symlink_metadata(work_tree.join(&path))?;
+                let existing = index
+                    .get(&path, 0)
+                    .filter(|e| !e.intent_to_add)
+                    .or_else(|| index.get(&path, 2));
+                let existing_mode = existing.map(|e| e.mode);
+                let hash = files.hash(&path, &metadata, !options.dry_run)?;
+                let entry = files.entry(&path, hash, &metadata, existing_mode);
+                let unchanged =
+                    existing.is_some_and(|e| e.oid == entry.oid && e.mode == entry.mode);
+                if !options.dry_run {
+                    // 内容が同じでもstat情報は更新する
+                    index.remove_in_the_way(&path);
+                    index.add(entry);
+                }
+                if unchanged {
+                    continue;
+                }
+            }
+            Action::Remove => {
+                if !options.dry_run {
+                    index.remove(&path);
+                }
+                if options.dry_run || options.verbose {
+                    println!("remove '{path}'");
+                }
+                continue;
+            }
+        }
+        if options.dry_run || options.verbose {
+            println!("add '{path}'");
+        }
     }
 
-    Some(index_entry_summaries)
-}
-
-fn decode_index_entry(entry: &[u8]) -> (usize, IndexEntrySummary) {
-    let flags = BigEndian::read_u16(&entry[60..62]);
-    let file_path_end_byte = (62 + flags) as usize;
-    let path: &Path = OsStr::from_bytes(&entry[62..file_path_end_byte]).as_ref();
-
-    let padding = 4 - (file_path_end_byte % 4);
-    let next_byte = file_path_end_byte + padding;
-    let index_entry_summary = IndexEntrySummary {
-        index_entry: entry[..next_byte].to_vec(),
-        path: path.to_path_buf(),
-    };
-
-    (next_byte, index_entry_summary)
+    Ok(())
 }
 
-fn update_index(file_names: &[PathBuf], hash_list: &[String]) -> anyhow::Result<()> {
-    // 既にindex fileが存在したらそれを読み込み、entriesをdecode
-    // headerは新しく作る(entryの数が違うため)
-
-    // 更新されるファイルのentries
-    let exists = decode_index_file();
-
-    // 新しく追加されるファイルのentries
-    let mut new_entries = Vec::<IndexEntrySummary>::new();
-
-    for (index, file_name) in file_names.iter().enumerate() {
-        let metadata = fs::metadata(file_name)?;
-
-        let new_file_name = &file_name.strip_prefix("./").unwrap_or(file_name);
-        let change_time = &metadata.st_ctime().to_be_bytes()[4..8];
-        let change_time_nsec = &metadata.st_ctime_nsec().to_be_bytes()[4..8];
-        let modification_time = &metadata.st_mtime().to_be_bytes()[4..8];
-        let modification_time_nsec = &metadata.st_mtime_nsec().to_be_bytes()[4..8];
-        let dev = &metadata.st_dev().to_be_bytes()[4..8];
-        let ino = &metadata.st_ino().to_be_bytes()[4..8];
-        let mode = &metadata.st_mode().to_be_bytes();
-        let user_id = &metadata.st_uid().to_be_bytes();
-        let group_id = &metadata.st_gid().to_be_bytes();
-        let file_size = &metadata.st_size().to_be_bytes()[4..8];
-        let oid = &hash_list[index];
-        let decoded_oid = hex::decode(oid)?;
-        let decoded_oid_slice = decoded_oid.as_slice();
-        // TODO: 正しく計算
-        let flags = &new_file_name.as_os_str().len().to_be_bytes()[6..8];
-        let path = new_file_name.to_path_buf();
-
-        let mut content: Vec<u8> = [
-            change_time,
-            change_time_nsec,
-            modification_time,
-            modification_time_nsec,
-            dev,
-            ino,
-            mode,
-            user_id,
-            group_id,
-            file_size,
-            decoded_oid_slice,
-            flags,
-            path.as_os_str().as_bytes(),
-        ]
-        .concat();
-
-        let padding = 4 - (content.len() % 4);
-        content.resize(content.len() + padding, 0);
-
-        let index_entry_summary = IndexEntrySummary {
-            index_entry: content,
-            path,
-        };
-        new_entries.push(index_entry_summary);
-    }
-
-    let merged_entries = match exists {
-        Some(e) => merge_entries(&e, new_entries),
-        None => new_entries,
-    };
-
-    // header
-    let signature = b"DIRC";
-    let version = &2u32.to_be_bytes();
-    let entrie_count = &merged_entries.len().to_be_bytes()[4..8];
-
-    let mut contents: Vec<u8> = [signature, version, entrie_count].concat();
-
-    // entries
-    for entry in merged_entries {
-        contents.extend(entry.index_entry);
+fn stage_intent_to_add(worktree: &Worktree, index: &mut Index, path: &str) -> Result<()> {
+    if index.entries().iter().any(|e| e.path == path) {
+        return Ok(());
     }
-
-    let mut file = File::create(".git/index")?;
-    file.write_all(&contents)?;
+    let repo = worktree.repository();
+    let metadata = fs::symlink_metadata(repo.require_work_tree()?.join(path))?;
+    let hash = repo.odb().write(Kind::Blob, b"")?;
+    let mut entry = worktree.entry(path, hash, &metadata, None);
+    entry.size = 0;
+    entry.intent_to_add = true;
+    index.remove_in_the_way(path);
+    index.add(entry);
     Ok(())
 }
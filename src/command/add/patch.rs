@@ -0,0 +1,180 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+use git::diff::{self, Edit, Hunk};
+use git::index::Entry;
+use git::object::tree::GITLINK_MODE;
+use git::object::Kind;
+use git::odb::ObjectDatabase;
+use git::pathspec::Pathspec;
+use git::worktree::Worktree;
+use git::Repository;
+
+const HELP: &str = "\
+y - stage this hunk
+n - do not stage this hunk
+q - quit; do not stage this hunk or any of the remaining ones
+a - stage this hunk and all later hunks in the file
+d - do not stage this hunk or any of the later hunks in the file
+? - print help";
+
+enum Answer {
+    Yes,
+    No,
+    Quit,
+    All,
+    Done,
+}
+
+/// Interactively choose which hunks of the changes between the index and
+/// the working tree to stage, reading answers from standard input.
+pub fn select_hunks(repo: &Repository, pathspec: &Pathspec) -> anyhow::Result<()> {
+    let work_tree = repo.require_work_tree()?;
+    let files = Worktree::new(repo)?;
+    let mut index = repo.index()?;
+    let entries: Vec<Entry> = index
+        .entries()
+        .iter()
+        .filter(|e| {
+            e.stage == 0 && !e.intent_to_add && e.mode != GITLINK_MODE && pathspec.matches(&e.path)
+        })
+        .cloned()
+        .collect();
+
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut stdout = io::stdout().lock();
+    let mut any_change = false;
+
+    'files: for entry in entries {
+        let old = repo.find_blob(&entry.oid)?;
+        let new = fs::symlink_metadata(work_tree.join(&entry.path))
+            .ok()
+            .and_then(|metadata| files.read(&entry.path, &metadata).ok());
+        let Some(new) = new else {
+            // 削除されたファイル
+            any_change = true;
+            writeln!(stdout, "diff --git a/{0} b/{0}", entry.path)?;
+            writeln!(stdout, "deleted file mode {:o}", entry.mode)?;
+            let prompt = "(1/1) Stage deletion [y,n,q,a,d,?]? ";
+            match ask(&mut input, &mut stdout, prompt)? {
+                Answer::Yes | Answer::All => {
+                    index.remove(&entry.path);
+                }
+                Answer::Quit => break 'files,
+                Answer::No | Answer::Done => {}
+            }
+            continue;
+        };
+        if old == new {
+            continue;
+        }
+        any_change = true;
+
+        let old_lines = diff::lines(&old);
+        let new_lines = diff::lines(&new);
+        let edits = diff::myers(&old_lines, &new_lines);
+        let hunks = diff::hunks(&edits, 3);
+
+        writeln!(stdout, "diff --git a/{0} b/{0}", entry.path)?;
+        writeln!(
+            stdout,
+            "index {}..{} {:o}",
+            &entry.oid[..7],
+            &ObjectDatabase::hash(Kind::Blob, &new)[..7],
+            entry.mode
+        )?;
+        writeln!(stdout, "--- a/{0}\n+++ b/{0}", entry.path)?;
+
+        let mut selected = vec![false; hunks.len()];
+        let mut quit = false;
+        let mut i = 0;
+        while i < hunks.len() {
+            hunks[i].write_to(&mut stdout, &edits, &old_lines, &new_lines)?;
+            let prompt = format!(
+                "({}/{}) Stage this hunk [y,n,q,a,d,?]? ",
+                i + 1,
+                hunks.len()
+            );
+            match ask(&mut input, &mut stdout, &prompt)? {
+                Answer::Yes => selected[i] = true,
+                Answer::No => {}
+                Answer::All => {
+                    selected[i..].fill(true);
+                    break;
+                }
+                Answer::Done => break,
+                Answer::Quit => {
+                    quit = true;
+                    break;
+                }
+            }
+            i += 1;
+        }
+
+        if selected.contains(&true) {
+            let content = apply_hunks(&edits, &hunks, &selected, &old_lines, &new_lines);
+            let mut staged = entry.clone();
+            staged.oid = repo.odb().write(Kind::Blob, &content)?;
+            // worktreeとは内容が違うので、stat情報が一致しないようにする
+            staged.mtime = (0, 0);
+            staged.size = u32::try_from(content.len()).unwrap_or(u32::MAX);
+            index.add(staged);
+        }
+        if quit {
+            break;
+        }
+    }
+
+    if !any_change {
+        eprintln!("No changes.");
+        return Ok(());
+    }
+    repo.write_index(&index)?;
+    Ok(())
+}
+
+fn ask(input: &mut impl BufRead, output: &mut impl Write, prompt: &str) -> io::Result<Answer> {
+    loop {
+        write!(output, "{prompt}")?;
+        output.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            // 入力が終わったらqと同じ扱いにする
+            writeln!(output)?;
+            return Ok(Answer::Quit);
+        }
+        match line.trim() {
+            "y" => return Ok(Answer::Yes),
+            "n" => return Ok(Answer::No),
+            "q" => return Ok(Answer::Quit),
+            "a" => return Ok(Answer::All),
+            "d" => return Ok(Answer::Done),
+            _ => writeln!(output, "{HELP}")?,
+        }
+    }
+}
+
+// 選ばれたhunkの変更だけを古い内容に適用する
+fn apply_hunks(
+    edits: &[Edit],
+    hunks: &[Hunk],
+    selected: &[bool],
+    old: &[&[u8]],
+    new: &[&[u8]],
+) -> Vec<u8> {
+    let mut content = Vec::new();
+    for (i, edit) in edits.iter().enumerate() {
+        let apply = hunks
+            .iter()
+            .zip(selected)
+            .any(|(hunk, &selected)| selected && hunk.edits.contains(&i));
+        match *edit {
+            Edit::Equal(old_index, _) => content.extend(old[old_index]),
+            Edit::Delete(old_index) if !apply => content.extend(old[old_index]),
+            Edit::Insert(new_index) if apply => content.extend(new[new_index]),
+            Edit::Delete(_) | Edit::Insert(_) => {}
+        }
+    }
+    content
+}
@@ -0,0 +1,324 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+
+use git::blame::{self, Blame, Start, NOT_COMMITTED};
+use git::object::commit::{Commit, Sign};
+use git::repository::GitRoot;
+use git::{revision, Repository};
+
+/// Options of `blame`.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `-L <start>,<end>`: annotate only these line ranges.
+    pub ranges: Vec<String>,
+    /// `-w`: ignore whitespace when comparing versions.
+    pub ignore_whitespace: bool,
+    /// `-M[<n>]`: detect lines moved within the file, with this score.
+    pub moves: Option<usize>,
+    /// `-C[<n>]`: detect lines copied from other files; how many times it
+    /// was given and the score.
+    pub copies: Option<(usize, usize)>,
+    /// `--ignore-rev`: revisions whose changes are looked through.
+    pub ignore_revs: Vec<String>,
+    /// `--ignore-revs-file`: files listing revisions to ignore; an empty
+    /// name clears the list read so far.
+    pub ignore_revs_files: Vec<PathBuf>,
+    /// `-p`/`--porcelain`: output meant for other programs.
+    pub porcelain: bool,
+}
+
+/// Show the commit that introduced each line of a file, given as
+/// `[<rev>] <file>` in `args`, or `[<rev>]` in `args` and `<file>` after
+/// `--` in `separated`. Without `<rev>`, the file in the working tree is
+/// annotated and lines not committed yet are shown as such.
+pub fn blame(
+    repo: &Repository,
+    root: &GitRoot,
+    args: &[String],
+    separated: &[String],
+    options: &Options,
+) -> anyhow::Result<()> {
+    let (rev, file) = match (args, separated) {
+        ([], [file]) | ([file], []) => (None, file),
+        ([rev], [file]) | ([rev, file], []) => (Some(rev.as_str()), file),
+        _ => anyhow::bail!("usage: git blame [<options>] [<rev>] [--] <file>"),
+    };
+    let path = root.resolve(file).to_string_lossy().into_owned();
+    if path.starts_with("..") {
+        let top = root.work_tree.as_deref().unwrap_or(&root.git_dir);
+        anyhow::bail!("'{file}' is outside repository at '{}'", top.display());
+    }
+
+    // 作業ツリーのファイルなら、HEADにあるファイルでなければならない
+    let commit = rev
+        .map(|rev| revision::resolve_commit(repo, rev))
+        .transpose()?;
+    let head = repo.refs().head()?;
+    let (start, data) = if let (Some(rev), Some(commit)) = (rev, &commit) {
+        let blob = revision::resolve(repo, &format!("{commit}:{path}"))
+            .map_err(|_| anyhow::anyhow!("no such path {path} in {rev}"))?;
+        (Start::Commit(commit), repo.find_blob(&blob)?)
+    } else {
+        let in_head = head
+            .as_ref()
+            .is_some_and(|head| revision::resolve(repo, &format!("{head}:{path}")).is_ok());
+        if !in_head {
+            anyhow::bail!("no such path '{path}' in HEAD");
+        }
+        let work_tree = repo.require_work_tree()?;
+        let data = fs::read(work_tree.join(&path))
+            .map_err(|e| anyhow::anyhow!("Cannot lstat '{path}': {e}"))?;
+        (Start::WorkTree(head.as_deref()), data)
+    };
+
+    let count = git::diff::lines(&data).len();
+    let mut ranges = Vec::new();
+    for spec in &options.ranges {
+        let range = blame::parse_range(spec, count).map_err(anyhow::Error::msg)?;
+        if range.start >= count && !(count == 0 && range.start == 0) {
+            let lines = if count == 1 { "line" } else { "lines" };
+            anyhow::bail!("file {path} has only {count} {lines}");
+        }
+        ranges.push(range);
+    }
+    let blame_options = blame::Options {
+        ranges,
+        ignore_whitespace: options.ignore_whitespace,
+        moves: options.moves,
+        copies: options.copies,
+        ignore_revs: ignore_revs(repo, options)?,
+    };
+    let result = blame::blame(repo, start, &path, &data, &blame_options)?;
+
+    let mut infos = HashMap::new();
+    for origin in &result.origins {
+        if !infos.contains_key(&origin.commit) {
+            infos.insert(
+                origin.commit.clone(),
+                commit_info(repo, &origin.commit, &path)?,
+            );
+        }
+    }
+    let lines = git::diff::lines(&data);
+    let mut out = io::stdout().lock();
+    if options.porcelain {
+        write_porcelain(&mut out, &result, &infos, &lines)?;
+    } else {
+        let marks = Marks {
+            ignored: repo.config().get_bool("blame.markIgnoredLines") == Some(true),
+            unblamable: repo.config().get_bool("blame.markUnblamableLines") == Some(true),
+        };
+        write_default(&mut out, &result, &infos, &lines, &path, marks)?;
+    }
+    Ok(())
+}
+
+// blame.ignoreRevsFile、--ignore-revs-file、--ignore-revの順に集める
+fn ignore_revs(repo: &Repository, options: &Options) -> anyhow::Result<HashSet<String>> {
+    let mut revs = HashSet::new();
+    let config = repo.config().get("blame.ignoreRevsFile").map(PathBuf::from);
+    for file in config.iter().chain(&options.ignore_revs_files) {
+        if file.as_os_str().is_empty() {
+            revs.clear();
+            continue;
+        }
+        let content = fs::read_to_string(file)
+            .map_err(|_| anyhow::anyhow!("could not open object name list: {}", file.display()))?;
+        for line in content.lines() {
+            let name = line.split('#').next().unwrap_or_default().trim();
+            if name.is_empty() {
+                continue;
+            }
+            let oid = revision::resolve_commit(repo, name)
+                .map_err(|_| anyhow::anyhow!("invalid object name: {name}"))?;
+            revs.insert(oid);
+        }
+    }
+    for rev in &options.ignore_revs {
+        let oid = revision::resolve_commit(repo, rev)
+            .map_err(|_| anyhow::anyhow!("cannot find revision {rev} to ignore"))?;
+        revs.insert(oid);
+    }
+    Ok(revs)
+}
+
+// まだcommitされていない行は、作業ツリーのファイルという仮のcommitのものとして表示する
+fn commit_info(repo: &Repository, oid: &str, path: &str) -> anyhow::Result<Commit> {
+    if oid != NOT_COMMITTED {
+        return Ok(repo.find_commit(oid)?);
+    }
+    let sign = Sign::now("Not Committed Yet", "not.committed.yet");
+    Ok(Commit {
+        tree: String::new(),
+        parents: Vec::new(),
+        author: sign.clone(),
+        committer: sign,
+        extra_headers: Vec::new(),
+        message: format!("Version of {path} from {path}\n"),
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Marks {
+    ignored: bool,
+    unblamable: bool,
+}
+
+fn write_default(
+    out: &mut impl Write,
+    result: &Blame,
+    infos: &HashMap<String, Commit>,
+    lines: &[&[u8]],
+    path: &str,
+    marks: Marks,
+) -> io::Result<()> {
+    // 名前が変わる前の行があるときだけパスの列を出す
+    let show_path = result.origins.iter().any(|origin| origin.path != path);
+    let path_width = result.origins.iter().map(|o| o.path.chars().count()).max();
+    let author_width = result
+        .origins
+        .iter()
+        .map(|o| infos[&o.commit].author.name.chars().count())
+        .max()
+        .unwrap_or_default();
+    let number_width = result
+        .lines
+        .last()
+        .map_or(1, |line| (line.number + 1).to_string().len());
+
+    for line in &result.lines {
+        let origin = &result.origins[line.origin];
+        let mut hex = String::new();
+        if origin.boundary {
+            hex.push('^');
+        }
+        if marks.unblamable && line.unblamable {
+            hex.push('*');
+        }
+        if marks.ignored && line.ignored {
+            hex.push('?');
+        }
+        hex.push_str(&origin.commit[..8 - hex.len()]);
+        write!(out, "{hex} ")?;
+        if show_path {
+            write!(
+                out,
+                "{:width$} ",
+                origin.path,
+                width = path_width.unwrap_or_default()
+            )?;
+        }
+        let author = &infos[&origin.commit].author;
+        write!(
+            out,
+            "({:author_width$} {} {:>number_width$}) ",
+            author.name,
+            author.time_stamp.format("%Y-%m-%d %H:%M:%S %z"),
+            line.number + 1,
+        )?;
+        write_content(out, lines[line.number])?;
+    }
+    Ok(())
+}
+
+fn write_porcelain(
+    out: &mut impl Write,
+    result: &Blame,
+    infos: &HashMap<String, Commit>,
+    lines: &[&[u8]],
+) -> io::Result<()> {
+    // 同じcommitの複数のパスから来ている場合は、毎回ファイル名を出す
+    let mut paths: HashMap<&str, HashSet<&str>> = HashMap::new();
+    for origin in &result.origins {
+        paths
+            .entry(&origin.commit)
+            .or_default()
+            .insert(&origin.path);
+    }
+    let mut shown = HashSet::new();
+    for group in groups(result) {
+        let first = result.lines[group.start];
+        let origin = &result.origins[first.origin];
+        writeln!(
+            out,
+            "{} {} {} {}",
+            origin.commit,
+            first.source + 1,
+            first.number + 1,
+            group.len(),
+        )?;
+        let first_time = shown.insert(origin.commit.as_str());
+        if first_time {
+            let info = &infos[&origin.commit];
+            write_sign(out, "author", &info.author)?;
+            write_sign(out, "committer", &info.committer)?;
+            writeln!(out, "summary {}", info.summary())?;
+            if origin.boundary {
+                writeln!(out, "boundary")?;
+            }
+        }
+        if first_time || paths[origin.commit.as_str()].len() > 1 {
+            if let Some((commit, path)) = &origin.previous {
+                writeln!(out, "previous {commit} {path}")?;
+            }
+            writeln!(out, "filename {}", origin.path)?;
+        }
+        for (i, line) in result.lines[group].iter().enumerate() {
+            if i > 0 {
+                writeln!(
+                    out,
+                    "{} {} {}",
+                    origin.commit,
+                    line.source + 1,
+                    line.number + 1
+                )?;
+            }
+            out.write_all(b"\t")?;
+            write_content(out, lines[line.number])?;
+        }
+    }
+    Ok(())
+}
+
+fn write_sign(out: &mut impl Write, role: &str, sign: &Sign) -> io::Result<()> {
+    writeln!(out, "{role} {}", sign.name)?;
+    writeln!(out, "{role}-mail <{}>", sign.email)?;
+    writeln!(out, "{role}-time {}", sign.time_stamp.timestamp())?;
+    writeln!(out, "{role}-tz {}", sign.time_stamp.format("%z"))
+}
+
+// 同じ版の連続した行のまとまり
+fn groups(result: &Blame) -> Vec<Range<usize>> {
+    let mut groups: Vec<Range<usize>> = Vec::new();
+    for (i, line) in result.lines.iter().enumerate() {
+        if let Some(group) = groups.last_mut() {
+            let last = result.lines[group.end - 1];
+            if last.origin == line.origin
+                && last.number + 1 == line.number
+                && last.source + 1 == line.source
+                && last.ignored == line.ignored
+                && last.unblamable == line.unblamable
+            {
+                group.end += 1;
+                continue;
+            }
+        }
+        groups.push(Range {
+            start: i,
+            end: i + 1,
+        });
+    }
+    groups
+}
+
+// 最後の行に改行がなくても改行して出す
+fn write_content(out: &mut impl Write, line: &[u8]) -> io::Result<()> {
+    out.write_all(line)?;
+    if !line.ends_with(b"\n") {
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
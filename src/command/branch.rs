@@ -1,31 +1,131 @@
-use std::{fs, io::Write};
+use git::revision;
+use git::revwalk;
+use git::Repository;
 
-use crate::util;
+use super::commit;
 
-pub fn create(branch_name: &str) -> std::io::Result<()> {
-    let head_commit_hash = util::path::get_head_commit_hash().unwrap_or_default();
-    let mut file = util::path::create_nested_file(format!(".git/refs/heads/{branch_name}"))?;
-    file.write_all(head_commit_hash.as_bytes())?;
-    let mut file = fs::File::create(".git/HEAD")?;
-    let content = format!("ref: refs/heads/{branch_name}");
-    file.write_all(content.as_bytes())?;
-    Ok(())
+/// Flags of `branch`.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `-d`/`--delete`: delete branches merged into `HEAD`.
+    pub delete: bool,
+    /// `-D`: delete branches even when they are not merged.
+    pub force_delete: bool,
+    /// `--merged`: list only branches merged into the commit given as the
+    /// argument, `HEAD` by default.
+    pub merged: bool,
+    /// `--no-merged`: list only branches not merged into the commit.
+    pub no_merged: bool,
+}
+
+/// Run `branch`: create the branch named by `args`, delete the ones named
+/// with `-d`/`-D`, or list branches.
+///
+/// Returns `false` when some branch could not be deleted.
+pub fn branch(repo: &Repository, args: &[String], options: &Options) -> anyhow::Result<bool> {
+    if options.delete || options.force_delete {
+        if args.is_empty() {
+            anyhow::bail!("branch name required");
+        }
+        return delete(repo, args, options.force_delete);
+    }
+    if options.merged || options.no_merged {
+        let filter = match args {
+            [] => "HEAD",
+            [commit] => commit.as_str(),
+            _ => anyhow::bail!("too many arguments"),
+        };
+        let filter = revision::resolve_commit(repo, filter)
+            .map_err(|_| anyhow::anyhow!("malformed object name {filter}"))?;
+        list(repo, Some((&filter, options.merged)))?;
+        return Ok(true);
+    }
+    match args {
+        [] => list(repo, None)?,
+        [name] => create(repo, name)?,
+        _ => anyhow::bail!("too many arguments"),
+    }
+    Ok(true)
 }
 
-pub fn delete(branch_name: &str) -> std::io::Result<()> {
-    let head_ref = util::path::get_head_ref();
-    if head_ref == format!(".git/refs/heads/{branch_name}") {
-        // Errを返した方がいいかも
-        println!("Cannot delete current branch");
-        return Ok(());
+// filterがあれば、そのcommitにマージ済みか(merged)どうかで絞り込む
+fn list(repo: &Repository, filter: Option<(&str, bool)>) -> anyhow::Result<()> {
+    let current = repo.refs().current_branch()?;
+    for (name, oid) in repo.refs().list("refs/heads/")? {
+        if let Some((commit, merged)) = filter {
+            if revwalk::is_ancestor(repo, &oid, commit)? != merged {
+                continue;
+            }
+        }
+        let marker = if current.as_ref() == Some(&name) {
+            '*'
+        } else {
+            ' '
+        };
+        println!("{marker} {}", name.trim_start_matches("refs/heads/"));
     }
-    fs::remove_file(format!(".git/refs/heads/{branch_name}"))?;
     Ok(())
 }
 
-pub fn checkout(branch_name: &str) -> std::io::Result<()> {
-    let mut file = fs::File::create(".git/HEAD")?;
-    let content = format!("ref: refs/heads/{branch_name}");
-    file.write_all(content.as_bytes())?;
+fn create(repo: &Repository, branch_name: &str) -> anyhow::Result<()> {
+    let branch_ref = format!("refs/heads/{branch_name}");
+    if repo.refs().resolve(&branch_ref)?.is_some() {
+        anyhow::bail!("a branch named '{branch_name}' already exists");
+    }
+    let Some(head_commit_hash) = repo.refs().head()? else {
+        anyhow::bail!("not a valid object name: 'HEAD'");
+    };
+    // gitと同じく、起点は今のブランチの名前で記録する
+    let start = repo.refs().current_branch()?;
+    let start = start
+        .as_deref()
+        .map_or("HEAD", |branch| branch.trim_start_matches("refs/heads/"));
+    let committer = commit::committer(repo)?;
+    repo.refs().update_logged(
+        &branch_ref,
+        &head_commit_hash,
+        &committer,
+        &format!("branch: Created from {start}"),
+    )?;
     Ok(())
 }
+
+// forceでなければ、HEADにマージされていないブランチは消さない
+fn delete(repo: &Repository, names: &[String], force: bool) -> anyhow::Result<bool> {
+    let current = repo.refs().current_branch()?;
+    let head = repo.refs().head()?;
+    let mut ok = true;
+    for name in names {
+        let branch_ref = format!("refs/heads/{name}");
+        let Some(oid) = repo.refs().resolve(&branch_ref)? else {
+            eprintln!("error: branch '{name}' not found.");
+            ok = false;
+            continue;
+        };
+        if current.as_ref() == Some(&branch_ref) {
+            let work_tree = repo.work_tree().unwrap_or(repo.git_dir());
+            eprintln!(
+                "error: Cannot delete branch '{name}' checked out at '{}'",
+                work_tree.display()
+            );
+            ok = false;
+            continue;
+        }
+        let merged = match &head {
+            Some(head) => revwalk::is_ancestor(repo, &oid, head)?,
+            None => false,
+        };
+        if !force && !merged {
+            eprintln!(
+                "error: The branch '{name}' is not fully merged.\n\
+                 If you are sure you want to delete it, run 'git branch -D {name}'."
+            );
+            ok = false;
+            continue;
+        }
+        repo.refs().delete(&branch_ref)?;
+        println!("Deleted branch {name} (was {}).", &oid[..7]);
+    }
+    Ok(ok)
+}
@@ -0,0 +1,69 @@
+use std::path::PathBuf;
+
+use git::ignore::Ignore;
+use git::repository::GitRoot;
+use git::Repository;
+
+/// Print which of `paths` are ignored, returning whether any of them is.
+///
+/// Tracked paths are never reported unless `no_index` is set, since ignore
+/// rules do not apply to them.
+pub fn check_ignore(
+    repo: &Repository,
+    root: &GitRoot,
+    paths: &[PathBuf],
+    verbose: bool,
+    non_matching: bool,
+    no_index: bool,
+) -> anyhow::Result<bool> {
+    if paths.is_empty() {
+        anyhow::bail!("no path specified");
+    }
+    if non_matching && !verbose {
+        anyhow::bail!("--non-matching is only valid with --verbose");
+    }
+    let work_tree = repo.require_work_tree()?;
+    let index = repo.index()?;
+    let mut ignore = Ignore::new(repo)?;
+
+    let mut any_ignored = false;
+    for path in paths {
+        let resolved = root.resolve(path);
+        let Some(relative) = resolved.to_str() else {
+            anyhow::bail!("{}: path is not valid UTF-8", path.display());
+        };
+        if relative.is_empty() || relative.starts_with("..") {
+            anyhow::bail!(
+                "{}: '{}' is outside repository",
+                path.display(),
+                path.display()
+            );
+        }
+        let is_dir = path.to_string_lossy().ends_with('/') || work_tree.join(relative).is_dir();
+        let tracked = index.entries().iter().any(|e| e.path == relative);
+
+        let pattern = if tracked && !no_index {
+            None
+        } else {
+            ignore.check(relative, is_dir)?
+        };
+        let display = path.display();
+        match pattern {
+            Some(pattern) if verbose => {
+                // gitと同じく、-vでは再包含のパターンも一致として数える
+                any_ignored = true;
+                println!(
+                    "{}:{}:{}\t{display}",
+                    pattern.source, pattern.line, pattern.original
+                );
+            }
+            Some(pattern) if !pattern.negative => {
+                any_ignored = true;
+                println!("{display}");
+            }
+            _ if non_matching => println!("::\t{display}"),
+            _ => {}
+        }
+    }
+    Ok(any_ignored)
+}
@@ -0,0 +1,134 @@
+use std::path::Path;
+
+use git::merge::ConflictStyle;
+use git::object::Kind;
+use git::revision;
+use git::Repository;
+
+use super::{restore, switch};
+
+/// Flags of `checkout`.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `-b`: create the branch named by the argument at `HEAD` and switch
+    /// to it.
+    pub new_branch: bool,
+    /// `--ours`/`--theirs`: check out unmerged files from our or their
+    /// version, stage 2 or 3 of the index.
+    pub stage: Option<u8>,
+    /// `-m`/`--merge`: recreate the conflicted merge of unmerged files.
+    pub merge: bool,
+    /// `--conflict=<style>`: like `--merge`, showing conflicts in `style`.
+    pub conflict: Option<ConflictStyle>,
+}
+
+/// Switch `HEAD` to the branch in `args`, or check out paths from the index
+/// or from the tree-ish leading `args`.
+///
+/// `separated` are the paths given after `--`, which are never taken for a
+/// branch. Returns `false` when some paths could not be checked out.
+pub fn checkout(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    separated: &[String],
+    options: &Options,
+) -> anyhow::Result<bool> {
+    if options.new_branch {
+        let [name] = args else {
+            anyhow::bail!("usage: git checkout -b <new-branch>");
+        };
+        let create = switch::Options {
+            create: Some(name.clone()),
+            ..switch::Options::default()
+        };
+        switch::switch(repo, &[], &create)?;
+        return Ok(true);
+    }
+
+    // "--"がなければ、ブランチ1つは切り替え先、treeの後はpathとして扱う
+    let (source, paths) = match args {
+        [] if separated.is_empty() => {
+            if options.stage.is_some() {
+                anyhow::bail!("'--ours/--theirs' cannot be used with switching branches");
+            }
+            return Ok(true);
+        }
+        [name] if separated.is_empty() && is_branch(repo, name)? => {
+            if options.stage.is_some() {
+                anyhow::bail!("'--ours/--theirs' cannot be used with switching branches");
+            }
+            let index = repo.index()?;
+            let mut unmerged: Vec<&str> = index
+                .entries()
+                .iter()
+                .filter(|e| e.stage != 0)
+                .map(|e| e.path.as_str())
+                .collect();
+            if !unmerged.is_empty() {
+                unmerged.dedup();
+                eprintln!("error: you need to resolve your current index first");
+                for path in unmerged {
+                    eprintln!("{path}: needs merge");
+                }
+                return Ok(false);
+            }
+            switch::switch(repo, &[name.clone()], &switch::Options::default())?;
+            return Ok(true);
+        }
+        [] => (None, separated.to_vec()),
+        [tree, rest @ ..]
+            if tree_of(repo, tree).is_some() && !(rest.is_empty() && separated.is_empty()) =>
+        {
+            (Some(tree.clone()), [rest, separated].concat())
+        }
+        [tree] if !separated.is_empty() => anyhow::bail!("invalid reference: {tree}"),
+        _ => (None, [args, separated].concat()),
+    };
+
+    let tree = source.as_deref().and_then(|rev| tree_of(repo, rev));
+    let merge = options.merge || options.conflict.is_some();
+    let restore_options = restore::Options {
+        staged: source.is_some(),
+        worktree: true,
+        stage: options.stage.filter(|_| source.is_none()),
+        merge: merge && source.is_none(),
+        conflict: options.conflict.filter(|_| source.is_none()),
+        overlay: true,
+        source,
+    };
+    // gitと同じく、"--"があるときは何を戻したかを伝えない
+    let quiet = !separated.is_empty();
+    let Some(restored) = restore::paths(repo, prefix, &paths, &restore_options)? else {
+        return Ok(false);
+    };
+    if quiet {
+        return Ok(true);
+    }
+    let count =
+        |n: usize, one: &str, many: &str| format!("{n} {}", if n == 1 { one } else { many });
+    if restored.recreated > 0 {
+        let recreated = count(restored.recreated, "merge conflict", "merge conflicts");
+        eprintln!("Recreated {recreated}");
+    }
+    let updated = count(restored.updated, "path", "paths");
+    if let Some(tree) = tree {
+        eprintln!("Updated {updated} from {}", &tree[..7]);
+    } else if restored.recreated == 0 || restored.updated > 0 {
+        eprintln!("Updated {updated} from the index");
+    }
+    Ok(true)
+}
+
+fn is_branch(repo: &Repository, name: &str) -> anyhow::Result<bool> {
+    Ok(repo
+        .refs()
+        .resolve(&format!("refs/heads/{name}"))?
+        .is_some())
+}
+
+fn tree_of(repo: &Repository, rev: &str) -> Option<String> {
+    revision::resolve(repo, rev)
+        .and_then(|oid| revision::peel(repo, &oid, Kind::Tree))
+        .ok()
+}
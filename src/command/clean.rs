@@ -0,0 +1,613 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use git::ignore::{Ignore, Pattern};
+use git::pathspec::Pathspec;
+use git::Repository;
+
+use super::relative_path;
+
+/// Flags of `clean`.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `-n`: only show what would be removed.
+    pub dry_run: bool,
+    /// `-f`, counted: twice also removes nested repositories.
+    pub force: usize,
+    /// `-d`: also remove untracked directories.
+    pub directories: bool,
+    /// `-x`: also remove ignored files.
+    pub ignored: bool,
+    /// `-X`: only remove ignored files.
+    pub ignored_only: bool,
+    /// `-i`: choose what to remove interactively.
+    pub interactive: bool,
+    /// `-q`: don't list removed files.
+    pub quiet: bool,
+}
+
+// 削除の対象にするファイルの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Untracked,
+    All,
+    IgnoredOnly,
+}
+
+impl Mode {
+    fn wants(self, ignored: bool) -> bool {
+        match self {
+            Self::Untracked => !ignored,
+            Self::All => true,
+            Self::IgnoredOnly => ignored,
+        }
+    }
+}
+
+/// Remove the untracked files matched by `args`, or everything below the
+/// directory `prefix` without them.
+///
+/// Returns `false` when some path could not be removed.
+pub fn clean(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<bool> {
+    if options.force == 0 && !options.dry_run && !options.interactive {
+        match repo.config().get_bool("clean.requireForce") {
+            Some(false) => {}
+            Some(true) => anyhow::bail!(
+                "clean.requireForce set to true and neither -i, -n, nor -f given; refusing to clean"
+            ),
+            None => anyhow::bail!(
+                "clean.requireForce defaults to true and neither -i, -n, nor -f given; refusing to clean"
+            ),
+        }
+    }
+    if options.ignored && options.ignored_only {
+        anyhow::bail!("-x and -X cannot be used together");
+    }
+
+    let mut paths = find_paths(repo, prefix, args, options)?;
+    if options.interactive && !paths.is_empty() {
+        paths = interactive(&paths, prefix)?;
+    }
+
+    let work_tree = repo.require_work_tree()?;
+    let mut ok = true;
+    for path in &paths {
+        let display = relative_path(path, prefix);
+        if options.dry_run {
+            println!("Would remove {display}");
+            continue;
+        }
+        let full_path = work_tree.join(path.trim_end_matches('/'));
+        let removed = if path.ends_with('/') {
+            fs::remove_dir_all(&full_path)
+        } else {
+            fs::remove_file(&full_path)
+        };
+        match removed {
+            Ok(()) if !options.quiet => println!("Removing {display}"),
+            Ok(()) => {}
+            Err(e) => {
+                let message = e.to_string();
+                let message = message.split(" (os error").next().unwrap_or_default();
+                eprintln!("warning: failed to remove {display}: {message}");
+                ok = false;
+            }
+        }
+    }
+    Ok(ok)
+}
+
+// 削除する対象を、ディレクトリは"dir/"の形でトップからのパスで集める
+fn find_paths(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<Vec<String>> {
+    // パスの指定がなければ今のディレクトリの下だけを対象にする
+    let explicit = !args.is_empty();
+    let pathspec = if explicit {
+        Pathspec::parse(args, prefix)?
+    } else {
+        Pathspec::parse(&["."], prefix)?
+    };
+    let index = repo.index()?;
+    let mode = if options.ignored {
+        Mode::All
+    } else if options.ignored_only {
+        Mode::IgnoredOnly
+    } else {
+        Mode::Untracked
+    };
+    let mut walker = Walker {
+        work_tree: repo.require_work_tree()?,
+        tracked: index.entries().iter().map(|e| e.path.as_str()).collect(),
+        tracked_dirs: index
+            .entries()
+            .iter()
+            .flat_map(|entry| entry.path.match_indices('/').map(|(i, _)| &entry.path[..i]))
+            .collect(),
+        ignore: Ignore::new(repo)?,
+        pathspec: &pathspec,
+        mode,
+        // 明示したパスに一致するディレクトリは-dがなくても消す
+        directories: options.directories || explicit,
+        nested: options.force > 1,
+        paths: Vec::new(),
+    };
+    walker.walk("")?;
+    let mut paths = walker.paths;
+    paths.sort();
+    Ok(paths)
+}
+
+struct Walker<'a> {
+    work_tree: &'a Path,
+    tracked: HashSet<&'a str>,
+    tracked_dirs: HashSet<&'a str>,
+    ignore: Ignore,
+    pathspec: &'a Pathspec,
+    mode: Mode,
+    directories: bool,
+    nested: bool,
+    paths: Vec<String>,
+}
+
+impl Walker<'_> {
+    fn walk(&mut self, dir: &str) -> anyhow::Result<()> {
+        let mut entries: Vec<_> =
+            fs::read_dir(self.work_tree.join(dir))?.collect::<Result<_, _>>()?;
+        entries.sort_by_key(fs::DirEntry::file_name);
+
+        for entry in entries {
+            let name = entry.file_name();
+            if name == ".git" {
+                continue;
+            }
+            let name = name.to_string_lossy();
+            let path = if dir.is_empty() {
+                name.into_owned()
+            } else {
+                format!("{dir}/{name}")
+            };
+            let is_dir = entry.file_type()?.is_dir();
+
+            if is_dir && self.tracked_dirs.contains(path.as_str()) {
+                self.walk(&path)?;
+                continue;
+            }
+            if self.tracked.contains(path.as_str()) {
+                continue;
+            }
+            let ignored = self.ignore.is_ignored(&path, is_dir)?;
+            if !is_dir {
+                if self.mode.wants(ignored) && self.pathspec.matches(&path) {
+                    self.paths.push(path);
+                }
+                continue;
+            }
+            // 埋め込まれたリポジトリは-ffのときだけ消す
+            if self.work_tree.join(&path).join(".git").exists() && !self.nested {
+                continue;
+            }
+            if ignored && self.mode == Mode::Untracked {
+                continue;
+            }
+            if self.pathspec.matches(&path) {
+                // -Xでは、-dがなくても中の無視されたファイルは消す
+                let whole = self.removes_whole(&path, ignored)?;
+                if whole && self.directories {
+                    self.paths.push(format!("{path}/"));
+                } else if !whole && (self.directories || self.mode == Mode::IgnoredOnly) {
+                    self.walk(&path)?;
+                }
+            } else if self.may_match_inside(&path) {
+                self.walk(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    // ディレクトリをまとめて消してよいか。無視されたファイルを残す場合や、
+    // 無視されたファイルだけを消す場合は中身を調べる
+    fn removes_whole(&mut self, dir: &str, ignored: bool) -> anyhow::Result<bool> {
+        Ok(match self.mode {
+            Mode::All => true,
+            Mode::Untracked => !self.contains(dir, true)?,
+            Mode::IgnoredOnly => {
+                ignored || (self.contains(dir, true)? && !self.contains(dir, false)?)
+            }
+        })
+    }
+
+    // ディレクトリの下に、無視された(`ignored`)または無視されないファイルがあるか
+    fn contains(&mut self, dir: &str, ignored: bool) -> anyhow::Result<bool> {
+        let mut entries: Vec<_> =
+            fs::read_dir(self.work_tree.join(dir))?.collect::<Result<_, _>>()?;
+        entries.sort_by_key(fs::DirEntry::file_name);
+        for entry in entries {
+            let path = format!("{dir}/{}", entry.file_name().to_string_lossy());
+            let is_dir = entry.file_type()?.is_dir();
+            let found = if self.ignore.is_ignored(&path, is_dir)? {
+                ignored
+            } else if is_dir {
+                self.contains(&path, ignored)?
+            } else {
+                !ignored
+            };
+            if found {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // パスの指定がディレクトリの中のパスに一致しうるか
+    fn may_match_inside(&self, dir: &str) -> bool {
+        let dir = format!("{dir}/");
+        self.pathspec.items.iter().any(|item| {
+            if item.exclude {
+                return false;
+            }
+            if item.pattern.starts_with(&dir) {
+                return true;
+            }
+            // ワイルドカードより前の部分が食い違わなければ、中を見る
+            match item.wildcard_start() {
+                Some(start) => dir.len() >= start && dir[..start] == item.pattern[..start],
+                None => false,
+            }
+        })
+    }
+}
+
+const MENU: [(&str, char); 6] = [
+    ("clean", 'c'),
+    ("filter by pattern", 'f'),
+    ("select by numbers", 's'),
+    ("ask each", 'a'),
+    ("quit", 'q'),
+    ("help", 'h'),
+];
+
+// -iの対話: 削除する`paths`を絞り込む。やめた場合は空を返す
+fn interactive(paths: &[String], prefix: &Path) -> io::Result<Vec<String>> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    // 入力や表示は、gitと同じく今のディレクトリからの相対パスで扱う
+    let mut items: Vec<(String, String)> = paths
+        .iter()
+        .map(|path| (relative_path(path, prefix), path.clone()))
+        .collect();
+
+    while !items.is_empty() {
+        println!(
+            "Would remove the following item{}:",
+            if items.len() == 1 { "" } else { "s" }
+        );
+        print_items(&items);
+        let names: Vec<_> = MENU.iter().map(|(name, _)| (*name).to_string()).collect();
+        let hotkeys: Vec<_> = MENU.iter().map(|(_, key)| *key).collect();
+        let menu = Menu {
+            header: Some("*** Commands ***"),
+            prompt: "What now> ",
+            single: true,
+            names: &names,
+            hotkeys: &hotkeys,
+        };
+        let Some(chosen) = menu.choose(&mut input)? else {
+            println!("Bye.");
+            return Ok(Vec::new());
+        };
+        match MENU[chosen[0]].0 {
+            "clean" => break,
+            "filter by pattern" => filter_by_patterns(&mut input, &mut items)?,
+            "select by numbers" => {
+                let names: Vec<_> = items.iter().map(|(name, _)| name.clone()).collect();
+                let menu = Menu {
+                    header: None,
+                    prompt: "Select items to delete>> ",
+                    single: false,
+                    names: &names,
+                    hotkeys: &[],
+                };
+                let chosen = menu.choose(&mut input)?.unwrap_or_default();
+                items = chosen.into_iter().map(|i| items[i].clone()).collect();
+            }
+            "ask each" => {
+                ask_each(&mut input, &mut items)?;
+                break;
+            }
+            "quit" => {
+                println!("Bye.");
+                return Ok(Vec::new());
+            }
+            _ => println!(
+                "clean               - start cleaning\n\
+                 filter by pattern   - exclude items from deletion\n\
+                 select by numbers   - select items to be deleted by numbers\n\
+                 ask each            - confirm each deletion (like \"rm -i\")\n\
+                 quit                - stop cleaning\n\
+                 help                - this screen\n\
+                 ?                   - help for prompt selection"
+            ),
+        }
+        if items.is_empty() {
+            println!("No more files to clean, exiting.");
+        }
+    }
+    Ok(items.into_iter().map(|(_, path)| path).collect())
+}
+
+// 入力されたパターンに一致するものを除いていく。空の入力で終わる
+fn filter_by_patterns(
+    input: &mut impl BufRead,
+    items: &mut Vec<(String, String)>,
+) -> io::Result<()> {
+    let mut changed = true;
+    while !items.is_empty() {
+        if changed {
+            print_items(items);
+        }
+        let Some(line) = prompt(input, "Input ignore patterns>> ")? else {
+            println!();
+            break;
+        };
+        if line.is_empty() {
+            break;
+        }
+        let patterns: Vec<_> = line
+            .split(' ')
+            .filter_map(|word| Pattern::parse(word.trim(), "", "manual exclude", 0))
+            .collect();
+        let before = items.len();
+        items.retain(|(name, _)| {
+            let is_dir = name.ends_with('/');
+            let name = name.trim_end_matches('/');
+            // 最後に一致したパターンで決まる
+            !patterns
+                .iter()
+                .rev()
+                .find(|pattern| pattern.matches(name, is_dir))
+                .is_some_and(|pattern| !pattern.negative)
+        });
+        changed = items.len() != before;
+        if !changed {
+            println!("WARNING: Cannot find items matched by: {line}");
+        }
+    }
+    Ok(())
+}
+
+// 1つずつ"rm -i"のように尋ねる。入力が終われば残りは消さない
+fn ask_each(input: &mut impl BufRead, items: &mut Vec<(String, String)>) -> io::Result<()> {
+    let mut chosen = Vec::new();
+    for (name, path) in items.drain(..) {
+        let Some(answer) = prompt(input, &format!("Remove {name} [y/N]? "))? else {
+            println!();
+            break;
+        };
+        if !answer.is_empty() && "yes".starts_with(&answer.to_lowercase()) {
+            chosen.push((name, path));
+        }
+    }
+    *items = chosen;
+    Ok(())
+}
+
+// 1行を読んで前後の空白を除く。入力が終われば`None`
+fn prompt(input: &mut impl BufRead, prompt: &str) -> io::Result<Option<String>> {
+    let mut stdout = io::stdout().lock();
+    write!(stdout, "{prompt}")?;
+    stdout.flush()?;
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
+
+fn print_items(items: &[(String, String)]) {
+    let names: Vec<_> = items.iter().map(|(name, _)| name.clone()).collect();
+    print_columns(&names, false);
+}
+
+// 番号や名前の前方一致で選ぶメニュー。`single`なら1つ選んだ時点で返す
+struct Menu<'a> {
+    header: Option<&'a str>,
+    prompt: &'a str,
+    single: bool,
+    names: &'a [String],
+    hotkeys: &'a [char],
+}
+
+impl Menu<'_> {
+    // 選ばれた番号(0から)を返す。入力が終われば`None`
+    fn choose(&self, input: &mut impl BufRead) -> io::Result<Option<Vec<usize>>> {
+        let mut chosen = vec![false; self.names.len()];
+        loop {
+            if let Some(header) = self.header {
+                println!("{header}");
+            }
+            let cells: Vec<_> = self
+                .names
+                .iter()
+                .zip(&chosen)
+                .enumerate()
+                .map(|(i, (name, &on))| {
+                    format!("{}{:2}: {name}", if on { "*" } else { " " }, i + 1)
+                })
+                .collect();
+            print_columns(&cells, true);
+            let Some(line) = prompt(input, self.prompt)? else {
+                return Ok(if self.single { None } else { Some(Vec::new()) });
+            };
+            if line == "?" {
+                print!("{}", self.prompt_help());
+                continue;
+            }
+            if !self.single && line.is_empty() {
+                break;
+            }
+            let count = self.parse(&line, &mut chosen);
+            if self.single && count > 0 {
+                break;
+            }
+        }
+        Ok(Some((0..chosen.len()).filter(|&i| chosen[i]).collect()))
+    }
+
+    // 入力を解釈して`chosen`に反映し、選ばれた(外された)数を返す
+    fn parse(&self, line: &str, chosen: &mut [bool]) -> usize {
+        let line = if self.single {
+            line.to_string()
+        } else {
+            line.replace(',', " ")
+        };
+        let tokens: Vec<&str> = if self.single {
+            vec![line.as_str()]
+        } else {
+            line.split(' ').collect()
+        };
+        let total = self.names.len();
+        let mut count = 0;
+        for token in tokens {
+            let token = token.trim();
+            if token.is_empty() {
+                continue;
+            }
+            let (token, choose) = match token.strip_prefix('-') {
+                Some(rest) => (rest, false),
+                None => (token, true),
+            };
+            // "3-5"や"3-"の範囲。gitと同じく、エラーには"-"より前だけを示す
+            let (shown, bottom, top) = if token == "*" {
+                (token, 1, total)
+            } else if token.starts_with(|c: char| c.is_ascii_digit()) {
+                match token.split_once('-') {
+                    Some((bottom, "")) => (bottom, leading_number(bottom), total),
+                    Some((bottom, top)) => (bottom, leading_number(bottom), leading_number(top)),
+                    None => (token, leading_number(token), leading_number(token)),
+                }
+            } else {
+                let found = self.find_unique(token);
+                (token, found, found)
+            };
+            if top == 0
+                || bottom == 0
+                || top > total
+                || bottom > top
+                || (self.single && bottom != top)
+            {
+                println!("Huh ({shown})?");
+                continue;
+            }
+            for on in &mut chosen[bottom - 1..top] {
+                *on = choose;
+            }
+            count += top - bottom + 1;
+        }
+        count
+    }
+
+    // 前方一致(大文字小文字を区別しない)で1つに決まる番号(1から)。なければ0
+    fn find_unique(&self, token: &str) -> usize {
+        let mut chars = token.chars();
+        if let (Some(key), None) = (chars.next(), chars.next()) {
+            if let Some(i) = self.hotkeys.iter().position(|&hotkey| hotkey == key) {
+                return i + 1;
+            }
+        }
+        let token = token.to_lowercase();
+        let mut found = 0;
+        for (i, name) in self.names.iter().enumerate() {
+            if name.to_lowercase().starts_with(&token) {
+                if found != 0 {
+                    return 0;
+                }
+                found = i + 1;
+            }
+        }
+        found
+    }
+
+    fn prompt_help(&self) -> &'static str {
+        if self.single {
+            "Prompt help:\n\
+             1          - select a numbered item\n\
+             foo        - select item based on unique prefix\n           \
+             - (empty) select nothing\n"
+        } else {
+            "Prompt help:\n\
+             1          - select a single item\n\
+             3-5        - select a range of items\n\
+             2-3,6-9    - select multiple ranges\n\
+             foo        - select item based on unique prefix\n\
+             -...       - unselect specified items\n\
+             *          - choose all items\n           \
+             - (empty) finish selecting\n"
+        }
+    }
+}
+
+// atoi()のように先頭の数字だけを読む
+fn leading_number(s: &str) -> usize {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s[..end].parse().unwrap_or(0)
+}
+
+// gitのcolumn表示のように、同じ幅の列に並べる。`by_row`なら行方向に詰める
+fn print_columns(cells: &[String], by_row: bool) {
+    const INDENT: &str = "  ";
+    if cells.is_empty() {
+        return;
+    }
+    let total = env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse::<usize>().ok())
+        .filter(|&columns| columns > 0)
+        .unwrap_or(80)
+        - 1;
+    let width = cells
+        .iter()
+        .map(|cell| cell.chars().count())
+        .max()
+        .unwrap_or(0)
+        + 2;
+    let columns = (total.saturating_sub(INDENT.len()) / width).max(1);
+    let rows = cells.len().div_ceil(columns);
+
+    for y in 0..rows {
+        let mut line = INDENT.to_string();
+        for x in 0..columns {
+            let i = if by_row {
+                y * columns + x
+            } else {
+                x * rows + y
+            };
+            let Some(cell) = cells.get(i) else {
+                break;
+            };
+            line.push_str(cell);
+            let last = if by_row {
+                x == columns - 1 || i == cells.len() - 1
+            } else {
+                i + rows >= cells.len()
+            };
+            if last {
+                break;
+            }
+            line.push_str(&" ".repeat(width - cell.chars().count()));
+        }
+        println!("{line}");
+    }
+}
@@ -1,237 +1,588 @@
-use std::io::{ErrorKind, Read};
-use std::path::Path;
-use std::{fs::File, io::Write};
-
-use byteorder::{BigEndian, ByteOrder};
-use chrono::Local;
-use hex;
-use itertools::Itertools;
-
-use crate::object::commit::{Commit, Sign};
-use crate::util;
-
-#[derive(Clone, Debug, PartialEq, Eq)]
-enum NodeType {
-    Blob,
-    Tree,
-}
-
-#[derive(Clone, Debug)]
-struct Node {
-    r#type: NodeType,
-    mode: u32,
-    name: String,
-    hash: String,
-    children: Vec<Node>,
-}
-
-pub fn commit(message: String) -> anyhow::Result<()> {
-    let mut index_tree = Node {
-        r#type: NodeType::Tree,
-        mode: 0,
-        name: "root".to_string(),
-        hash: String::new(),
-        children: Vec::new(),
-    };
-    decode_index_file(&mut index_tree)?;
-
-    generate_tree_objects(&mut index_tree)?;
-    let tree_hash = index_tree.hash.clone();
-    let commit_hash = generate_commit_object(tree_hash, message)?;
-    update_head(&commit_hash)?;
-    Ok(())
+use std::env;
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use chrono::{DateTime, FixedOffset, Local};
+
+use git::message::{self, Cleanup, SCISSORS};
+use git::object::commit::{Commit, Sign};
+use git::object::{Kind, Object};
+use git::odb::ObjectDatabase;
+use git::revision;
+use git::status::Status;
+use git::Repository;
+
+use super::status::{branch_line, long_lines, Context};
+use super::{add, rebase, reset};
+
+// マージのcommitでエディタに出す注意書き
+const MERGE_NOTE: &str = "#\n\
+    # It looks like you may be committing a merge.\n\
+    # If this is not correct, please run\n\
+    #\tgit update-ref -d MERGE_HEAD\n\
+    # and try again.\n\
+    \n";
+
+// cherry-pickのcommitでエディタに出す注意書き
+const CHERRY_PICK_NOTE: &str = "#\n\
+    # It looks like you may be committing a cherry-pick.\n\
+    # If this is not correct, please run\n\
+    #\tgit update-ref -d CHERRY_PICK_HEAD\n\
+    # and try again.\n\
+    \n";
+
+/// Flags of `commit`.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `-m`: paragraphs of the message.
+    pub messages: Vec<String>,
+    /// `-F`: file to read the message from, `-` for standard input.
+    pub file: Option<PathBuf>,
+    /// `-e`/`--no-edit`: whether to open the editor, which by default
+    /// happens only when no message is given.
+    pub edit: Option<bool>,
+    /// `-a`: stage modified and deleted tracked files first.
+    pub all: bool,
+    /// `--amend`: replace the `HEAD` commit instead of adding on top of it.
+    pub amend: bool,
+    /// `--reset-author`: with `--amend`, make the committer the author.
+    pub reset_author: bool,
+    /// `--allow-empty`: commit even when the tree does not change.
+    pub allow_empty: bool,
+    /// `--cleanup=<mode>`: overrides `commit.cleanup`.
+    pub cleanup: Option<Cleanup>,
+    /// `--fixup=<commit>`: mark the commit as a fixup of another.
+    pub fixup: Option<String>,
+    /// `--squash=<commit>`: mark the commit to be squashed into another.
+    pub squash: Option<String>,
+    /// `--trailer`: `<token>: <value>` lines to add to the message.
+    pub trailers: Vec<String>,
+    /// Author to record, as for a commit replayed by `rebase`; takes the
+    /// place of `GIT_AUTHOR_*` and the configured identity.
+    pub author: Option<Sign>,
+    /// What to write in the reflog instead of `commit`, as `GIT_REFLOG_ACTION`
+    /// would.
+    pub reflog_action: Option<String>,
 }
 
-fn travel_tree(node: &mut Node, path: &[&std::ffi::OsStr], children_mode: u32, hash: String) {
-    if path.len() == 1 {
-        let new_node = Node {
-            r#type: NodeType::Blob,
-            mode: children_mode,
-            name: path[0].to_str().unwrap().to_string(),
-            hash,
-            children: Vec::new(),
+/// Record the index as a new commit on `HEAD`, or as a replacement of it
+/// with `--amend`.
+///
+/// Returns `false` after saying why when there is nothing to commit, or
+/// when the message is empty or could not be edited.
+pub fn commit(repo: &Repository, prefix: &Path, options: &Options) -> anyhow::Result<bool> {
+    check_options(options)?;
+    if options.all {
+        let update = add::Options {
+            update: true,
+            ..add::Options::default()
         };
-        node.children.push(new_node);
-        return;
+        add::add(repo, Path::new(""), &[], &update)?;
+    }
+    let index = repo.index()?;
+    if index.entries().iter().any(|e| e.stage != 0) {
+        refuse_unmerged("Committing")?;
     }
 
-    let Some((first, rest)) = path.split_first() else {
-        return;
+    let head = repo.refs().head()?;
+    let merge_heads = merge_heads(repo)?;
+    let cherry_pick = cherry_pick_head(repo)?;
+    if options.amend && (!merge_heads.is_empty() || cherry_pick.is_some()) {
+        let operation = if merge_heads.is_empty() {
+            "cherry-pick"
+        } else {
+            "merge"
+        };
+        anyhow::bail!("You are in the middle of a {operation} -- cannot amend.");
+    }
+    let amended = match (&head, options.amend) {
+        (Some(head), true) => Some(repo.find_commit(head)?),
+        (None, true) => anyhow::bail!("You have nothing to amend."),
+        (_, false) => None,
     };
+    let parents = match &amended {
+        Some(amended) => amended.parents.clone(),
+        None => head.iter().chain(&merge_heads).cloned().collect(),
+    };
+    let tree = index.write_tree(repo.odb())?;
+
+    let now = Local::now().fixed_offset();
+    let committer = signature(repo, "COMMITTER", now)?;
+    let author = author(repo, amended.as_ref(), cherry_pick.as_deref(), options, now)?;
 
-    if let Some(child_node) = node
-        .children
-        .iter_mut()
-        .find(|child| child.name == first.to_str().unwrap())
-    {
-        // childrenにディレクトリがある場合はそのまま移動
-        travel_tree(child_node, rest, children_mode, hash);
+    let (mut message, given) = initial_message(repo, amended.as_ref(), options)?;
+    let edit = options.edit.unwrap_or(!given);
+    let cleanup = cleanup_mode(repo, options)?;
+    if cleanup != Cleanup::Verbatim {
+        message = message::stripspace(&message, false);
+    }
+    message = message::add_trailers(&message, &options.trailers);
+
+    // gitと同じく、空のcommitを断る前にCOMMIT_EDITMSGを書いておく
+    let path = repo.git_dir().join("COMMIT_EDITMSG");
+    if edit {
+        let base = parents.first().map(String::as_str);
+        let mut status = Status::collect_against(repo, base, false)?;
+        status.find_renames(repo, base)?;
+        let kept = (amended.is_some() && !options.reset_author) || cherry_pick.is_some();
+        let author_date = kept.then_some(&author);
+        let mut text = message.clone();
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push_str(note(!merge_heads.is_empty(), cherry_pick.is_some()));
+        text.push('\n');
+        write_template(&mut text, cleanup, &author, author_date, &committer)?;
+        for line in long_lines(repo, &status, prefix, parents.is_empty(), Context::Template)? {
+            match line.as_str() {
+                "" => text.push_str("#\n"),
+                line if line.starts_with('\t') => writeln!(text, "#{line}")?,
+                line => writeln!(text, "# {line}")?,
+            }
+        }
+        fs::write(&path, text)?;
     } else {
-        // ない場合は作成して追加して移動
-        let new_node = Node {
-            r#type: NodeType::Tree,
-            mode: 0o04_0000,
-            name: first.to_str().unwrap().to_string(),
-            hash: String::new(),
-            children: Vec::new(),
-        };
-        node.children.push(new_node);
-        let new_node = node.children.last_mut().unwrap();
-        travel_tree(new_node, rest, children_mode, hash);
+        fs::write(&path, &message)?;
     }
-}
 
-fn construct_tree(index_tree: &mut Node, file_path: &str, mode: u32, hash: String) {
-    let path = Path::new(file_path);
-    let path_vec: Vec<_> = path.iter().collect();
+    // 止まったpickを片付けているcommandの名前。rebase中ならrebaseとして扱う
+    let picking = match (&cherry_pick, rebase::is_active(repo)) {
+        (None, _) => None,
+        (Some(_), true) => Some("rebase"),
+        (Some(_), false) => Some("cherry-pick"),
+    };
+
+    // マージでなければ、親とtreeが同じcommitは作らない
+    if !options.allow_empty && parents.len() <= 1 && is_empty(repo, &parents, &tree)? {
+        refuse_empty(repo, prefix, amended.is_some(), picking, parents.is_empty())?;
+        return Ok(false);
+    }
 
-    travel_tree(index_tree, &path_vec, mode, hash);
+    if edit {
+        if !run_editor(repo, &path)? {
+            return Ok(false);
+        }
+        message = fs::read_to_string(&path)?;
+    }
+    let message = message::cleanup(&message, cleanup, edit);
+    if message.trim().is_empty() {
+        eprintln!("Aborting commit due to empty commit message.");
+        return Ok(false);
+    }
+    let kind = reflog_kind(amended.is_some(), parents.len(), picking);
+    let subject = message.lines().next().unwrap_or_default();
+    let reflog_message = reflog_message(options, kind, subject);
+    let commit_hash = write(repo, tree, parents, author, committer.clone(), message)?;
+    repo.refs()
+        .update_head_logged(&commit_hash, &committer, &reflog_message)?;
+    reset::clear_state(repo)?;
+    Ok(true)
 }
 
-fn decode_index_entry(entry: &[u8], index_tree: &mut Node) -> Result<usize, std::str::Utf8Error> {
-    let mode = BigEndian::read_u32(&entry[24..28]);
-    let hash = hex::encode(&entry[40..60]);
-    let file_name_size = BigEndian::read_u16(&entry[60..62]);
-    let file_path_end_byte = (62 + file_name_size) as usize;
-    let file_path = std::str::from_utf8(&entry[62..file_path_end_byte])?;
+// --cleanupの指定がなければcommit.cleanupに従う
+fn cleanup_mode(repo: &Repository, options: &Options) -> anyhow::Result<Cleanup> {
+    if let Some(cleanup) = options.cleanup {
+        return Ok(cleanup);
+    }
+    match repo.config().get("commit.cleanup") {
+        Some(name) => {
+            Cleanup::parse(name).ok_or_else(|| anyhow::anyhow!("Invalid cleanup mode {name}"))
+        }
+        None => Ok(Cleanup::Default),
+    }
+}
 
-    construct_tree(index_tree, file_path, mode, hash);
+/// The commits being merged into `HEAD`, listed in `MERGE_HEAD`.
+pub fn merge_heads(repo: &Repository) -> anyhow::Result<Vec<String>> {
+    match fs::read_to_string(repo.git_dir().join("MERGE_HEAD")) {
+        Ok(text) => Ok(text.split_whitespace().map(ToString::to_string).collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
 
-    let padding = 4 - (file_path_end_byte % 4);
-    let next_byte = file_path_end_byte + padding;
-    Ok(next_byte)
+// 指定があればその作者を、amendなら元のcommitの、cherry-pickなら取り出したcommitの作者を引き継ぐ
+fn author(
+    repo: &Repository,
+    amended: Option<&Commit>,
+    cherry_pick: Option<&str>,
+    options: &Options,
+    now: DateTime<FixedOffset>,
+) -> anyhow::Result<Sign> {
+    if let Some(author) = &options.author {
+        return Ok(author.clone());
+    }
+    match (amended, cherry_pick) {
+        (Some(amended), _) if !options.reset_author => Ok(amended.author.clone()),
+        (None, Some(picked)) => Ok(repo.find_commit(picked)?.author),
+        _ => signature(repo, "AUTHOR", now),
+    }
 }
 
-fn decode_index_file(index_tree: &mut Node) -> anyhow::Result<()> {
-    let mut file = File::open(".git/index")?;
-    let mut content = Vec::new();
-    file.read_to_end(&mut content)?;
+// gitと同じく、GIT_REFLOG_ACTIONがあれば"commit"の代わりにそれを使う
+fn reflog_message(options: &Options, kind: &str, subject: &str) -> String {
+    let action = options
+        .reflog_action
+        .clone()
+        .or_else(|| env::var("GIT_REFLOG_ACTION").ok());
+    match action {
+        Some(action) if !action.is_empty() => format!("{action}: {subject}"),
+        _ => format!("commit{kind}: {subject}"),
+    }
+}
 
-    // entriesを上から1 entryずつ消費していく
-    let entry_count = BigEndian::read_u32(&content[8..12]);
-    let mut entries = &content[12..];
-    for _ in 0..entry_count {
-        let next_byte = decode_index_entry(entries, index_tree)?;
-        entries = &entries[next_byte..];
+// reflogで"commit"に添える種類
+fn reflog_kind(amend: bool, parents: usize, picking: Option<&str>) -> &'static str {
+    if amend {
+        " (amend)"
+    } else if parents == 0 {
+        " (initial)"
+    } else if parents > 1 {
+        " (merge)"
+    } else {
+        match picking {
+            Some("rebase") => " (rebase)",
+            Some(_) => " (cherry-pick)",
+            None => "",
+        }
     }
-    Ok(())
 }
 
-fn generate_tree_object(node: &Node) -> anyhow::Result<String> {
-    // データの準備
-    let mut contents: Vec<u8> = Vec::new();
-    for child in &node.children {
-        let mode_and_type = format!("{:0>6o} ", child.mode).as_bytes().to_vec();
-        let pre_hash: Vec<u8> = child.hash[..].into();
-        let hash: Vec<u8> = hex::decode(pre_hash)?;
-        let path_name = format!("{}\0", child.name)[..].as_bytes().to_vec();
+// マージやcherry-pickの途中なら、エディタに出す注意書き
+fn note(merging: bool, cherry_pick: bool) -> &'static str {
+    if merging {
+        MERGE_NOTE
+    } else if cherry_pick {
+        CHERRY_PICK_NOTE
+    } else {
+        ""
+    }
+}
 
-        contents.extend(mode_and_type.clone());
-        contents.extend(path_name.clone());
-        contents.extend(hash.clone());
+// cherry-pickが衝突などで止まっていれば、そのcommit
+fn cherry_pick_head(repo: &Repository) -> anyhow::Result<Option<String>> {
+    match fs::read_to_string(repo.git_dir().join("CHERRY_PICK_HEAD")) {
+        Ok(text) => Ok(Some(text.trim().to_string())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
     }
+}
 
-    let file_length = contents.len();
-    let header = format!("tree {file_length}\0");
-    let header_vec: Vec<u8> = header.as_bytes().to_vec();
+/// Fail with git's advice for resolving conflicts before `action`, like
+/// `Committing`.
+pub fn refuse_unmerged(action: &str) -> anyhow::Result<()> {
+    advise_unmerged(action);
+    anyhow::bail!("Exiting because of an unresolved conflict.");
+}
+
+/// Tell that `action` needs the conflicts resolved first, with git's
+/// advice for it.
+pub fn advise_unmerged(action: &str) {
+    eprintln!(
+        "error: {action} is not possible because you have unmerged files.\n\
+         hint: Fix them up in the work tree, and then use 'git add/rm <file>'\n\
+         hint: as appropriate to mark resolution and make a commit."
+    );
+}
 
-    let full_contents = [&header_vec[..], &contents[..]].concat();
-    let hash = util::compress::hash(&full_contents);
+fn is_empty(repo: &Repository, parents: &[String], tree: &str) -> anyhow::Result<bool> {
+    let parent_tree = match parents.first() {
+        Some(parent) => repo.find_commit(parent)?.tree,
+        None => ObjectDatabase::hash(Kind::Tree, b""),
+    };
+    Ok(parent_tree == tree)
+}
+
+// 何もcommitするものがないことを、gitと同じくstatusで伝える
+fn refuse_empty(
+    repo: &Repository,
+    prefix: &Path,
+    amend: bool,
+    picking: Option<&str>,
+    initial: bool,
+) -> anyhow::Result<()> {
+    if amend {
+        eprintln!(
+            "You asked to amend the most recent commit, but doing so would make\n\
+             it empty. You can repeat your command with --allow-empty, or you can\n\
+             remove the commit entirely with \"git reset HEAD^\"."
+        );
+        println!("{}\nNo changes", branch_line(repo)?);
+    } else {
+        let status = Status::collect(repo, false)?;
+        for line in long_lines(repo, &status, prefix, initial, Context::Commit)? {
+            println!("{line}");
+        }
+    }
+    if let Some(command) = picking {
+        eprint!(
+            "The previous cherry-pick is now empty, possibly due to conflict resolution.\n\
+             If you wish to commit it anyway, use:\n\n    git commit --allow-empty\n\n\
+             Otherwise, please use 'git {command} --skip'\n"
+        );
+    }
+    Ok(())
+}
 
-    // ファイルの準備
-    let file_directory = format!(".git/objects/{}", &hash[0..2]);
-    let file_path = format!("{}/{}", file_directory, &hash[2..]);
-    std::fs::create_dir_all(file_directory)?;
-    let mut file = File::create(file_path)?;
+// gitと同じく、同時に使えないoptionを断る
+fn check_options(options: &Options) -> anyhow::Result<()> {
+    let conflicts = [
+        (
+            "-m",
+            !options.messages.is_empty(),
+            "-F",
+            options.file.is_some(),
+        ),
+        (
+            "--squash",
+            options.squash.is_some(),
+            "--fixup",
+            options.fixup.is_some(),
+        ),
+        (
+            "-F",
+            options.file.is_some(),
+            "--fixup",
+            options.fixup.is_some(),
+        ),
+    ];
+    for (a, a_given, b, b_given) in conflicts {
+        if a_given && b_given {
+            anyhow::bail!("options '{a}' and '{b}' cannot be used together");
+        }
+    }
+    if options.reset_author && !options.amend {
+        anyhow::bail!("--reset-author can be used only with -C, -c or --amend.");
+    }
+    Ok(())
+}
 
-    // zlib圧縮
-    let compressed_contents = util::compress::with_zlib(&full_contents)?;
+// (メッセージ, メッセージが指定されたか)。指定されなければエディタで書いてもらう
+fn initial_message(
+    repo: &Repository,
+    amended: Option<&Commit>,
+    options: &Options,
+) -> anyhow::Result<(String, bool)> {
+    let given = if !options.messages.is_empty() {
+        Some(options.messages.join("\n\n") + "\n")
+    } else if let Some(file) = &options.file {
+        let mut message = String::new();
+        if file.as_os_str() == "-" {
+            io::stdin().read_to_string(&mut message)?;
+        } else {
+            message = fs::read_to_string(file).map_err(|e| {
+                anyhow::anyhow!("could not read log file '{}': {e}", file.display())
+            })?;
+        }
+        Some(message)
+    } else {
+        None
+    };
 
-    // ファイルに書き込み
-    file.write_all(&compressed_contents)?;
+    if let Some(fixup) = &options.fixup {
+        let message = format!("fixup! {}\n", subject(repo, fixup)?);
+        return Ok(match given {
+            Some(body) => (format!("{message}\n{body}"), true),
+            None => (message, true),
+        });
+    }
+    if let Some(squash) = &options.squash {
+        let message = format!("squash! {}\n\n", subject(repo, squash)?);
+        return Ok(match given {
+            Some(body) => (message + &body, true),
+            None => (message, false),
+        });
+    }
+    if let Some(message) = given {
+        return Ok((message, true));
+    }
+    // マージの途中ならMERGE_MSGを使う
+    let message = match amended {
+        Some(amended) => amended.message.clone(),
+        None => match fs::read_to_string(repo.git_dir().join("MERGE_MSG")) {
+            Ok(message) => message,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        },
+    };
+    Ok((message, false))
+}
 
-    Ok(hash)
+fn subject(repo: &Repository, spec: &str) -> anyhow::Result<String> {
+    let commit = repo.find_commit(&revision::resolve_commit(repo, spec)?)?;
+    Ok(commit
+        .message
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string())
 }
 
-fn generate_tree_objects(index_tree: &mut Node) -> anyhow::Result<()> {
-    // childrenを左から探索していく深さ優先探索
-    for child in &mut index_tree.children {
-        if child.r#type == NodeType::Blob {
-            continue;
+// エディタで開くCOMMIT_EDITMSGの説明と、作者が自分でない場合や日付を引き継ぐ場合の注記
+fn write_template(
+    text: &mut String,
+    cleanup: Cleanup,
+    author: &Sign,
+    author_date: Option<&Sign>,
+    committer: &Sign,
+) -> fmt::Result {
+    text.push_str(match cleanup.resolve(true) {
+        Cleanup::Strip => {
+            "# Please enter the commit message for your changes. Lines starting\n\
+             # with '#' will be ignored, and an empty message aborts the commit.\n"
+        }
+        Cleanup::Scissors => "",
+        _ => {
+            "# Please enter the commit message for your changes. Lines starting\n\
+             # with '#' will be kept; you may remove them yourself if you want to.\n\
+             # An empty message aborts the commit.\n"
         }
-        generate_tree_objects(child)?;
+    });
+    if cleanup.resolve(true) == Cleanup::Scissors {
+        text.push_str(SCISSORS);
+        text.push_str(
+            "\n# Do not modify or remove the line above.\n\
+             # Everything below it will be ignored.\n",
+        );
+    }
+    text.push_str("#\n");
+    let other_author = author.name != committer.name || author.email != committer.email;
+    if other_author {
+        writeln!(text, "# Author:    {} <{}>", author.name, author.email)?;
+    }
+    if let Some(author) = author_date {
+        writeln!(
+            text,
+            "# Date:      {}",
+            author.time_stamp.format("%a %b %-d %H:%M:%S %Y %z")
+        )?;
+    }
+    if other_author || author_date.is_some() {
+        text.push_str("#\n");
     }
-    let hash = generate_tree_object(index_tree)?;
-    index_tree.hash = hash;
     Ok(())
 }
 
-fn generate_commit_object(tree_hash: String, message: String) -> anyhow::Result<String> {
-    let parent = util::path::get_head_commit_hash();
-    let now = Local::now();
+/// Open `path` in the editor from `GIT_EDITOR`, `core.editor`, `VISUAL` or
+/// `EDITOR`, or `vi`, run through the shell. Returns `false` after saying
+/// so when the editor fails.
+pub fn run_editor(repo: &Repository, path: &Path) -> anyhow::Result<bool> {
+    let editor = env::var("GIT_EDITOR")
+        .ok()
+        .or_else(|| repo.config().get("core.editor").map(ToString::to_string))
+        .or_else(|| env::var("VISUAL").ok())
+        .or_else(|| env::var("EDITOR").ok())
+        .unwrap_or_else(|| "vi".to_string());
+    let status = process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$@\""))
+        .arg(&editor)
+        .arg(path)
+        .status()?;
+    if !status.success() {
+        eprintln!("error: There was a problem with the editor '{editor}'.");
+        eprintln!("Please supply the message using either -m or -F option.");
+    }
+    Ok(status.success())
+}
 
-    let commit = Commit {
-        hash: String::new(),
-        size: 0,
-        tree: tree_hash,
-        parents: match parent {
-            Some(parent) => vec![parent],
-            None => vec![],
-        },
-        author: Sign {
-            name: "mehm8128".to_string(),
-            email: "mehm8128@example.com".to_string(),
-            time_stamp: now,
-        },
-        commiter: Sign {
-            name: "mehm8128".to_string(),
-            email: "mehm8128@example.com".to_string(),
-            time_stamp: now,
-        },
-        message,
+// 環境変数 > user.name/user.email の順に名前とメールアドレスを決める
+fn signature(repo: &Repository, role: &str, now: DateTime<FixedOffset>) -> anyhow::Result<Sign> {
+    let name = env::var(format!("GIT_{role}_NAME"))
+        .ok()
+        .or_else(|| repo.config().get("user.name").map(ToString::to_string));
+    let email = env::var(format!("GIT_{role}_EMAIL"))
+        .ok()
+        .or_else(|| repo.config().get("user.email").map(ToString::to_string));
+    let (Some(name), Some(email)) = (name, email) else {
+        anyhow::bail!(
+            "{} identity unknown\n\n*** Please tell me who you are.\n\n\
+             Run\n\n  git config user.email \"you@example.com\"\n  \
+             git config user.name \"Your Name\"",
+            if role == "AUTHOR" {
+                "Author"
+            } else {
+                "Committer"
+            }
+        );
+    };
+    let time_stamp = match env::var(format!("GIT_{role}_DATE")) {
+        Ok(date) => {
+            parse_date(&date).ok_or_else(|| anyhow::anyhow!("invalid date format: {date}"))?
+        }
+        Err(_) => now,
     };
+    Ok(Sign {
+        name,
+        email,
+        time_stamp,
+    })
+}
+
+// "[@]<秒> <タイムゾーン>"、RFC 2822、ISO 8601のいずれか
+fn parse_date(date: &str) -> Option<DateTime<FixedOffset>> {
+    let date = date.trim();
+    let raw = date.strip_prefix('@').unwrap_or(date);
+    if let Some(sign) = Sign::parse(&format!("<> {raw}")) {
+        return Some(sign.time_stamp);
+    }
+    DateTime::parse_from_rfc2822(date)
+        .or_else(|_| DateTime::parse_from_rfc3339(date))
+        .or_else(|_| DateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S %z"))
+        .ok()
+}
+
+/// Write a commit object of `tree` with the author and committer taken from
+/// the environment or the configuration, and return its hash.
+pub fn create(
+    repo: &Repository,
+    tree: String,
+    parents: Vec<String>,
+    message: String,
+) -> anyhow::Result<String> {
+    let now = Local::now().fixed_offset();
+    let author = signature(repo, "AUTHOR", now)?;
+    let committer = signature(repo, "COMMITTER", now)?;
+    write(repo, tree, parents, author, committer, message)
+}
+
+/// Like [`create`], but keeping `author`, as for a commit picked from
+/// elsewhere.
+pub fn create_authored(
+    repo: &Repository,
+    tree: String,
+    parents: Vec<String>,
+    author: Sign,
+    message: String,
+) -> anyhow::Result<String> {
+    let committer = signature(repo, "COMMITTER", Local::now().fixed_offset())?;
+    write(repo, tree, parents, author, committer, message)
+}
 
-    let Commit {
+/// The identity recorded in reflogs for updates made now.
+pub fn committer(repo: &Repository) -> anyhow::Result<Sign> {
+    signature(repo, "COMMITTER", Local::now().fixed_offset())
+}
+
+fn write(
+    repo: &Repository,
+    tree: String,
+    parents: Vec<String>,
+    author: Sign,
+    committer: Sign,
+    message: String,
+) -> anyhow::Result<String> {
+    let commit = Commit {
         tree,
         parents,
         author,
-        commiter,
+        committer,
+        extra_headers: Vec::new(),
         message,
-        ..
-    } = &commit;
-    let parents: String = parents.iter().map(|p| format!("\nparent {p}")).join("");
-
-    let content = indoc::formatdoc! {r#"
-        tree {tree}{parents}
-        author {author}
-        commiter {commiter}
-
-        {message}
-    "#};
-
-    let header = format!("commit {}\0", content.len());
-    let content = format!("{header}{content}");
-    let commit_hash = util::compress::hash(content.as_bytes());
-
-    let file_directory = format!(".git/objects/{}", &commit_hash[0..2]);
-    let file_path = format!("{}/{}", file_directory, &commit_hash[2..]);
-    // TODO: create_nested_fileでいい気がする
-    std::fs::create_dir(file_directory).or_else(|e| match e.kind() {
-        ErrorKind::AlreadyExists => Ok(()),
-        _ => Err(e),
-    })?;
-    let mut file = File::create(file_path)?;
-
-    // zlib圧縮
-    let compressed_contents = util::compress::with_zlib(content.as_bytes())?;
-    file.write_all(&compressed_contents)?;
-
-    Ok(commit_hash)
-}
-
-fn update_head(commit_hash: &str) -> std::io::Result<()> {
-    let head_ref = util::path::get_head_ref();
-    let mut file = File::create(head_ref)?;
-    file.write_all(commit_hash.as_bytes())?;
-    Ok(())
+    };
+    Ok(repo.write_object(&Object::Commit(commit))?)
 }
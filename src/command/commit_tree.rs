@@ -0,0 +1,38 @@
+use std::io::{self, Read};
+
+use git::object::Kind;
+use git::revision;
+use git::Repository;
+
+use super::commit;
+
+/// Write a commit of `tree` with `parents` and print its hash.
+///
+/// Each of `messages` becomes a paragraph; without any, the message is read
+/// from standard input as is.
+pub fn commit_tree(
+    repo: &Repository,
+    tree: &str,
+    parents: &[String],
+    messages: &[String],
+) -> anyhow::Result<()> {
+    let tree = revision::peel(repo, &revision::resolve(repo, tree)?, Kind::Tree)?;
+    let parents = parents
+        .iter()
+        .map(|parent| revision::resolve_commit(repo, parent))
+        .collect::<git::Result<Vec<_>>>()?;
+    let message = if messages.is_empty() {
+        let mut message = String::new();
+        io::stdin().read_to_string(&mut message)?;
+        message
+    } else {
+        // -mごとに段落を分ける
+        messages
+            .iter()
+            .map(|m| format!("{m}\n"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    println!("{}", commit::create(repo, tree, parents, message)?);
+    Ok(())
+}
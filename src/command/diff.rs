@@ -0,0 +1,422 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use git::diff::patch::{self, FilePair, Side, WordDiff};
+use git::diff::tree::{self, Change};
+use git::diff::{combined, rename, stat, Algorithm};
+use git::index::Index;
+use git::object::tree::GITLINK_MODE;
+use git::pathspec::Pathspec;
+use git::revision;
+use git::worktree::Worktree;
+use git::Repository;
+
+/// What `diff` prints.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Patch,
+    Stat,
+    Numstat,
+    NameOnly,
+    NameStatus,
+}
+
+/// Flags of `diff`.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `--cached`: compare the index instead of the working tree.
+    pub cached: bool,
+    pub format: Format,
+    /// `-U<n>`: lines of context.
+    pub context: Option<usize>,
+    pub algorithm: Option<Algorithm>,
+    pub word_diff: Option<WordDiff>,
+    /// `--color[=<when>]`
+    pub color: Option<String>,
+    /// `-M`/`-C`: how to look for renames instead of `diff.renames`.
+    pub renames: Option<rename::Options>,
+    pub no_renames: bool,
+    /// `-1`/`--base`, `-2`/`--ours`, `-3`/`--theirs`: the stage unmerged
+    /// files of the working tree are compared with.
+    pub stage: Option<u8>,
+    /// `--cc`: show unmerged files of the working tree as combined diffs
+    /// against our and their versions, the default for patches.
+    pub combined: bool,
+}
+
+// 比べる側: path -> (mode, oid)
+type Snapshot = BTreeMap<String, (u32, String)>;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Odb,
+    Worktree,
+}
+
+/// Show changes between the working tree, the index and commits.
+///
+/// Leading `args` that name revisions select what is compared; the rest
+/// are pathspecs relative to `prefix`.
+pub fn diff(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<()> {
+    let mut revisions = Vec::new();
+    let mut rest = args;
+    while let Some((arg, tail)) = rest.split_first() {
+        match arg.split_once("..") {
+            Some((from, to)) if revisions.is_empty() => {
+                let head = |rev: &str| if rev.is_empty() { "HEAD" } else { rev }.to_string();
+                revisions.push(revision::resolve_commit(repo, &head(from))?);
+                revisions.push(revision::resolve_commit(repo, &head(to))?);
+            }
+            _ => match revision::resolve_commit(repo, arg) {
+                Ok(oid) => revisions.push(oid),
+                Err(_) => break,
+            },
+        }
+        rest = tail;
+    }
+    let pathspec = Pathspec::parse(rest, prefix)?;
+
+    if let [from, to] = revisions.as_slice() {
+        let pairs = tree_pairs(repo, Some(from), to, &pathspec)?;
+        return write_output(repo, &mut io::stdout().lock(), pairs, options);
+    }
+    // worktreeと比べるパッチでは、衝突しているファイルを両方の版と合成した
+    // 差分で示す。ほかではステージの1つとの差分で示す
+    let combined = options.format == Format::Patch && (options.combined || options.stage.is_none());
+    let stage = if combined {
+        None
+    } else {
+        Some(options.stage.unwrap_or(2))
+    };
+    let pairs = snapshot_pairs(repo, &revisions, options.cached, stage, &pathspec)?;
+    write_pairs(
+        repo,
+        &mut io::stdout().lock(),
+        pairs,
+        options,
+        combined && revisions.is_empty() && !options.cached,
+    )
+}
+
+/// The files that differ between the trees of `from` (none for a root
+/// commit) and `to`, limited to `pathspec`.
+pub fn tree_pairs(
+    repo: &Repository,
+    from: Option<&str>,
+    to: &str,
+    pathspec: &Pathspec,
+) -> anyhow::Result<Vec<FilePair>> {
+    let changes = tree::changes(repo, from, Some(to))?;
+    load_pairs(repo, changes, (Source::Odb, Source::Odb), pathspec)
+}
+
+// -M/-Cがなければdiff.renamesに従う。既定では名前の変更を探す
+fn rename_options(repo: &Repository, options: &Options) -> Option<rename::Options> {
+    if options.no_renames {
+        return None;
+    }
+    if options.renames.is_some() {
+        return options.renames;
+    }
+    match repo.config().get("diff.renames") {
+        Some("copies" | "copy") => Some(rename::Options {
+            copies: true,
+            ..rename::Options::default()
+        }),
+        Some("false" | "no" | "off" | "0") => None,
+        _ => Some(rename::Options::default()),
+    }
+}
+
+// worktreeかindexが関わる比較。衝突しているファイルはindexとworktreeの比較では
+// `stage`の版(なければ比べない)と、indexとの比較では印だけで示す
+fn snapshot_pairs(
+    repo: &Repository,
+    revisions: &[String],
+    cached: bool,
+    stage: Option<u8>,
+    pathspec: &Pathspec,
+) -> anyhow::Result<Vec<FilePair>> {
+    let index = repo.index()?;
+    let unmerged = unmerged_paths(&index);
+    let tree = |commit: Option<&str>| -> anyhow::Result<Snapshot> {
+        let mut snapshot = tree_snapshot(repo, commit)?;
+        snapshot.retain(|path, _| !unmerged.contains(path));
+        Ok(snapshot)
+    };
+    let (old, new) = match (revisions, cached) {
+        ([], false) => {
+            let mut old = index_snapshot(&index);
+            for entry in index.entries() {
+                if Some(entry.stage) == stage && entry.stage != 0 {
+                    old.insert(entry.path.clone(), (entry.mode, entry.oid.clone()));
+                }
+            }
+            let mut new = worktree_snapshot(repo, &index)?;
+            new.retain(|path, _| !unmerged.contains(path) || old.contains_key(path));
+            ((old, Source::Odb), (new, Source::Worktree))
+        }
+        ([], true) => {
+            let head = repo.refs().head()?;
+            (
+                (tree(head.as_deref())?, Source::Odb),
+                (index_snapshot(&index), Source::Odb),
+            )
+        }
+        ([commit], false) => (
+            (tree_snapshot(repo, Some(commit))?, Source::Odb),
+            (worktree_snapshot(repo, &index)?, Source::Worktree),
+        ),
+        ([commit], true) => (
+            (tree(Some(commit))?, Source::Odb),
+            (index_snapshot(&index), Source::Odb),
+        ),
+        _ => anyhow::bail!("usage: git diff [<options>] [<commit> [<commit>]] [--] [<path>...]"),
+    };
+
+    let paths: BTreeSet<&String> = old.0.keys().chain(new.0.keys()).collect();
+    let changes = paths
+        .into_iter()
+        .filter(|path| old.0.get(*path) != new.0.get(*path))
+        .map(|path| Change {
+            path: path.clone(),
+            old: old.0.get(path).cloned(),
+            new: new.0.get(path).cloned(),
+        })
+        .collect();
+    let mut pairs = load_pairs(repo, changes, (old.1, new.1), pathspec)?;
+    if revisions.is_empty() || cached {
+        // 印は同じパスの差分より前に置く
+        let mut marked: Vec<FilePair> = unmerged
+            .iter()
+            .filter(|path| pathspec.matches(path))
+            .map(|path| FilePair::unmerged(path))
+            .collect();
+        marked.append(&mut pairs);
+        marked.sort_by(|a, b| a.path().cmp(b.path()));
+        pairs = marked;
+    }
+    Ok(pairs)
+}
+
+// 1から3のステージにentryがあるパス
+fn unmerged_paths(index: &Index) -> BTreeSet<String> {
+    index
+        .entries()
+        .iter()
+        .filter(|e| e.stage != 0)
+        .map(|e| e.path.clone())
+        .collect()
+}
+
+/// Look for renames in `pairs` and write them to `out` in the format of
+/// `options`.
+pub fn write_output(
+    repo: &Repository,
+    out: &mut impl Write,
+    pairs: Vec<FilePair>,
+    options: &Options,
+) -> anyhow::Result<()> {
+    write_pairs(repo, out, pairs, options, false)
+}
+
+// `combined`なら、衝突しているファイルの印の代わりに合成された差分を書く
+fn write_pairs(
+    repo: &Repository,
+    out: &mut impl Write,
+    pairs: Vec<FilePair>,
+    options: &Options,
+    combined: bool,
+) -> anyhow::Result<()> {
+    let config = repo.config();
+    let (pairs, needed) = match rename_options(repo, options) {
+        Some(mut renames) => {
+            if let Some(limit) = config.get("diff.renameLimit").and_then(|n| n.parse().ok()) {
+                renames.limit = limit;
+            }
+            rename::detect_limited(pairs, renames)
+        }
+        None => (pairs, None),
+    };
+    let algorithm = options
+        .algorithm
+        .or_else(|| config.get("diff.algorithm").and_then(Algorithm::parse))
+        .unwrap_or_default();
+    let color = super::use_color(repo, options.color.as_deref(), "diff");
+    match options.format {
+        Format::Patch => {
+            let patch_options = patch::Options {
+                context: options
+                    .context
+                    .or_else(|| config.get("diff.context").and_then(|c| c.parse().ok()))
+                    .unwrap_or(3),
+                algorithm,
+                word_diff: options.word_diff,
+                color,
+            };
+            // gitと同じく、合成された差分をほかのパッチより先に書く
+            let mut rest = Vec::new();
+            for pair in &pairs {
+                if !(pair.unmerged
+                    && combined
+                    && write_combined(repo, out, pair.path(), &patch_options)?)
+                {
+                    rest.push(pair);
+                }
+            }
+            for pair in rest {
+                patch::write(out, pair, &patch_options)?;
+            }
+        }
+        Format::Stat => stat::write(out, &pairs, algorithm, color)?,
+        Format::Numstat => stat::write_numstat(out, &pairs, algorithm)?,
+        Format::NameOnly => {
+            for pair in &pairs {
+                writeln!(out, "{}", pair.path())?;
+            }
+        }
+        Format::NameStatus => {
+            for pair in &pairs {
+                if pair.similarity.is_some() {
+                    write!(out, "{}\t{}", pair.status(), pair.old_path())?;
+                } else {
+                    write!(out, "{}", pair.status())?;
+                }
+                writeln!(out, "\t{}", pair.path())?;
+            }
+        }
+    }
+    // gitと同じく、差分を書いてから知らせる
+    if let Some(needed) = needed {
+        out.flush()?;
+        eprintln!("warning: exhaustive rename detection was skipped due to too many files.");
+        eprintln!(
+            "warning: you may want to set your diff.renameLimit variable to at least {needed} and retry the command."
+        );
+    }
+    Ok(())
+}
+
+// 衝突しているファイルを、ourとtheirの版とworktreeのファイルから合成した差分。
+// どちらかの版がなければ書かずにfalseを返す
+fn write_combined(
+    repo: &Repository,
+    out: &mut impl Write,
+    path: &str,
+    options: &patch::Options,
+) -> anyhow::Result<bool> {
+    let index = repo.index()?;
+    let mut parents = Vec::new();
+    for stage in [2, 3] {
+        let Some(entry) = index.get(path, stage) else {
+            return Ok(false);
+        };
+        let data = repo.find_blob(&entry.oid)?;
+        parents.push(Side::new(path, entry.mode, entry.oid.clone(), data));
+    }
+    let null = "0".repeat(40);
+    let files = Worktree::new(repo)?;
+    let result = match fs::symlink_metadata(repo.require_work_tree()?.join(path)) {
+        Ok(metadata) => Side::new(
+            path,
+            files.mode(&metadata, None),
+            null,
+            files.read(path, &metadata)?,
+        ),
+        Err(_) => Side::new(path, 0, null, Vec::new()),
+    };
+    combined::write(out, &parents, &result, options)?;
+    Ok(true)
+}
+
+fn tree_snapshot(repo: &Repository, tree: Option<&str>) -> anyhow::Result<Snapshot> {
+    let Some(tree) = tree else {
+        return Ok(Snapshot::new());
+    };
+    Ok(repo
+        .tree_files(tree)?
+        .into_iter()
+        .map(|e| (e.name, (e.mode, e.oid)))
+        .collect())
+}
+
+// -Nで追加したentryはindexにはまだないものとして扱う
+fn index_snapshot(index: &Index) -> Snapshot {
+    index
+        .entries()
+        .iter()
+        .filter(|e| e.stage == 0 && !e.intent_to_add)
+        .map(|e| (e.path.clone(), (e.mode, e.oid.clone())))
+        .collect()
+}
+
+// 追跡されているファイルのworktreeでの状態。変わっていなければindexの値を使う。
+// 衝突しているファイルは常にハッシュを計算する
+fn worktree_snapshot(repo: &Repository, index: &Index) -> anyhow::Result<Snapshot> {
+    let files = Worktree::new(repo)?;
+    let work_tree = repo.require_work_tree()?;
+    let mut snapshot = Snapshot::new();
+    for entry in index.entries() {
+        if snapshot.contains_key(&entry.path) {
+            continue;
+        }
+        let Ok(metadata) = fs::symlink_metadata(work_tree.join(&entry.path)) else {
+            continue;
+        };
+        if metadata.is_dir() && entry.mode != GITLINK_MODE {
+            continue;
+        }
+        let unchanged = entry.stage == 0 && !entry.intent_to_add;
+        let value = if unchanged && !files.is_modified(entry, &metadata)? {
+            (entry.mode, entry.oid.clone())
+        } else {
+            (
+                files.mode(&metadata, Some(entry.mode)),
+                files.hash(&entry.path, &metadata, false)?,
+            )
+        };
+        snapshot.insert(entry.path.clone(), value);
+    }
+    Ok(snapshot)
+}
+
+fn load_pairs(
+    repo: &Repository,
+    changes: Vec<Change>,
+    (old_source, new_source): (Source, Source),
+    pathspec: &Pathspec,
+) -> anyhow::Result<Vec<FilePair>> {
+    let files = Worktree::new(repo).ok();
+    let load = |path: &str, (mode, oid): (u32, String), source| -> anyhow::Result<Side> {
+        let data = match (source, &files) {
+            _ if mode == GITLINK_MODE => Vec::new(),
+            (Source::Worktree, Some(files)) => {
+                let metadata = fs::symlink_metadata(repo.require_work_tree()?.join(path))?;
+                files.read(path, &metadata)?
+            }
+            _ => repo.find_blob(&oid)?,
+        };
+        Ok(Side::new(path, mode, oid, data))
+    };
+
+    let mut pairs = Vec::new();
+    for change in changes {
+        if !pathspec.matches(&change.path) {
+            continue;
+        }
+        let path = change.path.as_str();
+        pairs.push(FilePair::new(
+            change.old.map(|v| load(path, v, old_source)).transpose()?,
+            change.new.map(|v| load(path, v, new_source)).transpose()?,
+        ));
+    }
+    Ok(pairs)
+}
@@ -0,0 +1,38 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+use git::object::Kind;
+use git::odb::ObjectDatabase;
+use git::Repository;
+
+/// Print the blob hash of standard input (with `stdin`) and of each of
+/// `paths`, also storing the blobs with `write`.
+pub fn hash_object(
+    repo: &Repository,
+    paths: &[PathBuf],
+    stdin: bool,
+    write: bool,
+) -> anyhow::Result<()> {
+    let mut contents = Vec::new();
+    if stdin {
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data)?;
+        contents.push(data);
+    }
+    for path in paths {
+        match fs::read(path) {
+            Ok(data) => contents.push(data),
+            Err(e) => anyhow::bail!("could not open '{}' for reading: {e}", path.display()),
+        }
+    }
+    for data in contents {
+        let oid = if write {
+            repo.odb().write(Kind::Blob, &data)?
+        } else {
+            ObjectDatabase::hash(Kind::Blob, &data)
+        };
+        println!("{oid}");
+    }
+    Ok(())
+}
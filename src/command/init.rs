@@ -1,13 +1,27 @@
-use crate::util;
 use std::fs;
-use std::fs::File;
-use std::io::{Result, Write};
+use std::path::Path;
 
-pub fn init() -> Result<()> {
-    fs::create_dir(".git")?;
-    fs::create_dir(".git/objects")?;
-    util::path::create_nested_file(".git/refs/heads/main")?;
-    let mut file = File::create(".git/HEAD")?;
-    file.write_all(b"ref: refs/heads/main\n")?;
+use git::repository::{InitOptions, Repository, SharedMode};
+
+pub fn init(path: &Path, options: &InitOptions) -> anyhow::Result<()> {
+    let (repo, reinit) = Repository::init(path, options)?;
+
+    if reinit {
+        if let Some(branch) = &options.initial_branch {
+            eprintln!("warning: re-init: ignored --initial-branch={branch}");
+        }
+    }
+    let git_dir = fs::canonicalize(repo.git_dir())?;
+    let state = if reinit {
+        "Reinitialized existing"
+    } else {
+        "Initialized empty"
+    };
+    let shared = if options.shared.is_some_and(|s| s != SharedMode::Umask) {
+        "shared "
+    } else {
+        ""
+    };
+    println!("{state} {shared}Git repository in {}/", git_dir.display());
     Ok(())
 }
@@ -0,0 +1,66 @@
+use std::io::{self, Write};
+
+use itertools::Itertools;
+
+use git::object::commit::Commit;
+use git::revwalk::RevWalk;
+use git::Repository;
+
+pub fn log(repo: &Repository) -> anyhow::Result<()> {
+    let Some(head) = repo.refs().head()? else {
+        let branch = repo.refs().current_branch()?.unwrap_or_default();
+        anyhow::bail!(
+            "your current branch '{}' does not have any commits yet",
+            branch.trim_start_matches("refs/heads/")
+        );
+    };
+
+    let mut out = io::stdout().lock();
+    let mut walk = RevWalk::new(repo);
+    walk.push(&head)?;
+    for (i, commit) in walk.enumerate() {
+        let (oid, commit) = commit?;
+        if i > 0 {
+            writeln!(out)?;
+        }
+        write_commit(&mut out, &oid, &commit, false)?;
+    }
+    Ok(())
+}
+
+/// Write the header and the indented message of `commit` the way `log` and
+/// `show` print them.
+pub fn write_commit(
+    out: &mut impl Write,
+    oid: &str,
+    commit: &Commit,
+    color: bool,
+) -> io::Result<()> {
+    if color {
+        writeln!(out, "\x1b[33mcommit {oid}\x1b[m")?;
+    } else {
+        writeln!(out, "commit {oid}")?;
+    }
+    if commit.parents.len() > 1 {
+        writeln!(
+            out,
+            "Merge: {}",
+            commit.parents.iter().map(|p| &p[..7]).join(" ")
+        )?;
+    }
+    writeln!(
+        out,
+        "Author: {} <{}>",
+        commit.author.name, commit.author.email
+    )?;
+    writeln!(
+        out,
+        "Date:   {}",
+        commit.author.time_stamp.format("%a %b %-d %H:%M:%S %Y %z")
+    )?;
+    writeln!(out)?;
+    for line in commit.message.lines() {
+        writeln!(out, "    {line}")?;
+    }
+    Ok(())
+}
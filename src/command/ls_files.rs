@@ -0,0 +1,111 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use git::index::Entry;
+use git::object::tree::GITLINK_MODE;
+use git::pathspec::Pathspec;
+use git::status::find_untracked;
+use git::worktree::Worktree;
+use git::Repository;
+
+use super::relative_path;
+
+/// Flags of `ls-files`.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `-c`: files in the index; the default when nothing else is asked for.
+    pub cached: bool,
+    /// `-s`: files in the index with their mode, hash and stage.
+    pub stage: bool,
+    /// `-u`: only unmerged files, in the format of `-s`.
+    pub unmerged: bool,
+    /// `-m`: files that differ from the index, including deleted ones.
+    pub modified: bool,
+    /// `-d`: files missing from the working tree.
+    pub deleted: bool,
+    /// `-o`: files not in the index.
+    pub others: bool,
+    /// `--exclude-standard`: leave out ignored files from `-o`.
+    pub exclude_standard: bool,
+}
+
+/// List files of the index and the working tree matching `paths`, relative
+/// to `prefix`.
+pub fn ls_files(
+    repo: &Repository,
+    prefix: &Path,
+    paths: &[String],
+    options: &Options,
+) -> anyhow::Result<()> {
+    // サブディレクトリからはその中だけを表示する
+    let pathspec = if paths.is_empty() && !prefix.as_os_str().is_empty() {
+        Pathspec::parse(&["."], prefix)?
+    } else {
+        Pathspec::parse(paths, prefix)?
+    };
+    let index = repo.index()?;
+    let mut out = io::stdout().lock();
+    let name = |path: &str| relative_path(path, prefix);
+    // -sではmode、hash、stageも書く
+    let write_entry = |out: &mut dyn Write, entry: &Entry| -> io::Result<()> {
+        if options.stage || options.unmerged {
+            write!(out, "{:o} {} {}\t", entry.mode, entry.oid, entry.stage)?;
+        }
+        writeln!(out, "{}", name(&entry.path))
+    };
+
+    if options.others {
+        let found = find_untracked(repo, &index, false, !options.exclude_standard)?;
+        let mut others = found.untracked;
+        others.extend(found.ignored);
+        others.sort();
+        for path in others.iter().filter(|path| pathspec.matches(path)) {
+            writeln!(out, "{}", name(path))?;
+        }
+    }
+
+    let nothing_else = !(options.stage
+        || options.unmerged
+        || options.modified
+        || options.deleted
+        || options.others);
+    let show_cached = options.cached || options.stage || nothing_else;
+    let files = Worktree::new(repo)?;
+    // entryごとにindexの内容、消えているか、変更されているかの順に書く
+    for entry in index.entries() {
+        if !pathspec.matches(&entry.path) {
+            continue;
+        }
+        // -uでは-cでも衝突していないentryは書かない
+        let listed = if options.unmerged {
+            entry.stage != 0
+        } else {
+            show_cached
+        };
+        if listed {
+            write_entry(&mut out, entry)?;
+        }
+        if options.deleted || options.modified {
+            let (deleted, modified) = check(repo, &files, entry)?;
+            if deleted && options.deleted {
+                write_entry(&mut out, entry)?;
+            }
+            if modified && options.modified {
+                write_entry(&mut out, entry)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// (消えているか, 変更されているか)。消えたファイルも変更されたものとする
+fn check(repo: &Repository, files: &Worktree, entry: &Entry) -> anyhow::Result<(bool, bool)> {
+    let path = repo.require_work_tree()?.join(&entry.path);
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() && entry.mode != GITLINK_MODE => Ok((true, true)),
+        Ok(metadata) => Ok((false, files.is_modified(entry, &metadata)?)),
+        Err(_) => Ok((true, true)),
+    }
+}
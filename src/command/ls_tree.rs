@@ -0,0 +1,120 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use git::object::tree::{Entry, GITLINK_MODE};
+use git::object::Kind;
+use git::revision;
+use git::Repository;
+
+use super::relative_path;
+
+/// Flags of `ls-tree`.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `-r`: recurse into subtrees.
+    pub recursive: bool,
+    /// `-t`: show trees even when recursing into them.
+    pub trees: bool,
+    /// `-l`: show the size of blobs.
+    pub long: bool,
+    pub name_only: bool,
+}
+
+/// List the entries of `tree_ish`, limited to `paths`, given relative to
+/// `prefix`.
+///
+/// A path names an entry itself, or with a trailing slash the entries of a
+/// directory.
+pub fn ls_tree(
+    repo: &Repository,
+    prefix: &Path,
+    tree_ish: &str,
+    paths: &[String],
+    options: &Options,
+) -> anyhow::Result<()> {
+    let tree = revision::peel(repo, &revision::resolve(repo, tree_ish)?, Kind::Tree)?;
+    let prefix_str = prefix.to_string_lossy();
+    // サブディレクトリからはその中だけを表示する
+    let mut filters: Vec<String> = paths
+        .iter()
+        .map(|path| {
+            let slash = if path.ends_with('/') { "/" } else { "" };
+            let joined = prefix.join(path.trim_end_matches('/'));
+            let joined = joined.to_string_lossy();
+            let joined = joined.trim_start_matches("./").trim_end_matches("/.");
+            format!("{}{slash}", joined.trim_end_matches('/'))
+        })
+        .collect();
+    if filters.is_empty() && !prefix_str.is_empty() {
+        filters.push(format!("{prefix_str}/"));
+    }
+    let lister = Lister {
+        repo,
+        prefix,
+        filters,
+        options,
+    };
+    let mut out = io::stdout().lock();
+    lister.list(&mut out, &tree, "")
+}
+
+struct Lister<'a> {
+    repo: &'a Repository,
+    prefix: &'a Path,
+    filters: Vec<String>,
+    options: &'a Options,
+}
+
+impl Lister<'_> {
+    fn list(&self, out: &mut impl Write, tree: &str, base: &str) -> anyhow::Result<()> {
+        for entry in self.repo.find_tree(tree)?.entries {
+            let path = format!("{base}{}", entry.name);
+            let dir = format!("{path}/");
+            // 指定されたパスがこのディレクトリの中を指しているなら中に入る
+            let inside = self.filters.iter().any(|f| f.starts_with(&dir));
+            let selected = inside
+                || self.filters.is_empty()
+                || self.filters.iter().any(|f| {
+                    let f = f.trim_end_matches('/');
+                    path == f || path.starts_with(&format!("{f}/"))
+                });
+            if !selected {
+                continue;
+            }
+            let descend = entry.is_tree() && (inside || self.options.recursive);
+            if !descend || self.options.trees {
+                self.write_entry(out, &entry, &path)?;
+            }
+            if descend {
+                self.list(out, &entry.oid, &dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_entry(&self, out: &mut impl Write, entry: &Entry, path: &str) -> anyhow::Result<()> {
+        let name = relative_path(path, self.prefix);
+        if self.options.name_only {
+            writeln!(out, "{name}")?;
+            return Ok(());
+        }
+        let kind = if entry.is_tree() {
+            Kind::Tree
+        } else if entry.mode == GITLINK_MODE {
+            Kind::Commit
+        } else {
+            Kind::Blob
+        };
+        write!(out, "{:06o} {kind} {}", entry.mode, entry.oid)?;
+        if self.options.long {
+            let size = match kind {
+                Kind::Blob => self.repo.find_blob(&entry.oid)?.len().to_string(),
+                _ => "-".to_string(),
+            };
+            write!(out, " {size:>7}")?;
+        }
+        writeln!(out, "\t{name}")?;
+        Ok(())
+    }
+}
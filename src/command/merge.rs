@@ -0,0 +1,482 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+
+use git::diff::{rename, stat, Algorithm};
+use git::index::Index;
+use git::merge::{self, ConflictStyle, Labels};
+use git::message::{self, Cleanup};
+use git::pathspec::Pathspec;
+use git::revision;
+use git::revwalk;
+use git::worktree::Worktree;
+use git::Repository;
+
+use super::{commit, diff, reset};
+
+/// Whether `merge` may fast-forward instead of creating a merge commit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FastForward {
+    /// `--ff`: fast-forward when possible.
+    #[default]
+    Allow,
+    /// `--no-ff`: always create a merge commit.
+    Never,
+    /// `--ff-only`: refuse anything but a fast-forward.
+    Only,
+}
+
+/// Flags of `merge`.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Overrides `merge.ff`.
+    pub ff: Option<FastForward>,
+    /// `-m`: paragraphs of the message of the merge commit.
+    pub messages: Vec<String>,
+    /// `-e`/`--no-edit`: whether to edit the message, which by default
+    /// happens only when no message is given and git runs in a terminal.
+    pub edit: Option<bool>,
+    /// `--abort`: give up the merge in progress.
+    pub abort: bool,
+    /// `--continue`: commit the merge in progress once conflicts are
+    /// resolved.
+    pub continue_: bool,
+}
+
+// マージ中の状態を記録するファイル
+const MERGE_HEAD: &str = "MERGE_HEAD";
+const MERGE_MSG: &str = "MERGE_MSG";
+const MERGE_MODE: &str = "MERGE_MODE";
+
+/// Merge the commit named by `args` into `HEAD`: fast-forward when `HEAD`
+/// is its ancestor, otherwise merge the trees and commit the result with
+/// both as parents.
+///
+/// When the merge has conflicts, the index gets their stages, the files
+/// their conflict markers, and `MERGE_HEAD`/`MERGE_MSG` are left for
+/// `--continue` or `commit`. Returns the exit code: 1 for conflicts and
+/// other failures that were already reported, 2 when local changes were in
+/// the way.
+pub fn merge(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<u8> {
+    if options.abort {
+        if commit::merge_heads(repo)?.is_empty() {
+            anyhow::bail!("There is no merge to abort (MERGE_HEAD missing).");
+        }
+        let reset = reset::Options {
+            mode: Some(reset::Mode::Merge),
+            quiet: true,
+        };
+        reset::reset(repo, prefix, &[], &[], &reset)?;
+        return Ok(0);
+    }
+    if options.continue_ {
+        if commit::merge_heads(repo)?.is_empty() {
+            anyhow::bail!("There is no merge in progress (MERGE_HEAD missing).");
+        }
+        let committed = commit::commit(repo, prefix, &commit::Options::default())?;
+        return Ok(u8::from(!committed));
+    }
+
+    if repo.index()?.entries().iter().any(|e| e.stage != 0) {
+        commit::refuse_unmerged("Merging")?;
+    }
+    if !commit::merge_heads(repo)?.is_empty() {
+        anyhow::bail!(
+            "You have not concluded your merge (MERGE_HEAD exists).\n\
+             Please, commit your changes before you merge."
+        );
+    }
+    let name = match args {
+        [name] => name,
+        [] => anyhow::bail!("No remote for the current branch."),
+        _ => anyhow::bail!("merging more than one commit at a time is not supported"),
+    };
+    let Ok(theirs) = revision::resolve_commit(repo, name) else {
+        eprintln!("merge: {name} - not something we can merge");
+        return Ok(1);
+    };
+    let ff = options
+        .ff
+        .unwrap_or_else(|| match repo.config().get("merge.ff") {
+            Some("false" | "no" | "off") => FastForward::Never,
+            Some("only") => FastForward::Only,
+            _ => FastForward::Allow,
+        });
+
+    let Some(head) = repo.refs().head()? else {
+        // 生まれていないブランチはそのまま進める
+        return fast_forward(repo, None, &theirs, name);
+    };
+    // gitと同じく、何もしない場合でもORIG_HEADは更新する
+    repo.refs().update("ORIG_HEAD", &head)?;
+    let bases = revwalk::merge_bases(repo, &head, &theirs)?;
+    if bases.contains(&theirs) {
+        println!("Already up to date.");
+        return Ok(0);
+    }
+    if bases.contains(&head) && ff != FastForward::Never {
+        return fast_forward(repo, Some(&head), &theirs, name);
+    }
+    if ff == FastForward::Only {
+        anyhow::bail!("Not possible to fast-forward, aborting.");
+    }
+    if bases.is_empty() {
+        anyhow::bail!("refusing to merge unrelated histories");
+    }
+    three_way(repo, &head, &theirs, name, ff, options)
+}
+
+fn fast_forward(
+    repo: &Repository,
+    head: Option<&str>,
+    theirs: &str,
+    name: &str,
+) -> anyhow::Result<u8> {
+    if let Some(head) = head {
+        println!("Updating {}..{}", &head[..7], &theirs[..7]);
+    }
+    let files = Worktree::new(repo)?;
+    let mut index = repo.index()?;
+    match files.checkout_tree(&mut index, head, theirs, false) {
+        Err(git::Error::LocalChanges(paths)) => {
+            report_local_changes(&paths, "\t");
+            eprintln!("Aborting");
+            return Ok(1);
+        }
+        Err(git::Error::UntrackedOverwritten(paths)) => {
+            report_untracked(&paths);
+            return Ok(1);
+        }
+        result => result?,
+    }
+    repo.write_index(&index)?;
+    let committer = commit::committer(repo)?;
+    let reflog_message = if head.is_some() {
+        println!("Fast-forward");
+        format!("merge {name}: Fast-forward")
+    } else {
+        "initial pull".to_string()
+    };
+    repo.refs()
+        .update_head_logged(theirs, &committer, &reflog_message)?;
+    if let Some(head) = head {
+        print_diffstat(repo, head, theirs)?;
+    }
+    Ok(0)
+}
+
+fn three_way(
+    repo: &Repository,
+    head: &str,
+    theirs: &str,
+    name: &str,
+    ff: FastForward,
+    options: &Options,
+) -> anyhow::Result<u8> {
+    let style = repo
+        .config()
+        .get("merge.conflictStyle")
+        .and_then(ConflictStyle::parse)
+        .unwrap_or_default();
+    let labels = Labels {
+        ours: "HEAD".to_string(),
+        base: String::new(),
+        theirs: name.to_string(),
+    };
+    let outcome = merge::commits(repo, head, theirs, style, &labels)?;
+
+    let files = Worktree::new(repo)?;
+    let mut index = repo.index()?;
+    let head_files = files.tree_map(Some(head))?;
+    // indexに変更があってはならない
+    let staged = staged_changes(&index, &head_files);
+    if !staged.is_empty() {
+        report_local_changes(&staged, "  ");
+        // gitはHEADへ戻したことをreflogに残す
+        let committer = commit::committer(repo)?;
+        repo.refs().update_head_logged(
+            head,
+            &committer,
+            &format!("merge {name}: updating HEAD"),
+        )?;
+        println!("Merge with strategy ort failed.");
+        return Ok(2);
+    }
+    if !apply_outcome(repo, &files, &mut index, &head_files, &outcome)? {
+        println!("Merge with strategy ort failed.");
+        return Ok(2);
+    }
+    for message in &outcome.messages {
+        println!("{message}");
+    }
+
+    let mut message = merge_message(repo, name, options)?;
+    if !outcome.is_clean() {
+        message.push_str("\n# Conflicts:\n");
+        for path in &outcome.conflicts {
+            writeln!(message, "#\t{path}")?;
+        }
+        write_state(repo, theirs, ff, &message)?;
+        println!("Automatic merge failed; fix conflicts and then commit the result.");
+        return Ok(1);
+    }
+
+    if edit_message(options) {
+        write_state(repo, theirs, ff, &message)?;
+        let path = repo.git_dir().join(MERGE_MSG);
+        fs::write(&path, message + EDIT_NOTE)?;
+        let edited = commit::run_editor(repo, &path)?;
+        message = message::cleanup(&fs::read_to_string(&path)?, Cleanup::Strip, true);
+        if !edited || message.is_empty() {
+            if edited {
+                eprintln!("Not committing merge; use 'git commit' to complete the merge.");
+            }
+            return Ok(1);
+        }
+        reset::clear_state(repo)?;
+    }
+    let tree = index.write_tree(repo.odb())?;
+    let parents = vec![head.to_string(), theirs.to_string()];
+    let merge_commit = commit::create(repo, tree, parents, message)?;
+    let committer = commit::committer(repo)?;
+    let reflog_message = format!("merge {name}: Merge made by the 'ort' strategy.");
+    repo.refs()
+        .update_head_logged(&merge_commit, &committer, &reflog_message)?;
+    println!("Merge made by the 'ort' strategy.");
+    print_diffstat(repo, head, &merge_commit)?;
+    Ok(0)
+}
+
+/// Update the index and the working tree from `head_files`, the files
+/// of `HEAD`, to the merged `outcome`, giving conflicted paths their
+/// stages.
+///
+/// Returns `false` after saying so when local changes or untracked files
+/// are in the way, leaving everything as it was.
+pub fn apply_outcome(
+    repo: &Repository,
+    files: &Worktree,
+    index: &mut Index,
+    head_files: &BTreeMap<String, (u32, String)>,
+    outcome: &merge::Outcome,
+) -> anyhow::Result<bool> {
+    let paths: BTreeSet<&String> = head_files.keys().chain(outcome.files.keys()).collect();
+    let mut updates = Vec::new();
+    let (mut local_changes, mut untracked) = (Vec::new(), Vec::new());
+    for path in paths {
+        let (old, new) = (head_files.get(path), outcome.files.get(path));
+        if old == new {
+            continue;
+        }
+        if files.is_dirty(index, path)? {
+            local_changes.push(path.clone());
+        } else if old.is_none()
+            && !is_tracked_dir(head_files, path)
+            && files.is_untracked(path, None)?
+        {
+            untracked.push(path.clone());
+        }
+        updates.push((path.clone(), new.cloned()));
+    }
+    if !local_changes.is_empty() || !untracked.is_empty() {
+        if local_changes.is_empty() {
+            report_untracked(&untracked);
+        } else {
+            report_local_changes(&local_changes, "\t");
+        }
+        eprintln!("Aborting");
+        return Ok(false);
+    }
+
+    files.update_files(index, updates)?;
+    for path in &outcome.conflicts {
+        index.remove(path);
+    }
+    for entry in outcome.entries.iter().filter(|e| e.stage != 0) {
+        index.add(entry.clone());
+    }
+    repo.write_index(index)?;
+    Ok(true)
+}
+
+// 追跡しているファイルを含むディレクトリは、中のファイルごとに調べる
+fn is_tracked_dir(head_files: &BTreeMap<String, (u32, String)>, path: &str) -> bool {
+    let dir = format!("{path}/");
+    head_files
+        .range(dir.clone()..)
+        .next()
+        .is_some_and(|(next, _)| next.starts_with(&dir))
+}
+
+/// The paths whose index entries differ from `head_files`.
+pub fn staged_changes(index: &Index, head_files: &BTreeMap<String, (u32, String)>) -> Vec<String> {
+    index
+        .entries()
+        .iter()
+        .filter(|e| head_files.get(&e.path) != Some(&(e.mode, e.oid.clone())))
+        .map(|e| e.path.clone())
+        .chain(
+            head_files
+                .keys()
+                .filter(|path| index.get(path, 0).is_none())
+                .cloned(),
+        )
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+// エディタでマージのメッセージを書くときの説明
+const EDIT_NOTE: &str = "\n\
+    # Please enter a commit message to explain why this merge is necessary,\n\
+    # especially if it merges an updated upstream into a topic branch.\n\
+    #\n\
+    # Lines starting with '#' will be ignored, and an empty message aborts\n\
+    # the commit.\n";
+
+// gitと同じく、-mがなく端末で動いているときだけエディタを開く
+fn edit_message(options: &Options) -> bool {
+    if let Some(edit) = options.edit {
+        return edit;
+    }
+    match env::var("GIT_MERGE_AUTOEDIT").as_deref() {
+        Ok("no") => false,
+        Ok("yes") => options.messages.is_empty(),
+        _ => options.messages.is_empty() && io::stdin().is_terminal() && io::stdout().is_terminal(),
+    }
+}
+
+// "Merge branch 'side'"。master/main以外へのマージには" into <branch>"を付ける
+fn merge_message(repo: &Repository, name: &str, options: &Options) -> anyhow::Result<String> {
+    if !options.messages.is_empty() {
+        return Ok(message::stripspace(&options.messages.join("\n\n"), false));
+    }
+    let mut message = format!("Merge {}", describe(repo, name)?);
+    match repo.refs().current_branch()? {
+        Some(branch) => {
+            let branch = branch.trim_start_matches("refs/heads/");
+            if branch != "master" && branch != "main" {
+                write!(message, " into {branch}")?;
+            }
+        }
+        None => message.push_str(" into HEAD"),
+    }
+    message.push('\n');
+    Ok(message)
+}
+
+// マージするものの説明。ブランチの祖先("side^"や"side~2")は"(early part)"とする
+fn describe(repo: &Repository, name: &str) -> anyhow::Result<String> {
+    if let Some((branch, early)) = branch_ancestor(name) {
+        if repo
+            .refs()
+            .resolve(&format!("refs/heads/{branch}"))?
+            .is_some()
+        {
+            let early = if early { " (early part)" } else { "" };
+            return Ok(format!("branch '{branch}'{early}"));
+        }
+    }
+    Ok(match revision::full_ref_name(repo, name)? {
+        Some(full) if full.starts_with("refs/heads/") => {
+            format!("branch '{}'", &full["refs/heads/".len()..])
+        }
+        Some(full) if full.starts_with("refs/tags/") => {
+            format!("tag '{}'", &full["refs/tags/".len()..])
+        }
+        Some(full) if full.starts_with("refs/remotes/") => {
+            format!(
+                "remote-tracking branch '{}'",
+                &full["refs/remotes/".len()..]
+            )
+        }
+        _ => format!("commit '{name}'"),
+    })
+}
+
+// "<name>^^.."や"<name>~<number>"を<name>と、祖先を指すかどうかに分ける
+fn branch_ancestor(name: &str) -> Option<(&str, bool)> {
+    let stripped = name.trim_end_matches('^');
+    if stripped.len() < name.len() {
+        return Some((stripped, true));
+    }
+    let (branch, number) = name.rsplit_once('~')?;
+    if !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    // "name~"は"name~1"と同じ
+    Some((
+        branch,
+        number.is_empty() || number.bytes().any(|b| b != b'0'),
+    ))
+}
+
+fn write_state(
+    repo: &Repository,
+    theirs: &str,
+    ff: FastForward,
+    message: &str,
+) -> anyhow::Result<()> {
+    let git_dir = repo.git_dir();
+    fs::write(git_dir.join(MERGE_HEAD), format!("{theirs}\n"))?;
+    let mode = if ff == FastForward::Never {
+        "no-ff"
+    } else {
+        ""
+    };
+    fs::write(git_dir.join(MERGE_MODE), mode)?;
+    fs::write(git_dir.join(MERGE_MSG), message)?;
+    Ok(())
+}
+
+fn report_local_changes(paths: &[String], indent: &str) {
+    eprintln!("error: Your local changes to the following files would be overwritten by merge:");
+    for path in paths {
+        eprintln!("{indent}{path}");
+    }
+    if indent == "\t" {
+        eprintln!("Please commit your changes or stash them before you merge.");
+    }
+}
+
+fn report_untracked(paths: &[String]) {
+    eprintln!("error: The following untracked working tree files would be overwritten by merge:");
+    for path in paths {
+        eprintln!("\t{path}");
+    }
+    eprintln!("Please move or remove them before you merge.");
+}
+
+// マージの結果をdiffstatと、ファイルの作成・削除・名前の変更の一覧で示す
+fn print_diffstat(repo: &Repository, from: &str, to: &str) -> anyhow::Result<()> {
+    let pairs = diff::tree_pairs(repo, Some(from), to, &Pathspec::default())?;
+    let pairs = rename::detect(pairs, rename::Options::default());
+    let mut out = io::stdout().lock();
+    stat::write(&mut out, &pairs, Algorithm::default(), false)?;
+    for pair in &pairs {
+        match (&pair.old, &pair.new) {
+            (None, Some(new)) => writeln!(out, " create mode {:06o} {}", new.mode, new.path)?,
+            (Some(old), None) => writeln!(out, " delete mode {:06o} {}", old.mode, old.path)?,
+            (Some(_), Some(_)) if pair.similarity.is_some() => {
+                let verb = if pair.copy { "copy" } else { "rename" };
+                let similarity = pair.similarity.unwrap_or_default();
+                writeln!(out, " {verb} {} ({similarity}%)", pair.display_name())?;
+            }
+            (Some(old), Some(new)) if old.mode != new.mode => writeln!(
+                out,
+                " mode change {:06o} => {:06o} {}",
+                old.mode, new.mode, new.path
+            )?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
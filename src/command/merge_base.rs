@@ -0,0 +1,100 @@
+use git::object::Kind;
+use git::revision;
+use git::revwalk;
+use git::Repository;
+
+/// Flags of `merge-base`.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `-a`/`--all`: show all the best common ancestors, not just one.
+    pub all: bool,
+    /// `--octopus`: the common ancestors of all the commits at once.
+    pub octopus: bool,
+    /// `--is-ancestor`: only tell by the exit status whether the first
+    /// commit is an ancestor of the second.
+    pub is_ancestor: bool,
+    /// `--fork-point`: where a commit forked from a branch, according to the
+    /// reflog of the branch.
+    pub fork_point: bool,
+}
+
+const USAGE: &str = "usage: git merge-base [-a | --all] <commit> <commit>...\n   \
+    or: git merge-base [-a | --all] --octopus <commit>...\n   \
+    or: git merge-base --is-ancestor <commit> <commit>\n   \
+    or: git merge-base --fork-point <ref> [<commit>]";
+
+/// Print the best common ancestors of the commits in `args`: of the first
+/// and all the others, as if those were merged together first.
+///
+/// Returns `false` when there is none, or with `--is-ancestor` when the
+/// first commit is not an ancestor of the second.
+pub fn merge_base(repo: &Repository, args: &[String], options: &Options) -> anyhow::Result<bool> {
+    for (name, used) in [
+        ("--is-ancestor", options.is_ancestor),
+        ("--fork-point", options.fork_point),
+    ] {
+        if used && options.all {
+            anyhow::bail!("options '{name}' and '--all' cannot be used together");
+        }
+        if used && options.octopus {
+            anyhow::bail!("options '{name}' and '--octopus' cannot be used together");
+        }
+    }
+    if options.is_ancestor && options.fork_point {
+        anyhow::bail!("options '--is-ancestor' and '--fork-point' cannot be used together");
+    }
+
+    if options.fork_point {
+        let (name, commit) = match args {
+            [name] => (name, "HEAD"),
+            [name, commit] => (name, commit.as_str()),
+            _ => anyhow::bail!(USAGE),
+        };
+        let Some(full_name) = revision::full_ref_name(repo, name)? else {
+            anyhow::bail!("No such ref: '{name}'");
+        };
+        let commit = revision::resolve_commit(repo, commit)
+            .map_err(|_| anyhow::anyhow!("Not a valid object name: '{commit}'"))?;
+        let Some(base) = revwalk::fork_point(repo, &full_name, &commit)? else {
+            return Ok(false);
+        };
+        println!("{base}");
+        return Ok(true);
+    }
+
+    let commits = args
+        .iter()
+        .map(|arg| resolve(repo, arg))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if options.is_ancestor {
+        let [ancestor, descendant] = &commits[..] else {
+            anyhow::bail!(USAGE);
+        };
+        return Ok(revwalk::is_ancestor(repo, ancestor, descendant)?);
+    }
+    let bases = if options.octopus {
+        revwalk::octopus_merge_bases(repo, &commits)?
+    } else {
+        let Some((one, others)) = commits.split_first().filter(|_| commits.len() >= 2) else {
+            anyhow::bail!(USAGE);
+        };
+        revwalk::merge_bases_many(repo, one, others)?
+    };
+    if bases.is_empty() {
+        return Ok(false);
+    }
+    let shown = if options.all { bases.len() } else { 1 };
+    for base in &bases[..shown] {
+        println!("{base}");
+    }
+    Ok(true)
+}
+
+// gitと同じく、commitでないものとないものでエラーを分ける
+fn resolve(repo: &Repository, spec: &str) -> anyhow::Result<String> {
+    let oid = revision::resolve(repo, spec)
+        .map_err(|_| anyhow::anyhow!("Not a valid object name {spec}"))?;
+    revision::peel(repo, &oid, Kind::Commit)
+        .map_err(|_| anyhow::anyhow!("Not a valid commit name {spec}"))
+}
@@ -0,0 +1,298 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::process;
+
+use git::pathspec::Pathspec;
+use git::worktree::Worktree;
+use git::Repository;
+
+use super::add;
+
+/// Flags of `mergetool`.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `-t`/`--tool`: the tool to run instead of `merge.tool`.
+    pub tool: Option<String>,
+    /// `--prompt`/`-y`: whether to ask before running the tool on each file,
+    /// instead of `mergetool.prompt`.
+    pub prompt: Option<bool>,
+}
+
+// 一時ファイルを置くステージ: (名前, ステージ)
+const VERSIONS: [(&str, u8); 3] = [("BASE", 1), ("LOCAL", 2), ("REMOTE", 3)];
+
+/// Run the configured merge tool on each unmerged file matched by `args`,
+/// staging the files it resolves.
+///
+/// The tool is the shell command `mergetool.<tool>.cmd`, run from the top of
+/// the working tree with `$BASE`, `$LOCAL` and `$REMOTE` naming temporary
+/// files with the versions of the index and `$MERGED` the file itself.
+/// Returns `false` when a file was not resolved.
+pub fn mergetool(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<bool> {
+    let pathspec = Pathspec::parse(args, prefix)?;
+    let index = repo.index()?;
+    // path -> そのpathにあるステージ
+    let mut unmerged: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+    for entry in index.entries() {
+        if entry.stage != 0 && pathspec.matches(&entry.path) {
+            unmerged
+                .entry(entry.path.clone())
+                .or_default()
+                .push(entry.stage);
+        }
+    }
+    if unmerged.is_empty() {
+        println!("No files need merging");
+        return Ok(true);
+    }
+
+    let config = repo.config();
+    let settings = Settings {
+        keep_backup: config.get_bool("mergetool.keepBackup").unwrap_or(true),
+        prompt: options
+            .prompt
+            .or_else(|| config.get_bool("mergetool.prompt"))
+            .unwrap_or(false),
+    };
+
+    println!("Merging:");
+    for path in unmerged.keys() {
+        println!("{path}");
+    }
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut ok = true;
+    let mut paths = unmerged.iter().peekable();
+    while let Some((path, stages)) = paths.next() {
+        println!();
+        // ツールは両側に版があるファイルにだけ使う
+        let resolved = if stages.contains(&2) && stages.contains(&3) {
+            let tool = find_tool(repo, options)?;
+            merge_file(repo, &tool, &settings, &mut input, path, stages)?
+        } else {
+            resolve_deleted(repo, &settings, &mut input, path, stages)?
+        };
+        match resolved {
+            Resolved::Yes => {}
+            Resolved::No => {
+                ok = false;
+                if paths.peek().is_some() {
+                    let prompt = "Continue merging other unresolved paths [y/n]? ";
+                    if ask(&mut input, prompt, &["y", "n"])?.as_deref() != Some("y") {
+                        break;
+                    }
+                }
+            }
+            Resolved::Abort => return Ok(false),
+        }
+    }
+    Ok(ok)
+}
+
+struct Tool<'a> {
+    name: &'a str,
+    command: &'a str,
+    trust_exit_code: bool,
+}
+
+struct Settings {
+    keep_backup: bool,
+    prompt: bool,
+}
+
+fn find_tool<'a>(repo: &'a Repository, options: &'a Options) -> anyhow::Result<Tool<'a>> {
+    let config = repo.config();
+    let name = options
+        .tool
+        .as_deref()
+        .or_else(|| config.get("merge.tool"))
+        .ok_or_else(|| anyhow::anyhow!("no merge tool configured; set merge.tool or use --tool"))?;
+    let Some(command) = config.get(&format!("mergetool.{name}.cmd")) else {
+        anyhow::bail!("Unknown merge tool {name}");
+    };
+    let trust_exit_code = config
+        .get_bool(&format!("mergetool.{name}.trustExitCode"))
+        .unwrap_or(false);
+    Ok(Tool {
+        name,
+        command,
+        trust_exit_code,
+    })
+}
+
+enum Resolved {
+    Yes,
+    No,
+    /// Stop without looking at the other files.
+    Abort,
+}
+
+// 両側に版があるファイルをツールでマージする
+fn merge_file(
+    repo: &Repository,
+    tool: &Tool,
+    settings: &Settings,
+    input: &mut impl BufRead,
+    path: &str,
+    stages: &[u8],
+) -> anyhow::Result<Resolved> {
+    let created = !stages.contains(&1);
+    let state = if created {
+        "created file"
+    } else {
+        "modified file"
+    };
+    println!("Normal merge conflict for '{path}':");
+    println!("  {{local}}: {state}");
+    println!("  {{remote}}: {state}");
+    if settings.prompt {
+        let prompt = format!(
+            "Hit return to start merge resolution tool ({}): ",
+            tool.name
+        );
+        if ask(input, &prompt, &[])?.is_none() {
+            return Ok(Resolved::Abort);
+        }
+    }
+
+    let work_tree = repo.require_work_tree()?;
+    let index = repo.index()?;
+    let merged = work_tree.join(path);
+    let backup = temp_name(path, "BACKUP");
+    fs::copy(&merged, work_tree.join(&backup))?;
+    let mut temps = vec![backup.clone()];
+    let mut command = process::Command::new("sh");
+    command
+        .arg("-c")
+        .arg(tool.command)
+        .current_dir(work_tree)
+        .env("MERGED", path);
+    for (name, stage) in VERSIONS {
+        let temp = temp_name(path, name);
+        let data = match index.get(path, stage) {
+            Some(entry) => repo.find_blob(&entry.oid)?,
+            None => Vec::new(),
+        };
+        fs::write(work_tree.join(&temp), data)?;
+        command.env(name, &temp);
+        temps.push(temp);
+    }
+    let status = command.status()?;
+
+    let success = if tool.trust_exit_code {
+        status.success()
+    } else if fs::read(&merged).ok() == fs::read(work_tree.join(&backup)).ok() {
+        // 内容が変わっていなければ、うまくいったかを尋ねる
+        loop {
+            println!("{path} seems unchanged.");
+            match ask(input, "Was the merge successful [y/n]? ", &[])?.as_deref() {
+                Some("y") => break true,
+                Some("n") | None => break false,
+                Some(_) => {}
+            }
+        }
+    } else {
+        true
+    };
+    if !success {
+        eprintln!("merge of {path} failed");
+        fs::rename(work_tree.join(&backup), &merged)?;
+    } else if settings.keep_backup {
+        fs::rename(
+            work_tree.join(&backup),
+            work_tree.join(format!("{path}.orig")),
+        )?;
+    }
+    for temp in &temps {
+        let _ = fs::remove_file(work_tree.join(temp));
+    }
+    if !success {
+        return Ok(Resolved::No);
+    }
+    stage(repo, path)?;
+    Ok(Resolved::Yes)
+}
+
+// 片側で削除されたファイルは、残すか消すかを選んでもらう
+fn resolve_deleted(
+    repo: &Repository,
+    settings: &Settings,
+    input: &mut impl BufRead,
+    path: &str,
+    stages: &[u8],
+) -> anyhow::Result<Resolved> {
+    let state = |stage| {
+        if stages.contains(&stage) {
+            "modified file"
+        } else {
+            "deleted"
+        }
+    };
+    println!("Deleted merge conflict for '{path}':");
+    println!("  {{local}}: {}", state(2));
+    println!("  {{remote}}: {}", state(3));
+    let prompt = "Use (m)odified or (d)eleted file, or (a)bort? ";
+    match ask(input, prompt, &["m", "d", "a"])?.as_deref() {
+        Some("m") => {
+            // gitと同じく、残したファイルの控えも作る
+            let work_tree = repo.require_work_tree()?;
+            if settings.keep_backup {
+                fs::copy(work_tree.join(path), work_tree.join(format!("{path}.orig")))?;
+            }
+            stage(repo, path)?;
+        }
+        Some("d") => {
+            let mut index = repo.index()?;
+            index.remove(path);
+            Worktree::new(repo)?.remove(path)?;
+            repo.write_index(&index)?;
+        }
+        _ => return Ok(Resolved::Abort),
+    }
+    Ok(Resolved::Yes)
+}
+
+// 解決したファイルをaddと同じように記録する
+fn stage(repo: &Repository, path: &str) -> anyhow::Result<()> {
+    let options = add::Options::default();
+    add::add(repo, Path::new(""), &[path.to_string()], &options)?;
+    Ok(())
+}
+
+// gitと同じく、ファイルと同じディレクトリに拡張子を残した名前で置く:
+// "dir/a.c" -> "./dir/a_BASE_1234.c"
+fn temp_name(path: &str, kind: &str) -> String {
+    let (dir, name) = match path.rsplit_once('/') {
+        Some((dir, name)) => (format!("{dir}/"), name),
+        None => (String::new(), path),
+    };
+    let (stem, ext) = match name.rfind('.') {
+        Some(i) if i > 0 => name.split_at(i),
+        _ => (name, ""),
+    };
+    format!("./{dir}{stem}_{kind}_{}{ext}", process::id())
+}
+
+// `choices`のどれかが入力されるまで尋ねる。空なら何でもよい。入力が終われば`None`
+fn ask(input: &mut impl BufRead, prompt: &str, choices: &[&str]) -> io::Result<Option<String>> {
+    let mut stdout = io::stdout().lock();
+    loop {
+        write!(stdout, "{prompt}")?;
+        stdout.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let answer = line.trim();
+        if choices.is_empty() || choices.contains(&answer) {
+            return Ok(Some(answer.to_string()));
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use git::index::Index;
+use git::repository::GitRoot;
+use git::Repository;
+
+/// Flags of `mv`.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `-f`: overwrite an existing destination.
+    pub force: bool,
+    /// `-k`: skip moves that would fail instead of aborting.
+    pub skip_errors: bool,
+    pub dry_run: bool,
+    pub verbose: bool,
+}
+
+/// Move or rename tracked files and directories, given relative to the
+/// starting directory of `root`, in both the index and the working tree.
+///
+/// With one source, `args` is `<source> <destination>`; otherwise, or when
+/// the destination is a directory, every source is moved into it.
+pub fn mv(
+    repo: &Repository,
+    root: &GitRoot,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<()> {
+    let Some((destination, sources)) = args.split_last().filter(|(_, s)| !s.is_empty()) else {
+        anyhow::bail!("usage: git mv [<options>] <source>... <destination>");
+    };
+    let work_tree = repo.require_work_tree()?;
+    let top_relative = |path: &str| {
+        let path = root.resolve(path).to_string_lossy().into_owned();
+        // 最上位のディレクトリは空のパスで表す
+        if path == "." {
+            String::new()
+        } else {
+            path
+        }
+    };
+    let destination = top_relative(destination);
+    let into_directory = sources.len() > 1 || work_tree.join(&destination).is_dir();
+    if into_directory && !work_tree.join(&destination).is_dir() {
+        anyhow::bail!("destination '{destination}' is not a directory");
+    }
+
+    let mut index = repo.index()?;
+    let mut moves: Vec<(String, String)> = Vec::new();
+    for source in sources {
+        let source = top_relative(source);
+        let target = if into_directory {
+            let name = Path::new(&source).file_name().unwrap_or_default();
+            Path::new(&destination)
+                .join(name)
+                .to_string_lossy()
+                .into_owned()
+        } else {
+            destination.clone()
+        };
+        if let Err(message) = check_move(work_tree, &index, &source, &target, options.force) {
+            if options.skip_errors {
+                continue;
+            }
+            anyhow::bail!("{message}, source={source}, destination={target}");
+        }
+        if moves.iter().any(|(_, t)| *t == target) {
+            if options.skip_errors {
+                continue;
+            }
+            anyhow::bail!(
+                "multiple sources for the same target, source={source}, destination={target}"
+            );
+        }
+        moves.push((source, target));
+    }
+
+    for (source, target) in moves {
+        if options.dry_run {
+            println!("Checking rename of '{source}' to '{target}'");
+        }
+        if options.dry_run || options.verbose {
+            println!("Renaming {source} to {target}");
+        }
+        if options.dry_run {
+            continue;
+        }
+        fs::rename(work_tree.join(&source), work_tree.join(&target))?;
+        rename_entries(&mut index, &source, &target);
+    }
+    if !options.dry_run {
+        repo.write_index(&index)?;
+    }
+    Ok(())
+}
+
+// 移動できない理由を返す
+fn check_move(
+    work_tree: &Path,
+    index: &Index,
+    source: &str,
+    target: &str,
+    force: bool,
+) -> Result<(), &'static str> {
+    let Ok(metadata) = fs::symlink_metadata(work_tree.join(source)) else {
+        return Err("bad source");
+    };
+    if metadata.is_dir() {
+        if target
+            .strip_prefix(source)
+            .is_some_and(|rest| rest.starts_with('/'))
+        {
+            return Err("can not move directory into itself");
+        }
+        let tracked = index.entries().iter().any(|e| {
+            e.path
+                .strip_prefix(source)
+                .is_some_and(|rest| rest.starts_with('/'))
+        });
+        if !tracked {
+            return Err("source directory is empty");
+        }
+    } else if index.get(source, 0).is_none() {
+        return Err("not under version control");
+    }
+
+    if let Ok(existing) = fs::symlink_metadata(work_tree.join(target)) {
+        // 大文字小文字だけの変更は、大文字小文字を区別しないfsでは同じファイルになる
+        let same_file = existing.dev() == metadata.dev() && existing.ino() == metadata.ino();
+        if !same_file && (!force || existing.is_dir() || metadata.is_dir()) {
+            return Err("destination exists");
+        }
+    }
+    let parent = Path::new(target).parent().unwrap_or(Path::new(""));
+    if !work_tree.join(parent).is_dir() {
+        return Err("destination directory does not exist");
+    }
+    Ok(())
+}
+
+// sourceそのもの、またはその下のentryをtargetへ付け替える
+fn rename_entries(index: &mut Index, source: &str, target: &str) {
+    index.remove(target);
+    let entries: Vec<_> = index
+        .entries()
+        .iter()
+        .filter(|e| {
+            e.path == source
+                || e.path
+                    .strip_prefix(source)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+        .cloned()
+        .collect();
+    for mut entry in entries {
+        index.remove(&entry.path);
+        entry.path = format!("{target}{}", &entry.path[source.len()..]);
+        index.add(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::testing::TempRepo;
+    use git::worktree::Worktree;
+
+    fn checkout(test: &TempRepo, files: &[(&str, &str)]) {
+        let commit = test.commit(files, &[]);
+        let mut index = Index::default();
+        Worktree::new(&test.repo)
+            .unwrap()
+            .checkout_tree(&mut index, None, &commit, true)
+            .unwrap();
+        test.repo.write_index(&index).unwrap();
+    }
+
+    fn run(test: &TempRepo, prefix: &str, args: &[&str], options: &Options) -> anyhow::Result<()> {
+        let root = GitRoot {
+            git_dir: test.repo.git_dir().to_path_buf(),
+            work_tree: Some(test.root.clone()),
+            prefix: PathBuf::from(prefix),
+        };
+        let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+        mv(&test.repo, &root, &args, options)
+    }
+
+    fn tracked(test: &TempRepo) -> Vec<String> {
+        let index = test.repo.index().unwrap();
+        index.entries().iter().map(|e| e.path.clone()).collect()
+    }
+
+    #[test]
+    fn test_check_move() {
+        let test = TempRepo::new();
+        checkout(&test, &[("a", "a\n"), ("b", "b\n"), ("d/c", "c\n")]);
+        test.write("untracked", "u\n");
+        fs::create_dir(test.root.join("empty")).unwrap();
+        let index = test.repo.index().unwrap();
+        let check = |source, target, force| check_move(&test.root, &index, source, target, force);
+
+        assert_eq!(check("x", "y", false), Err("bad source"));
+        assert_eq!(
+            check("untracked", "y", false),
+            Err("not under version control")
+        );
+        assert_eq!(check("empty", "y", false), Err("source directory is empty"));
+        assert_eq!(
+            check("d", "d/e", false),
+            Err("can not move directory into itself")
+        );
+        assert_eq!(check("a", "b", false), Err("destination exists"));
+        assert_eq!(check("a", "b", true), Ok(()));
+        // ディレクトリは-fでも上書きしない
+        assert_eq!(check("a", "d", true), Err("destination exists"));
+        assert_eq!(check("d", "empty", true), Err("destination exists"));
+        assert_eq!(
+            check("a", "x/a", false),
+            Err("destination directory does not exist")
+        );
+        assert_eq!(check("d", "e", false), Ok(()));
+    }
+
+    #[test]
+    fn test_mv() {
+        let test = TempRepo::new();
+        checkout(&test, &[("a", "a\n"), ("b", "b\n"), ("d/c", "c\n")]);
+        let options = Options::default();
+
+        run(&test, "", &["a", "e"], &options).unwrap();
+        assert_eq!(tracked(&test), ["b", "d/c", "e"]);
+        assert_eq!(fs::read(test.root.join("e")).unwrap(), b"a\n");
+
+        // 開始ディレクトリからの相対パスで指定する
+        run(&test, "d", &["../e", "../b", "."], &options).unwrap();
+        assert_eq!(tracked(&test), ["d/b", "d/c", "d/e"]);
+
+        run(&test, "", &["d", "f"], &options).unwrap();
+        assert_eq!(tracked(&test), ["f/b", "f/c", "f/e"]);
+        assert!(!test.root.join("d").exists());
+
+        let error = run(&test, "", &["f/b", "f/c"], &options).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "destination exists, source=f/b, destination=f/c"
+        );
+        let error = run(&test, "", &["f/b", "x", "f/c"], &options).unwrap_err();
+        assert_eq!(error.to_string(), "destination 'f/c' is not a directory");
+
+        // -kでは移動できないものだけを飛ばす
+        fs::create_dir(test.root.join("g")).unwrap();
+        let skip = Options {
+            skip_errors: true,
+            ..Options::default()
+        };
+        run(&test, "", &["f/b", "x", "g"], &skip).unwrap();
+        assert_eq!(tracked(&test), ["f/c", "f/e", "g/b"]);
+
+        let dry_run = Options {
+            dry_run: true,
+            ..Options::default()
+        };
+        run(&test, "", &["f/c", "c"], &dry_run).unwrap();
+        assert_eq!(tracked(&test), ["f/c", "f/e", "g/b"]);
+        let force = Options {
+            force: true,
+            ..Options::default()
+        };
+        run(&test, "", &["f/c", "f/e"], &force).unwrap();
+        assert_eq!(tracked(&test), ["f/e", "g/b"]);
+        assert_eq!(fs::read(test.root.join("f/e")).unwrap(), b"c\n");
+    }
+}
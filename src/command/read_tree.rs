@@ -0,0 +1,472 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use git::index::{verify_path, Entry, Index};
+use git::object::Kind;
+use git::revision;
+use git::Repository;
+
+/// Flags of `read-tree`.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `-m`: merge one, two or three trees with the index instead of
+    /// replacing it.
+    pub merge: bool,
+    /// `--prefix=<dir>/`: add the files of the tree under `<dir>` to the
+    /// current index.
+    pub prefix: Option<String>,
+}
+
+/// Read `trees` into the index without touching the working tree.
+///
+/// Without `-m` the index is replaced by the trees. With one tree `-m` does
+/// the same but keeps the stat data of unchanged entries; with two it moves
+/// the index from the first tree to the second, refusing to lose staged
+/// changes; with three (base, ours, theirs) it resolves the trivial cases
+/// and leaves the rest as conflict stages.
+pub fn read_tree(repo: &Repository, trees: &[String], options: &Options) -> anyhow::Result<()> {
+    let trees = trees
+        .iter()
+        .map(|tree| revision::peel(repo, &revision::resolve(repo, tree)?, Kind::Tree))
+        .collect::<git::Result<Vec<_>>>()?;
+    let index = repo.index()?;
+    if (options.merge || options.prefix.is_some()) && index.entries().iter().any(|e| e.stage != 0) {
+        anyhow::bail!("You need to resolve your current index first");
+    }
+
+    let index = if let Some(prefix) = &options.prefix {
+        let [tree] = trees.as_slice() else {
+            anyhow::bail!("--prefix needs exactly one tree");
+        };
+        bind(index, read(repo, tree)?, prefix)?
+    } else if options.merge {
+        let trees = trees
+            .iter()
+            .map(|tree| read(repo, tree))
+            .collect::<git::Result<Vec<_>>>()?;
+        match trees.as_slice() {
+            [] => anyhow::bail!("you must specify at least one tree to merge"),
+            [tree] => one_way(&index, tree),
+            [old, new] => {
+                // indexファイルがまだなければ、最初のcheckoutとして扱う
+                let unborn = !repo.index_path().exists();
+                two_way(&index, old, new, unborn)?
+            }
+            [base, ours, theirs] => three_way(&index, base, ours, theirs)?,
+            _ => anyhow::bail!("cannot merge more than three trees"),
+        }
+    } else {
+        if trees.is_empty() {
+            eprintln!(
+                "warning: read-tree: emptying the index with no arguments is deprecated; \
+                 use --empty"
+            );
+        }
+        // 後のtreeが前のtreeを上書きする
+        let mut index = Index::default();
+        for tree in &trees {
+            for entry in read(repo, tree)?.into_values() {
+                index.add(entry);
+            }
+        }
+        index
+    };
+    repo.write_index(&index)?;
+    Ok(())
+}
+
+// treeの全ファイルを、stat情報のないstage 0のentryにする
+fn read(repo: &Repository, tree: &str) -> git::Result<BTreeMap<String, Entry>> {
+    repo.tree_files(tree)?
+        .into_iter()
+        .map(|file| {
+            if !verify_path(&file.name) {
+                return Err(git::Error::InvalidPath(file.name));
+            }
+            let entry = Entry {
+                mode: file.mode,
+                oid: file.oid,
+                path: file.name.clone(),
+                ..Entry::default()
+            };
+            Ok((file.name, entry))
+        })
+        .collect()
+}
+
+// --prefix: 既存のentryと重ならない場合だけ追加する
+fn bind(mut index: Index, tree: BTreeMap<String, Entry>, prefix: &str) -> anyhow::Result<Index> {
+    let prefix = prefix.trim_end_matches('/');
+    let existing: BTreeSet<String> = index.entries().iter().map(|e| e.path.clone()).collect();
+    for (name, mut entry) in tree {
+        entry.path = if prefix.is_empty() {
+            name
+        } else {
+            format!("{prefix}/{name}")
+        };
+        if !verify_path(&entry.path) {
+            return Err(git::Error::InvalidPath(entry.path).into());
+        }
+        // 同じpathか、一方がもう一方のディレクトリになっているもの
+        let inside = format!("{}/", entry.path);
+        let overlap = existing
+            .range(entry.path.clone()..)
+            .next()
+            .filter(|path| **path == entry.path || path.starts_with(&inside))
+            .or_else(|| {
+                existing.iter().find(|path| {
+                    entry
+                        .path
+                        .strip_prefix(path.as_str())
+                        .is_some_and(|rest| rest.starts_with('/'))
+                })
+            });
+        if let Some(path) = overlap {
+            anyhow::bail!(
+                "Entry '{}' overlaps with '{path}'.  Cannot bind.",
+                entry.path
+            );
+        }
+        index.add(entry);
+    }
+    Ok(index)
+}
+
+fn one_way(index: &Index, tree: &BTreeMap<String, Entry>) -> Index {
+    let mut result = Index::default();
+    for entry in tree.values() {
+        result.add(merged(entry, index.get(&entry.path, 0)));
+    }
+    result
+}
+
+// git read-treeの2-way mergeの表の通り。indexに記録された変更は失わない
+fn two_way(
+    index: &Index,
+    old: &BTreeMap<String, Entry>,
+    new: &BTreeMap<String, Entry>,
+    unborn: bool,
+) -> anyhow::Result<Index> {
+    let mut result = Index::default();
+    for path in paths(index, &[old, new]) {
+        let current = index.get(&path, 0);
+        let (old, new) = (old.get(&path), new.get(&path));
+        let Some(current) = current else {
+            match (old, new) {
+                // indexから削除されたことを記録していれば、消したままにする
+                (Some(old), Some(new)) if !unborn => {
+                    if !same(Some(old), Some(new)) {
+                        return Err(would_overwrite(&path));
+                    }
+                }
+                (_, Some(new)) => result.add(new.clone()),
+                (_, None) => {}
+            }
+            continue;
+        };
+        let keep = match (old, new) {
+            (None, None) => true,
+            (None, Some(_)) => same(Some(current), new),
+            (Some(_), Some(_)) => same(old, new) || same(Some(current), new),
+            (Some(_), None) => false,
+        };
+        if keep {
+            result.add(current.clone());
+        } else if same(Some(current), old) {
+            if let Some(new) = new {
+                result.add(merged(new, Some(current)));
+            }
+        } else {
+            return Err(would_overwrite(&path));
+        }
+    }
+    Ok(result)
+}
+
+// git read-treeの3-way mergeのうち、自明なものだけを解決する
+fn three_way(
+    index: &Index,
+    base: &BTreeMap<String, Entry>,
+    ours: &BTreeMap<String, Entry>,
+    theirs: &BTreeMap<String, Entry>,
+) -> anyhow::Result<Index> {
+    let mut result = Index::default();
+    for path in paths(index, &[base, ours, theirs]) {
+        let current = index.get(&path, 0);
+        let (base, ours, theirs) = (base.get(&path), ours.get(&path), theirs.get(&path));
+        // 両側が同じなら、baseとの比較は要らない
+        let (ours_match, theirs_match) = if same(ours, theirs) {
+            (false, false)
+        } else {
+            (same(base, ours), same(base, theirs))
+        };
+
+        // theirsだけが変更した
+        if let Some(theirs) = theirs.filter(|_| ours_match && !theirs_match) {
+            if current.is_some() && !same(current, Some(theirs)) && !same(current, ours) {
+                return Err(would_overwrite(&path));
+            }
+            result.add(merged(theirs, current));
+            continue;
+        }
+        if current.is_some() && !same(current, ours) {
+            return Err(would_overwrite(&path));
+        }
+        if let Some(ours) = ours {
+            // 両側が同じか、oursだけが変更した
+            if same(Some(ours), theirs) || (theirs_match && !ours_match) {
+                result.add(merged(ours, current));
+                continue;
+            }
+        }
+
+        // 解決できなければstage 1~3に残す
+        if !ours_match || !theirs_match {
+            if let Some(base) = base {
+                result.add(Entry {
+                    stage: 1,
+                    ..base.clone()
+                });
+            }
+        }
+        for (stage, entry) in [(2, ours), (3, theirs)] {
+            if let Some(entry) = entry {
+                result.add(Entry {
+                    stage,
+                    ..entry.clone()
+                });
+            }
+        }
+    }
+    Ok(result)
+}
+
+// indexとtreeのどれかにあるpath
+fn paths(index: &Index, trees: &[&BTreeMap<String, Entry>]) -> BTreeSet<String> {
+    let mut paths: BTreeSet<String> = index.entries().iter().map(|e| e.path.clone()).collect();
+    for tree in trees {
+        paths.extend(tree.keys().cloned());
+    }
+    paths
+}
+
+// modeとhashが同じ(どちらもない場合も含む)
+fn same(a: Option<&Entry>, b: Option<&Entry>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.mode == b.mode && a.oid == b.oid,
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+// 内容が変わらなければ、indexのentryをstat情報ごと残す
+fn merged(entry: &Entry, current: Option<&Entry>) -> Entry {
+    match current {
+        Some(current) if same(Some(current), Some(entry)) => current.clone(),
+        _ => entry.clone(),
+    }
+}
+
+fn would_overwrite(path: &str) -> anyhow::Error {
+    anyhow::anyhow!("Entry '{path}' would be overwritten by merge. Cannot merge.")
+}
+
+#[cfg(test)]
+mod tests {
+    use git::object::tree::BLOB_MODE;
+
+    use super::*;
+
+    // oidは内容の代わりに1文字を40回繰り返したもの
+    fn entry(path: &str, content: char) -> Entry {
+        Entry {
+            mode: BLOB_MODE,
+            oid: content.to_string().repeat(40),
+            path: path.to_string(),
+            ..Entry::default()
+        }
+    }
+
+    fn tree(files: &[(&str, char)]) -> BTreeMap<String, Entry> {
+        let files = files.iter();
+        files
+            .map(|&(path, content)| (path.to_string(), entry(path, content)))
+            .collect()
+    }
+
+    fn index(files: &[(&str, char)]) -> Index {
+        let mut index = Index::default();
+        for &(path, content) in files {
+            // stat情報が残るかどうかを見るための大きさ
+            index.add(Entry {
+                size: 1,
+                ..entry(path, content)
+            });
+        }
+        index
+    }
+
+    // (path, stage, 内容, stat情報が残っているか)
+    fn entries(index: &Index) -> Vec<(&str, u8, char, bool)> {
+        let entries = index.entries().iter();
+        entries
+            .map(|e| {
+                (
+                    e.path.as_str(),
+                    e.stage,
+                    char::from(e.oid.as_bytes()[0]),
+                    e.size == 1,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_one_way() {
+        let result = one_way(
+            &index(&[("a", '1'), ("b", '1'), ("c", '1')]),
+            &tree(&[("a", '1'), ("b", '2'), ("d", '1')]),
+        );
+        assert_eq!(
+            entries(&result),
+            [
+                ("a", 0, '1', true),
+                ("b", 0, '2', false),
+                ("d", 0, '1', false)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_two_way() {
+        let old = tree(&[
+            ("same", '1'),
+            ("update", '1'),
+            ("staged", '1'),
+            ("removed", '1'),
+            ("gone", '1'),
+        ]);
+        let new = tree(&[
+            ("same", '1'),
+            ("update", '2'),
+            ("staged", '1'),
+            ("removed", '1'),
+            ("added", '2'),
+        ]);
+        let current = index(&[
+            ("same", '1'),
+            ("update", '1'),
+            ("staged", '3'),
+            ("gone", '1'),
+            ("local", '3'),
+        ]);
+        let result = two_way(&current, &old, &new, false).unwrap();
+        assert_eq!(
+            entries(&result),
+            [
+                ("added", 0, '2', false),
+                ("local", 0, '3', true),
+                ("same", 0, '1', true),
+                ("staged", 0, '3', true),
+                ("update", 0, '2', false),
+            ]
+        );
+
+        // indexがまだなければ、削除されたものとは見ない
+        let result = two_way(&Index::default(), &old, &new, true).unwrap();
+        assert_eq!(result.entries().len(), new.len());
+
+        // indexの変更もtreeの変更もあるものは上書きしない
+        let error = two_way(&index(&[("update", '3')]), &old, &new, false).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Entry 'update' would be overwritten by merge. Cannot merge."
+        );
+        let error = two_way(&index(&[]), &old, &new, false).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Entry 'update' would be overwritten by merge. Cannot merge."
+        );
+    }
+
+    #[test]
+    fn test_three_way() {
+        let base = tree(&[
+            ("theirs", '1'),
+            ("ours", '1'),
+            ("both", '1'),
+            ("conflict", '1'),
+            ("deleted", '1'),
+        ]);
+        let ours = tree(&[
+            ("theirs", '1'),
+            ("ours", '2'),
+            ("both", '2'),
+            ("conflict", '2'),
+            ("deleted", '1'),
+        ]);
+        let theirs = tree(&[
+            ("theirs", '3'),
+            ("ours", '1'),
+            ("both", '2'),
+            ("conflict", '3'),
+            ("added", '3'),
+        ]);
+        let current = index(&[
+            ("theirs", '1'),
+            ("ours", '2'),
+            ("both", '2'),
+            ("conflict", '2'),
+            ("deleted", '1'),
+        ]);
+        let result = three_way(&current, &base, &ours, &theirs).unwrap();
+        assert_eq!(
+            entries(&result),
+            [
+                ("added", 0, '3', false),
+                ("both", 0, '2', true),
+                ("conflict", 1, '1', false),
+                ("conflict", 2, '2', false),
+                ("conflict", 3, '3', false),
+                // 片側の削除は--aggressiveがなければ解決しない
+                ("deleted", 1, '1', false),
+                ("deleted", 2, '1', false),
+                ("ours", 0, '2', true),
+                ("theirs", 0, '3', false),
+            ]
+        );
+
+        // indexがoursと違えば、theirsの変更で上書きしない
+        let current = index(&[("theirs", '4')]);
+        let error = three_way(&current, &base, &ours, &theirs).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Entry 'theirs' would be overwritten by merge. Cannot merge."
+        );
+    }
+
+    #[test]
+    fn test_bind() {
+        let files = tree(&[("a", '1'), ("d/b", '2')]);
+        let result = bind(index(&[("a", '3')]), files.clone(), "sub/").unwrap();
+        assert_eq!(
+            entries(&result),
+            [
+                ("a", 0, '3', true),
+                ("sub/a", 0, '1', false),
+                ("sub/d/b", 0, '2', false)
+            ]
+        );
+
+        let error = bind(index(&[("sub/d", '3')]), files.clone(), "sub").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Entry 'sub/d/b' overlaps with 'sub/d'.  Cannot bind."
+        );
+        let error = bind(index(&[("sub/a/x", '3')]), files.clone(), "sub").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Entry 'sub/a' overlaps with 'sub/a/x'.  Cannot bind."
+        );
+        assert!(bind(Index::default(), files, ".git").is_err());
+    }
+}
@@ -0,0 +1,1706 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use git::diff::patch;
+use git::message::{self, Cleanup};
+use git::object::commit::{Commit, Sign};
+use git::object::Kind;
+use git::odb::ObjectDatabase;
+use git::pathspec::Pathspec;
+use git::reflog;
+use git::revision;
+use git::revwalk::{self, RevWalk};
+use git::status::Status;
+use git::todo::{self, Command, Item};
+use git::worktree::Worktree;
+use git::Repository;
+
+use super::merge::apply_outcome;
+use super::sequencer::{self, Action};
+use super::{commit, diff, reset, stash};
+
+/// How to go on with a rebase that stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    /// `--continue`: commit the resolved changes and run the rest of the
+    /// todo list.
+    Continue,
+    /// `--skip`: drop the changes of the commit that stopped and go on.
+    Skip,
+    /// `--abort`: go back to the branch as it was before the rebase.
+    Abort,
+    /// `--quit`: forget about the rebase, leaving `HEAD` where it is.
+    Quit,
+    /// `--edit-todo`: edit the rest of the todo list.
+    EditTodo,
+}
+
+/// Flags of `rebase`.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `--continue`, `--skip`, `--abort`, `--quit` or `--edit-todo`.
+    pub control: Option<Control>,
+    /// `--onto <newbase>`: where to put the commits instead of on the
+    /// upstream.
+    pub onto: Option<String>,
+    /// `--root`: rebase every commit of the branch, not only the ones that
+    /// are not in the upstream.
+    pub root: bool,
+    /// `-i`/`--interactive`: edit the todo list before running it.
+    pub interactive: bool,
+    /// `--[no-]autosquash`: move `fixup!` and `squash!` commits after the
+    /// commits they are meant for; `rebase.autoSquash` by default. Only
+    /// with `-i`.
+    pub autosquash: Option<bool>,
+    /// `--[no-]update-refs`: also move the other branches pointing at the
+    /// rebased commits; `rebase.updateRefs` by default.
+    pub update_refs: Option<bool>,
+    /// `--[no-]autostash`: stash local changes before the rebase and apply
+    /// them after it; `rebase.autoStash` by default.
+    pub autostash: Option<bool>,
+}
+
+/// A rebase in progress, as `status` shows it.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    /// The branch being rebased, or `None` when `HEAD` was detached.
+    pub branch: Option<String>,
+    /// The commit the branch is rebased onto.
+    pub onto: String,
+    /// The lines of the todo list already run, the current one last, with
+    /// commits abbreviated.
+    pub done: Vec<String>,
+    /// The lines left to run, with commits abbreviated.
+    pub todo: Vec<String>,
+}
+
+// 続きを記録するディレクトリ
+const REBASE_MERGE: &str = "rebase-merge";
+
+// 止まったcommitについて残すファイル
+const STOPPED_FILES: &[&str] = &["stopped-sha", "message", "author-script", "amend"];
+
+// squashやfixupが続く間の状態
+const FIXUP_FILES: &[&str] = &["current-fixups", "message-squash", "message-fixup"];
+
+// 何もないcommitの印
+const NULL_OID: &str = "0000000000000000000000000000000000000000";
+
+// エディタでtodoに添える説明
+const COMMANDS_HELP: &str = "
+Commands:
+p, pick <commit> = use commit
+r, reword <commit> = use commit, but edit the commit message
+e, edit <commit> = use commit, but stop for amending
+s, squash <commit> = use commit, but meld into previous commit
+f, fixup [-C | -c] <commit> = like \"squash\" but keep only the previous
+                   commit's log message, unless -C is used, in which case
+                   keep only this commit's message; -c is same as -C but
+                   opens the editor
+x, exec <command> = run command (the rest of the line) using shell
+b, break = stop here (continue rebase later with 'git rebase --continue')
+d, drop <commit> = remove commit
+l, label <label> = label current HEAD with a name
+t, reset <label> = reset HEAD to a label
+m, merge [-C <commit> | -c <commit>] <label> [# <oneline>]
+        create a merge commit using the original merge commit's
+        message (or the oneline, if no original merge commit was
+        specified); use -c <commit> to reword the commit message
+u, update-ref <ref> = track a placeholder for the <ref> to be updated
+                      to this position in the new commits. The <ref> is
+                      updated at the end of the rebase
+
+These lines can be re-ordered; they are executed from top to bottom.
+
+If you remove a line here THAT COMMIT WILL BE LOST.
+";
+
+const START_HELP: &str = "
+However, if you remove everything, the rebase will be aborted.
+
+";
+
+const EDIT_HELP: &str = "
+You are editing the todo file of an ongoing interactive rebase.
+To continue rebase after editing, run:
+    git rebase --continue
+
+";
+
+// エディタで直したtodo
+enum Edited {
+    Items(Vec<Item>),
+    /// 読めない行があった。その行番号と行
+    Invalid(usize, String),
+    /// エディタが失敗した
+    Failed,
+}
+
+// todoの1行を実行した結果
+enum Step {
+    /// 次の行へ進む
+    Next,
+    /// ここで止まる。falseならエラーとして止まる
+    Stop(bool),
+}
+
+/// Reapply the commits of the current branch, or of the branch in `args`
+/// after the upstream, on top of the upstream, or of `--onto`. With `-i`
+/// the list of what to do can be edited first.
+///
+/// The todo list and everything needed to go on are kept in
+/// `.git/rebase-merge`, so that a rebase stopped by conflicts, `edit`,
+/// `break` or a failing `exec` can be continued, skipped or aborted by
+/// later invocations. Returns `false` when stopped by an error.
+pub fn rebase(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<bool> {
+    let dir = state_dir(repo);
+    if let Some(control) = options.control {
+        if !dir.exists() {
+            anyhow::bail!("No rebase in progress?");
+        }
+        return match control {
+            Control::Continue if !check_todo(repo)? => Ok(false),
+            Control::Continue => resume(repo, prefix),
+            Control::Skip => skip(repo, prefix),
+            Control::Abort => abort(repo),
+            Control::Quit => quit(repo),
+            Control::EditTodo => edit_todo(repo),
+        };
+    }
+    if dir.exists() {
+        anyhow::bail!(
+            "It seems that there is already a {REBASE_MERGE} directory, and\n\
+             I wonder if you are in the middle of another rebase.  If that is the\n\
+             case, please try\n\tgit rebase (--continue | --abort | --skip)\n\
+             If that is not the case, please\n\trm -fr \".git/{REBASE_MERGE}\"\n\
+             and run me again.  I am stopping in case you still have something\n\
+             valuable there.\n"
+        );
+    }
+    start(repo, prefix, args, options)
+}
+
+/// Whether a rebase is in progress.
+pub fn is_active(repo: &Repository) -> bool {
+    state_dir(repo).is_dir()
+}
+
+/// The rebase in progress, if any.
+pub fn in_progress(repo: &Repository) -> Option<Progress> {
+    let dir = state_dir(repo);
+    let head_name = fs::read_to_string(dir.join("head-name")).ok()?;
+    let onto = fs::read_to_string(dir.join("onto")).ok()?;
+    // 読めない行も、そのまま見せる
+    let read = |name: &str| {
+        let text = fs::read_to_string(dir.join(name)).unwrap_or_default();
+        text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| match todo::parse(line).as_deref() {
+                Ok([item]) => item.line(Some(7)),
+                _ => line.to_string(),
+            })
+            .collect()
+    };
+    Some(Progress {
+        branch: head_name
+            .trim()
+            .strip_prefix("refs/heads/")
+            .map(ToString::to_string),
+        onto: onto.trim().to_string(),
+        done: read("done"),
+        todo: read("git-rebase-todo"),
+    })
+}
+
+// 引数と設定から始める
+fn start(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<bool> {
+    // --rootなら、引数はrebaseするブランチだけ
+    let (upstream_arg, branch_arg) = if options.root {
+        (None, args.first())
+    } else if let Some(arg) = args.first() {
+        (Some(arg.clone()), args.get(1))
+    } else {
+        let Some(upstream) = default_upstream(repo)? else {
+            advise_no_upstream(repo)?;
+            return Ok(false);
+        };
+        (Some(upstream), None)
+    };
+    let upstream = match &upstream_arg {
+        Some(arg) => Some(
+            revision::resolve_commit(repo, arg)
+                .map_err(|_| anyhow::anyhow!("invalid upstream '{arg}'"))?,
+        ),
+        None => None,
+    };
+    let (head_name, orig_head) = resolve_branch(repo, branch_arg)?;
+
+    let autostash = options
+        .autostash
+        .or_else(|| repo.config().get_bool("rebase.autoStash"))
+        .unwrap_or(false);
+    let stash = if autostash {
+        create_autostash(repo)?
+    } else {
+        if !check_clean(repo)? {
+            return Ok(false);
+        }
+        None
+    };
+
+    let (onto, onto_name) = resolve_onto(repo, options, upstream.as_deref(), upstream_arg)?;
+
+    if !options.interactive
+        && !options.root
+        && can_fast_forward(repo, &onto, upstream.as_deref(), &orig_head)?
+    {
+        if let Some(name) = branch_arg {
+            checkout_up_to_date(repo, name, head_name.as_deref(), &orig_head)?;
+        }
+        match head_name.as_deref() {
+            Some(name) => println!("Current branch {} is up to date.", short_branch(name)),
+            None => println!("HEAD is up to date."),
+        }
+        if let Some(stash) = stash {
+            stash::apply_autostash(repo, &stash)?;
+        }
+        return Ok(true);
+    }
+
+    // gitと同じく、--rootでも載せる先にあるcommitは除く
+    let base = if options.root {
+        Some(onto.as_str())
+    } else {
+        upstream.as_deref()
+    };
+    let mut items = todo_items(repo, options, base, &orig_head, head_name.as_deref())?;
+    fs::create_dir_all(state_dir(repo))?;
+    write_start_state(repo, options, head_name.as_deref(), &onto, &orig_head)?;
+    if let Some(stash) = &stash {
+        write_state(repo, "autostash", stash)?;
+    }
+
+    let header = header(upstream.as_deref(), &orig_head, &onto, items.len());
+    fs::write(
+        state_dir(repo).join("git-rebase-todo.backup"),
+        format!("{}{}", show_todo(&items, None), help(Some(&header))),
+    )?;
+    if options.interactive {
+        match edit(repo, &show_todo(&items, Some(7)), &help(Some(&header)))? {
+            Edited::Items(edited) if !edited.is_empty() => items = edited,
+            Edited::Items(_) => {
+                eprintln!("error: nothing to do");
+                remove_state(repo, stash.as_deref())?;
+                return Ok(false);
+            }
+            Edited::Failed => {
+                remove_state(repo, stash.as_deref())?;
+                return Ok(false);
+            }
+            Edited::Invalid(number, line) => {
+                // 直したtodoは残して、--edit-todoで直してもらう
+                eprintln!(
+                    "error: invalid line {number}: {line}\n\
+                     You can fix this with 'git rebase --edit-todo' and then run 'git rebase --continue'.\n\
+                     Or you can abort the rebase with 'git rebase --abort'."
+                );
+                let message = format!("rebase (start): checkout {onto_name}");
+                detach(repo, &onto, &message, &orig_head, stash.as_deref())?;
+                return Ok(false);
+            }
+        }
+    }
+    write_update_refs(repo, &items)?;
+    let text = todo::format(&items, None);
+    write_state(repo, "end", &text.lines().count().to_string())?;
+
+    let base = skip_unnecessary_picks(repo, &mut items, onto)?;
+    write_todo(repo, &items)?;
+
+    let message = format!("rebase (start): checkout {onto_name}");
+    if !detach(repo, &base, &message, &orig_head, stash.as_deref())? {
+        return Ok(false);
+    }
+    run_todo(repo, prefix)
+}
+
+// rebaseするブランチとcommit。ブランチでなければ名前はNone
+fn resolve_branch(
+    repo: &Repository,
+    name: Option<&String>,
+) -> anyhow::Result<(Option<String>, String)> {
+    let Some(name) = name else {
+        let head = repo
+            .refs()
+            .head()?
+            .ok_or_else(|| anyhow::anyhow!("invalid upstream 'HEAD'"))?;
+        return Ok((repo.refs().current_branch()?, head));
+    };
+    let branch = format!("refs/heads/{name}");
+    if let Some(oid) = repo.refs().resolve(&branch)? {
+        return Ok((Some(branch), oid));
+    }
+    let oid = revision::resolve_commit(repo, name)
+        .map_err(|_| anyhow::anyhow!("no such branch/commit '{name}'"))?;
+    Ok((None, oid))
+}
+
+// 載せる先と、reflogに残すその名前。--ontoも上流もなければ、空のcommitを作って使う
+fn resolve_onto(
+    repo: &Repository,
+    options: &Options,
+    upstream: Option<&str>,
+    upstream_name: Option<String>,
+) -> anyhow::Result<(String, String)> {
+    if let Some(name) = &options.onto {
+        let oid = revision::resolve_commit(repo, name)
+            .map_err(|_| anyhow::anyhow!("Does not point to a valid commit '{name}'"))?;
+        return Ok((oid, name.clone()));
+    }
+    if let (Some(upstream), Some(name)) = (upstream, upstream_name) {
+        return Ok((upstream.to_string(), name));
+    }
+    let empty_tree = repo.odb().write(Kind::Tree, b"")?;
+    let root = commit::create(repo, empty_tree, Vec::new(), String::new())?;
+    Ok((root.clone(), root))
+}
+
+// todoに添える、何をどこに載せるかの説明
+fn header(upstream: Option<&str>, head: &str, onto: &str, count: usize) -> String {
+    let revisions = match upstream {
+        Some(upstream) => format!("{}..{}", &upstream[..7], &head[..7]),
+        None => head[..7].to_string(),
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    format!(
+        "Rebase {revisions} onto {} ({count} command{plural})",
+        &onto[..7]
+    )
+}
+
+// 並べたcommitに、設定に従ってautosquashとupdate-refを加える
+fn todo_items(
+    repo: &Repository,
+    options: &Options,
+    upstream: Option<&str>,
+    head: &str,
+    head_name: Option<&str>,
+) -> anyhow::Result<Vec<Item>> {
+    let config = repo.config();
+    let mut items = list_commits(repo, upstream, head)?;
+    if options.interactive
+        && options
+            .autosquash
+            .or_else(|| config.get_bool("rebase.autoSquash"))
+            .unwrap_or(false)
+    {
+        todo::autosquash(&mut items, |name| revision::resolve_commit(repo, name).ok());
+    }
+    if options
+        .update_refs
+        .or_else(|| config.get_bool("rebase.updateRefs"))
+        .unwrap_or(false)
+    {
+        add_update_refs(repo, &mut items, head_name)?;
+    }
+    if items.is_empty() {
+        items.push(Item::new(Command::Noop, "", ""));
+    }
+    Ok(items)
+}
+
+fn write_start_state(
+    repo: &Repository,
+    options: &Options,
+    head_name: Option<&str>,
+    onto: &str,
+    orig_head: &str,
+) -> anyhow::Result<()> {
+    let dir = state_dir(repo);
+    write_state(repo, "head-name", head_name.unwrap_or("detached HEAD"))?;
+    write_state(repo, "onto", onto)?;
+    write_state(repo, "orig-head", orig_head)?;
+    fs::write(dir.join("interactive"), "")?;
+    if !options.interactive {
+        fs::write(dir.join("drop_redundant_commits"), "")?;
+    }
+    if options.root && options.onto.is_none() {
+        write_state(repo, "squash-onto", onto)?;
+    }
+    Ok(())
+}
+
+// 載せる先の子をそのままpickするだけなら、作り直さずにそのcommitから始める。
+// 飛ばした行はdoneに移し、始める位置を返す
+fn skip_unnecessary_picks(
+    repo: &Repository,
+    items: &mut Vec<Item>,
+    onto: String,
+) -> anyhow::Result<String> {
+    let mut base = onto;
+    let mut done = String::new();
+    while let Some(item) = items.first() {
+        let skip = match item.command {
+            Command::Noop | Command::Drop => true,
+            Command::Pick => repo.find_commit(&item.arg)?.parents.first() == Some(&base),
+            _ => false,
+        };
+        if !skip {
+            break;
+        }
+        if item.command == Command::Pick {
+            base = item.arg.clone();
+        }
+        done.push_str(&item.line(None));
+        done.push('\n');
+        items.remove(0);
+    }
+    fs::write(state_dir(repo).join("done"), done)?;
+    Ok(base)
+}
+
+// 始める位置にHEADを切り離す。切り替えられなければ、gitと同じく何も残さずにやめる
+fn detach(
+    repo: &Repository,
+    base: &str,
+    message: &str,
+    orig_head: &str,
+    stash: Option<&str>,
+) -> anyhow::Result<bool> {
+    let Err(e) = checkout(repo, base, message) else {
+        repo.refs().update("ORIG_HEAD", orig_head)?;
+        return Ok(true);
+    };
+    match e.downcast_ref::<git::Error>() {
+        Some(git::Error::LocalChanges(paths)) => {
+            eprintln!(
+                "error: Your local changes to the following files would be overwritten by checkout:"
+            );
+            eprintln!("\t{}", paths.join("\n\t"));
+            eprintln!("Please commit your changes or stash them before you switch branches.");
+        }
+        Some(git::Error::UntrackedOverwritten(paths)) => {
+            eprintln!(
+                "error: The following untracked working tree files would be overwritten by checkout:"
+            );
+            eprintln!("\t{}", paths.join("\n\t"));
+            eprintln!("Please move or remove them before you switch branches.");
+        }
+        _ => return Err(e),
+    }
+    eprintln!("Aborting");
+    eprintln!("error: could not detach HEAD");
+    remove_state(repo, stash)?;
+    Ok(false)
+}
+
+// 現在のブランチの上流。branch.<name>.mergeがなければNone
+fn default_upstream(repo: &Repository) -> anyhow::Result<Option<String>> {
+    let Some(branch) = repo.refs().current_branch()? else {
+        return Ok(None);
+    };
+    let name = short_branch(&branch);
+    let config = repo.config();
+    let (Some(remote), Some(merge)) = (
+        config.get(&format!("branch.{name}.remote")),
+        config.get(&format!("branch.{name}.merge")),
+    ) else {
+        return Ok(None);
+    };
+    if remote == "." {
+        return Ok(Some(merge.to_string()));
+    }
+    let merge = merge.strip_prefix("refs/heads/").unwrap_or(merge);
+    Ok(Some(format!("refs/remotes/{remote}/{merge}")))
+}
+
+fn advise_no_upstream(repo: &Repository) -> anyhow::Result<()> {
+    match repo.refs().current_branch()? {
+        Some(branch) => eprint!(
+            "There is no tracking information for the current branch.\n\
+             Please specify which branch you want to rebase against.\n\
+             See git-rebase(1) for details.\n\n    git rebase '<branch>'\n\n\
+             If you wish to set tracking information for this branch you can do so with:\n\n    \
+             git branch --set-upstream-to=<remote>/<branch> {}\n\n",
+            short_branch(&branch)
+        ),
+        None => eprint!(
+            "You are not currently on a branch.\n\
+             Please specify which branch you want to rebase against.\n\
+             See git-rebase(1) for details.\n\n    git rebase '<branch>'\n\n"
+        ),
+    }
+    Ok(())
+}
+
+fn short_branch(name: &str) -> &str {
+    name.strip_prefix("refs/heads/").unwrap_or(name)
+}
+
+// gitと同じく、indexやworking treeに変更があれば始めない
+fn check_clean(repo: &Repository) -> anyhow::Result<bool> {
+    let status = Status::collect(repo, false)?;
+    for (path, _) in &status.unmerged {
+        println!("{path}: needs merge");
+    }
+    if report_changes(&status) {
+        eprintln!("error: Please commit or stash them.");
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+// 変更があれば、その種類を知らせてtrueを返す
+fn report_changes(status: &Status) -> bool {
+    let unstaged = !status.unstaged.is_empty() || !status.unmerged.is_empty();
+    let staged = status.has_staged() || !status.unmerged.is_empty();
+    if unstaged {
+        eprintln!("error: cannot rebase: You have unstaged changes.");
+    }
+    if staged {
+        if unstaged {
+            eprintln!("error: additionally, your index contains uncommitted changes.");
+        } else {
+            eprintln!("error: cannot rebase: Your index contains uncommitted changes.");
+        }
+    }
+    unstaged || staged
+}
+
+// ontoがheadの祖先で、その間がまっすぐなら、作り直すものはない
+fn can_fast_forward(
+    repo: &Repository,
+    onto: &str,
+    upstream: Option<&str>,
+    head: &str,
+) -> anyhow::Result<bool> {
+    if revwalk::merge_bases(repo, onto, head)? != [onto] {
+        return Ok(false);
+    }
+    if let Some(upstream) = upstream {
+        if revwalk::merge_bases(repo, upstream, head)? != [onto] {
+            return Ok(false);
+        }
+    }
+    let mut walk = RevWalk::new(repo);
+    walk.push(head)?;
+    walk.hide(onto)?;
+    for item in walk {
+        let (oid, _) = item?;
+        if repo.find_commit(&oid)?.parents.len() > 1 {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+// 引数のブランチに切り替えるだけで済ませる
+fn checkout_up_to_date(
+    repo: &Repository,
+    name: &str,
+    head_name: Option<&str>,
+    oid: &str,
+) -> anyhow::Result<()> {
+    let head = repo.refs().head()?;
+    let files = Worktree::new(repo)?;
+    let mut index = repo.index()?;
+    files.checkout_tree(&mut index, head.as_deref(), oid, false)?;
+    repo.write_index(&index)?;
+    match head_name {
+        Some(branch) => repo.refs().set_symbolic("HEAD", branch)?,
+        None => repo.refs().update("HEAD", oid)?,
+    }
+    let entry = reflog::Entry::new(
+        head.as_deref(),
+        oid,
+        commit::committer(repo)?,
+        &format!("rebase: checkout {name}"),
+    );
+    repo.refs().append_log("HEAD", &entry)?;
+    Ok(())
+}
+
+// upstreamにないcommitを古い順に並べる。マージと、同じ変更がupstreamにあるものは除く
+fn list_commits(
+    repo: &Repository,
+    upstream: Option<&str>,
+    head: &str,
+) -> anyhow::Result<Vec<Item>> {
+    let mut walk = RevWalk::new(repo);
+    walk.push(head)?;
+    if let Some(upstream) = upstream {
+        walk.hide(upstream)?;
+    }
+    let oids = walk
+        .map(|item| item.map(|(oid, _)| oid))
+        .collect::<Result<Vec<_>, _>>()?;
+    let oids = topo_order(repo, head, &oids)?;
+
+    let mut upstream_ids = HashSet::new();
+    if let Some(upstream) = upstream {
+        let mut walk = RevWalk::new(repo);
+        walk.push(upstream)?;
+        walk.hide(head)?;
+        for item in walk {
+            let (oid, _) = item?;
+            let commit = repo.find_commit(&oid)?;
+            if commit.parents.len() <= 1 {
+                upstream_ids.insert(patch_id(repo, &commit)?);
+            }
+        }
+    }
+
+    let mut items = Vec::new();
+    for oid in oids {
+        let commit = repo.find_commit(&oid)?;
+        if commit.parents.len() > 1 {
+            continue;
+        }
+        let empty = commit.tree == parent_tree(repo, &commit)?;
+        if !empty && !upstream_ids.is_empty() && upstream_ids.contains(&patch_id(repo, &commit)?) {
+            eprintln!("warning: skipped previously applied commit {}", &oid[..7]);
+            eprintln!(
+                "hint: use --reapply-cherry-picks to include skipped commits\n\
+                 hint: Disable this message with \"git config advice.skippedCherryPicks false\""
+            );
+            continue;
+        }
+        items.push(Item::commit(Command::Pick, &oid, commit.summary(), empty));
+    }
+    Ok(items)
+}
+
+// gitの--topo-orderを逆にした順。子を全部出してから親を出し、後の親の側を先に辿る
+fn topo_order(repo: &Repository, head: &str, oids: &[String]) -> anyhow::Result<Vec<String>> {
+    let parents = oids
+        .iter()
+        .map(|oid| Ok((oid.as_str(), repo.find_commit(oid)?.parents)))
+        .collect::<anyhow::Result<HashMap<&str, Vec<String>>>>()?;
+    let mut children: HashMap<&str, usize> = HashMap::new();
+    for parent in parents.values().flatten() {
+        *children.entry(parent.as_str()).or_default() += 1;
+    }
+    let mut order = Vec::with_capacity(oids.len());
+    let mut stack: Vec<&str> = Vec::new();
+    if parents.contains_key(head) {
+        stack.push(head);
+    }
+    while let Some(oid) = stack.pop() {
+        order.push(oid.to_string());
+        for parent in &parents[oid] {
+            let Some(count) = children.get_mut(parent.as_str()) else {
+                continue;
+            };
+            *count -= 1;
+            if *count == 0 && parents.contains_key(parent.as_str()) {
+                stack.push(parent);
+            }
+        }
+    }
+    order.reverse();
+    Ok(order)
+}
+
+fn parent_tree(repo: &Repository, commit: &Commit) -> anyhow::Result<String> {
+    match commit.parents.first() {
+        Some(parent) => Ok(repo.find_commit(parent)?.tree),
+        None => Ok(ObjectDatabase::hash(Kind::Tree, b"")),
+    }
+}
+
+// commitの変更の指紋。行番号とindex行、空白の違いは見ない
+fn patch_id(repo: &Repository, commit: &Commit) -> anyhow::Result<String> {
+    let parent = commit.parents.first().map(String::as_str);
+    let pairs = diff::tree_pairs(repo, parent, &commit.tree, &Pathspec::default())?;
+    let mut text = Vec::new();
+    for pair in &pairs {
+        patch::write(&mut text, pair, &patch::Options::default())?;
+    }
+    let text = String::from_utf8_lossy(&text);
+    let mut normalized = String::new();
+    for line in text.lines() {
+        if line.starts_with("index ") {
+            continue;
+        }
+        let line = if line.starts_with("@@") { "@@" } else { line };
+        normalized.extend(line.chars().filter(|c| !c.is_whitespace()));
+        normalized.push('\n');
+    }
+    Ok(ObjectDatabase::hash(Kind::Blob, normalized.as_bytes()))
+}
+
+// 並べたcommitを指している他のブランチを、そのcommit(とfixup)の後でupdate-refする
+fn add_update_refs(
+    repo: &Repository,
+    items: &mut Vec<Item>,
+    head_name: Option<&str>,
+) -> anyhow::Result<()> {
+    let branches: Vec<(String, String)> = repo
+        .refs()
+        .list("refs/heads/")?
+        .into_iter()
+        .filter(|(name, _)| Some(name.as_str()) != head_name)
+        .collect();
+    let mut result = Vec::with_capacity(items.len());
+    let mut pending: Vec<Item> = Vec::new();
+    for item in items.drain(..) {
+        if !item.command.is_fixup() {
+            result.append(&mut pending);
+        }
+        if item.command.takes_commit() {
+            pending.extend(
+                // gitと同じく、名前の逆順に並べる
+                branches
+                    .iter()
+                    .rev()
+                    .filter(|(_, oid)| *oid == item.arg)
+                    .map(|(name, _)| Item::new(Command::UpdateRef, name.clone(), "")),
+            );
+        }
+        result.push(item);
+    }
+    result.append(&mut pending);
+    *items = result;
+    Ok(())
+}
+
+// todoのupdate-refの対象と、その今の位置を残す。新しい位置は実行したときに書く
+fn write_update_refs(repo: &Repository, items: &[Item]) -> anyhow::Result<()> {
+    let mut text = String::new();
+    for item in items.iter().filter(|i| i.command == Command::UpdateRef) {
+        let old = repo.refs().resolve(&item.arg)?.unwrap_or_default();
+        writeln!(text, "{}\n{old}\n{NULL_OID}", item.arg)?;
+    }
+    if !text.is_empty() {
+        write_state(repo, "update-refs", &text)?;
+    }
+    Ok(())
+}
+
+// 説明を付けて見せるtodo。gitと同じく、最後のupdate-refの後にも空行を置く
+fn show_todo(items: &[Item], abbrev: Option<usize>) -> String {
+    let mut text = todo::format(items, abbrev);
+    if items
+        .last()
+        .is_some_and(|item| item.command == Command::UpdateRef)
+    {
+        text.push('\n');
+    }
+    text
+}
+
+// todoをエディタで直してもらう
+fn edit(repo: &Repository, text: &str, help: &str) -> anyhow::Result<Edited> {
+    let path = state_dir(repo).join("git-rebase-todo");
+    fs::write(&path, format!("{text}{help}"))?;
+    if !run_sequence_editor(repo, &path)? {
+        return Ok(Edited::Failed);
+    }
+    let text = fs::read_to_string(&path)?;
+    Ok(match parse_todo(repo, &text) {
+        Ok(items) => Edited::Items(items),
+        Err((number, line)) => Edited::Invalid(number, line),
+    })
+}
+
+// GIT_SEQUENCE_EDITORかsequence.editorがあればそれで、なければcommitと同じエディタで開く
+fn run_sequence_editor(repo: &Repository, path: &Path) -> anyhow::Result<bool> {
+    let editor = env::var("GIT_SEQUENCE_EDITOR").ok().or_else(|| {
+        repo.config()
+            .get("sequence.editor")
+            .map(ToString::to_string)
+    });
+    let Some(editor) = editor else {
+        return commit::run_editor(repo, path);
+    };
+    let status = process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$@\""))
+        .arg(&editor)
+        .arg(path)
+        .status()?;
+    if !status.success() {
+        eprintln!("error: There was a problem with the editor '{editor}'.");
+    }
+    Ok(status.success())
+}
+
+fn help(header: Option<&str>) -> String {
+    let mut text = String::new();
+    if let Some(header) = header {
+        writeln!(text, "\n# {header}").unwrap_or_default();
+    }
+    let tail = if header.is_some() {
+        START_HELP
+    } else {
+        EDIT_HELP
+    };
+    for line in COMMANDS_HELP.lines().chain(tail.lines()) {
+        if line.is_empty() {
+            text.push_str("#\n");
+        } else {
+            writeln!(text, "# {line}").unwrap_or_default();
+        }
+    }
+    text
+}
+
+// todoを読んで、commitを完全なhashにする
+fn parse_todo(repo: &Repository, text: &str) -> Result<Vec<Item>, (usize, String)> {
+    let mut items = todo::parse(text)?;
+    for item in &mut items {
+        if item.command.takes_commit() {
+            let Ok(oid) = revision::resolve_commit(repo, &item.arg) else {
+                let line = item.line(None);
+                let number = text
+                    .lines()
+                    .position(|l| l.trim() == line)
+                    .map_or(0, |n| n + 1);
+                return Err((number, line));
+            };
+            item.arg = oid;
+        }
+    }
+    Ok(items)
+}
+
+fn read_todo(repo: &Repository) -> anyhow::Result<Vec<Item>> {
+    let text = read_state(repo, "git-rebase-todo")?.unwrap_or_default();
+    parse_todo(repo, &text).map_err(|(number, line)| {
+        anyhow::anyhow!(
+            "invalid line {number}: {line}\n\
+             You can fix this with 'git rebase --edit-todo' and then run 'git rebase --continue'.\n\
+             Or you can abort the rebase with 'git rebase --abort'."
+        )
+    })
+}
+
+fn write_todo(repo: &Repository, items: &[Item]) -> anyhow::Result<()> {
+    write_state(repo, "git-rebase-todo", &todo::format(items, None))
+}
+
+// HEADを切り離して、commitに移る
+fn checkout(repo: &Repository, oid: &str, reflog_message: &str) -> anyhow::Result<()> {
+    let head = repo.refs().head()?;
+    let files = Worktree::new(repo)?;
+    let mut index = repo.index()?;
+    files.checkout_tree(&mut index, head.as_deref(), oid, false)?;
+    repo.write_index(&index)?;
+    repo.refs().update("HEAD", oid)?;
+    let entry = reflog::Entry::new(
+        head.as_deref(),
+        oid,
+        commit::committer(repo)?,
+        reflog_message,
+    );
+    repo.refs().append_log("HEAD", &entry)?;
+    Ok(())
+}
+
+// todoを先頭から1行ずつ実行する。実行した行はdoneに移す
+fn run_todo(repo: &Repository, prefix: &Path) -> anyhow::Result<bool> {
+    loop {
+        let mut items = read_todo(repo)?;
+        if items.is_empty() {
+            return finish(repo);
+        }
+        let item = items.remove(0);
+        write_todo(repo, &items)?;
+        let mut done = read_state(repo, "done")?.unwrap_or_default();
+        writeln!(done, "{}", item.line(None))?;
+        write_state(repo, "done", &done)?;
+        let number = done.lines().filter(|line| !line.is_empty()).count();
+        write_state(repo, "msgnum", &number.to_string())?;
+        let end = read_state(repo, "end")?.unwrap_or_default();
+        eprint!("Rebasing ({number}/{})\r", end.trim());
+
+        let final_fixup = !items.first().is_some_and(|next| next.command.is_fixup());
+        let step = match item.command {
+            Command::Pick | Command::Reword | Command::Edit | Command::Squash | Command::Fixup => {
+                pick(repo, prefix, &item, final_fixup)?
+            }
+            Command::Exec => exec(repo, &item.arg)?,
+            Command::Break => {
+                let head = head(repo)?;
+                let subject = repo.find_commit(&head)?.summary().to_string();
+                eprint!("{}", clear_line());
+                eprintln!("Stopped at {} ({subject})", &head[..7]);
+                Step::Stop(true)
+            }
+            Command::UpdateRef => {
+                record_update_ref(repo, &item.arg)?;
+                Step::Next
+            }
+            Command::Drop | Command::Noop => Step::Next,
+        };
+        if let Step::Stop(ok) = step {
+            return Ok(ok);
+        }
+    }
+}
+
+fn head(repo: &Repository) -> anyhow::Result<String> {
+    repo.refs()
+        .head()?
+        .ok_or_else(|| anyhow::anyhow!("could not read HEAD"))
+}
+
+// gitと同じく、端末でなければ空白で行を消す
+fn clear_line() -> String {
+    match env::var("TERM") {
+        Ok(term) if term != "dumb" => "\r\x1b[K".to_string(),
+        _ => format!("\r{}\r", " ".repeat(80)),
+    }
+}
+
+// commitの変更をHEADに重ねる。親がHEADならそのcommitに進むだけにする
+fn pick(repo: &Repository, prefix: &Path, item: &Item, final_fixup: bool) -> anyhow::Result<Step> {
+    let oid = &item.arg;
+    let commit = repo.find_commit(oid)?;
+    if commit.parents.len() > 1 {
+        eprintln!("error: commit {oid} is a merge but no -m option was given.");
+        return stop_with_error(repo, item, &commit);
+    }
+    let parent = commit.parents.first().map(String::as_str);
+    let head = head(repo)?;
+    // --rootで作った空のcommitの上には、親のないcommitとして載せる
+    let unborn = read_line(repo, "squash-onto")?.as_deref() == Some(head.as_str());
+    let fixup = item.command.is_fixup();
+    if !fixup && (parent == Some(head.as_str()) || (parent.is_none() && unborn)) {
+        checkout(repo, oid, "rebase: fast-forward")?;
+        return match item.command {
+            Command::Edit => stop_to_edit(repo, oid, &commit),
+            Command::Reword => {
+                let options = commit::Options {
+                    amend: true,
+                    edit: Some(true),
+                    reflog_action: Some("rebase (reword)".to_string()),
+                    ..commit::Options::default()
+                };
+                Ok(step(commit::commit(repo, prefix, &options)?))
+            }
+            _ => Ok(Step::Next),
+        };
+    }
+
+    if fixup {
+        // 衝突しても--continueがメッセージを使えるよう、先にまとめておく
+        update_squash_messages(repo, item, &commit, &repo.find_commit(&head)?)?;
+    }
+    let files = Worktree::new(repo)?;
+    let mut index = repo.index()?;
+    let head_files = files.tree_map(Some(&head))?;
+    let outcome = sequencer::merge_changes(repo, Action::Pick, oid, &commit, parent, &head)?;
+    if !apply_outcome(repo, &files, &mut index, &head_files, &outcome)? {
+        return stop_with_error(repo, item, &commit);
+    }
+    if !outcome.is_clean() {
+        // gitと同じく、マージの経過は衝突したときだけ見せる
+        for message in &outcome.messages {
+            println!("{message}");
+        }
+        return stop_for_conflicts(repo, item, &commit, &outcome.conflicts);
+    }
+    let tree = index.write_tree(repo.odb())?;
+    if fixup {
+        return squash(repo, prefix, item, tree, final_fixup);
+    }
+
+    let head_tree = revision::peel(repo, &head, Kind::Tree)?;
+    if tree == head_tree && commit.tree != parent_tree(repo, &commit)? {
+        if state_dir(repo).join("drop_redundant_commits").exists() {
+            println!(
+                "dropping {oid} {} -- patch contents already upstream",
+                commit.summary()
+            );
+            return Ok(Step::Next);
+        }
+        return stop_empty(repo, prefix, item, &commit);
+    }
+    let parents = if unborn { Vec::new() } else { vec![head] };
+    if item.command == Command::Reword {
+        let path = state_dir(repo).join("message");
+        fs::write(&path, &commit.message)?;
+        let options = commit::Options {
+            file: Some(path),
+            edit: Some(true),
+            allow_empty: true,
+            author: Some(commit.author.clone()),
+            reflog_action: Some("rebase (reword)".to_string()),
+            ..commit::Options::default()
+        };
+        return Ok(step(commit::commit(repo, prefix, &options)?));
+    }
+    let subject = commit
+        .message
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let new = commit::create_authored(
+        repo,
+        tree,
+        parents,
+        commit.author.clone(),
+        commit.message.clone(),
+    )?;
+    let reflog_message = format!("rebase ({}): {subject}", item.command.name());
+    repo.refs()
+        .update_head_logged(&new, &commit::committer(repo)?, &reflog_message)?;
+    if item.command == Command::Edit {
+        return stop_to_edit(repo, oid, &commit);
+    }
+    Ok(Step::Next)
+}
+
+fn step(committed: bool) -> Step {
+    if committed {
+        Step::Next
+    } else {
+        Step::Stop(false)
+    }
+}
+
+// squashとfixupは、変更をHEADに足してcommitし直す。続きの最後でメッセージを決める
+fn squash(
+    repo: &Repository,
+    prefix: &Path,
+    item: &Item,
+    tree: String,
+    final_fixup: bool,
+) -> anyhow::Result<Step> {
+    let head = head(repo)?;
+    let amended = repo.find_commit(&head)?;
+    let command = item.command.name();
+    if final_fixup && read_state(repo, "message-fixup")?.is_none() {
+        // squashがあれば、まとめたメッセージをエディタで直してもらう
+        let path = state_dir(repo).join("message-squash");
+        let options = commit::Options {
+            file: Some(path),
+            edit: Some(true),
+            amend: true,
+            allow_empty: true,
+            reflog_action: Some(format!("rebase ({command})")),
+            ..commit::Options::default()
+        };
+        let committed = commit::commit(repo, prefix, &options)?;
+        if committed {
+            remove_files(&state_dir(repo), FIXUP_FILES)?;
+        }
+        return Ok(step(committed));
+    }
+    let message = if final_fixup {
+        read_state(repo, "message-fixup")?.unwrap_or_default()
+    } else {
+        let text = read_state(repo, "message-squash")?.unwrap_or_default();
+        message::stripspace(&text, false)
+    };
+    let subject = message.lines().next().unwrap_or_default().to_string();
+    let new = commit::create_authored(
+        repo,
+        tree,
+        amended.parents.clone(),
+        amended.author.clone(),
+        message,
+    )?;
+    let reflog_message = format!("rebase ({command}): {subject}");
+    repo.refs()
+        .update_head_logged(&new, &commit::committer(repo)?, &reflog_message)?;
+    if final_fixup {
+        remove_files(&state_dir(repo), FIXUP_FILES)?;
+    }
+    Ok(Step::Next)
+}
+
+// gitと同じ形で、まとめるcommitのメッセージを並べていく
+fn update_squash_messages(
+    repo: &Repository,
+    item: &Item,
+    commit: &Commit,
+    amended: &Commit,
+) -> anyhow::Result<()> {
+    let fixups = read_state(repo, "current-fixups")?.unwrap_or_default();
+    let count = fixups.lines().count();
+    let mut text = if count == 0 {
+        if item.command == Command::Fixup {
+            write_state(repo, "message-fixup", &amended.message)?;
+        }
+        format!(
+            "# This is a combination of 2 commits.\n# This is the 1st commit message:\n\n{}",
+            amended.message
+        )
+    } else {
+        let text = read_state(repo, "message-squash")?.unwrap_or_default();
+        let rest = text.split_once('\n').map_or("", |(_, rest)| rest);
+        format!("# This is a combination of {} commits.\n{rest}", count + 2)
+    };
+    let body = &commit.message;
+    if item.command == Command::Squash {
+        fs::remove_file(state_dir(repo).join("message-fixup")).or_else(ignore_missing)?;
+        writeln!(text, "\n# This is the commit message #{}:\n", count + 2)?;
+        // まとめる先を示すだけの件名は、コメントにしておく
+        let commented = if body.starts_with("squash!") || body.starts_with("fixup!") {
+            body.lines()
+                .take_while(|line| !line.trim().is_empty())
+                .count()
+        } else {
+            0
+        };
+        for (i, line) in body.lines().enumerate() {
+            if i < commented {
+                push_commented(&mut text, line);
+            } else {
+                writeln!(text, "{line}")?;
+            }
+        }
+    } else {
+        writeln!(
+            text,
+            "\n# The commit message #{} will be skipped:\n",
+            count + 2
+        )?;
+        for line in body.lines() {
+            push_commented(&mut text, line);
+        }
+    }
+    write_state(repo, "message-squash", &text)?;
+    // gitと同じく、最後の行は改行で終えない
+    let separator = if fixups.is_empty() { "" } else { "\n" };
+    fs::write(
+        state_dir(repo).join("current-fixups"),
+        format!("{fixups}{separator}{} {}", item.command.name(), item.arg),
+    )?;
+    Ok(())
+}
+
+fn push_commented(text: &mut String, line: &str) {
+    if line.is_empty() {
+        text.push_str("#\n");
+    } else {
+        text.push_str("# ");
+        text.push_str(line);
+        text.push('\n');
+    }
+}
+
+fn ignore_missing(e: std::io::Error) -> std::io::Result<()> {
+    if e.kind() == ErrorKind::NotFound {
+        Ok(())
+    } else {
+        Err(e)
+    }
+}
+
+// 止まったcommitを、--continueがcommitできるよう残す
+fn save_stopped(repo: &Repository, oid: &str, commit: &Commit) -> anyhow::Result<()> {
+    write_state(repo, "stopped-sha", oid)?;
+    fs::write(
+        state_dir(repo).join("message"),
+        format!("{}\n", commit.message),
+    )?;
+    fs::write(
+        state_dir(repo).join("author-script"),
+        author_script(&commit.author),
+    )?;
+    fs::write(repo.git_dir().join("REBASE_HEAD"), format!("{oid}\n"))?;
+    Ok(())
+}
+
+fn stop_to_edit(repo: &Repository, oid: &str, commit: &Commit) -> anyhow::Result<Step> {
+    save_stopped(repo, oid, commit)?;
+    write_state(repo, "amend", &head(repo)?)?;
+    eprint!("{}", clear_line());
+    eprint!(
+        "Stopped at {}...  {}\n\
+         You can amend the commit now, with\n\n  git commit --amend \n\n\
+         Once you are satisfied with your changes, run\n\n  git rebase --continue\n",
+        &oid[..7],
+        commit.summary()
+    );
+    Ok(Step::Stop(true))
+}
+
+fn stop_for_conflicts(
+    repo: &Repository,
+    item: &Item,
+    commit: &Commit,
+    conflicts: &[String],
+) -> anyhow::Result<Step> {
+    save_stopped(repo, &item.arg, commit)?;
+    if item.command.is_fixup() {
+        // まとめている途中なら、--continueはまとめたメッセージでamendする
+        let message = read_state(repo, "message-squash")?.unwrap_or_default();
+        fs::write(repo.git_dir().join("MERGE_MSG"), &message)?;
+        if !state_dir(repo).join("message-fixup").exists() {
+            fs::write(repo.git_dir().join("SQUASH_MSG"), &message)?;
+        }
+        fs::write(state_dir(repo).join("message"), &message)?;
+        write_state(repo, "amend", &head(repo)?)?;
+    } else {
+        let mut message = commit.message.clone();
+        message.push_str("\n# Conflicts:\n");
+        for path in conflicts {
+            writeln!(message, "#\t{path}")?;
+        }
+        fs::write(repo.git_dir().join("MERGE_MSG"), message)?;
+    }
+    let label = format!("{}... {}", &item.arg[..7], commit.summary());
+    eprintln!("error: could not apply {label}");
+    eprintln!(
+        "hint: Resolve all conflicts manually, mark them as resolved with\n\
+         hint: \"git add/rm <conflicted_files>\", then run \"git rebase --continue\".\n\
+         hint: You can instead skip this commit: run \"git rebase --skip\".\n\
+         hint: To abort and get back to the state before \"git rebase\", run \"git rebase --abort\"."
+    );
+    eprintln!("Could not apply {label}");
+    Ok(Step::Stop(false))
+}
+
+// 上流に同じ変更があって空になったcommitは、-iでは--skipするか決めてもらう
+fn stop_empty(
+    repo: &Repository,
+    prefix: &Path,
+    item: &Item,
+    commit: &Commit,
+) -> anyhow::Result<Step> {
+    fs::write(repo.git_dir().join("MERGE_MSG"), &commit.message)?;
+    // gitと同じく、commit --allow-emptyで取り出したcommitの作者を使えるようにする
+    fs::write(
+        repo.git_dir().join("CHERRY_PICK_HEAD"),
+        format!("{}\n", item.arg),
+    )?;
+    save_stopped(repo, &item.arg, commit)?;
+    eprint!(
+        "The previous cherry-pick is now empty, possibly due to conflict resolution.\n\
+         If you wish to commit it anyway, use:\n\n    git commit --allow-empty\n\n\
+         Otherwise, please use 'git rebase --skip'\n"
+    );
+    let status = Status::collect(repo, false)?;
+    let lines =
+        super::status::long_lines(repo, &status, prefix, false, super::status::Context::Commit)?;
+    for line in lines {
+        println!("{line}");
+    }
+    eprintln!("Could not apply {}... {}", &item.arg[..7], commit.summary());
+    Ok(Step::Stop(false))
+}
+
+fn stop_with_error(repo: &Repository, item: &Item, commit: &Commit) -> anyhow::Result<Step> {
+    save_stopped(repo, &item.arg, commit)?;
+    eprintln!("Could not apply {}... {}", &item.arg[..7], commit.summary());
+    Ok(Step::Stop(false))
+}
+
+// シェルのコマンドを実行する。失敗したり変更を残したりすれば止まる
+fn exec(repo: &Repository, command: &str) -> anyhow::Result<Step> {
+    eprint!("{}", clear_line());
+    eprintln!("Executing: {command}");
+    let status = process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(repo.require_work_tree()?)
+        .status()?;
+    let dirty = report_changes(&Status::collect(repo, false)?);
+    if !status.success() {
+        eprintln!("warning: execution failed: {command}");
+        if dirty {
+            eprintln!("and made changes to the index and/or the working tree");
+        }
+        eprint!("You can fix the problem, and then run\n\n  git rebase --continue\n\n\n");
+        return Ok(Step::Stop(false));
+    }
+    if dirty {
+        eprint!(
+            "warning: execution succeeded: {command}\n\
+             but left changes to the index and/or the working tree\n\
+             Commit or stash your changes, and then run\n\n  git rebase --continue\n\n\n"
+        );
+        return Ok(Step::Stop(false));
+    }
+    Ok(Step::Next)
+}
+
+// update-refの行に来たら、そのときのHEADを新しい位置として残す
+fn record_update_ref(repo: &Repository, name: &str) -> anyhow::Result<()> {
+    let head = head(repo)?;
+    let text = read_state(repo, "update-refs")?.unwrap_or_default();
+    let mut lines: Vec<String> = text.lines().map(ToString::to_string).collect();
+    match lines.iter().position(|line| line == name) {
+        Some(i) if i + 2 < lines.len() => lines[i + 2] = head,
+        _ => {
+            let old = repo.refs().resolve(name)?.unwrap_or_default();
+            lines.extend([name.to_string(), old, head]);
+        }
+    }
+    write_state(repo, "update-refs", &(lines.join("\n") + "\n"))
+}
+
+// ブランチを作り直したcommitに向けて戻る
+fn finish(repo: &Repository) -> anyhow::Result<bool> {
+    let head = head(repo)?;
+    let head_name = read_line(repo, "head-name")?.unwrap_or_default();
+    let onto = read_line(repo, "onto")?.unwrap_or_default();
+    let committer = commit::committer(repo)?;
+    if head_name.starts_with("refs/") {
+        let message = format!("rebase (finish): {head_name} onto {onto}");
+        let old = repo.refs().resolve(&head_name)?;
+        if old.as_deref() != Some(head.as_str()) {
+            repo.refs().update(&head_name, &head)?;
+            let entry = reflog::Entry::new(old.as_deref(), &head, committer.clone(), &message);
+            repo.refs().append_log(&head_name, &entry)?;
+        }
+        repo.refs().set_symbolic("HEAD", &head_name)?;
+        let message = format!("rebase (finish): returning to {head_name}");
+        let entry = reflog::Entry::new(Some(&head), &head, committer.clone(), &message);
+        repo.refs().append_log("HEAD", &entry)?;
+    }
+    let updated = update_refs(repo, &committer)?;
+    // gitと同じく、終わったと伝える前にautostashを戻す
+    let stash = read_line(repo, "autostash")?;
+    remove_state(repo, stash.as_deref())?;
+    eprint!("{}", clear_line());
+    eprintln!("Successfully rebased and updated {head_name}.");
+    if !updated.is_empty() {
+        eprintln!("Updated the following refs with --update-refs:");
+        for name in updated {
+            eprintln!("\t{name}");
+        }
+    }
+    Ok(true)
+}
+
+// update-refの行で残した位置にブランチを動かす
+fn update_refs(repo: &Repository, committer: &Sign) -> anyhow::Result<Vec<String>> {
+    let text = read_state(repo, "update-refs")?.unwrap_or_default();
+    let lines: Vec<&str> = text.lines().collect();
+    let mut updated = Vec::new();
+    for chunk in lines.chunks(3) {
+        let [name, _, new] = chunk else { continue };
+        if *new == NULL_OID {
+            continue;
+        }
+        repo.refs()
+            .update_logged(name, new, committer, "rewritten during rebase")?;
+        updated.push((*name).to_string());
+    }
+    updated.sort();
+    Ok(updated)
+}
+
+// 止まったところの変更をcommitしてから、残りを続ける
+fn resume(repo: &Repository, prefix: &Path) -> anyhow::Result<bool> {
+    let index = repo.index()?;
+    let mut unmerged: Vec<&str> = index
+        .entries()
+        .iter()
+        .filter(|e| e.stage != 0)
+        .map(|e| e.path.as_str())
+        .collect();
+    if !unmerged.is_empty() {
+        unmerged.dedup();
+        for path in unmerged {
+            println!("{path}: needs merge");
+        }
+        eprintln!(
+            "You must edit all merge conflicts and then\nmark them as resolved using git add"
+        );
+        return Ok(false);
+    }
+    let status = Status::collect(repo, false)?;
+    if !status.unstaged.is_empty() {
+        eprintln!("error: cannot rebase: You have unstaged changes.");
+        eprintln!("error: Please commit or stash them.");
+        return Ok(false);
+    }
+    if status.has_staged() && !commit_staged(repo, prefix)? {
+        return Ok(false);
+    }
+    clear_stopped(repo)?;
+    let done = read_state(repo, "done")?.unwrap_or_default();
+    let remaining = read_todo(repo)?.len();
+    let count = done.lines().filter(|line| !line.is_empty()).count();
+    write_state(repo, "end", &(count + remaining).to_string())?;
+    run_todo(repo, prefix)
+}
+
+// --continueで、止まったcommitを解決した変更でcommitする
+fn commit_staged(repo: &Repository, prefix: &Path) -> anyhow::Result<bool> {
+    let dir = state_dir(repo);
+    let reflog_action = Some("rebase (continue)".to_string());
+    let last = todo::parse(&read_state(repo, "done")?.unwrap_or_default())
+        .unwrap_or_default()
+        .pop();
+    if last.is_some_and(|item| item.command.is_fixup()) && dir.join("current-fixups").exists() {
+        // まとめている途中なら、最後の1つのときだけメッセージを直してもらう
+        let final_fixup = !read_todo(repo)?
+            .first()
+            .is_some_and(|next| next.command.is_fixup());
+        let fixup = dir.join("message-fixup");
+        let (file, edit, cleanup) = match (final_fixup, fixup.exists()) {
+            (true, true) => (fixup, false, None),
+            (true, false) => (dir.join("message-squash"), true, None),
+            (false, _) => (dir.join("message-squash"), false, Some(Cleanup::Strip)),
+        };
+        let options = commit::Options {
+            file: Some(file),
+            edit: Some(edit),
+            amend: true,
+            cleanup,
+            reflog_action,
+            ..commit::Options::default()
+        };
+        let committed = commit::commit(repo, prefix, &options)?;
+        if committed && final_fixup {
+            remove_files(&dir, FIXUP_FILES)?;
+        }
+        return Ok(committed);
+    }
+    let amend = read_line(repo, "amend")?;
+    if amend.is_some() && amend == repo.refs().head()? {
+        // editで止まっていれば、そのcommitを直す
+        let options = commit::Options {
+            amend: true,
+            edit: Some(true),
+            reflog_action,
+            ..commit::Options::default()
+        };
+        return commit::commit(repo, prefix, &options);
+    }
+    let author = read_state(repo, "author-script")?
+        .as_deref()
+        .and_then(parse_author_script);
+    let options = commit::Options {
+        file: Some(dir.join("message")),
+        edit: Some(true),
+        author,
+        reflog_action,
+        ..commit::Options::default()
+    };
+    commit::commit(repo, prefix, &options)
+}
+
+// 止まったcommitの変更を捨てて、残りを続ける
+fn skip(repo: &Repository, prefix: &Path) -> anyhow::Result<bool> {
+    let head = head(repo)?;
+    reset_hard(repo, &head)?;
+    if !check_todo(repo)? {
+        return Ok(false);
+    }
+    clear_stopped(repo)?;
+    let done = read_state(repo, "done")?.unwrap_or_default();
+    let remaining = read_todo(repo)?.len();
+    let count = done.lines().filter(|line| !line.is_empty()).count();
+    write_state(repo, "end", &(count + remaining).to_string())?;
+    run_todo(repo, prefix)
+}
+
+// 始める前のブランチに戻す
+fn abort(repo: &Repository) -> anyhow::Result<bool> {
+    let orig_head =
+        read_line(repo, "orig-head")?.ok_or_else(|| anyhow::anyhow!("could not read orig-head"))?;
+    let head_name = read_line(repo, "head-name")?.unwrap_or_default();
+    let head = repo.refs().head()?;
+    reset_hard(repo, &orig_head)?;
+    let target = if head_name.starts_with("refs/") {
+        repo.refs().set_symbolic("HEAD", &head_name)?;
+        head_name
+    } else {
+        repo.refs().update("HEAD", &orig_head)?;
+        orig_head.clone()
+    };
+    let entry = reflog::Entry::new(
+        head.as_deref(),
+        &orig_head,
+        commit::committer(repo)?,
+        &format!("rebase (abort): returning to {target}"),
+    );
+    repo.refs().append_log("HEAD", &entry)?;
+    clear_stopped(repo)?;
+    let stash = read_line(repo, "autostash")?;
+    remove_state(repo, None)?;
+    if let Some(stash) = stash {
+        stash::apply_autostash(repo, &stash)?;
+    }
+    Ok(true)
+}
+
+// HEADはそのままで、rebaseのことを忘れる
+fn quit(repo: &Repository) -> anyhow::Result<bool> {
+    let stash = read_line(repo, "autostash")?;
+    remove_state(repo, None)?;
+    if let Some(stash) = stash {
+        eprintln!("Autostash exists; creating a new stash entry.");
+        stash::store_autostash(repo, &stash)?;
+    }
+    Ok(true)
+}
+
+fn edit_todo(repo: &Repository) -> anyhow::Result<bool> {
+    if !state_dir(repo).join("interactive").exists() {
+        anyhow::bail!("The --edit-todo action can only be used during interactive rebase.");
+    }
+    let help = help(None);
+    let text = read_state(repo, "git-rebase-todo")?.unwrap_or_default();
+    let text = match parse_todo(repo, &text) {
+        Ok(items) => {
+            fs::write(
+                state_dir(repo).join("git-rebase-todo.backup"),
+                format!("{}{help}", todo::format(&items, None)),
+            )?;
+            todo::format(&items, Some(7))
+        }
+        Err((number, line)) => {
+            // 読めない行があれば、コメントを除いてそのままの形で直してもらう
+            eprintln!("error: invalid line {number}: {line}");
+            let mut lines = String::new();
+            for line in text.lines() {
+                if !line.trim().is_empty() && !line.trim_start().starts_with('#') {
+                    writeln!(lines, "{line}")?;
+                }
+            }
+            lines
+        }
+    };
+    match edit(repo, &text, &help)? {
+        Edited::Items(items) => write_todo(repo, &items)?,
+        Edited::Invalid(number, line) => {
+            eprintln!(
+                "error: invalid line {number}: {line}\n\
+                 You can fix this with 'git rebase --edit-todo' and then run 'git rebase --continue'.\n\
+                 Or you can abort the rebase with 'git rebase --abort'."
+            );
+            return Ok(false);
+        }
+        Edited::Failed => return Ok(false),
+    }
+    Ok(true)
+}
+
+// --continueや--skipの前に、todoが読めるか確かめる
+fn check_todo(repo: &Repository) -> anyhow::Result<bool> {
+    let text = read_state(repo, "git-rebase-todo")?.unwrap_or_default();
+    if let Err((number, line)) = parse_todo(repo, &text) {
+        eprintln!("error: invalid line {number}: {line}");
+        eprintln!("error: please fix this using 'git rebase --edit-todo'.");
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+// indexとworking treeをcommitに戻す。reflogには残さない
+fn reset_hard(repo: &Repository, oid: &str) -> anyhow::Result<()> {
+    let head = repo.refs().head()?;
+    let files = Worktree::new(repo)?;
+    let mut index = repo.index()?;
+    files.checkout_tree(&mut index, head.as_deref(), oid, true)?;
+    repo.write_index(&index)?;
+    reset::clear_state(repo)?;
+    Ok(())
+}
+
+fn clear_stopped(repo: &Repository) -> anyhow::Result<()> {
+    reset::clear_state(repo)?;
+    fs::remove_file(repo.git_dir().join("REBASE_HEAD")).or_else(ignore_missing)?;
+    remove_files(&state_dir(repo), STOPPED_FILES)
+}
+
+// 変更をstashのcommitにして、working treeをHEADに戻す。変更がなければNone
+fn create_autostash(repo: &Repository) -> anyhow::Result<Option<String>> {
+    let stash = stash::create_autostash(repo)?;
+    if stash.is_some() {
+        reset_hard(repo, &head(repo)?)?;
+    }
+    Ok(stash)
+}
+
+// 状態を消す。autostashを渡せば、それも戻す
+fn remove_state(repo: &Repository, stash: Option<&str>) -> anyhow::Result<()> {
+    fs::remove_dir_all(state_dir(repo)).or_else(ignore_missing)?;
+    if let Some(stash) = stash {
+        stash::apply_autostash(repo, stash)?;
+    }
+    Ok(())
+}
+
+fn remove_files(dir: &Path, names: &[&str]) -> anyhow::Result<()> {
+    for name in names {
+        fs::remove_file(dir.join(name)).or_else(ignore_missing)?;
+    }
+    Ok(())
+}
+
+fn state_dir(repo: &Repository) -> PathBuf {
+    repo.git_dir().join(REBASE_MERGE)
+}
+
+fn read_state(repo: &Repository, name: &str) -> anyhow::Result<Option<String>> {
+    match fs::read_to_string(state_dir(repo).join(name)) {
+        Ok(text) => Ok(Some(text)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// 1行だけのファイルを、改行を除いて読む
+fn read_line(repo: &Repository, name: &str) -> anyhow::Result<Option<String>> {
+    Ok(read_state(repo, name)?.map(|text| text.trim().to_string()))
+}
+
+// 1行だけのファイルは、gitと同じく改行で終える
+fn write_state(repo: &Repository, name: &str, text: &str) -> anyhow::Result<()> {
+    let mut text = text.to_string();
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    fs::write(state_dir(repo).join(name), text)?;
+    Ok(())
+}
+
+// gitと同じく、シェルで読める形で作者を残す
+fn author_script(author: &Sign) -> String {
+    let quote = |value: &str| format!("'{}'", value.replace('\'', "'\\''"));
+    format!(
+        "GIT_AUTHOR_NAME={}\nGIT_AUTHOR_EMAIL={}\nGIT_AUTHOR_DATE={}\n",
+        quote(&author.name),
+        quote(&author.email),
+        quote(&author.time_stamp.format("@%s %z").to_string())
+    )
+}
+
+// author-scriptから、止まったcommitの作者を読み戻す
+fn parse_author_script(script: &str) -> Option<Sign> {
+    let mut fields = HashMap::new();
+    for line in script.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value
+            .strip_prefix('\'')
+            .and_then(|value| value.strip_suffix('\''))
+            .unwrap_or(value);
+        fields.insert(key, value.replace("'\\''", "'"));
+    }
+    let date = fields.get("GIT_AUTHOR_DATE")?;
+    Sign::parse(&format!(
+        "{} <{}> {}",
+        fields.get("GIT_AUTHOR_NAME")?,
+        fields.get("GIT_AUTHOR_EMAIL")?,
+        date.strip_prefix('@').unwrap_or(date)
+    ))
+}
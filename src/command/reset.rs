@@ -0,0 +1,358 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use git::index::{Entry, Index};
+use git::object::Kind;
+use git::pathspec::Pathspec;
+use git::revision;
+use git::worktree::Worktree;
+use git::Repository;
+
+use super::{commit, sequencer};
+
+/// How much `reset` resets besides the current branch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// `--soft`: nothing else.
+    Soft,
+    /// `--mixed`: the index.
+    #[default]
+    Mixed,
+    /// `--hard`: the index and the working tree, dropping all changes.
+    Hard,
+    /// `--merge`: the index and the files that differ from it, keeping
+    /// unstaged changes to other files.
+    Merge,
+    /// `--keep`: the index and the files that differ between `HEAD` and the
+    /// target, refusing to lose local changes to them.
+    Keep,
+}
+
+impl Mode {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Soft => "soft",
+            Self::Mixed => "mixed",
+            Self::Hard => "hard",
+            Self::Merge => "merge",
+            Self::Keep => "keep",
+        }
+    }
+}
+
+/// Flags of `reset`.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub mode: Option<Mode>,
+    pub quiet: bool,
+}
+
+// 中断されたマージやcherry-pickの状態
+const STATE_FILES: &[&str] = &[
+    "MERGE_HEAD",
+    "MERGE_MSG",
+    "MERGE_MODE",
+    "MERGE_RR",
+    "AUTO_MERGE",
+    "CHERRY_PICK_HEAD",
+    "REVERT_HEAD",
+    "SQUASH_MSG",
+];
+
+// path -> (mode, oid)
+type Files = BTreeMap<String, (u32, String)>;
+
+/// Point the current branch at a commit and reset the index, and with
+/// `--hard`, `--merge` or `--keep` the working tree, to it.
+///
+/// With paths only their index entries are reset to a tree instead,
+/// leaving the branch alone. `args` are the arguments before `--`, of
+/// which the first may be the revision, and `paths` the ones after it.
+pub fn reset(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    paths: &[String],
+    options: &Options,
+) -> anyhow::Result<()> {
+    let (rev, paths) = split_args(repo, prefix, args, paths)?;
+    if !paths.is_empty() {
+        match options.mode {
+            Some(Mode::Mixed) => eprintln!(
+                "warning: --mixed with paths is deprecated; use 'git reset -- <paths>' instead."
+            ),
+            Some(mode) => anyhow::bail!("Cannot do {} reset with paths.", mode.name()),
+            None => {}
+        }
+        let pathspec = Pathspec::parse(&paths, prefix)?;
+        return reset_paths(repo, rev.as_deref().unwrap_or("HEAD"), &pathspec, options);
+    }
+
+    let mode = options.mode.unwrap_or_default();
+    let index = repo.index()?;
+    if matches!(mode, Mode::Soft | Mode::Keep)
+        && (repo.git_dir().join("MERGE_HEAD").exists()
+            || index.entries().iter().any(|e| e.stage != 0))
+    {
+        anyhow::bail!(
+            "Cannot do a {} reset in the middle of a merge.",
+            mode.name()
+        );
+    }
+    let head = repo.refs().head()?;
+    // 生まれていないブランチは空のtreeに戻す
+    let rev_name = rev.unwrap_or_else(|| "HEAD".to_string());
+    let target = match (&head, rev_name.as_str()) {
+        (None, "HEAD") => None,
+        _ => match revision::resolve_commit(repo, &rev_name) {
+            Ok(target) => Some(target),
+            Err(error) => {
+                eprintln!("error: {error}");
+                anyhow::bail!("Could not parse object '{rev_name}'.");
+            }
+        },
+    };
+
+    if mode != Mode::Soft {
+        let files = Worktree::new(repo)?;
+        let tree = match &target {
+            Some(target) => revision::peel(repo, target, Kind::Tree)?,
+            None => repo.odb().write(Kind::Tree, b"")?,
+        };
+        let index = match mode {
+            Mode::Mixed => reset_index(&index, &files.tree_map(Some(&tree))?),
+            Mode::Hard => {
+                let mut index = index;
+                files.checkout_tree(&mut index, head.as_deref(), &tree, true)?;
+                index
+            }
+            _ => {
+                let old = files.tree_map(head.as_deref())?;
+                let new = files.tree_map(Some(&tree))?;
+                match update_worktree(&files, index, &old, &new, mode == Mode::Keep) {
+                    Ok(index) => index,
+                    Err(error) => {
+                        eprintln!("error: {error}");
+                        anyhow::bail!("Could not reset index file to revision '{rev_name}'.");
+                    }
+                }
+            }
+        };
+        write_refreshed(repo, &files, index, mode == Mode::Mixed && !options.quiet)?;
+    }
+
+    if let Some(target) = &target {
+        if let Some(head) = &head {
+            repo.refs().update("ORIG_HEAD", head)?;
+        }
+        let committer = commit::committer(repo)?;
+        let message = format!("reset: moving to {rev_name}");
+        repo.refs()
+            .update_head_logged(target, &committer, &message)?;
+    }
+    clear_state(repo)?;
+    if mode == Mode::Hard && !options.quiet {
+        if let Some(target) = &target {
+            let subject = repo.find_commit(target)?.message;
+            let subject = subject.lines().next().unwrap_or_default();
+            println!("HEAD is now at {} {subject}", &target[..7]);
+        }
+    }
+    Ok(())
+}
+
+/// Remove the files recording an interrupted merge, cherry-pick or revert.
+pub fn clear_state(repo: &Repository) -> anyhow::Result<()> {
+    sequencer::finish_stopped(repo)?;
+    for name in STATE_FILES {
+        match fs::remove_file(repo.git_dir().join(name)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// gitと同じく、"--"がなければ最初の引数がrevisionかpathかを見分ける
+fn split_args(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    paths: &[String],
+) -> anyhow::Result<(Option<String>, Vec<String>)> {
+    let Some((first, rest)) = args.split_first() else {
+        return Ok((None, paths.to_vec()));
+    };
+    if rest.is_empty() && !paths.is_empty() {
+        return Ok((Some(first.clone()), paths.to_vec()));
+    }
+    // pathが続く場合はtreeでなければならない
+    let is_rev = revision::resolve(repo, first)
+        .and_then(|oid| {
+            if rest.is_empty() && paths.is_empty() {
+                Ok(oid)
+            } else {
+                revision::peel(repo, &oid, Kind::Tree)
+            }
+        })
+        .is_ok();
+    let is_path = repo
+        .work_tree()
+        .is_some_and(|work_tree| work_tree.join(prefix).join(first).exists());
+    let help = "Use '--' to separate paths from revisions, like this:\n\
+                'git <command> [<revision>...] -- [<file>...]'";
+    match (is_rev, is_path) {
+        (true, true) => {
+            anyhow::bail!("ambiguous argument '{first}': both revision and filename\n{help}")
+        }
+        (true, false) => Ok((Some(first.clone()), [rest, paths].concat())),
+        (false, true) => Ok((None, [args, paths].concat())),
+        (false, false) => anyhow::bail!(
+            "ambiguous argument '{first}': unknown revision or path not in the working tree.\n\
+             {help}"
+        ),
+    }
+}
+
+// pathspecに合うindexのentryだけをtreeの内容に戻す
+fn reset_paths(
+    repo: &Repository,
+    rev: &str,
+    pathspec: &Pathspec,
+    options: &Options,
+) -> anyhow::Result<()> {
+    let tree = match revision::resolve(repo, rev) {
+        Ok(oid) => Some(revision::peel(repo, &oid, Kind::Tree)?),
+        // 生まれていないブランチでは、すべてindexから取り除く
+        Err(_) if rev == "HEAD" && repo.refs().head()?.is_none() => None,
+        Err(error) => return Err(error.into()),
+    };
+    let files = Worktree::new(repo)?;
+    let current = repo.index()?;
+    let mut index = current.clone();
+    let matched: BTreeSet<String> = current
+        .entries()
+        .iter()
+        .filter(|e| pathspec.matches(&e.path))
+        .map(|e| e.path.clone())
+        .collect();
+    for path in &matched {
+        index.remove(path);
+    }
+    for (path, (mode, oid)) in files.tree_map(tree.as_deref())? {
+        if pathspec.matches(&path) {
+            index.add(tree_entry(&current, path, mode, oid));
+        }
+    }
+    write_refreshed(repo, &files, index, !options.quiet)
+}
+
+// --mixed: treeの内容のindexにする。変わらないentryはstat情報を残す
+fn reset_index(index: &Index, tree: &Files) -> Index {
+    let mut result = Index::default();
+    for (path, (mode, oid)) in tree {
+        result.add(tree_entry(index, path.clone(), *mode, oid.clone()));
+    }
+    result
+}
+
+fn tree_entry(index: &Index, path: String, mode: u32, oid: String) -> Entry {
+    match index.get(&path, 0) {
+        Some(entry) if entry.mode == mode && entry.oid == oid && !entry.intent_to_add => {
+            entry.clone()
+        }
+        _ => Entry {
+            mode,
+            oid,
+            path,
+            ..Entry::default()
+        },
+    }
+}
+
+// --merge/--keep: 変わるファイルだけを書き換え、それ以外のファイルの変更は残す。
+// --mergeはindexと違うファイルを、--keepはHEADと違うファイルを書き換える
+fn update_worktree(
+    files: &Worktree,
+    mut index: Index,
+    old: &Files,
+    new: &Files,
+    keep: bool,
+) -> anyhow::Result<Index> {
+    let mut paths: BTreeSet<&String> = new.keys().collect();
+    paths.extend(index.entries().iter().map(|e| &e.path));
+    if keep {
+        paths.extend(old.keys());
+    }
+    let mut updates = Vec::new();
+    for path in paths {
+        let current = index.get(path, 0).map(|e| (e.mode, e.oid.clone()));
+        let unmerged = index
+            .entries()
+            .iter()
+            .any(|e| e.path == *path && e.stage != 0);
+        let (old, new) = (old.get(path), new.get(path));
+        if !unmerged && (current.as_ref() == new || (keep && old == new)) {
+            continue;
+        }
+        if keep && current.as_ref() != old {
+            anyhow::bail!("Entry '{path}' would be overwritten by merge. Cannot merge.");
+        }
+        if !unmerged {
+            if current.is_some() && files.is_dirty(&index, path)? {
+                anyhow::bail!("Entry '{path}' not uptodate. Cannot merge.");
+            }
+            if current.is_none() && files.is_untracked(path, new)? {
+                anyhow::bail!(
+                    "Untracked working tree file '{path}' would be overwritten by merge."
+                );
+            }
+        }
+        updates.push((path.clone(), new.cloned()));
+    }
+    files.update_files(&mut index, updates)?;
+    Ok(index)
+}
+
+// 内容の変わっていないファイルのstat情報を更新してindexを書き、
+// reportならworking treeに残っている変更を表示する
+fn write_refreshed(
+    repo: &Repository,
+    files: &Worktree,
+    mut index: Index,
+    report: bool,
+) -> anyhow::Result<()> {
+    let work_tree = repo.require_work_tree()?;
+    let mut unstaged = Vec::new();
+    let entries = index.entries().to_vec();
+    for entry in entries {
+        if entry.stage != 0 {
+            if unstaged.last() != Some(&('U', entry.path.clone())) {
+                unstaged.push(('U', entry.path));
+            }
+            continue;
+        }
+        if entry.assume_valid || entry.skip_worktree || entry.intent_to_add {
+            continue;
+        }
+        match fs::symlink_metadata(work_tree.join(&entry.path)) {
+            Ok(metadata) if !files.is_modified(&entry, &metadata)? => {
+                if !entry.is_stat_clean(&metadata) {
+                    index.add(files.entry(&entry.path, entry.oid, &metadata, Some(entry.mode)));
+                }
+            }
+            Ok(_) => unstaged.push(('M', entry.path)),
+            Err(_) => unstaged.push(('D', entry.path)),
+        }
+    }
+    repo.write_index(&index)?;
+    if report && !unstaged.is_empty() {
+        println!("Unstaged changes after reset:");
+        for (status, path) in unstaged {
+            println!("{status}\t{path}");
+        }
+    }
+    Ok(())
+}
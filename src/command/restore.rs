@@ -0,0 +1,408 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use git::index::{Entry, Index};
+use git::merge::{self, ConflictStyle, Labels};
+use git::object::Kind;
+use git::pathspec::Pathspec;
+use git::revision;
+use git::worktree::Worktree;
+use git::Repository;
+
+/// Flags of `restore`.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `-s`/`--source`: the tree to restore from, instead of the index, or
+    /// `HEAD` with `--staged`.
+    pub source: Option<String>,
+    /// `-S`/`--staged`: restore the index.
+    pub staged: bool,
+    /// `-W`/`--worktree`: restore the working tree, the default without
+    /// `--staged`.
+    pub worktree: bool,
+    /// `--ours`/`--theirs`: restore unmerged files from our or their
+    /// version, stage 2 or 3 of the index.
+    pub stage: Option<u8>,
+    /// `-m`/`--merge`: recreate the conflicted merge of unmerged files.
+    pub merge: bool,
+    /// `--conflict=<style>`: like `--merge`, showing conflicts in `style`.
+    pub conflict: Option<ConflictStyle>,
+    /// `--overlay`: keep files that are missing from the source.
+    pub overlay: bool,
+}
+
+/// What [`paths`] changed in the working tree.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Restored {
+    /// Files written from the source.
+    pub updated: usize,
+    /// Unmerged files written with their conflicts again.
+    pub recreated: usize,
+}
+
+/// Restore the paths matched by `args` in the working tree and/or the index
+/// from the source. Paths missing from the source are removed.
+///
+/// When a pathspec matches nothing, or a path to restore from the index is
+/// unmerged, nothing is restored and `false` is returned.
+pub fn restore(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<bool> {
+    if args.is_empty() {
+        anyhow::bail!("you must specify path(s) to restore");
+    }
+    Ok(paths(repo, prefix, args, options)?.is_some())
+}
+
+/// Like [`restore`], returning what was changed, or `None` after reporting
+/// why nothing was.
+pub fn paths(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<Option<Restored>> {
+    let merge = options.merge || options.conflict.is_some();
+    check_options(options, merge)?;
+    let pathspec = Pathspec::parse(args, prefix)?;
+    let worktree = options.worktree || !options.staged;
+    let files = Worktree::new(repo)?;
+    let mut index = repo.index()?;
+
+    // 復元元: 指定がなければ--stagedならHEAD、そうでなければindex
+    let rev = match &options.source {
+        Some(rev) => Some(rev.as_str()),
+        None if options.staged => Some("HEAD"),
+        None => None,
+    };
+    let source: BTreeMap<String, (u32, String)> = match rev {
+        Some(rev) => {
+            let tree = revision::resolve(repo, rev)
+                .and_then(|oid| revision::peel(repo, &oid, Kind::Tree))
+                .map_err(|_| anyhow::anyhow!("could not resolve {rev}"))?;
+            files.tree_map(Some(&tree))?
+        }
+        None => index
+            .entries()
+            .iter()
+            .filter(|e| e.stage == 0)
+            .map(|e| (e.path.clone(), (e.mode, e.oid.clone())))
+            .collect(),
+    };
+
+    let mut candidates: BTreeSet<String> = index.entries().iter().map(|e| e.path.clone()).collect();
+    if rev.is_some() {
+        candidates.extend(source.keys().cloned());
+    }
+    let mut ok = true;
+    for item in pathspec.items.iter().filter(|item| !item.exclude) {
+        if !candidates.iter().any(|path| item.matches(path)) {
+            eprintln!(
+                "error: pathspec '{}' did not match any file(s) known to git",
+                item.original
+            );
+            ok = false;
+        }
+    }
+    let paths: Vec<String> = candidates
+        .into_iter()
+        .filter(|path| pathspec.matches(path))
+        .collect();
+    let unmerged = if rev.is_none() {
+        let (unmerged, resolvable) = unmerged_paths(&index, &paths, options.stage, merge);
+        ok &= resolvable;
+        unmerged
+    } else {
+        BTreeSet::new()
+    };
+    if !ok {
+        return Ok(None);
+    }
+
+    let mut restored = Restored::default();
+    for path in &paths {
+        if unmerged.contains(path.as_str()) {
+            if let Some(stage) = options.stage {
+                let entry = index.get(path, stage).expect("checked above");
+                files.write(path, entry.mode, &entry.oid)?;
+                restored.updated += 1;
+            } else {
+                recreate_conflict(repo, &files, &index, path, options.conflict)?;
+                restored.recreated += 1;
+            }
+            continue;
+        }
+        let new = source.get(path);
+        if options.staged {
+            index.remove(path);
+            if let Some((mode, oid)) = new {
+                index.add(Entry {
+                    mode: *mode,
+                    oid: oid.clone(),
+                    path: path.clone(),
+                    ..Entry::default()
+                });
+            }
+        }
+        if !worktree {
+            continue;
+        }
+        let Some((mode, oid)) = new else {
+            if !options.overlay {
+                files.remove(path)?;
+            }
+            continue;
+        };
+        let current = index.get(path, 0).map(|e| (e.mode, e.oid.clone()));
+        if current.as_ref() == Some(&(*mode, oid.clone())) && !files.is_dirty(&index, path)? {
+            continue;
+        }
+        let metadata = files.write(path, *mode, oid)?;
+        restored.updated += 1;
+        // 書いた内容がindexと同じなら、stat情報も更新しておく
+        if current.as_ref() == Some(&(*mode, oid.clone())) {
+            index.add(files.entry(path, oid.clone(), &metadata, Some(*mode)));
+        }
+    }
+    repo.write_index(&index)?;
+    Ok(Some(restored))
+}
+
+fn check_options(options: &Options, merge: bool) -> anyhow::Result<()> {
+    if options.staged && (options.stage.is_some() || merge) {
+        let name = if merge {
+            "--merge' or '--conflict"
+        } else {
+            "--ours' or '--theirs"
+        };
+        anyhow::bail!("'{name}' cannot be used with --staged");
+    }
+    if options.source.is_none() && options.stage.is_some() && merge {
+        anyhow::bail!(
+            "git checkout: --ours/--theirs, --force and --merge are incompatible when\n\
+             checking out of the index."
+        );
+    }
+    Ok(())
+}
+
+// indexから戻すときは、衝突中のパスはステージを選ぶか、マージし直すほかない。
+// 戻せる衝突中のパスと、ほかがすべて戻せるかを返す
+fn unmerged_paths<'a>(
+    index: &Index,
+    paths: &'a [String],
+    stage: Option<u8>,
+    merge: bool,
+) -> (BTreeSet<&'a str>, bool) {
+    let mut unmerged = BTreeSet::new();
+    let mut ok = true;
+    for path in paths {
+        if index.get(path, 0).is_some() {
+            continue;
+        }
+        let has = |stage| index.get(path, stage).is_some();
+        let missing = match stage {
+            Some(2) => (!has(2)).then_some("our version"),
+            Some(_) => (!has(3)).then_some("their version"),
+            None if merge => (!has(2) || !has(3)).then_some("all necessary versions"),
+            None => {
+                eprintln!("error: path '{path}' is unmerged");
+                ok = false;
+                continue;
+            }
+        };
+        if let Some(missing) = missing {
+            eprintln!("error: path '{path}' does not have {missing}");
+            ok = false;
+        } else {
+            unmerged.insert(path.as_str());
+        }
+    }
+    (unmerged, ok)
+}
+
+// 衝突しているファイルを、indexのステージから衝突の印つきでもう一度マージする
+fn recreate_conflict(
+    repo: &Repository,
+    files: &Worktree,
+    index: &Index,
+    path: &str,
+    style: Option<ConflictStyle>,
+) -> anyhow::Result<()> {
+    let style = style
+        .or_else(|| {
+            repo.config()
+                .get("merge.conflictStyle")
+                .and_then(ConflictStyle::parse)
+        })
+        .unwrap_or_default();
+    let labels = Labels {
+        ours: "ours".to_string(),
+        base: "base".to_string(),
+        theirs: "theirs".to_string(),
+    };
+    let blob = |stage| -> anyhow::Result<Vec<u8>> {
+        match index.get(path, stage) {
+            Some(entry) => Ok(repo.find_blob(&entry.oid)?),
+            None => Ok(Vec::new()),
+        }
+    };
+    let (data, _) = merge::file(&blob(1)?, &blob(2)?, &blob(3)?, style, &labels);
+    let oid = repo.odb().write(Kind::Blob, &data)?;
+    let mode = index.get(path, 2).map_or(0o100_644, |e| e.mode);
+    files.write(path, mode, &oid)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::testing::TempRepo;
+
+    fn checkout(test: &TempRepo, commit: &str) {
+        let mut index = Index::default();
+        Worktree::new(&test.repo)
+            .unwrap()
+            .checkout_tree(&mut index, None, commit, true)
+            .unwrap();
+        test.repo.write_index(&index).unwrap();
+        test.repo.refs().update_head(commit).unwrap();
+    }
+
+    fn run(test: &TempRepo, args: &[&str], options: &Options) -> anyhow::Result<bool> {
+        let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+        restore(&test.repo, Path::new(""), &args, options)
+    }
+
+    fn read(test: &TempRepo, path: &str) -> String {
+        fs::read_to_string(test.root.join(path)).unwrap()
+    }
+
+    fn staged(test: &TempRepo, path: &str) -> String {
+        let index = test.repo.index().unwrap();
+        let oid = &index.get(path, 0).unwrap().oid;
+        String::from_utf8(test.repo.find_blob(oid).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_restore() {
+        let test = TempRepo::new();
+        let first = test.commit(&[("a", "a\n")], &[]);
+        let second = test.commit(&[("a", "a2\n"), ("d/b", "b\n")], &[&first]);
+        checkout(&test, &second);
+
+        test.write("a", "local\n");
+        assert!(run(&test, &["a"], &Options::default()).unwrap());
+        assert_eq!(read(&test, "a"), "a2\n");
+
+        // --sourceにないファイルは、--overlayがなければ消す
+        let from_first = |overlay| Options {
+            source: Some(first.clone()),
+            overlay,
+            ..Options::default()
+        };
+        assert!(run(&test, &["d"], &from_first(true)).unwrap());
+        assert!(test.root.join("d/b").exists());
+        assert!(run(&test, &["."], &from_first(false)).unwrap());
+        assert_eq!(read(&test, "a"), "a\n");
+        assert!(!test.root.join("d/b").exists());
+        // working treeだけを戻し、indexはそのまま
+        assert_eq!(staged(&test, "a"), "a2\n");
+
+        let staged_only = Options {
+            source: Some(first.clone()),
+            staged: true,
+            ..Options::default()
+        };
+        assert!(run(&test, &["a", "d"], &staged_only).unwrap());
+        assert_eq!(staged(&test, "a"), "a\n");
+        assert!(test.repo.index().unwrap().get("d/b", 0).is_none());
+        let both = Options {
+            staged: true,
+            worktree: true,
+            ..Options::default()
+        };
+        assert!(run(&test, &["."], &both).unwrap());
+        assert_eq!(
+            (staged(&test, "a"), read(&test, "a")),
+            ("a2\n".to_string(), "a2\n".to_string())
+        );
+        assert_eq!(read(&test, "d/b"), "b\n");
+
+        // 一致しないpathspecがあれば何も戻さない
+        test.write("a", "local\n");
+        assert!(!run(&test, &["a", "nothing"], &Options::default()).unwrap());
+        assert_eq!(read(&test, "a"), "local\n");
+        let error = run(&test, &[], &Options::default()).unwrap_err();
+        assert_eq!(error.to_string(), "you must specify path(s) to restore");
+    }
+
+    #[test]
+    fn test_restore_unmerged() {
+        let test = TempRepo::new();
+        let mut index = Index::default();
+        for (stage, content) in [(1, "a\n"), (2, "ours\n"), (3, "theirs\n")] {
+            let oid = test
+                .repo
+                .odb()
+                .write(Kind::Blob, content.as_bytes())
+                .unwrap();
+            index.add(Entry {
+                mode: 0o100_644,
+                oid,
+                stage,
+                path: "m".to_string(),
+                ..Entry::default()
+            });
+        }
+        test.repo.write_index(&index).unwrap();
+        test.write("m", "edited\n");
+
+        assert!(!run(&test, &["m"], &Options::default()).unwrap());
+        assert_eq!(read(&test, "m"), "edited\n");
+        let stage = |stage| Options {
+            stage: Some(stage),
+            ..Options::default()
+        };
+        assert!(run(&test, &["m"], &stage(3)).unwrap());
+        assert_eq!(read(&test, "m"), "theirs\n");
+        assert!(run(&test, &["m"], &stage(2)).unwrap());
+        assert_eq!(read(&test, "m"), "ours\n");
+        let merge = Options {
+            merge: true,
+            ..Options::default()
+        };
+        assert!(run(&test, &["m"], &merge).unwrap());
+        assert_eq!(
+            read(&test, "m"),
+            "<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\n"
+        );
+        // 衝突はindexに残ったまま
+        assert!(test.repo.index().unwrap().get("m", 0).is_none());
+
+        let error = |options: &Options| run(&test, &["m"], options).unwrap_err().to_string();
+        let staged = |options: Options| Options {
+            staged: true,
+            ..options
+        };
+        assert_eq!(
+            error(&staged(stage(2))),
+            "'--ours' or '--theirs' cannot be used with --staged"
+        );
+        assert_eq!(
+            error(&staged(merge.clone())),
+            "'--merge' or '--conflict' cannot be used with --staged"
+        );
+        assert!(error(&Options {
+            merge: true,
+            ..stage(2)
+        })
+        .starts_with("git checkout: --ours/--theirs, --force and --merge are incompatible"));
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use git::index::{Entry, Index};
+use git::pathspec::Pathspec;
+use git::worktree::Worktree;
+use git::Repository;
+
+/// Flags of `rm`.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `--cached`: only remove from the index, keeping the file.
+    pub cached: bool,
+    /// `-r`: allow removing whole directories.
+    pub recursive: bool,
+    /// `-f`: skip the checks for local changes.
+    pub force: bool,
+    pub dry_run: bool,
+    pub quiet: bool,
+}
+
+// 安全のために削除を拒否する理由。gitと同じくこの順に報告する
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Refusal {
+    StagedAndModified,
+    Staged,
+    Modified,
+}
+
+impl Refusal {
+    fn describe(self, plural: bool) -> String {
+        let subject = if plural {
+            "the following files have"
+        } else {
+            "the following file has"
+        };
+        match self {
+            Self::StagedAndModified => {
+                format!("{subject} staged content different from both the\nfile and the HEAD:")
+            }
+            Self::Staged => format!("{subject} changes staged in the index:"),
+            Self::Modified => format!("{subject} local modifications:"),
+        }
+    }
+
+    fn hint(self) -> &'static str {
+        match self {
+            Self::StagedAndModified => "(use -f to force removal)",
+            Self::Staged | Self::Modified => {
+                "(use --cached to keep the file, or -f to force removal)"
+            }
+        }
+    }
+}
+
+/// Remove the tracked paths matched by `args` from the index and, unless
+/// `--cached`, from the working tree.
+///
+/// Paths whose changes would be lost are reported and nothing is removed;
+/// `false` is returned in that case.
+pub fn rm(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<bool> {
+    if args.is_empty() {
+        anyhow::bail!("No pathspec was given. Which files should I remove?");
+    }
+    let pathspec = Pathspec::parse(args, prefix)?;
+    let mut index = repo.index()?;
+    let paths = matched_paths(&index, &pathspec, options.recursive)?;
+
+    if !options.force {
+        let refusals = check_changes(repo, &index, &paths, options.cached)?;
+        if !refusals.is_empty() {
+            for (refusal, paths) in refusals {
+                eprintln!("error: {}", refusal.describe(paths.len() > 1));
+                for path in &paths {
+                    eprintln!("    {path}");
+                }
+                eprintln!("{}", refusal.hint());
+            }
+            return Ok(false);
+        }
+    }
+
+    let files = Worktree::new(repo)?;
+    for path in &paths {
+        if !options.quiet {
+            println!("rm '{path}'");
+        }
+        if options.dry_run {
+            continue;
+        }
+        index.remove(path);
+        if !options.cached {
+            files.remove(path)?;
+        }
+    }
+    if !options.dry_run {
+        repo.write_index(&index)?;
+    }
+    Ok(true)
+}
+
+// pathspecに一致する追跡済みのパス。ディレクトリごとの削除には-rが必要
+fn matched_paths(
+    index: &Index,
+    pathspec: &Pathspec,
+    recursive: bool,
+) -> anyhow::Result<Vec<String>> {
+    let mut paths: Vec<String> = Vec::new();
+    for item in pathspec.items.iter().filter(|item| !item.exclude) {
+        let matched: Vec<&Entry> = index
+            .entries()
+            .iter()
+            .filter(|e| item.matches(&e.path) && pathspec.matches(&e.path))
+            .collect();
+        if matched.is_empty() {
+            anyhow::bail!("pathspec '{}' did not match any files", item.original);
+        }
+        let is_directory = matched.iter().any(|e| {
+            item.pattern.is_empty()
+                || e.path
+                    .strip_prefix(item.pattern.as_str())
+                    .is_some_and(|rest| rest.starts_with('/'))
+        });
+        if is_directory && !recursive {
+            anyhow::bail!("not removing '{}' recursively without -r", item.original);
+        }
+        paths.extend(matched.into_iter().map(|e| e.path.clone()));
+    }
+    paths.sort();
+    paths.dedup();
+    Ok(paths)
+}
+
+// HEAD・index・worktreeを比べて、削除すると失われる変更があるパス
+fn check_changes(
+    repo: &Repository,
+    index: &Index,
+    paths: &[String],
+    cached: bool,
+) -> anyhow::Result<Vec<(Refusal, Vec<String>)>> {
+    let head: BTreeMap<String, (u32, String)> = match repo.refs().head()? {
+        Some(oid) => repo
+            .tree_files(&oid)?
+            .into_iter()
+            .map(|e| (e.name, (e.mode, e.oid)))
+            .collect(),
+        None => BTreeMap::new(),
+    };
+    let files = Worktree::new(repo)?;
+    let work_tree = repo.require_work_tree()?;
+
+    let mut refusals: Vec<(Refusal, Vec<String>)> = Vec::new();
+    for path in paths {
+        let Some(entry) = index.get(path, 0) else {
+            continue;
+        };
+        let staged = head.get(path) != Some(&(entry.mode, entry.oid.clone()));
+        // 削除済みのファイルは変更とみなさない
+        let modified = match fs::symlink_metadata(work_tree.join(path)) {
+            Ok(metadata) => files.is_modified(entry, &metadata)?,
+            Err(_) => false,
+        };
+        let refusal = match (staged, modified) {
+            (true, true) => Refusal::StagedAndModified,
+            (true, false) if !cached => Refusal::Staged,
+            (false, true) if !cached => Refusal::Modified,
+            _ => continue,
+        };
+        match refusals.iter_mut().find(|(r, _)| *r == refusal) {
+            Some((_, paths)) => paths.push(path.clone()),
+            None => refusals.push((refusal, vec![path.clone()])),
+        }
+    }
+    refusals.sort_by_key(|(refusal, _)| *refusal);
+    Ok(refusals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempRepo;
+
+    // filesをコミットしてチェックアウトする
+    fn checkout(test: &TempRepo, files: &[(&str, &str)]) {
+        let commit = test.commit(files, &[]);
+        let mut index = Index::default();
+        Worktree::new(&test.repo)
+            .unwrap()
+            .checkout_tree(&mut index, None, &commit, true)
+            .unwrap();
+        test.repo.write_index(&index).unwrap();
+        test.repo.refs().update_head(&commit).unwrap();
+    }
+
+    fn stage(test: &TempRepo, path: &str, content: &str) {
+        test.write(path, content);
+        let files = Worktree::new(&test.repo).unwrap();
+        let metadata = fs::symlink_metadata(test.root.join(path)).unwrap();
+        let oid = files.hash(path, &metadata, true).unwrap();
+        let mut index = test.repo.index().unwrap();
+        index.add(files.entry(path, oid, &metadata, None));
+        test.repo.write_index(&index).unwrap();
+    }
+
+    fn run(test: &TempRepo, args: &[&str], options: &Options) -> anyhow::Result<bool> {
+        let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+        rm(&test.repo, Path::new(""), &args, options)
+    }
+
+    fn tracked(test: &TempRepo) -> Vec<String> {
+        let index = test.repo.index().unwrap();
+        index.entries().iter().map(|e| e.path.clone()).collect()
+    }
+
+    #[test]
+    fn test_rm_refuses_losing_changes() {
+        let test = TempRepo::new();
+        checkout(&test, &[("a", "a\n"), ("b", "b\n"), ("c", "c\n")]);
+        test.write("a", "modified\n");
+        stage(&test, "b", "staged\n");
+        stage(&test, "c", "staged\n");
+        test.write("c", "modified again\n");
+
+        let paths = ["a", "b", "c"].map(ToString::to_string);
+        let index = test.repo.index().unwrap();
+        assert_eq!(
+            check_changes(&test.repo, &index, &paths, false).unwrap(),
+            [
+                (Refusal::StagedAndModified, vec!["c".to_string()]),
+                (Refusal::Staged, vec!["b".to_string()]),
+                (Refusal::Modified, vec!["a".to_string()]),
+            ]
+        );
+        assert_eq!(
+            check_changes(&test.repo, &index, &paths, true).unwrap(),
+            [(Refusal::StagedAndModified, vec!["c".to_string()])]
+        );
+
+        // 1つでも拒否されれば何も消さない
+        assert!(!run(&test, &["a", "b", "c"], &Options::default()).unwrap());
+        assert_eq!(tracked(&test), ["a", "b", "c"]);
+        let cached = Options {
+            cached: true,
+            quiet: true,
+            ..Options::default()
+        };
+        assert!(run(&test, &["a", "b"], &cached).unwrap());
+        assert_eq!(tracked(&test), ["c"]);
+        assert!(test.root.join("a").exists() && test.root.join("b").exists());
+
+        assert!(!run(&test, &["c"], &cached).unwrap());
+        let force = Options {
+            force: true,
+            quiet: true,
+            ..Options::default()
+        };
+        assert!(run(&test, &["c"], &force).unwrap());
+        assert!(tracked(&test).is_empty());
+        assert!(!test.root.join("c").exists());
+    }
+
+    #[test]
+    fn test_rm_paths() {
+        let test = TempRepo::new();
+        checkout(&test, &[("a", "a\n"), ("d/b", "b\n"), ("d/c", "c\n")]);
+        let quiet = Options {
+            quiet: true,
+            ..Options::default()
+        };
+        let error = |args: &[&str]| run(&test, args, &quiet).unwrap_err().to_string();
+        assert_eq!(
+            error(&[]),
+            "No pathspec was given. Which files should I remove?"
+        );
+        assert_eq!(error(&["x"]), "pathspec 'x' did not match any files");
+        assert_eq!(error(&["d"]), "not removing 'd' recursively without -r");
+
+        let dry_run = Options {
+            recursive: true,
+            dry_run: true,
+            quiet: true,
+            ..Options::default()
+        };
+        assert!(run(&test, &["d"], &dry_run).unwrap());
+        assert_eq!(tracked(&test), ["a", "d/b", "d/c"]);
+        let recursive = Options {
+            recursive: true,
+            quiet: true,
+            ..Options::default()
+        };
+        assert!(run(&test, &["d", "a"], &recursive).unwrap());
+        assert!(tracked(&test).is_empty());
+        assert!(!test.root.join("d").exists());
+    }
+}
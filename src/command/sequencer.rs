@@ -0,0 +1,659 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, ErrorKind, IsTerminal};
+use std::path::{Path, PathBuf};
+
+use git::config::Config;
+use git::merge::{self, ConflictStyle, Labels};
+use git::message::{self, Cleanup};
+use git::object::commit::Commit;
+use git::object::Kind;
+use git::revision;
+use git::revwalk::RevWalk;
+use git::worktree::Worktree;
+use git::Repository;
+
+use super::merge::{apply_outcome, staged_changes};
+use super::{commit, reset};
+
+/// What is done with each commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Apply the changes the commit made.
+    Pick,
+    /// Undo the changes the commit made.
+    Revert,
+}
+
+impl Action {
+    /// The command doing it, like `cherry-pick`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Pick => "cherry-pick",
+            Self::Revert => "revert",
+        }
+    }
+
+    // todoの行の先頭の語
+    fn word(self) -> &'static str {
+        match self {
+            Self::Pick => "pick",
+            Self::Revert => "revert",
+        }
+    }
+
+    // 止まったcommitを記録するファイル
+    fn head_file(self) -> &'static str {
+        match self {
+            Self::Pick => "CHERRY_PICK_HEAD",
+            Self::Revert => "REVERT_HEAD",
+        }
+    }
+}
+
+/// How to go on with a cherry-pick or revert that stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    /// `--continue`: commit the resolved conflicts and go on with the
+    /// remaining commits.
+    Continue,
+    /// `--skip`: drop the commit that stopped and go on with the remaining
+    /// ones.
+    Skip,
+    /// `--abort`: go back to where the sequence started.
+    Abort,
+    /// `--quit`: forget about the sequence, keeping what was done.
+    Quit,
+}
+
+/// Flags of `cherry-pick` and `revert`.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `--continue`, `--skip`, `--abort` or `--quit`.
+    pub control: Option<Control>,
+    /// `-n`/`--no-commit`: only apply the changes to the index and the
+    /// working tree.
+    pub no_commit: bool,
+    /// `-e`/`--no-edit`: whether to edit the messages, which by default
+    /// happens only for `revert` run in a terminal.
+    pub edit: Option<bool>,
+    /// `-x`: add `(cherry picked from commit <oid>)` to the messages.
+    pub record_origin: bool,
+    /// `-m`: the parent, from 1, whose changes a merge commit counts as.
+    pub mainline: Option<usize>,
+}
+
+// 続きのcommitを記録するディレクトリ
+const SEQUENCER: &str = "sequencer";
+
+// 1つのcommitのtodo
+type Step = (Action, String);
+
+/// Apply the changes made by the commits in `args` to `HEAD`, committing
+/// each with its message and author. Ranges are picked oldest first.
+///
+/// A commit with conflicts leaves them to be resolved, with
+/// `CHERRY_PICK_HEAD` telling which commit stopped and, when there are
+/// more, the rest of them in `.git/sequencer` for `--continue`. Returns
+/// `false` when stopped.
+pub fn cherry_pick(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<bool> {
+    run(repo, prefix, Action::Pick, args, options)
+}
+
+/// Record commits undoing the changes made by the commits in `args`,
+/// newest first for ranges; otherwise like [`cherry_pick`].
+pub fn revert(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<bool> {
+    run(repo, prefix, Action::Revert, args, options)
+}
+
+/// The cherry-pick or revert in progress, with the commit it stopped at
+/// unless it is in a sequence of commits.
+pub fn in_progress(repo: &Repository) -> Option<(Action, Option<String>)> {
+    // gitと同じく、todoがあれば止まったcommitより優先する
+    if let Ok(todo) = fs::read_to_string(sequencer_dir(repo).join("todo")) {
+        match todo.split_whitespace().next() {
+            Some("pick" | "p") => return Some((Action::Pick, None)),
+            Some("revert") => return Some((Action::Revert, None)),
+            _ => {}
+        }
+    }
+    [Action::Pick, Action::Revert]
+        .into_iter()
+        .find_map(|action| {
+            let oid = fs::read_to_string(repo.git_dir().join(action.head_file())).ok()?;
+            Some((action, Some(oid.trim().to_string())))
+        })
+}
+
+/// Forget the sequence when the commit it stopped at was its last one,
+/// since committing or resetting that commit finishes it.
+pub fn finish_stopped(repo: &Repository) -> anyhow::Result<()> {
+    let stopped = is_stopped(repo);
+    let todo = fs::read_to_string(sequencer_dir(repo).join("todo")).unwrap_or_default();
+    if stopped && todo.lines().count() == 1 {
+        remove_sequencer(repo)?;
+    }
+    Ok(())
+}
+
+fn run(
+    repo: &Repository,
+    prefix: &Path,
+    action: Action,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<bool> {
+    let name = action.name();
+    match options.control {
+        Some(Control::Continue) => return resume(repo, prefix, action, options),
+        Some(Control::Skip) => return skip(repo, prefix, action, options),
+        Some(Control::Abort) => return abort(repo, prefix, action),
+        Some(Control::Quit) => {
+            remove_sequencer(repo)?;
+            reset::clear_state(repo)?;
+            return Ok(true);
+        }
+        None => {}
+    }
+    if args.is_empty() {
+        anyhow::bail!("empty commit set passed");
+    }
+    if repo.index()?.entries().iter().any(|e| e.stage != 0) {
+        commit::advise_unmerged(match action {
+            Action::Pick => "Cherry-picking",
+            Action::Revert => "Reverting",
+        });
+        anyhow::bail!("{name} failed");
+    }
+    if sequencer_dir(repo).exists() {
+        eprintln!("error: {name} is already in progress");
+        eprintln!("hint: try \"git {name} (--continue | --quit | --abort)\"");
+        anyhow::bail!("{name} failed");
+    }
+    let commits = list_commits(repo, action, args)?;
+
+    // 1つだけなら続きを記録しない
+    if let ([arg], [oid]) = (args, commits.as_slice()) {
+        if !is_range(arg) {
+            return pick(repo, prefix, action, oid, options);
+        }
+    }
+    let Some(head) = repo.refs().head()? else {
+        anyhow::bail!("can't {name} into an unborn branch with several commits");
+    };
+    let dir = sequencer_dir(repo);
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join("head"), format!("{head}\n"))?;
+    save_options(repo, options)?;
+    let todo: Vec<Step> = commits.into_iter().map(|oid| (action, oid)).collect();
+    run_todo(repo, prefix, &todo, options)
+}
+
+// 引数のcommitを適用する順に並べる。範囲はcherry-pickなら古いものから、revertなら
+// 新しいものから
+fn list_commits(repo: &Repository, action: Action, args: &[String]) -> anyhow::Result<Vec<String>> {
+    if !args.iter().any(|arg| is_range(arg)) {
+        return args.iter().map(|arg| resolve(repo, arg)).collect();
+    }
+    let mut walk = RevWalk::new(repo);
+    for arg in args {
+        if let Some((from, to)) = arg.split_once("..") {
+            let or_head = |rev: &str| if rev.is_empty() { "HEAD" } else { rev }.to_string();
+            walk.hide(&resolve(repo, &or_head(from))?)?;
+            walk.push(&resolve(repo, &or_head(to))?)?;
+        } else if let Some(hidden) = arg.strip_prefix('^') {
+            walk.hide(&resolve(repo, hidden)?)?;
+        } else {
+            walk.push(&resolve(repo, arg)?)?;
+        }
+    }
+    let mut commits = walk
+        .map(|item| item.map(|(oid, _)| oid))
+        .collect::<Result<Vec<_>, _>>()?;
+    if commits.is_empty() {
+        anyhow::bail!("empty commit set passed");
+    }
+    if action == Action::Pick {
+        commits.reverse();
+    }
+    Ok(commits)
+}
+
+fn is_range(arg: &str) -> bool {
+    arg.contains("..") || arg.starts_with('^')
+}
+
+fn resolve(repo: &Repository, spec: &str) -> anyhow::Result<String> {
+    revision::resolve_commit(repo, spec).map_err(|_| anyhow::anyhow!("bad revision '{spec}'"))
+}
+
+// todoのcommitを順に適用する。止まったときは、そのcommitから後をtodoに残す
+fn run_todo(
+    repo: &Repository,
+    prefix: &Path,
+    todo: &[Step],
+    options: &Options,
+) -> anyhow::Result<bool> {
+    save_abort_safety(repo)?;
+    for (i, (action, oid)) in todo.iter().enumerate() {
+        write_todo(repo, &todo[i..])?;
+        if !pick(repo, prefix, *action, oid, options)? {
+            return Ok(false);
+        }
+        save_abort_safety(repo)?;
+    }
+    remove_sequencer(repo)?;
+    Ok(true)
+}
+
+// commitを1つ適用する。衝突などで止まればfalse
+fn pick(
+    repo: &Repository,
+    prefix: &Path,
+    action: Action,
+    oid: &str,
+    options: &Options,
+) -> anyhow::Result<bool> {
+    let name = action.name();
+    let commit = repo.find_commit(oid)?;
+    let parent = match parent(&commit, oid, options.mainline) {
+        Ok(parent) => parent,
+        Err(message) => {
+            eprintln!("error: {message}");
+            anyhow::bail!("{name} failed");
+        }
+    };
+    let empty_tree = repo.odb().write(Kind::Tree, b"")?;
+    let head = repo.refs().head()?;
+    let files = Worktree::new(repo)?;
+    let mut index = repo.index()?;
+    // -nなら、indexにある変更に重ねる
+    let ours = if options.no_commit {
+        index.write_tree(repo.odb())?
+    } else {
+        head.clone().unwrap_or_else(|| empty_tree.clone())
+    };
+    let ours_files = files.tree_map(Some(&ours))?;
+    if !options.no_commit && !staged_changes(&index, &ours_files).is_empty() {
+        eprintln!("error: your local changes would be overwritten by {name}.");
+        eprintln!("hint: commit your changes or stash them to proceed.");
+        anyhow::bail!("{name} failed");
+    }
+
+    let outcome = merge_changes(repo, action, oid, &commit, parent, &ours)?;
+    if !apply_outcome(repo, &files, &mut index, &ours_files, &outcome)? {
+        anyhow::bail!("{name} failed");
+    }
+    for message in &outcome.messages {
+        println!("{message}");
+    }
+
+    let mut message = match action {
+        Action::Pick if options.record_origin => message::add_cherry_picked(&commit.message, oid),
+        Action::Pick => commit.message.clone(),
+        Action::Revert => revert_message(&commit, oid, parent),
+    };
+    let git_dir = repo.git_dir();
+    if !outcome.is_clean() {
+        message.push_str("\n# Conflicts:\n");
+        for path in &outcome.conflicts {
+            writeln!(message, "#\t{path}")?;
+        }
+        fs::write(git_dir.join("MERGE_MSG"), &message)?;
+        if !options.no_commit || action == Action::Revert {
+            fs::write(git_dir.join(action.head_file()), format!("{oid}\n"))?;
+        }
+        let verb = match action {
+            Action::Pick => "apply",
+            Action::Revert => "revert",
+        };
+        eprintln!(
+            "error: could not {verb} {}... {}",
+            &oid[..7],
+            commit.summary()
+        );
+        advise_conflicts(action, options.no_commit);
+        return Ok(false);
+    }
+    if options.no_commit {
+        fs::write(git_dir.join("MERGE_MSG"), &message)?;
+        if action == Action::Revert {
+            fs::write(git_dir.join(action.head_file()), format!("{oid}\n"))?;
+        }
+        return Ok(true);
+    }
+
+    let tree = index.write_tree(repo.odb())?;
+    let edit = options
+        .edit
+        .unwrap_or_else(|| action == Action::Revert && io::stdin().is_terminal());
+    if edit || tree == revision::peel(repo, &ours, Kind::Tree)? {
+        // メッセージを直すときや、変更が何もないときはcommitに任せる
+        fs::write(git_dir.join("MERGE_MSG"), &message)?;
+        if action == Action::Pick {
+            fs::write(git_dir.join(action.head_file()), format!("{oid}\n"))?;
+        }
+        let commit_options = commit::Options {
+            edit: Some(edit),
+            reflog_action: Some(name.to_string()),
+            ..commit::Options::default()
+        };
+        return commit::commit(repo, prefix, &commit_options);
+    }
+    let parents = head.into_iter().collect();
+    let reflog_message = format!("{name}: {}", message.lines().next().unwrap_or_default());
+    let new = match action {
+        Action::Pick => {
+            commit::create_authored(repo, tree, parents, commit.author.clone(), message)?
+        }
+        Action::Revert => commit::create(repo, tree, parents, message)?,
+    };
+    let committer = commit::committer(repo)?;
+    repo.refs()
+        .update_head_logged(&new, &committer, &reflog_message)?;
+    Ok(true)
+}
+
+// commitの変更をoursに重ねる。revertはcommitから親への変更を重ねる
+pub fn merge_changes(
+    repo: &Repository,
+    action: Action,
+    oid: &str,
+    commit: &Commit,
+    parent: Option<&str>,
+    ours: &str,
+) -> anyhow::Result<merge::Outcome> {
+    let empty_tree = repo.odb().write(Kind::Tree, b"")?;
+    let label = format!("{} ({})", &oid[..7], commit.summary());
+    let parent_label = format!("parent of {label}");
+    let (base, theirs, base_label, theirs_label) = match action {
+        Action::Pick => (parent, oid, parent_label, label),
+        Action::Revert => (
+            Some(oid),
+            parent.unwrap_or(&empty_tree),
+            label,
+            parent_label,
+        ),
+    };
+    let labels = Labels {
+        ours: "HEAD".to_string(),
+        base: base_label,
+        theirs: theirs_label,
+    };
+    let style = repo
+        .config()
+        .get("merge.conflictStyle")
+        .and_then(ConflictStyle::parse)
+        .unwrap_or_default();
+    Ok(merge::trees(repo, base, ours, theirs, style, &labels)?)
+}
+
+// 変更を取り出すときに比べる親。ルートのcommitなら空のtreeと比べる
+fn parent<'c>(
+    commit: &'c Commit,
+    oid: &str,
+    mainline: Option<usize>,
+) -> Result<Option<&'c str>, String> {
+    let count = commit.parents.len();
+    match mainline {
+        None if count > 1 => Err(format!(
+            "commit {oid} is a merge but no -m option was given."
+        )),
+        Some(number) if number == 0 || number > count.max(1) => {
+            Err(format!("commit {oid} does not have parent {number}"))
+        }
+        _ if count == 0 => Ok(None),
+        number => Ok(Some(&commit.parents[number.unwrap_or(1) - 1])),
+    }
+}
+
+fn revert_message(commit: &Commit, oid: &str, parent: Option<&str>) -> String {
+    let mut message = format!(
+        "Revert \"{}\"\n\nThis reverts commit {oid}",
+        commit.summary()
+    );
+    if let (true, Some(parent)) = (commit.parents.len() > 1, parent) {
+        let _ = write!(message, ", reversing\nchanges made to {parent}");
+    }
+    message.push_str(".\n");
+    message
+}
+
+fn advise_conflicts(action: Action, no_commit: bool) {
+    let name = action.name();
+    let advice = if no_commit {
+        "after resolving the conflicts, mark the corrected paths\n\
+         with 'git add <paths>' or 'git rm <paths>'"
+            .to_string()
+    } else {
+        format!(
+            "After resolving the conflicts, mark them with\n\
+             \"git add/rm <pathspec>\", then run\n\
+             \"git {name} --continue\".\n\
+             You can instead skip this commit with \"git {name} --skip\".\n\
+             To abort and get back to the state before \"git {name}\",\n\
+             run \"git {name} --abort\"."
+        )
+    };
+    for line in advice.lines() {
+        eprintln!("hint: {line}");
+    }
+}
+
+// 解決した衝突をcommitしてから、残りのcommitを適用する
+fn resume(
+    repo: &Repository,
+    prefix: &Path,
+    action: Action,
+    options: &Options,
+) -> anyhow::Result<bool> {
+    let name = action.name();
+    let dir = sequencer_dir(repo);
+    let stopped = is_stopped(repo);
+    if !stopped && !dir.exists() {
+        eprintln!("error: no cherry-pick or revert in progress");
+        anyhow::bail!("{name} failed");
+    }
+    let saved = read_options(repo)?;
+    let edit = options.edit.or(saved.edit);
+    if stopped {
+        // gitと同じく、端末でなければ"# Conflicts:"の行を消してそのままcommitする
+        let edit = edit.unwrap_or_else(|| io::stdin().is_terminal());
+        let commit_options = commit::Options {
+            edit: Some(edit),
+            cleanup: (!edit).then_some(Cleanup::Strip),
+            ..commit::Options::default()
+        };
+        if !commit::commit(repo, prefix, &commit_options)? {
+            return Ok(false);
+        }
+    }
+    if !dir.exists() {
+        return Ok(true);
+    }
+    if !saved.no_commit {
+        let index = repo.index()?;
+        let head_files = Worktree::new(repo)?.tree_map(repo.refs().head()?.as_deref())?;
+        if !staged_changes(&index, &head_files).is_empty() {
+            eprintln!("error: your local changes would be overwritten by {name}.");
+            eprintln!("hint: commit your changes or stash them to proceed.");
+            anyhow::bail!("{name} failed");
+        }
+    }
+    let todo = read_todo(repo)?;
+    let options = Options { edit, ..saved };
+    run_todo(repo, prefix, todo.get(1..).unwrap_or_default(), &options)
+}
+
+// 止まったcommitを捨てて、残りのcommitを適用する
+fn skip(
+    repo: &Repository,
+    prefix: &Path,
+    action: Action,
+    options: &Options,
+) -> anyhow::Result<bool> {
+    let name = action.name();
+    let dir = sequencer_dir(repo);
+    if !repo.git_dir().join(action.head_file()).exists() && !dir.exists() {
+        eprintln!("error: no {name} in progress");
+        anyhow::bail!("{name} failed");
+    }
+    if let Some(head) = repo.refs().head()? {
+        reset_merge(repo, prefix, &head)?;
+    }
+    if !dir.exists() {
+        return Ok(true);
+    }
+    let todo = read_todo(repo)?;
+    let saved = read_options(repo)?;
+    let options = Options {
+        edit: options.edit.or(saved.edit),
+        ..saved
+    };
+    run_todo(repo, prefix, todo.get(1..).unwrap_or_default(), &options)
+}
+
+// 始める前のHEADに戻す。1つだけのときは、そのcommitの変更を捨てる
+fn abort(repo: &Repository, prefix: &Path, action: Action) -> anyhow::Result<bool> {
+    let name = action.name();
+    let dir = sequencer_dir(repo);
+    let stopped = is_stopped(repo);
+    let target = if dir.exists() {
+        let target = fs::read_to_string(dir.join("head"))?.trim().to_string();
+        // 途中でHEADが動かされていたら、それを捨てないよう戻さない
+        let safety = fs::read_to_string(dir.join("abort-safety")).unwrap_or_default();
+        if repo.refs().head()?.as_deref() != Some(safety.trim()) {
+            eprintln!("warning: You seem to have moved HEAD. Not rewinding, check your HEAD!");
+            remove_sequencer(repo)?;
+            return Ok(true);
+        }
+        target
+    } else if stopped {
+        repo.refs()
+            .head()?
+            .ok_or_else(|| anyhow::anyhow!("cannot abort from a branch yet to be born"))?
+    } else {
+        eprintln!("error: no cherry-pick or revert in progress");
+        anyhow::bail!("{name} failed");
+    };
+    reset_merge(repo, prefix, &target)?;
+    remove_sequencer(repo)?;
+    Ok(true)
+}
+
+fn reset_merge(repo: &Repository, prefix: &Path, target: &str) -> anyhow::Result<()> {
+    let options = reset::Options {
+        mode: Some(reset::Mode::Merge),
+        quiet: true,
+    };
+    reset::reset(repo, prefix, &[target.to_string()], &[], &options)
+}
+
+// 衝突などでcommitが止まっているか
+fn is_stopped(repo: &Repository) -> bool {
+    [Action::Pick, Action::Revert]
+        .iter()
+        .any(|action| repo.git_dir().join(action.head_file()).exists())
+}
+
+fn sequencer_dir(repo: &Repository) -> PathBuf {
+    repo.git_dir().join(SEQUENCER)
+}
+
+fn remove_sequencer(repo: &Repository) -> anyhow::Result<()> {
+    match fs::remove_dir_all(sequencer_dir(repo)) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+// sequenceが作ったHEAD。--abortはHEADがこれと違えば戻さない
+fn save_abort_safety(repo: &Repository) -> anyhow::Result<()> {
+    if !sequencer_dir(repo).exists() {
+        return Ok(());
+    }
+    let head = repo.refs().head()?.unwrap_or_default();
+    fs::write(
+        sequencer_dir(repo).join("abort-safety"),
+        format!("{head}\n"),
+    )?;
+    Ok(())
+}
+
+// gitと同じく"pick <短いhash> <件名>"の行で書く
+fn write_todo(repo: &Repository, todo: &[Step]) -> anyhow::Result<()> {
+    let mut text = String::new();
+    for (action, oid) in todo {
+        let commit = repo.find_commit(oid)?;
+        writeln!(text, "{} {} {}", action.word(), &oid[..7], commit.summary())?;
+    }
+    fs::write(sequencer_dir(repo).join("todo"), text)?;
+    Ok(())
+}
+
+fn read_todo(repo: &Repository) -> anyhow::Result<Vec<Step>> {
+    let path = sequencer_dir(repo).join("todo");
+    let text = fs::read_to_string(&path)?;
+    let mut todo = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let action = match words.next() {
+            Some("pick" | "p") => Action::Pick,
+            Some("revert") => Action::Revert,
+            _ => anyhow::bail!("invalid line in {}: {line}", path.display()),
+        };
+        let oid = words.next().unwrap_or_default();
+        todo.push((action, resolve(repo, oid)?));
+    }
+    Ok(todo)
+}
+
+// gitと同じく、指定されたoptionだけをconfigの形式で残す
+fn save_options(repo: &Repository, options: &Options) -> anyhow::Result<()> {
+    let path = sequencer_dir(repo).join("opts");
+    if options.no_commit {
+        Config::set_in_file(&path, "options.no-commit", "true")?;
+    }
+    if let Some(edit) = options.edit {
+        Config::set_in_file(&path, "options.edit", &edit.to_string())?;
+    }
+    if options.record_origin {
+        Config::set_in_file(&path, "options.record-origin", "true")?;
+    }
+    if let Some(mainline) = options.mainline {
+        Config::set_in_file(&path, "options.mainline", &mainline.to_string())?;
+    }
+    Ok(())
+}
+
+fn read_options(repo: &Repository) -> anyhow::Result<Options> {
+    let path = sequencer_dir(repo).join("opts");
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let config = Config::parse(&text)
+        .map_err(|e| anyhow::anyhow!("malformed options sheet: '{}': {e}", path.display()))?;
+    Ok(Options {
+        control: None,
+        no_commit: config.get_bool("options.no-commit").unwrap_or(false),
+        edit: config.get_bool("options.edit"),
+        record_origin: config.get_bool("options.record-origin").unwrap_or(false),
+        mainline: config.get("options.mainline").and_then(|n| n.parse().ok()),
+    })
+}
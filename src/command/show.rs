@@ -0,0 +1,298 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use git::object::commit::Commit;
+use git::object::Object;
+use git::pathspec::Pathspec;
+use git::revision;
+use git::Repository;
+
+use super::diff;
+use super::log::write_commit;
+
+/// Flags of `show`.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub diff: diff::Options,
+    /// `-s`: leave out the patch of commits.
+    pub no_patch: bool,
+}
+
+// 表示するobject: (指定した名前, oid)
+type Named = (String, String);
+
+/// Show commits with their patches, tags with the objects they point at,
+/// tree listings and blob contents.
+///
+/// Leading `args` name the objects (`HEAD` if there are none); the rest
+/// are pathspecs relative to `prefix` limiting the patches.
+pub fn show(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<()> {
+    let (objects, paths) = split_args(repo, prefix, args)?;
+    let pathspec = Pathspec::parse(paths, prefix)?;
+    write_objects(repo, &mut io::stdout().lock(), objects, &pathspec, options)
+}
+
+// 先頭のobjectを指す引数とそのoid(なければHEAD)、および残りのpathspec
+fn split_args<'a>(
+    repo: &Repository,
+    prefix: &Path,
+    args: &'a [String],
+) -> anyhow::Result<(Vec<Named>, &'a [String])> {
+    let mut objects = Vec::new();
+    let mut rest = args;
+    while let Some((arg, tail)) = rest.split_first() {
+        match revision::resolve(repo, arg) {
+            Ok(oid) => objects.push((arg.clone(), oid)),
+            Err(_) if is_path(repo, prefix, arg) => break,
+            Err(error) => return Err(unknown_object(repo, arg, error)),
+        }
+        rest = tail;
+    }
+    if objects.is_empty() {
+        objects.push(("HEAD".to_string(), revision::resolve(repo, "HEAD")?));
+    }
+    Ok((objects, rest))
+}
+
+fn write_objects(
+    repo: &Repository,
+    out: &mut impl Write,
+    objects: Vec<Named>,
+    pathspec: &Pathspec,
+    options: &Options,
+) -> anyhow::Result<()> {
+    let color = super::use_color(repo, options.diff.color.as_deref(), "diff");
+    // commit, tree, tagの間には空行を入れる。blobはそのまま続ける
+    let mut shown_one = false;
+    for object in objects {
+        let mut next = Some(object);
+        while let Some((name, oid)) = next.take() {
+            let object = repo.read_object(&oid)?;
+            let is_blob = matches!(object, Object::Blob(_));
+            if shown_one && !is_blob {
+                writeln!(out)?;
+            }
+            match object {
+                Object::Blob(data) => out.write_all(&data)?,
+                Object::Tree(tree) => {
+                    writeln!(out, "tree {name}\n")?;
+                    for entry in &tree.entries {
+                        let slash = if entry.is_tree() { "/" } else { "" };
+                        writeln!(out, "{}{slash}", entry.name)?;
+                    }
+                }
+                Object::Tag(tag) => {
+                    writeln!(out, "tag {}", tag.name)?;
+                    if let Some(tagger) = &tag.tagger {
+                        writeln!(out, "Tagger: {} <{}>", tagger.name, tagger.email)?;
+                        writeln!(
+                            out,
+                            "Date:   {}",
+                            tagger.time_stamp.format("%a %b %-d %H:%M:%S %Y %z")
+                        )?;
+                    }
+                    write!(out, "\n{}", tag.message)?;
+                    // タグが指すobjectを続けて表示する
+                    next = Some((tag.object.clone(), tag.object));
+                }
+                Object::Commit(commit) => {
+                    write_commit(out, &oid, &commit, color)?;
+                    if !options.no_patch {
+                        write_patch(repo, out, &commit, pathspec, &options.diff)?;
+                    }
+                }
+            }
+            shown_one |= !is_blob;
+        }
+    }
+    Ok(())
+}
+
+// 最初の親との差分。マージの差分は表示しない
+fn write_patch(
+    repo: &Repository,
+    out: &mut impl Write,
+    commit: &Commit,
+    pathspec: &Pathspec,
+    options: &diff::Options,
+) -> anyhow::Result<()> {
+    if commit.parents.len() > 1 {
+        writeln!(out)?;
+        return Ok(());
+    }
+    let parent = commit.parents.first().map(String::as_str);
+    let pairs = diff::tree_pairs(repo, parent, &commit.tree, pathspec)?;
+    if !pairs.is_empty() {
+        writeln!(out)?;
+        diff::write_output(repo, out, pairs, options)?;
+    }
+    Ok(())
+}
+
+fn is_path(repo: &Repository, prefix: &Path, arg: &str) -> bool {
+    repo.work_tree()
+        .is_some_and(|work_tree| work_tree.join(prefix).join(arg).exists())
+}
+
+// gitと同じ言い方でエラーにする
+fn unknown_object(repo: &Repository, spec: &str, error: git::Error) -> anyhow::Error {
+    if let Some((rev, path)) = spec.split_once(':') {
+        if !rev.is_empty() && revision::resolve(repo, rev).is_ok() {
+            return anyhow::anyhow!("path '{path}' does not exist in '{rev}'");
+        }
+        return error.into();
+    }
+    anyhow::anyhow!(
+        "ambiguous argument '{spec}': unknown revision or path not in the working tree.\n\
+         Use '--' to separate paths from revisions, like this:\n\
+         'git <command> [<revision>...] -- [<file>...]'"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempRepo;
+    use git::object::commit::Sign;
+    use git::object::tag::Tag;
+    use git::object::Kind;
+
+    fn show_to_string(test: &TempRepo, args: &[&str], options: &Options) -> String {
+        let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+        let (objects, paths) = split_args(&test.repo, Path::new(""), &args).unwrap();
+        let pathspec = Pathspec::parse(paths, Path::new("")).unwrap();
+        let mut out = Vec::new();
+        write_objects(&test.repo, &mut out, objects, &pathspec, options).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_split_args() {
+        let test = TempRepo::new();
+        let first = test.commit(&[("a", "a\n")], &[]);
+        let second = test.commit(&[("a", "b\n")], &[&first]);
+        test.repo.refs().update_head(&second).unwrap();
+        test.write("file", "");
+        let split = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+            split_args(&test.repo, Path::new(""), &args)
+                .map(|(objects, rest)| (objects, rest.to_vec()))
+                .map_err(|error| error.to_string())
+        };
+
+        assert_eq!(
+            split(&[]).unwrap(),
+            (vec![("HEAD".to_string(), second.clone())], vec![])
+        );
+        // 最初のpathより後はすべてpathspecとする
+        assert_eq!(
+            split(&["HEAD~1", "file", "HEAD"]).unwrap(),
+            (
+                vec![("HEAD~1".to_string(), first)],
+                vec!["file".to_string(), "HEAD".to_string()]
+            )
+        );
+        assert!(split(&["nothing"])
+            .unwrap_err()
+            .starts_with("ambiguous argument 'nothing': unknown revision or path"));
+        assert_eq!(
+            split(&["HEAD:b"]).unwrap_err(),
+            "path 'b' does not exist in 'HEAD'"
+        );
+    }
+
+    #[test]
+    fn test_write_objects() {
+        let test = TempRepo::new();
+        let first = test.commit(&[("a", "a\n"), ("d/b", "b\n")], &[]);
+        let second = test.commit(&[("a", "c\n"), ("d/b", "b\n")], &[&first]);
+        let commit = test.repo.find_commit(&second).unwrap();
+        let tag = Tag {
+            object: commit.tree.clone(),
+            kind: Kind::Tree,
+            name: "v1".to_string(),
+            tagger: Some(Sign {
+                name: "T".to_string(),
+                ..commit.author.clone()
+            }),
+            message: "tagged\n".to_string(),
+        };
+        let tag = test.repo.write_object(&Object::Tag(tag)).unwrap();
+        test.repo.refs().update("refs/tags/v1", &tag).unwrap();
+        let options = Options {
+            diff: diff::Options {
+                color: Some("never".to_string()),
+                ..diff::Options::default()
+            },
+            ..Options::default()
+        };
+
+        let header = |oid: &str, second: u8| {
+            format!(
+                "commit {oid}\n\
+                 Author: A <a@example.com>\n\
+                 Date:   Tue Nov 14 22:13:{second} 2023 +0000\n\
+                 \n    message\n"
+            )
+        };
+        assert_eq!(
+            show_to_string(&test, &[&second], &options),
+            header(&second, 22)
+                + "\ndiff --git a/a b/a\n\
+                   index 7898192..f2ad6c7 100644\n\
+                   --- a/a\n\
+                   +++ b/a\n\
+                   @@ -1 +1 @@\n\
+                   -a\n\
+                   +c\n"
+        );
+        // タグは指すobjectを続けて表示し、blobの間には空行を入れない
+        assert_eq!(
+            show_to_string(
+                &test,
+                &["v1", &format!("{first}:a"), &format!("{second}:a")],
+                &options
+            ),
+            format!(
+                "tag v1\n\
+                 Tagger: T <a@example.com>\n\
+                 Date:   Tue Nov 14 22:13:22 2023 +0000\n\
+                 \ntagged\n\
+                 \ntree {}\n\
+                 \na\nd/\n\
+                 a\nc\n",
+                commit.tree
+            )
+        );
+
+        let no_patch = Options {
+            no_patch: true,
+            ..options.clone()
+        };
+        assert_eq!(
+            show_to_string(&test, &[&second, &first], &no_patch),
+            header(&second, 22) + "\n" + &header(&first, 21)
+        );
+        test.write("d/b", "b\n");
+        assert_eq!(
+            show_to_string(&test, &[&first, "d"], &options),
+            header(&first, 21)
+                + "\ndiff --git a/d/b b/d/b\n\
+                   new file mode 100644\n\
+                   index 0000000..6178079\n\
+                   --- /dev/null\n\
+                   +++ b/d/b\n\
+                   @@ -0,0 +1 @@\n\
+                   +b\n"
+        );
+        assert_eq!(
+            show_to_string(&test, &[&second, "d"], &options),
+            header(&second, 22)
+        );
+    }
+}
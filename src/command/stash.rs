@@ -0,0 +1,728 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use git::index::{Entry, Index};
+use git::merge::{self as merging, ConflictStyle, Labels};
+use git::pathspec::Pathspec;
+use git::reflog;
+use git::revision;
+use git::status::find_untracked;
+use git::worktree::Worktree;
+use git::Repository;
+
+use super::merge::{apply_outcome, staged_changes};
+use super::{commit, diff, reset, status, switch};
+
+/// Which `stash` subcommand to run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Subcommand {
+    /// `push`, also `stash` alone: save local changes and drop them.
+    #[default]
+    Push,
+    /// `list`: show the stash entries.
+    List,
+    /// `show`: show the changes of a stash entry.
+    Show,
+    /// `apply`: apply a stash entry to the working tree.
+    Apply,
+    /// `pop`: apply a stash entry and drop it.
+    Pop,
+    /// `drop`: remove a stash entry.
+    Drop,
+    /// `branch`: apply a stash entry on a new branch at the commit it was
+    /// made on.
+    Branch,
+}
+
+/// Flags of `stash`.
+#[derive(Debug, Clone, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Options {
+    /// `-k`/`--keep-index`: leave the staged changes in the index and the
+    /// working tree when pushing.
+    pub keep_index: bool,
+    /// `-u`/`--include-untracked`: also stash untracked files, and remove
+    /// them.
+    pub include_untracked: bool,
+    /// `-m`/`--message`: the description of the new stash entry.
+    pub message: Option<String>,
+    /// `--index`: also restore the staged changes when applying.
+    pub index: bool,
+    /// `-q`/`--quiet`
+    pub quiet: bool,
+    /// How `show` shows the changes: a diffstat by default.
+    pub format: diff::Format,
+}
+
+// path -> (mode, oid)
+type Files = BTreeMap<String, (u32, String)>;
+
+// stashの一覧を記録するref。一覧はそのreflog
+const STASH_REF: &str = "refs/stash";
+
+// stashを戻すときに何を出力するか
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Output {
+    /// マージのメッセージと、最後にstatus
+    Normal,
+    /// -q: マージのメッセージだけ
+    Quiet,
+    /// autostash: 何も出さない
+    Silent,
+}
+
+impl Output {
+    fn new(quiet: bool) -> Self {
+        if quiet {
+            Self::Quiet
+        } else {
+            Self::Normal
+        }
+    }
+}
+
+// 取り出すstash
+struct Stash {
+    /// 指定された名前
+    revision: String,
+    /// working treeのcommit
+    oid: String,
+    /// stashを作ったときのHEAD
+    base: String,
+    /// indexのcommit
+    index: String,
+    /// 追跡していないファイルのcommit
+    untracked: Option<String>,
+    /// refs/stashのreflogで何番目か。ほかのcommitならNone
+    position: Option<usize>,
+}
+
+/// Run the `stash` subcommand: `args` are the pathspecs for `push`, the
+/// branch and the stash entry for `branch`, and the stash entry for the
+/// others.
+pub fn stash(
+    repo: &Repository,
+    prefix: &Path,
+    subcommand: Subcommand,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<bool> {
+    match subcommand {
+        Subcommand::Push => push(repo, prefix, args, options),
+        Subcommand::List => list(repo),
+        Subcommand::Show => show(repo, prefix, args, options),
+        Subcommand::Apply => {
+            let Some(stash) = find(repo, args)? else {
+                return Ok(false);
+            };
+            apply(
+                repo,
+                prefix,
+                &stash,
+                options.index,
+                Output::new(options.quiet),
+            )
+        }
+        Subcommand::Pop => pop(repo, prefix, args, options),
+        Subcommand::Drop => {
+            let Some(stash) = find(repo, args)? else {
+                return Ok(false);
+            };
+            if !check_stash_ref(&stash) {
+                return Ok(false);
+            }
+            drop_entry(repo, &stash, options.quiet)?;
+            Ok(true)
+        }
+        Subcommand::Branch => branch(repo, prefix, args, options),
+    }
+}
+
+/// Record the local changes of the paths matching `pathspec` as a stash
+/// commit, without touching the working tree: the commit of the working
+/// tree has `HEAD`, the commit of the index and, with `include_untracked`,
+/// a commit of the untracked files as parents.
+///
+/// Returns `None` when there is nothing to stash.
+pub fn create(
+    repo: &Repository,
+    pathspec: &Pathspec,
+    include_untracked: bool,
+    message: Option<&str>,
+) -> anyhow::Result<Option<String>> {
+    let head = repo
+        .refs()
+        .head()?
+        .ok_or_else(|| anyhow::anyhow!("You do not have the initial commit yet"))?;
+    let head_commit = repo.find_commit(&head)?;
+    let files = Worktree::new(repo)?;
+    let index = repo.index()?;
+    let staged = staged_changes(&index, &files.tree_map(Some(&head_commit.tree))?)
+        .iter()
+        .any(|path| pathspec.matches(path));
+
+    // 追跡しているファイルの、working treeの内容
+    let mut work = index.clone();
+    let mut unstaged = false;
+    for entry in index.entries() {
+        if !pathspec.matches(&entry.path) || !files.is_dirty(&index, &entry.path)? {
+            continue;
+        }
+        unstaged = true;
+        let path = repo.require_work_tree()?.join(&entry.path);
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) => {
+                let oid = files.hash(&entry.path, &metadata, true)?;
+                let mode = files.mode(&metadata, Some(entry.mode));
+                work.add(Entry {
+                    mode,
+                    oid,
+                    ..entry.clone()
+                });
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                work.remove(&entry.path);
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let untracked: Vec<String> = if include_untracked {
+        find_untracked(repo, &index, false, false)?
+            .untracked
+            .into_iter()
+            .filter(|path| pathspec.matches(path))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    if !staged && !unstaged && untracked.is_empty() {
+        return Ok(None);
+    }
+
+    let branch = match repo.refs().current_branch()? {
+        Some(branch) => branch.trim_start_matches("refs/heads/").to_string(),
+        None => "(no branch)".to_string(),
+    };
+    let summary = format!("{branch}: {} {}", &head[..7], head_commit.summary());
+    let index_tree = index.write_tree(repo.odb())?;
+    let index_commit = commit::create(
+        repo,
+        index_tree,
+        vec![head.clone()],
+        format!("index on {summary}\n"),
+    )?;
+    let mut parents = vec![head, index_commit];
+    if !untracked.is_empty() {
+        // 追跡していないファイルだけのtreeを、親のないcommitにする
+        let mut untracked_index = Index::default();
+        for path in untracked {
+            let metadata = fs::symlink_metadata(repo.require_work_tree()?.join(&path))?;
+            untracked_index.add(Entry {
+                mode: files.mode(&metadata, None),
+                oid: files.hash(&path, &metadata, true)?,
+                path,
+                ..Entry::default()
+            });
+        }
+        let tree = untracked_index.write_tree(repo.odb())?;
+        parents.push(commit::create(
+            repo,
+            tree,
+            Vec::new(),
+            format!("untracked files on {summary}\n"),
+        )?);
+    }
+    let message = message.map_or_else(
+        || format!("WIP on {summary}"),
+        |message| format!("On {branch}: {message}"),
+    );
+    let work_tree = work.write_tree(repo.odb())?;
+    Ok(Some(commit::create(repo, work_tree, parents, message)?))
+}
+
+/// Make the stash commit `oid` the newest stash entry.
+pub fn store(repo: &Repository, oid: &str, message: &str) -> anyhow::Result<()> {
+    let committer = commit::committer(repo)?;
+    let previous = repo.refs().resolve(STASH_REF)?;
+    repo.refs().update(STASH_REF, oid)?;
+    let entry = reflog::Entry::new(previous.as_deref(), oid, committer, message);
+    repo.refs().append_log(STASH_REF, &entry)?;
+    Ok(())
+}
+
+/// Stash the local changes for `rebase --autostash`, leaving the working
+/// tree as it is. Returns `None` when there is nothing to stash.
+pub fn create_autostash(repo: &Repository) -> anyhow::Result<Option<String>> {
+    let stash = create(repo, &Pathspec::default(), false, Some("autostash"))?;
+    if let Some(stash) = &stash {
+        println!("Created autostash: {}", &stash[..7]);
+    }
+    Ok(stash)
+}
+
+/// Apply the changes stashed by [`create_autostash`], keeping them as a
+/// stash entry when they do not apply cleanly.
+pub fn apply_autostash(repo: &Repository, oid: &str) -> anyhow::Result<()> {
+    let stash = describe(repo, oid.to_string(), oid.to_string(), None)?;
+    if apply(repo, Path::new(""), &stash, false, Output::Silent)? {
+        eprintln!("Applied autostash.");
+    } else {
+        eprintln!("Applying autostash resulted in conflicts.");
+        store_autostash(repo, oid)?;
+    }
+    Ok(())
+}
+
+/// Keep the changes stashed by [`create_autostash`] as a stash entry.
+pub fn store_autostash(repo: &Repository, oid: &str) -> anyhow::Result<()> {
+    store(repo, oid, "autostash")?;
+    eprint!(
+        "Your changes are safe in the stash.\n\
+         You can run \"git stash pop\" or \"git stash drop\" at any time.\n"
+    );
+    Ok(())
+}
+
+// 変更をstashに入れて、working treeから取り除く
+fn push(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<bool> {
+    let pathspec = Pathspec::parse(args, prefix)?;
+    let index = repo.index()?;
+    // gitと同じく、-uがなければpathspecは追跡しているファイルに当たらなければならない
+    if !options.include_untracked {
+        let mut matched = true;
+        for item in pathspec.items.iter().filter(|item| !item.exclude) {
+            if !index.entries().iter().any(|e| item.matches(&e.path)) {
+                eprintln!(
+                    "error: pathspec '{}' did not match any file(s) known to git",
+                    item.original
+                );
+                matched = false;
+            }
+        }
+        if !matched {
+            eprintln!("Did you forget to 'git add'?");
+            return Ok(false);
+        }
+    }
+    if !check_unmerged(&index) {
+        return Ok(false);
+    }
+    let Some(head) = repo.refs().head()? else {
+        eprintln!("You do not have the initial commit yet");
+        return Ok(false);
+    };
+    let message = options.message.as_deref();
+    let Some(oid) = create(repo, &pathspec, options.include_untracked, message)? else {
+        if !options.quiet {
+            println!("No local changes to save");
+        }
+        return Ok(true);
+    };
+    let stash = repo.find_commit(&oid)?;
+    store(repo, &oid, stash.summary())?;
+    if !options.quiet {
+        println!(
+            "Saved working directory and index state {}",
+            stash.summary()
+        );
+    }
+
+    let files = Worktree::new(repo)?;
+    if let Some(untracked) = stash.parents.get(2) {
+        for path in files.tree_map(Some(untracked))?.keys() {
+            files.remove(path)?;
+        }
+    }
+    if pathspec.is_empty() {
+        let hard = reset::Options {
+            mode: Some(reset::Mode::Hard),
+            quiet: true,
+        };
+        reset::reset(repo, prefix, &[], &[], &hard)?;
+    } else {
+        let head_tree = repo.find_commit(&head)?.tree;
+        reset_paths(repo, &files, &head_tree, &pathspec)?;
+    }
+    if options.keep_index {
+        let index_tree = repo.find_commit(&stash.parents[1])?.tree;
+        reset_paths(repo, &files, &index_tree, &pathspec)?;
+    }
+    Ok(true)
+}
+
+// pathspecに当たるファイルを、indexでもworking treeでもtreeの内容に戻す
+fn reset_paths(
+    repo: &Repository,
+    files: &Worktree,
+    tree: &str,
+    pathspec: &Pathspec,
+) -> anyhow::Result<()> {
+    let tree = files.tree_map(Some(tree))?;
+    let mut index = repo.index()?;
+    let paths: BTreeSet<String> = index
+        .entries()
+        .iter()
+        .map(|e| e.path.clone())
+        .chain(tree.keys().cloned())
+        .filter(|path| pathspec.matches(path))
+        .collect();
+    let mut updates = Vec::new();
+    for path in paths {
+        let new = tree.get(&path);
+        let current = index.get(&path, 0).map(|e| (e.mode, e.oid.clone()));
+        if current.as_ref() != new || files.is_dirty(&index, &path)? {
+            updates.push((path, new.cloned()));
+        }
+    }
+    files.update_files(&mut index, updates)?;
+    repo.write_index(&index)?;
+    Ok(())
+}
+
+fn list(repo: &Repository) -> anyhow::Result<bool> {
+    for (n, entry) in repo.refs().log(STASH_REF)?.iter().rev().enumerate() {
+        println!("stash@{{{n}}}: {}", entry.message);
+    }
+    Ok(true)
+}
+
+fn show(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<bool> {
+    let Some(stash) = find(repo, args)? else {
+        return Ok(false);
+    };
+    let diff_options = diff::Options {
+        format: options.format,
+        ..diff::Options::default()
+    };
+    diff::diff(repo, prefix, &[stash.base, stash.oid], &diff_options)?;
+    Ok(true)
+}
+
+fn pop(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<bool> {
+    let Some(stash) = find(repo, args)? else {
+        return Ok(false);
+    };
+    if !check_stash_ref(&stash) {
+        return Ok(false);
+    }
+    if !apply(
+        repo,
+        prefix,
+        &stash,
+        options.index,
+        Output::new(options.quiet),
+    )? {
+        println!("The stash entry is kept in case you need it again.");
+        return Ok(false);
+    }
+    drop_entry(repo, &stash, options.quiet)?;
+    Ok(true)
+}
+
+// stashを作ったcommitに新しいブランチを作って、そこでpopする
+fn branch(
+    repo: &Repository,
+    prefix: &Path,
+    args: &[String],
+    options: &Options,
+) -> anyhow::Result<bool> {
+    let Some((name, args)) = args.split_first() else {
+        eprintln!("No branch name specified");
+        return Ok(false);
+    };
+    let Some(stash) = find(repo, args)? else {
+        return Ok(false);
+    };
+    let create = switch::Options {
+        create: Some(name.clone()),
+        ..switch::Options::default()
+    };
+    switch::switch(repo, &[stash.base.clone()], &create)?;
+    if !apply(repo, prefix, &stash, true, Output::new(options.quiet))? {
+        return Ok(false);
+    }
+    if stash.position.is_some() {
+        drop_entry(repo, &stash, options.quiet)?;
+    }
+    Ok(true)
+}
+
+// 引数のstashを探す。なければそれを伝えてNone
+fn find(repo: &Repository, args: &[String]) -> anyhow::Result<Option<Stash>> {
+    let revision = match args {
+        [] => {
+            if repo.refs().resolve(STASH_REF)?.is_none() {
+                eprintln!("No stash entries found.");
+                return Ok(None);
+            }
+            format!("{STASH_REF}@{{0}}")
+        }
+        // 数字だけなら、stashの番号
+        [arg] if !arg.is_empty() && arg.bytes().all(|b| b.is_ascii_digit()) => {
+            format!("{STASH_REF}@{{{arg}}}")
+        }
+        [arg] => arg.clone(),
+        _ => {
+            let quoted: Vec<String> = args.iter().map(|arg| format!("'{arg}'")).collect();
+            eprintln!("Too many revisions specified: {}", quoted.join(" "));
+            return Ok(None);
+        }
+    };
+
+    // refs/stashのreflogなら、何番目か
+    let (name, selector) = match revision.split_once("@{") {
+        Some((name, selector)) => (name, selector.strip_suffix('}')),
+        None => (revision.as_str(), Some("0")),
+    };
+    let is_stash = revision::full_ref_name(repo, name)?.as_deref() == Some(STASH_REF);
+    let position = match selector.and_then(|n| n.parse::<usize>().ok()) {
+        Some(n) if is_stash => {
+            let entries = repo.refs().log(STASH_REF)?.len();
+            if n >= entries {
+                anyhow::bail!("log for '{name}' only has {entries} entries");
+            }
+            Some(n)
+        }
+        _ => None,
+    };
+    let Ok(oid) = revision::resolve_commit(repo, &revision) else {
+        eprintln!("error: {revision} is not a valid reference");
+        return Ok(None);
+    };
+    describe(repo, revision, oid, position).map(Some)
+}
+
+// stashのcommitから、親のcommitを調べる
+fn describe(
+    repo: &Repository,
+    revision: String,
+    oid: String,
+    position: Option<usize>,
+) -> anyhow::Result<Stash> {
+    let commit = repo.find_commit(&oid)?;
+    let [base, index, rest @ ..] = commit.parents.as_slice() else {
+        anyhow::bail!("'{revision}' is not a stash-like commit");
+    };
+    Ok(Stash {
+        base: base.clone(),
+        index: index.clone(),
+        untracked: rest.first().cloned(),
+        revision,
+        oid,
+        position,
+    })
+}
+
+// dropやpopは、refs/stashのreflogにあるものにしか使えない
+fn check_stash_ref(stash: &Stash) -> bool {
+    if stash.position.is_none() {
+        eprintln!("error: '{}' is not a stash reference", stash.revision);
+        return false;
+    }
+    true
+}
+
+// 衝突中のファイルがあれば、それを伝える
+fn check_unmerged(index: &Index) -> bool {
+    let unmerged: BTreeSet<&str> = index
+        .entries()
+        .iter()
+        .filter(|e| e.stage != 0)
+        .map(|e| e.path.as_str())
+        .collect();
+    for path in &unmerged {
+        println!("{path}: needs merge");
+    }
+    unmerged.is_empty()
+}
+
+// stashの変更を、今のindexのtreeとの3-way mergeでworking treeに戻す
+fn apply(
+    repo: &Repository,
+    prefix: &Path,
+    stash: &Stash,
+    restore_index: bool,
+    output: Output,
+) -> anyhow::Result<bool> {
+    let mut index = repo.index()?;
+    if !check_unmerged(&index) {
+        return Ok(false);
+    }
+    let files = Worktree::new(repo)?;
+    let current = index.write_tree(repo.odb())?;
+    let base_tree = repo.find_commit(&stash.base)?.tree;
+    let index_tree = repo.find_commit(&stash.index)?.tree;
+    let work_tree = repo.find_commit(&stash.oid)?.tree;
+    let style = repo
+        .config()
+        .get("merge.conflictStyle")
+        .and_then(ConflictStyle::parse)
+        .unwrap_or_default();
+    let labels = Labels {
+        ours: if base_tree == current {
+            "Version stash was based on"
+        } else {
+            "Updated upstream"
+        }
+        .to_string(),
+        base: "Stash base".to_string(),
+        theirs: "Stashed changes".to_string(),
+    };
+
+    // --indexなら、stashしたindexの変更を今のindexに当てておく
+    let staged = if restore_index && base_tree != index_tree && current != index_tree {
+        let outcome = merging::trees(
+            repo,
+            Some(&base_tree),
+            &current,
+            &index_tree,
+            style,
+            &labels,
+        )?;
+        if !outcome.is_clean() {
+            eprintln!("error: conflicts in index. Try without --index.");
+            return Ok(false);
+        }
+        // gitと同じく、HEADのreflogにresetとして残る
+        if let Some(head) = repo.refs().head()? {
+            let committer = commit::committer(repo)?;
+            repo.refs()
+                .update_head_logged(&head, &committer, "reset: moving to HEAD")?;
+        }
+        Some(outcome.write_tree(repo)?)
+    } else {
+        None
+    };
+
+    let ours_files = files.tree_map(Some(&current))?;
+    let mut applied = true;
+    if base_tree == work_tree {
+        if output != Output::Silent {
+            println!("Already up to date.");
+        }
+    } else {
+        let outcome = merging::trees(repo, Some(&base_tree), &current, &work_tree, style, &labels)?;
+        if apply_outcome(repo, &files, &mut index, &ours_files, &outcome)? {
+            if output != Output::Silent {
+                for message in &outcome.messages {
+                    println!("{message}");
+                }
+            }
+            if !outcome.is_clean() {
+                if restore_index {
+                    eprintln!("Index was not unstashed.");
+                }
+                applied = false;
+            }
+        } else {
+            applied = false;
+        }
+    }
+    if applied {
+        // --indexなら、indexはstashしたindexの変更を当てたもの。
+        // そうでなければ、stashで新しくできたファイルのほかはindexを元に戻す
+        if let Some(tree) = staged {
+            let staged_files = files.tree_map(Some(&tree))?;
+            let mut paths: BTreeSet<String> =
+                index.entries().iter().map(|e| e.path.clone()).collect();
+            paths.extend(staged_files.keys().cloned());
+            reset_entries(&mut index, paths, &staged_files);
+        } else {
+            let paths = ours_files.keys().cloned().collect();
+            reset_entries(&mut index, paths, &ours_files);
+        }
+        repo.write_index(&index)?;
+    }
+    if let Some(untracked) = &stash.untracked {
+        applied &= restore_untracked(repo, &files, untracked)?;
+    }
+    if output == Output::Normal {
+        status::status(repo, prefix, false, false)?;
+    }
+    Ok(applied)
+}
+
+// indexのpathsのentryを、filesの内容にする
+fn reset_entries(index: &mut Index, paths: BTreeSet<String>, files: &Files) {
+    for path in paths {
+        let new = files.get(&path);
+        if index
+            .get(&path, 0)
+            .map(|e| (e.mode, e.oid.clone()))
+            .as_ref()
+            == new
+        {
+            continue;
+        }
+        index.remove(&path);
+        if let Some((mode, oid)) = new {
+            index.add(Entry {
+                mode: *mode,
+                oid: oid.clone(),
+                path,
+                ..Entry::default()
+            });
+        }
+    }
+}
+
+// stashした追跡していないファイルを戻す。すでにあるファイルは上書きしない
+fn restore_untracked(repo: &Repository, files: &Worktree, commit: &str) -> anyhow::Result<bool> {
+    let work_tree = repo.require_work_tree()?;
+    let mut restored = true;
+    let tree = repo.find_commit(commit)?.tree;
+    for (path, (mode, oid)) in files.tree_map(Some(&tree))? {
+        if fs::symlink_metadata(work_tree.join(&path)).is_ok() {
+            eprintln!("{path} already exists, no checkout");
+            restored = false;
+            continue;
+        }
+        files.write(&path, mode, &oid)?;
+    }
+    if !restored {
+        eprintln!("error: could not restore untracked files from stash");
+    }
+    Ok(restored)
+}
+
+// refs/stashのreflogからstashを除く。最後の1つならrefs/stashも消す
+fn drop_entry(repo: &Repository, stash: &Stash, quiet: bool) -> anyhow::Result<()> {
+    let Some(position) = stash.position else {
+        return Ok(());
+    };
+    let mut entries = repo.refs().log(STASH_REF)?;
+    let i = entries.len() - 1 - position;
+    let dropped = entries.remove(i);
+    // gitのreflog delete --rewriteと同じく、次のエントリを前のエントリにつなぐ
+    if let Some(next) = entries.get_mut(i) {
+        next.old = dropped.old;
+    }
+    match entries.last() {
+        Some(last) => {
+            repo.refs().update(STASH_REF, &last.new)?;
+            repo.refs().write_log(STASH_REF, &entries)?;
+        }
+        None => repo.refs().delete(STASH_REF)?,
+    }
+    if !quiet {
+        println!("Dropped {} ({})", stash.revision, stash.oid);
+    }
+    Ok(())
+}
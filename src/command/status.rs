@@ -0,0 +1,447 @@
+use std::path::Path;
+
+use git::status::{Change, Conflict, Status};
+use git::Repository;
+
+use super::rebase;
+use super::relative_path;
+use super::sequencer::{self, Action};
+
+pub fn status(repo: &Repository, prefix: &Path, short: bool, ignored: bool) -> anyhow::Result<()> {
+    let mut status = Status::collect(repo, ignored)?;
+    status.find_renames(repo, repo.refs().head()?.as_deref())?;
+    if short {
+        print_short(&status, prefix);
+    } else {
+        print_long(repo, &status, prefix)?;
+    }
+    Ok(())
+}
+
+fn print_short(status: &Status, prefix: &Path) {
+    let mut lines: Vec<(&str, char, char)> = status
+        .unmerged
+        .iter()
+        .map(|(path, conflict)| {
+            let [x, y] = conflict_code(*conflict);
+            (path.as_str(), x, y)
+        })
+        .collect();
+    for (path, change) in &status.staged {
+        lines.push((path, code(*change), ' '));
+    }
+    for (path, change) in &status.unstaged {
+        match lines.iter_mut().find(|(p, _, _)| p == path) {
+            Some(line) => line.2 = code(*change),
+            None => lines.push((path, ' ', code(*change))),
+        }
+    }
+    lines.sort_by_key(|(path, _, _)| *path);
+
+    for (path, x, y) in lines {
+        println!("{x}{y} {}", display_path(status, path, prefix));
+    }
+    for path in &status.untracked {
+        println!("?? {}", relative_path(path, prefix));
+    }
+    for path in &status.ignored {
+        println!("!! {}", relative_path(path, prefix));
+    }
+}
+
+fn code(change: Change) -> char {
+    match change {
+        Change::Added => 'A',
+        Change::Modified => 'M',
+        Change::Deleted => 'D',
+        Change::Renamed => 'R',
+    }
+}
+
+// 名前が変わったファイルは"<元のパス> -> <新しいパス>"と示す
+fn display_path(status: &Status, path: &str, prefix: &Path) -> String {
+    match status.renamed_from.get(path) {
+        Some(from) => format!(
+            "{} -> {}",
+            relative_path(from, prefix),
+            relative_path(path, prefix)
+        ),
+        None => relative_path(path, prefix),
+    }
+}
+
+fn conflict_code(conflict: Conflict) -> [char; 2] {
+    match conflict {
+        Conflict::BothDeleted => ['D', 'D'],
+        Conflict::AddedByUs => ['A', 'U'],
+        Conflict::DeletedByThem => ['U', 'D'],
+        Conflict::AddedByThem => ['U', 'A'],
+        Conflict::DeletedByUs => ['D', 'U'],
+        Conflict::BothAdded => ['A', 'A'],
+        Conflict::BothModified => ['U', 'U'],
+    }
+}
+
+fn conflict_label(conflict: Conflict) -> &'static str {
+    match conflict {
+        Conflict::BothDeleted => "both deleted:",
+        Conflict::AddedByUs => "added by us:",
+        Conflict::DeletedByThem => "deleted by them:",
+        Conflict::AddedByThem => "added by them:",
+        Conflict::DeletedByUs => "deleted by us:",
+        Conflict::BothAdded => "both added:",
+        Conflict::BothModified => "both modified:",
+    }
+}
+
+fn label(change: Change) -> &'static str {
+    match change {
+        Change::Added => "new file:",
+        Change::Modified => "modified:",
+        Change::Deleted => "deleted:",
+        Change::Renamed => "renamed:",
+    }
+}
+
+fn print_long(repo: &Repository, status: &Status, prefix: &Path) -> anyhow::Result<()> {
+    let initial = repo.refs().head()?.is_none();
+    for line in long_lines(repo, status, prefix, initial, Context::Status)? {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+/// Where the long format is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Context {
+    Status,
+    /// `commit` telling that there is nothing to commit.
+    Commit,
+    /// The commit message template, without hints and the closing summary.
+    Template,
+}
+
+/// The lines of the long format of `status`, where `initial` tells that
+/// the next commit will be the first one.
+pub fn long_lines(
+    repo: &Repository,
+    status: &Status,
+    prefix: &Path,
+    initial: bool,
+    context: Context,
+) -> anyhow::Result<Vec<String>> {
+    let template = context == Context::Template;
+    let rebase = rebase::in_progress(repo);
+    let mut lines = vec![head_line(repo, rebase.as_ref())?];
+    let hint = |lines: &mut Vec<String>, hint: &str| {
+        if !template {
+            lines.push(format!("  ({hint})"));
+        }
+    };
+    if initial {
+        let title = if context == Context::Status {
+            "No commits yet"
+        } else {
+            "Initial commit"
+        };
+        lines.extend([String::new(), title.to_string(), String::new()]);
+    }
+    let merging = repo.git_dir().join("MERGE_HEAD").exists();
+    push_state(
+        &mut lines,
+        repo,
+        status,
+        merging,
+        rebase.as_ref(),
+        !template,
+    );
+    // cherry-pickで止まっている間も、取り消し方は示さない
+    let merging = merging || repo.git_dir().join("CHERRY_PICK_HEAD").exists();
+
+    if !status.staged.is_empty() {
+        lines.push("Changes to be committed:".to_string());
+        if let Some(text) = unstage_hint(merging, initial) {
+            hint(&mut lines, text);
+        }
+        push_changes(&mut lines, status, &status.staged, prefix);
+    }
+    if !status.unmerged.is_empty() {
+        lines.push("Unmerged paths:".to_string());
+        if let Some(text) = unstage_hint(merging, initial) {
+            hint(&mut lines, text);
+        }
+        hint(&mut lines, unmerged_hint(&status.unmerged));
+        for (path, conflict) in &status.unmerged {
+            lines.push(format!(
+                "\t{:<17}{}",
+                conflict_label(*conflict),
+                relative_path(path, prefix)
+            ));
+        }
+        lines.push(String::new());
+    }
+    if !status.unstaged.is_empty() {
+        lines.push("Changes not staged for commit:".to_string());
+        if status.unstaged.iter().any(|(_, c)| *c == Change::Deleted) {
+            hint(
+                &mut lines,
+                "use \"git add/rm <file>...\" to update what will be committed",
+            );
+        } else {
+            hint(
+                &mut lines,
+                "use \"git add <file>...\" to update what will be committed",
+            );
+        }
+        hint(
+            &mut lines,
+            "use \"git restore <file>...\" to discard changes in working directory",
+        );
+        push_changes(&mut lines, status, &status.unstaged, prefix);
+    }
+    if !status.untracked.is_empty() {
+        lines.push("Untracked files:".to_string());
+        hint(
+            &mut lines,
+            "use \"git add <file>...\" to include in what will be committed",
+        );
+        push_paths(&mut lines, &status.untracked, prefix);
+    }
+    if !status.ignored.is_empty() {
+        lines.push("Ignored files:".to_string());
+        hint(
+            &mut lines,
+            "use \"git add -f <file>...\" to include in what will be committed",
+        );
+        push_paths(&mut lines, &status.ignored, prefix);
+    }
+
+    if template || status.has_staged() {
+        return Ok(lines);
+    }
+    lines.push(
+        if !status.unstaged.is_empty() || !status.unmerged.is_empty() {
+            "no changes added to commit (use \"git add\" and/or \"git commit -a\")"
+        } else if !status.untracked.is_empty() {
+            "nothing added to commit but untracked files present (use \"git add\" to track)"
+        } else if initial {
+            "nothing to commit (create/copy files and use \"git add\" to track)"
+        } else {
+            "nothing to commit, working tree clean"
+        }
+        .to_string(),
+    );
+    Ok(lines)
+}
+
+// マージやcherry-pickの途中であることと、続け方。エディタ向けには続け方を省く
+fn push_state(
+    out: &mut Vec<String>,
+    repo: &Repository,
+    status: &Status,
+    merging: bool,
+    rebase: Option<&rebase::Progress>,
+    hints: bool,
+) {
+    let mut lines = Vec::new();
+    if merging {
+        if status.unmerged.is_empty() {
+            lines.extend([
+                "All conflicts fixed but you are still merging.".to_string(),
+                "  (use \"git commit\" to conclude merge)".to_string(),
+            ]);
+        } else {
+            lines.extend([
+                "You have unmerged paths.".to_string(),
+                "  (fix conflicts and run \"git commit\")".to_string(),
+                "  (use \"git merge --abort\" to abort the merge)".to_string(),
+            ]);
+        }
+        lines.push(String::new());
+    } else if let Some(progress) = rebase {
+        push_rebase_state(&mut lines, repo, status, progress);
+    } else if let Some((action, stopped)) = sequencer::in_progress(repo) {
+        push_sequencer_state(&mut lines, status, action, stopped.as_deref());
+    }
+    out.extend(
+        lines
+            .into_iter()
+            .filter(|line| hints || !line.starts_with("  (")),
+    );
+}
+
+fn push_sequencer_state(
+    lines: &mut Vec<String>,
+    status: &Status,
+    action: Action,
+    stopped: Option<&str>,
+) {
+    let name = action.name();
+    lines.push(match (action, stopped) {
+        (Action::Pick, None) => "Cherry-pick currently in progress.".to_string(),
+        (Action::Pick, Some(oid)) => {
+            format!("You are currently cherry-picking commit {}.", &oid[..7])
+        }
+        (Action::Revert, None) => "Revert currently in progress.".to_string(),
+        (Action::Revert, Some(oid)) => format!("You are currently reverting commit {}.", &oid[..7]),
+    });
+    let next = if !status.unmerged.is_empty() {
+        format!("fix conflicts and run \"git {name} --continue\"")
+    } else if stopped.is_none() {
+        format!("run \"git {name} --continue\" to continue")
+    } else {
+        format!("all conflicts fixed: run \"git {name} --continue\"")
+    };
+    let operation = match action {
+        Action::Pick => "cherry-pick operation",
+        Action::Revert => "revert operation",
+    };
+    lines.extend([
+        format!("  ({next})"),
+        format!("  (use \"git {name} --skip\" to skip this patch)"),
+        format!("  (use \"git {name} --abort\" to cancel the {operation})"),
+        String::new(),
+    ]);
+}
+
+// todoの実行済みの最後の2行と、次の2行。続け方は止まった理由による
+fn push_rebase_state(
+    lines: &mut Vec<String>,
+    repo: &Repository,
+    status: &Status,
+    progress: &rebase::Progress,
+) {
+    let plural = |count: usize| if count == 1 { "" } else { "s" };
+    let done = progress.done.len();
+    if done == 0 {
+        lines.push("No commands done.".to_string());
+    } else {
+        lines.push(format!(
+            "Last command{} done ({done} command{} done):",
+            plural(done),
+            plural(done)
+        ));
+        for line in &progress.done[done.saturating_sub(2)..] {
+            lines.push(format!("   {line}"));
+        }
+        if done > 2 {
+            lines.push("  (see more in file .git/rebase-merge/done)".to_string());
+        }
+    }
+    let remaining = progress.todo.len();
+    if remaining == 0 {
+        lines.push("No commands remaining.".to_string());
+    } else {
+        lines.push(format!(
+            "Next command{} to do ({remaining} remaining command{}):",
+            plural(remaining),
+            plural(remaining)
+        ));
+        for line in progress.todo.iter().take(2) {
+            lines.push(format!("   {line}"));
+        }
+        lines.push("  (use \"git rebase --edit-todo\" to view and edit)".to_string());
+    }
+    let onto = &progress.onto[..7];
+    let rebasing = match &progress.branch {
+        Some(branch) => format!("rebasing branch '{branch}' on '{onto}'"),
+        None => "rebasing".to_string(),
+    };
+    if !status.unmerged.is_empty() {
+        lines.extend([
+            format!("You are currently {rebasing}."),
+            "  (fix conflicts and then run \"git rebase --continue\")".to_string(),
+            "  (use \"git rebase --skip\" to skip this patch)".to_string(),
+            "  (use \"git rebase --abort\" to check out the original branch)".to_string(),
+        ]);
+    } else if repo.git_dir().join("MERGE_MSG").exists() {
+        lines.extend([
+            format!("You are currently {rebasing}."),
+            "  (all conflicts fixed: run \"git rebase --continue\")".to_string(),
+        ]);
+    } else {
+        let editing = match &progress.branch {
+            Some(branch) => format!("while rebasing branch '{branch}' on '{onto}'"),
+            None => "during a rebase".to_string(),
+        };
+        lines.extend([
+            format!("You are currently editing a commit {editing}."),
+            "  (use \"git commit --amend\" to amend the current commit)".to_string(),
+            "  (use \"git rebase --continue\" once you are satisfied with your changes)"
+                .to_string(),
+        ]);
+    }
+    lines.push(String::new());
+}
+
+// マージ中は取り消し方を示さない
+fn unstage_hint(merging: bool, initial: bool) -> Option<&'static str> {
+    if merging {
+        None
+    } else if initial {
+        Some("use \"git rm --cached <file>...\" to unstage")
+    } else {
+        Some("use \"git restore --staged <file>...\" to unstage")
+    }
+}
+
+// 衝突の種類によって、解決に使うコマンドが変わる
+fn unmerged_hint(unmerged: &[(String, Conflict)]) -> &'static str {
+    let both_deleted = unmerged.iter().any(|(_, c)| *c == Conflict::BothDeleted);
+    let delete_modify = unmerged.iter().any(|(_, c)| c.is_delete_modify());
+    let not_deleted = unmerged
+        .iter()
+        .any(|(_, c)| *c != Conflict::BothDeleted && !c.is_delete_modify());
+    if !both_deleted && !delete_modify {
+        "use \"git add <file>...\" to mark resolution"
+    } else if both_deleted && !delete_modify && !not_deleted {
+        "use \"git rm <file>...\" to mark resolution"
+    } else {
+        "use \"git add/rm <file>...\" as appropriate to mark resolution"
+    }
+}
+
+// rebase中は、ブランチの代わりに載せる先を示す
+fn head_line(repo: &Repository, rebase: Option<&rebase::Progress>) -> anyhow::Result<String> {
+    match rebase {
+        Some(progress) => Ok(format!(
+            "interactive rebase in progress; onto {}",
+            &progress.onto[..7]
+        )),
+        None => branch_line(repo),
+    }
+}
+
+/// "On branch <name>", or where `HEAD` is detached.
+pub fn branch_line(repo: &Repository) -> anyhow::Result<String> {
+    if let Some(branch) = repo.refs().current_branch()? {
+        let name = branch.strip_prefix("refs/heads/").unwrap_or(&branch);
+        return Ok(format!("On branch {name}"));
+    }
+    let head = repo.refs().head()?;
+    let at = head.as_deref().map_or("HEAD", |oid| &oid[..7]);
+    Ok(format!("HEAD detached at {at}"))
+}
+
+fn push_changes(
+    lines: &mut Vec<String>,
+    status: &Status,
+    changes: &[(String, Change)],
+    prefix: &Path,
+) {
+    for (path, change) in changes {
+        let path = match change {
+            Change::Renamed => display_path(status, path, prefix),
+            _ => relative_path(path, prefix),
+        };
+        lines.push(format!("\t{:<12}{path}", label(*change)));
+    }
+    lines.push(String::new());
+}
+
+fn push_paths(lines: &mut Vec<String>, paths: &[String], prefix: &Path) {
+    for path in paths {
+        lines.push(format!("\t{}", relative_path(path, prefix)));
+    }
+    lines.push(String::new());
+}
@@ -0,0 +1,408 @@
+use git::reflog;
+use git::refs;
+use git::revision;
+use git::status::{Change, Status};
+use git::worktree::Worktree;
+use git::Repository;
+
+use super::commit;
+
+/// Flags of `switch`.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `-c <branch>`: create the branch at the start point and switch to it.
+    pub create: Option<String>,
+    /// `-C <branch>`: like `-c`, resetting the branch if it exists.
+    pub force_create: Option<String>,
+    /// `--detach`: check out a commit without a branch.
+    pub detach: bool,
+    /// `-f`/`--discard-changes`: drop local changes instead of refusing.
+    pub discard_changes: bool,
+}
+
+// 切り替え先
+enum Target {
+    Branch(String),
+    Detached(String),
+}
+
+/// Switch `HEAD` to a branch, or with `--detach` to a commit, updating the
+/// index and the working tree while keeping local changes that do not
+/// conflict with the switch.
+///
+/// `args` is the branch, `-` for the previous one, or the start point of
+/// the branch to create.
+pub fn switch(repo: &Repository, args: &[String], options: &Options) -> anyhow::Result<()> {
+    let (new_branch, reset) = match (&options.create, &options.force_create) {
+        (_, Some(branch)) => (Some(branch), true),
+        (Some(branch), None) => (Some(branch), false),
+        (None, None) => (None, false),
+    };
+    let arg = target_arg(repo, args)?;
+    if arg.is_none() && new_branch.is_none() && !options.detach {
+        anyhow::bail!("missing branch or commit argument");
+    }
+
+    let head = repo.refs().head()?;
+    let current = repo.refs().current_branch()?;
+    let (target, commit, existed) =
+        resolve_target(repo, arg, new_branch, reset, options.detach, head.as_ref())?;
+
+    // 今のcommitから新しいブランチを作るだけなら、working treeには触れない
+    let create_here = new_branch.is_some() && args.is_empty();
+    if let Some(commit) = &commit {
+        if !create_here {
+            let mut index = repo.index()?;
+            if head.as_ref() != Some(commit) || options.discard_changes {
+                let files = Worktree::new(repo)?;
+                files.checkout_tree(
+                    &mut index,
+                    head.as_deref(),
+                    commit,
+                    options.discard_changes,
+                )?;
+                repo.write_index(&index)?;
+            }
+            show_local_changes(repo, commit)?;
+        }
+    }
+
+    let committer = commit::committer(repo)?;
+    let from = match &current {
+        Some(branch) => branch.trim_start_matches("refs/heads/").to_string(),
+        None => head.clone().unwrap_or_default(),
+    };
+    let to = match &target {
+        Target::Branch(branch) => {
+            let full_name = format!("refs/heads/{branch}");
+            if let (Some(branch), Some(commit)) = (new_branch, &commit) {
+                let start = args.first().map_or("HEAD", String::as_str);
+                let message = if existed {
+                    format!("branch: Reset to {start}")
+                } else {
+                    format!("branch: Created from {start}")
+                };
+                repo.refs().update_logged(
+                    &format!("refs/heads/{branch}"),
+                    commit,
+                    &committer,
+                    &message,
+                )?;
+            }
+            repo.refs().set_symbolic("HEAD", &full_name)?;
+            branch
+        }
+        Target::Detached(rev) => {
+            if let Some(commit) = &commit {
+                repo.refs().update("HEAD", commit)?;
+            }
+            rev
+        }
+    };
+    if let Some(commit) = &commit {
+        let entry = reflog::Entry::new(
+            head.as_deref(),
+            commit,
+            committer,
+            &format!("checkout: moving from {from} to {to}"),
+        );
+        repo.refs().append_log("HEAD", &entry)?;
+    }
+
+    if let (None, Some(old)) = (&current, &head) {
+        if commit.as_ref() != Some(old) {
+            eprintln!("Previous HEAD position was {}", describe(repo, old)?);
+        }
+    }
+    print_switched(
+        repo,
+        &target,
+        commit.as_deref(),
+        existed,
+        new_branch.is_some(),
+        current.as_deref(),
+    )?;
+    Ok(())
+}
+
+// 引数のブランチやcommit。"-"と@{-<n>}は前のブランチの名前にする
+fn target_arg(repo: &Repository, args: &[String]) -> anyhow::Result<Option<String>> {
+    let arg = match args {
+        [] => None,
+        [arg] if arg == "-" => Some("@{-1}".to_string()),
+        [arg] => Some(arg.clone()),
+        _ => anyhow::bail!("only one reference expected"),
+    };
+    match arg {
+        Some(arg) if arg.starts_with("@{-") => {
+            let n = arg[3..].strip_suffix('}').and_then(|n| n.parse().ok());
+            match n
+                .map(|n| revision::previous_checkout(repo, n))
+                .transpose()?
+            {
+                Some(Some(previous)) => Ok(Some(previous)),
+                _ => anyhow::bail!("invalid reference: {arg}"),
+            }
+        }
+        arg => Ok(arg),
+    }
+}
+
+// 切り替え先とそのcommit、作るブランチがすでにあったかどうか
+fn resolve_target(
+    repo: &Repository,
+    arg: Option<String>,
+    new_branch: Option<&String>,
+    reset: bool,
+    detach: bool,
+    head: Option<&String>,
+) -> anyhow::Result<(Target, Option<String>, bool)> {
+    if let Some(branch) = new_branch {
+        let full_name = format!("refs/heads/{branch}");
+        if !refs::is_valid_name(&full_name) {
+            anyhow::bail!("'{branch}' is not a valid branch name");
+        }
+        let existed = repo.refs().resolve(&full_name)?.is_some();
+        if existed && !reset {
+            anyhow::bail!("a branch named '{branch}' already exists");
+        }
+        let commit = match &arg {
+            Some(start) => Some(revision::resolve_commit(repo, start)?),
+            None => head.cloned(),
+        };
+        Ok((Target::Branch(branch.clone()), commit, existed))
+    } else if detach {
+        let rev = arg.as_deref().unwrap_or("HEAD");
+        let Ok(commit) = revision::resolve_commit(repo, rev) else {
+            anyhow::bail!("invalid reference: {rev}");
+        };
+        Ok((Target::Detached(rev.to_string()), Some(commit), false))
+    } else {
+        let name = arg.unwrap_or_default();
+        if let Some(commit) = repo.refs().resolve(&format!("refs/heads/{name}"))? {
+            Ok((Target::Branch(name), Some(commit), false))
+        } else if revision::resolve_commit(repo, &name).is_ok() {
+            anyhow::bail!(
+                "a branch is expected, got commit '{name}'\n\
+                 hint: If you want to detach HEAD at the commit, try again with the --detach option."
+            );
+        } else {
+            anyhow::bail!("invalid reference: {name}");
+        }
+    }
+}
+
+fn print_switched(
+    repo: &Repository,
+    target: &Target,
+    commit: Option<&str>,
+    existed: bool,
+    created: bool,
+    current: Option<&str>,
+) -> anyhow::Result<()> {
+    match target {
+        Target::Detached(_) => {
+            eprintln!(
+                "HEAD is now at {}",
+                describe(repo, commit.unwrap_or_default())?
+            );
+        }
+        Target::Branch(branch) if existed => {
+            eprintln!("Switched to and reset branch '{branch}'");
+        }
+        Target::Branch(branch) if created => {
+            eprintln!("Switched to a new branch '{branch}'");
+        }
+        Target::Branch(branch) if current == Some(&format!("refs/heads/{branch}")) => {
+            eprintln!("Already on '{branch}'");
+        }
+        Target::Branch(branch) => eprintln!("Switched to branch '{branch}'"),
+    }
+    Ok(())
+}
+
+// "<短縮hash> <件名>"
+fn describe(repo: &Repository, oid: &str) -> anyhow::Result<String> {
+    let message = repo.find_commit(oid)?.message;
+    let subject = message.lines().next().unwrap_or_default();
+    Ok(format!("{} {subject}", &oid[..7]))
+}
+
+// 切り替え後も残っている、新しいcommitからの変更を一覧にする
+fn show_local_changes(repo: &Repository, commit: &str) -> anyhow::Result<()> {
+    let status = Status::collect_against(repo, Some(commit), false)?;
+    let mut changes = status.staged;
+    for (path, change) in status.unstaged {
+        match changes.iter_mut().find(|(p, _)| *p == path) {
+            Some((_, Change::Added)) => {}
+            Some((_, staged)) => *staged = change,
+            None => changes.push((path, change)),
+        }
+    }
+    changes.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (path, change) in changes {
+        let status = match change {
+            Change::Added => 'A',
+            Change::Modified => 'M',
+            Change::Deleted => 'D',
+            Change::Renamed => 'R',
+        };
+        println!("{status}\t{path}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::testing::TempRepo;
+    use git::index::Index;
+
+    // mainにfirstをチェックアウトし、topicにsecondを置く
+    fn setup(test: &TempRepo) -> (Repository, String, String) {
+        let first = test.commit(&[("a", "a\n"), ("b", "b\n")], &[]);
+        let second = test.commit(&[("a", "a2\n"), ("b", "b\n")], &[&first]);
+        let mut config = fs::read_to_string(test.repo.git_dir().join("config")).unwrap();
+        config.push_str("[user]\n\tname = A\n\temail = a@example.com\n");
+        fs::write(test.repo.git_dir().join("config"), config).unwrap();
+        let repo = Repository::open(test.repo.git_dir(), Some(test.root.clone())).unwrap();
+
+        let mut index = Index::default();
+        Worktree::new(&repo)
+            .unwrap()
+            .checkout_tree(&mut index, None, &first, true)
+            .unwrap();
+        repo.write_index(&index).unwrap();
+        repo.refs().update_head(&first).unwrap();
+        repo.refs().update("refs/heads/topic", &second).unwrap();
+        (repo, first, second)
+    }
+
+    fn run(repo: &Repository, args: &[&str], options: &Options) -> anyhow::Result<()> {
+        let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+        switch(repo, &args, options)
+    }
+
+    #[test]
+    fn test_switch() {
+        let test = TempRepo::new();
+        let (repo, first, second) = setup(&test);
+        let read = |path: &str| fs::read_to_string(test.root.join(path)).unwrap();
+        let branch = || repo.refs().current_branch().unwrap();
+
+        // 切り替えと関係のない変更は残す
+        test.write("b", "local\n");
+        run(&repo, &["topic"], &Options::default()).unwrap();
+        assert_eq!(branch().as_deref(), Some("refs/heads/topic"));
+        assert_eq!(repo.refs().head().unwrap(), Some(second));
+        assert_eq!(
+            (read("a"), read("b")),
+            ("a2\n".to_string(), "local\n".to_string())
+        );
+
+        assert_eq!(
+            target_arg(&repo, &["-".to_string()]).unwrap().as_deref(),
+            Some("main")
+        );
+        run(&repo, &["-"], &Options::default()).unwrap();
+        assert_eq!(branch().as_deref(), Some("refs/heads/main"));
+        assert_eq!(read("a"), "a\n");
+
+        // 上書きされる変更があれば、-fなしでは切り替えない
+        test.write("a", "local\n");
+        assert!(run(&repo, &["topic"], &Options::default()).is_err());
+        assert_eq!(branch().as_deref(), Some("refs/heads/main"));
+        let discard = Options {
+            discard_changes: true,
+            ..Options::default()
+        };
+        run(&repo, &["topic"], &discard).unwrap();
+        assert_eq!(
+            (read("a"), read("b")),
+            ("a2\n".to_string(), "b\n".to_string())
+        );
+
+        let error = run(&repo, &[&first], &Options::default()).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with(&format!("a branch is expected, got commit '{first}'")));
+        let detach = Options {
+            detach: true,
+            ..Options::default()
+        };
+        run(&repo, &[&first], &detach).unwrap();
+        assert_eq!(branch(), None);
+        assert_eq!(repo.refs().head().unwrap(), Some(first.clone()));
+        assert_eq!(
+            target_arg(&repo, &["@{-1}".to_string()])
+                .unwrap()
+                .as_deref(),
+            Some("topic")
+        );
+        run(&repo, &["-"], &Options::default()).unwrap();
+        assert_eq!(branch().as_deref(), Some("refs/heads/topic"));
+        // 切り離されていたときはcommitが前の切り替え先になる
+        assert_eq!(
+            target_arg(&repo, &["-".to_string()]).unwrap(),
+            Some(first.clone())
+        );
+
+        let error =
+            |args: &[&str], options: &Options| run(&repo, args, options).unwrap_err().to_string();
+        assert_eq!(
+            error(&[], &Options::default()),
+            "missing branch or commit argument"
+        );
+        assert_eq!(
+            error(&["a", "b"], &Options::default()),
+            "only one reference expected"
+        );
+        assert_eq!(
+            error(&["@{-9}"], &Options::default()),
+            "invalid reference: @{-9}"
+        );
+        assert_eq!(
+            error(&["nothing"], &Options::default()),
+            "invalid reference: nothing"
+        );
+    }
+
+    #[test]
+    fn test_switch_create() {
+        let test = TempRepo::new();
+        let (repo, first, second) = setup(&test);
+        let create = |branch: &str| Options {
+            create: Some(branch.to_string()),
+            ..Options::default()
+        };
+
+        // 今のcommitから作るときはworking treeの変更をそのままにする
+        test.write("a", "local\n");
+        run(&repo, &[], &create("new")).unwrap();
+        assert_eq!(
+            repo.refs().current_branch().unwrap().as_deref(),
+            Some("refs/heads/new")
+        );
+        assert_eq!(repo.refs().resolve("refs/heads/new").unwrap(), Some(first));
+        assert_eq!(fs::read_to_string(test.root.join("a")).unwrap(), "local\n");
+        fs::write(test.root.join("a"), "a\n").unwrap();
+
+        let error = run(&repo, &["topic"], &create("new")).unwrap_err();
+        assert_eq!(error.to_string(), "a branch named 'new' already exists");
+        assert!(run(&repo, &[], &create("a..b")).is_err());
+        let force_create = Options {
+            force_create: Some("new".to_string()),
+            ..Options::default()
+        };
+        run(&repo, &["topic"], &force_create).unwrap();
+        assert_eq!(repo.refs().resolve("refs/heads/new").unwrap(), Some(second));
+        let log = repo.refs().log("refs/heads/new").unwrap();
+        let messages: Vec<&str> = log.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            ["branch: Created from HEAD", "branch: Reset to topic"]
+        );
+    }
+}
@@ -0,0 +1,426 @@
+use std::fs;
+use std::io::{self, BufRead, ErrorKind};
+
+use git::index::{verify_path, Entry, Index};
+use git::object::tree::{normalize_mode, BLOB_MODE, EXECUTABLE_MODE};
+use git::repository::GitRoot;
+use git::worktree::Worktree;
+use git::Repository;
+
+/// Flags of `update-index`.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `--add`: let files that are not in the index yet be added.
+    pub add: bool,
+    /// `--remove`: remove files that are missing from the working tree.
+    pub remove: bool,
+    /// `--refresh`: update the stat data of files whose content is unchanged.
+    pub refresh: bool,
+    /// `--cacheinfo <mode>,<object>,<path>`: entries to record without files.
+    pub cacheinfo: Vec<CacheInfo>,
+    /// `--index-info`: read entries to record from standard input.
+    pub index_info: bool,
+    /// `--chmod=(+|-)x`: set or clear the executable bit of the files.
+    pub chmod: Option<bool>,
+    /// `--[no-]assume-unchanged`: only change this flag of the files.
+    pub assume_unchanged: Option<bool>,
+    /// `--[no-]skip-worktree`: only change this flag of the files.
+    pub skip_worktree: Option<bool>,
+}
+
+/// An entry given by `--cacheinfo` or a line of `--index-info`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheInfo {
+    /// 0 removes the path.
+    pub mode: u32,
+    pub oid: String,
+    pub stage: u8,
+    /// Slash-separated path from the top of the working tree.
+    pub path: String,
+}
+
+impl CacheInfo {
+    /// Parse the `<mode>,<object>,<path>` argument of `--cacheinfo`.
+    pub fn parse(arg: &str) -> Option<Self> {
+        let mut fields = arg.splitn(3, ',');
+        let (mode, oid, path) = (fields.next()?, fields.next()?, fields.next()?);
+        Self::new(mode, oid, "0", path)
+    }
+
+    // ls-tree形式の"<mode> <type> <object>\t<path>"か、
+    // ls-files -s形式の"<mode> <object> <stage>\t<path>"
+    fn parse_line(line: &str) -> Option<Self> {
+        let (info, path) = line.split_once('\t')?;
+        match info.split(' ').collect::<Vec<_>>().as_slice() {
+            [mode, oid, stage] if is_oid(oid) => Self::new(mode, oid, stage, path),
+            [mode, oid] | [mode, _, oid] => Self::new(mode, oid, "0", path),
+            _ => None,
+        }
+    }
+
+    fn new(mode: &str, oid: &str, stage: &str, path: &str) -> Option<Self> {
+        let stage = stage.parse().ok().filter(|stage| *stage <= 3)?;
+        if !is_oid(oid) || path.is_empty() {
+            return None;
+        }
+        Some(Self {
+            mode: u32::from_str_radix(mode, 8).ok()?,
+            oid: oid.to_ascii_lowercase(),
+            stage,
+            path: path.to_string(),
+        })
+    }
+
+    fn entry(&self) -> Entry {
+        Entry {
+            mode: normalize_mode(self.mode),
+            oid: self.oid.clone(),
+            stage: self.stage,
+            path: self.path.clone(),
+            ..Entry::default()
+        }
+    }
+}
+
+fn is_oid(oid: &str) -> bool {
+    oid.len() == 40 && oid.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Record `paths`, relative to the starting directory, and the entries of
+/// `options` in the index without touching the working tree.
+///
+/// Returns `false` when `--refresh` finds files that need updating.
+pub fn update_index(
+    repo: &Repository,
+    root: &GitRoot,
+    paths: &[String],
+    options: &Options,
+) -> anyhow::Result<bool> {
+    let mut index = repo.index()?;
+    for info in &options.cacheinfo {
+        if !verify_path(&info.path) {
+            eprintln!("error: Invalid path '{}'", info.path);
+            anyhow::bail!("git update-index: --cacheinfo cannot add {}", info.path);
+        }
+        if index.get(&info.path, info.stage).is_none() {
+            if let Err(error) = check_add(&index, &info.path, options.add) {
+                eprintln!("error: {error}");
+                anyhow::bail!("git update-index: --cacheinfo cannot add {}", info.path);
+            }
+        }
+        add_entry(&mut index, info.entry());
+    }
+
+    let files = Worktree::new(repo)?;
+    for path in paths {
+        let path = root.resolve(path).to_string_lossy().into_owned();
+        // gitと同じく、記録できないパスは飛ばして続ける
+        if !verify_path(&path) {
+            eprintln!("Ignoring path {path}");
+            continue;
+        }
+        if options.assume_unchanged.is_some() || options.skip_worktree.is_some() {
+            mark(&mut index, &path, options)?;
+            continue;
+        }
+        if let Err(error) = update_path(&files, &mut index, &path, options) {
+            eprintln!("error: {error:#}");
+            anyhow::bail!("Unable to process path {path}");
+        }
+        if let Some(executable) = options.chmod {
+            chmod(&mut index, &path, executable)?;
+        }
+    }
+
+    if options.index_info {
+        for line in io::stdin().lock().lines() {
+            let line = line?;
+            let info = CacheInfo::parse_line(&line)
+                .ok_or_else(|| anyhow::anyhow!("malformed index info {line}"))?;
+            if !verify_path(&info.path) {
+                eprintln!("Ignoring path {}", info.path);
+                continue;
+            }
+            if info.mode == 0 {
+                index.remove(&info.path);
+            } else {
+                add_entry(&mut index, info.entry());
+            }
+        }
+    }
+
+    let clean = !options.refresh || refresh(&files, &mut index)?;
+    repo.write_index(&index)?;
+    Ok(clean)
+}
+
+// indexにないpathを追加してよいか
+fn check_add(index: &Index, path: &str, add: bool) -> anyhow::Result<()> {
+    let inside = format!("{path}/");
+    let conflict = index.entries().iter().any(|e| {
+        e.path.starts_with(&inside)
+            || path
+                .strip_prefix(e.path.as_str())
+                .is_some_and(|rest| rest.starts_with('/'))
+    });
+    // gitはファイルとディレクトリの衝突も--addがないせいにする
+    if conflict {
+        eprintln!("error: '{path}' appears as both a file and as a directory");
+    }
+    if conflict || !add {
+        anyhow::bail!("{path}: cannot add to the index - missing --add option?");
+    }
+    Ok(())
+}
+
+// stage 0を記録するとconflictのstageは消え、conflictを記録するとstage 0が消える
+fn add_entry(index: &mut Index, entry: Entry) {
+    if entry.stage == 0 {
+        index.remove(&entry.path);
+    } else {
+        index.remove_stage(&entry.path, 0);
+    }
+    index.add(entry);
+}
+
+// working treeのファイルを記録する。なくなっていれば--removeで削除する
+fn update_path(
+    files: &Worktree,
+    index: &mut Index,
+    path: &str,
+    options: &Options,
+) -> anyhow::Result<()> {
+    let full_path = files.repository().require_work_tree()?.join(path);
+    let metadata = match fs::symlink_metadata(&full_path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            if !options.remove {
+                anyhow::bail!("{path}: does not exist and --remove not passed");
+            }
+            index.remove(path);
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    // 埋め込まれたリポジトリ以外のディレクトリは記録できない
+    if metadata.is_dir() && !full_path.join(".git").exists() {
+        anyhow::bail!("{path}: is a directory - add files inside instead");
+    }
+    let existing = index.get(path, 0).map(|e| e.mode);
+    // conflictを解決する場合は--addは要らない
+    if !index.entries().iter().any(|e| e.path == path) {
+        check_add(index, path, options.add)?;
+    }
+    let hash = files.hash(path, &metadata, true)?;
+    add_entry(index, files.entry(path, hash, &metadata, existing));
+    Ok(())
+}
+
+fn mark(index: &mut Index, path: &str, options: &Options) -> anyhow::Result<()> {
+    let Some(entry) = index.get(path, 0) else {
+        anyhow::bail!("Unable to mark file {path}");
+    };
+    let mut entry = entry.clone();
+    if let Some(assume_unchanged) = options.assume_unchanged {
+        entry.assume_valid = assume_unchanged;
+    }
+    if let Some(skip_worktree) = options.skip_worktree {
+        entry.skip_worktree = skip_worktree;
+    }
+    index.add(entry);
+    Ok(())
+}
+
+fn chmod(index: &mut Index, path: &str, executable: bool) -> anyhow::Result<()> {
+    let flag = if executable { '+' } else { '-' };
+    let entry = index
+        .get(path, 0)
+        .filter(|e| matches!(e.mode, BLOB_MODE | EXECUTABLE_MODE));
+    let Some(entry) = entry else {
+        anyhow::bail!("git update-index: cannot chmod {flag}x '{path}'");
+    };
+    let mut entry = entry.clone();
+    entry.mode = if executable {
+        EXECUTABLE_MODE
+    } else {
+        BLOB_MODE
+    };
+    index.add(entry);
+    Ok(())
+}
+
+// 内容が変わっていないファイルのstat情報を更新し、変わったファイルを報告する
+fn refresh(files: &Worktree, index: &mut Index) -> anyhow::Result<bool> {
+    let work_tree = files.repository().require_work_tree()?;
+    let mut clean = true;
+    let mut reported = None;
+    // 更新しながら回すので先に複製しておく
+    let entries = index.entries().to_vec();
+    for entry in entries {
+        if entry.stage != 0 {
+            if reported.as_ref() != Some(&entry.path) {
+                println!("{}: needs merge", entry.path);
+                reported = Some(entry.path.clone());
+            }
+            clean = false;
+            continue;
+        }
+        if entry.assume_valid || entry.skip_worktree || entry.intent_to_add {
+            continue;
+        }
+        match fs::symlink_metadata(work_tree.join(&entry.path)) {
+            Ok(metadata) if !files.is_modified(&entry, &metadata)? => {
+                if !entry.is_stat_clean(&metadata) {
+                    let refreshed =
+                        files.entry(&entry.path, entry.oid, &metadata, Some(entry.mode));
+                    index.add(refreshed);
+                }
+            }
+            _ => {
+                println!("{}: needs update", entry.path);
+                clean = false;
+            }
+        }
+    }
+    Ok(clean)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::testing::TempRepo;
+
+    fn update(temp: &TempRepo, paths: &[&str], options: &Options) -> anyhow::Result<bool> {
+        let root = GitRoot {
+            git_dir: temp.repo.git_dir().to_path_buf(),
+            work_tree: Some(temp.root.clone()),
+            prefix: PathBuf::new(),
+        };
+        let paths: Vec<String> = paths.iter().map(ToString::to_string).collect();
+        update_index(&temp.repo, &root, &paths, options)
+    }
+
+    #[test]
+    fn test_parse_cache_info() {
+        let oid = "a".repeat(40);
+        let info = CacheInfo::parse(&format!("100644,{oid},d/a,b")).unwrap();
+        assert_eq!(
+            (info.mode, info.stage, info.path.as_str()),
+            (0o100_644, 0, "d/a,b")
+        );
+        assert!(CacheInfo::parse(&format!("100644,{oid}")).is_none());
+        assert!(CacheInfo::parse("100644,xyz,a").is_none());
+
+        let info = CacheInfo::parse_line(&format!("100755 blob {oid}\ta b")).unwrap();
+        assert_eq!(
+            (info.mode, info.stage, info.path.as_str()),
+            (0o100_755, 0, "a b")
+        );
+        let info = CacheInfo::parse_line(&format!("100644 {oid} 2\ta")).unwrap();
+        assert_eq!(info.stage, 2);
+        let info = CacheInfo::parse_line(&format!("0 {oid}\ta")).unwrap();
+        assert_eq!(info.mode, 0);
+        assert!(CacheInfo::parse_line(&format!("100644 {oid} 4\ta")).is_none());
+        assert!(CacheInfo::parse_line(&format!("100644 {oid}")).is_none());
+    }
+
+    #[test]
+    fn test_add_entry() {
+        let entry = |path: &str, stage: u8| Entry {
+            path: path.to_string(),
+            stage,
+            ..Entry::default()
+        };
+        let mut index = Index::default();
+        index.add(entry("d/a", 0));
+        assert!(check_add(&index, "b", false).is_err());
+        assert!(check_add(&index, "b", true).is_ok());
+        // ファイルとディレクトリが重なるものは--addがあっても追加しない
+        assert!(check_add(&index, "d", true).is_err());
+        assert!(check_add(&index, "d/a/x", true).is_err());
+
+        for stage in 1..=3 {
+            add_entry(&mut index, entry("d/a", stage));
+        }
+        assert!(index.get("d/a", 0).is_none());
+        add_entry(&mut index, entry("d/a", 0));
+        let stages: Vec<u8> = index.entries().iter().map(|e| e.stage).collect();
+        assert_eq!(stages, [0]);
+    }
+
+    #[test]
+    fn test_update_index() {
+        let temp = TempRepo::new();
+        temp.write("a", "a\n");
+        assert!(update(&temp, &["a"], &Options::default()).is_err());
+        let add = Options {
+            add: true,
+            ..Options::default()
+        };
+        assert!(update(&temp, &["a", ".git/config", "b/../a"], &add).unwrap());
+        let paths = |temp: &TempRepo| -> Vec<String> {
+            let index = temp.repo.index().unwrap();
+            index.entries().iter().map(|e| e.path.clone()).collect()
+        };
+        assert_eq!(paths(&temp), ["a"]);
+
+        let chmod = Options {
+            chmod: Some(true),
+            ..Options::default()
+        };
+        update(&temp, &["a"], &chmod).unwrap();
+        assert_eq!(
+            temp.repo.index().unwrap().entries()[0].mode,
+            EXECUTABLE_MODE
+        );
+        let mark = Options {
+            assume_unchanged: Some(true),
+            ..Options::default()
+        };
+        update(&temp, &["a"], &mark).unwrap();
+        assert!(temp.repo.index().unwrap().entries()[0].assume_valid);
+
+        // 記録できないパスは--cacheinfoでも追加しない
+        let cacheinfo = |path: &str| Options {
+            add: true,
+            cacheinfo: vec![
+                CacheInfo::parse(&format!("100644,{},{path}", "b".repeat(40))).unwrap(),
+            ],
+            ..Options::default()
+        };
+        let error = update(&temp, &[], &cacheinfo(".git/hooks/x")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "git update-index: --cacheinfo cannot add .git/hooks/x"
+        );
+        update(&temp, &[], &cacheinfo("c")).unwrap();
+        assert_eq!(paths(&temp), ["a", "c"]);
+
+        // なくなったファイルは--removeがなければ消さない
+        fs::remove_file(temp.root.join("a")).unwrap();
+        let mark = Options {
+            assume_unchanged: Some(false),
+            ..Options::default()
+        };
+        update(&temp, &["a"], &mark).unwrap();
+        assert!(update(&temp, &["a"], &Options::default()).is_err());
+        let remove = Options {
+            remove: true,
+            ..Options::default()
+        };
+        update(&temp, &["a"], &remove).unwrap();
+        assert_eq!(paths(&temp), ["c"]);
+
+        let refresh = Options {
+            refresh: true,
+            ..Options::default()
+        };
+        assert!(!update(&temp, &[], &refresh).unwrap());
+        temp.write("c", "");
+        update(&temp, &["c"], &Options::default()).unwrap();
+        assert!(update(&temp, &[], &refresh).unwrap());
+    }
+}
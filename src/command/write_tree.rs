@@ -0,0 +1,8 @@
+use git::Repository;
+
+/// Write tree objects for the index and print the root tree hash.
+pub fn write_tree(repo: &Repository) -> anyhow::Result<()> {
+    let index = repo.index()?;
+    println!("{}", index.write_tree(repo.odb())?);
+    Ok(())
+}
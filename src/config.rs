@@ -0,0 +1,382 @@
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::util::lockfile::LockFile;
+
+#[derive(Debug, Clone)]
+struct Entry {
+    section: String,
+    subsection: Option<String>,
+    name: String,
+    value: Option<String>,
+}
+
+/// Parsed git configuration, merged from the global and repository files.
+///
+/// Later entries win, so files are loaded from the least to the most specific.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    entries: Vec<Entry>,
+}
+
+impl Config {
+    /// Load `~/.gitconfig`, `$XDG_CONFIG_HOME/git/config` and `<git_dir>/config`.
+    pub fn load(git_dir: &Path) -> Result<Self> {
+        let mut config = Self::load_global()?;
+        config.read_file(&git_dir.join("config"))?;
+        Ok(config)
+    }
+
+    /// Load only the user's files, for commands run outside a repository.
+    pub fn load_global() -> Result<Self> {
+        let mut config = Self::default();
+        for path in global_config_paths() {
+            config.read_file(&path)?;
+        }
+        Ok(config)
+    }
+
+    fn read_file(&mut self, path: &Path) -> Result<()> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(Error::io(path)(e)),
+        };
+        let parsed = Self::parse(&text)
+            .map_err(|e| Error::Config(format!("bad config file {}: {e}", path.display())))?;
+        self.entries.extend(parsed.entries);
+        Ok(())
+    }
+
+    /// Parse the contents of a single config file.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut entries = Vec::new();
+        let mut section: Option<(String, Option<String>)> = None;
+
+        let mut lines = text.lines().enumerate();
+        while let Some((number, line)) = lines.next() {
+            let line = line.trim_start();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            let rest = if let Some(header) = line.strip_prefix('[') {
+                let (parsed, rest) = parse_section_header(header)
+                    .ok_or_else(|| format!("line {}: invalid section header", number + 1))?;
+                section = Some(parsed);
+                rest.trim_start()
+            } else {
+                line
+            };
+            if rest.is_empty() || rest.starts_with('#') || rest.starts_with(';') {
+                continue;
+            }
+
+            let Some((section, subsection)) = section.clone() else {
+                return Err(format!("line {}: key outside of a section", number + 1));
+            };
+            let name_end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+                .unwrap_or(rest.len());
+            let name = rest[..name_end].to_ascii_lowercase();
+            if name.is_empty() || !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                return Err(format!("line {}: invalid key", number + 1));
+            }
+
+            let rest = rest[name_end..].trim_start();
+            let value = if let Some(raw) = rest.strip_prefix('=') {
+                // 行末の\で次の行に継続する
+                let mut raw = raw.to_string();
+                while raw.ends_with('\\') && !raw.ends_with("\\\\") {
+                    raw.pop();
+                    match lines.next() {
+                        Some((_, continued)) => raw.push_str(continued),
+                        None => break,
+                    }
+                }
+                Some(
+                    parse_value(&raw)
+                        .ok_or_else(|| format!("line {}: invalid value", number + 1))?,
+                )
+            } else if rest.is_empty() || rest.starts_with('#') || rest.starts_with(';') {
+                None
+            } else {
+                return Err(format!("line {}: invalid key", number + 1));
+            };
+
+            entries.push(Entry {
+                section,
+                subsection,
+                name,
+                value,
+            });
+        }
+        Ok(Self { entries })
+    }
+
+    /// Last value of `key` (`section.name` or `section.subsection.name`).
+    ///
+    /// Keys without `=` are reported as `"true"`, as git does.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_all(key).pop()
+    }
+
+    /// Every value of a multi-valued `key`, in file order.
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        let Some((section, subsection, name)) = split_key(key) else {
+            return Vec::new();
+        };
+        self.entries
+            .iter()
+            .filter(|e| {
+                e.section == section && e.subsection.as_deref() == subsection && e.name == name
+            })
+            .map(|e| e.value.as_deref().unwrap_or("true"))
+            .collect()
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        self.get(key).and_then(parse_bool)
+    }
+
+    /// Set `key` to `value` in the config file at `path`, replacing the last
+    /// existing value and keeping the rest of the file as it is.
+    pub fn set_in_file(path: &Path, key: &str, value: &str) -> Result<()> {
+        edit_file(path, key, Some(value))
+    }
+
+    /// Remove every value of `key` from the config file at `path`.
+    pub fn unset_in_file(path: &Path, key: &str) -> Result<()> {
+        edit_file(path, key, None)
+    }
+}
+
+fn edit_file(path: &Path, key: &str, value: Option<&str>) -> Result<()> {
+    let Some((section, subsection, name)) = split_key(key) else {
+        return Err(Error::Config(format!(
+            "key does not contain a section: {key}"
+        )));
+    };
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(Error::io(path)(e)),
+    };
+
+    let mut lines: Vec<String> = text.lines().map(ToString::to_string).collect();
+    let mut in_section = false;
+    // 対象のsectionの最後の行と、既存のkeyの行
+    let mut section_end = None;
+    let mut key_lines = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        if let Some(header) = trimmed.strip_prefix('[') {
+            in_section = parse_section_header(header)
+                .is_some_and(|((s, sub), _)| s == section && sub.as_deref() == subsection);
+            if in_section {
+                section_end = Some(i);
+            }
+            continue;
+        }
+        if !in_section {
+            continue;
+        }
+        section_end = Some(i);
+        let line_name_end = trimmed
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+            .unwrap_or(trimmed.len());
+        if trimmed[..line_name_end].eq_ignore_ascii_case(&name) {
+            key_lines.push(i);
+        }
+    }
+
+    match (value, key_lines.last(), section_end) {
+        (Some(value), Some(&i), _) => lines[i] = format!("\t{name} = {}", quote_value(value)),
+        (Some(value), None, Some(end)) => {
+            lines.insert(end + 1, format!("\t{name} = {}", quote_value(value)));
+        }
+        (Some(value), None, None) => {
+            let header = subsection.map_or_else(
+                || format!("[{section}]"),
+                |subsection| {
+                    let subsection = subsection.replace('\\', "\\\\").replace('"', "\\\"");
+                    format!("[{section} \"{subsection}\"]")
+                },
+            );
+            lines.push(header);
+            lines.push(format!("\t{name} = {}", quote_value(value)));
+        }
+        (None, _, _) => {
+            for &i in key_lines.iter().rev() {
+                lines.remove(i);
+            }
+        }
+    }
+
+    let mut lock = LockFile::acquire(path)?;
+    lock.write_all((lines.join("\n") + "\n").as_bytes())?;
+    lock.commit()
+}
+
+fn quote_value(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    let needs_quotes = value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+        || value.contains(['#', ';']);
+    if needs_quotes {
+        format!("\"{escaped}\"")
+    } else {
+        escaped
+    }
+}
+
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" | "" => Some(false),
+        _ => None,
+    }
+}
+
+fn global_config_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    let xdg = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+    if let Some(xdg) = xdg {
+        paths.push(xdg.join("git/config"));
+    }
+    if let Some(home) = env::var_os("HOME") {
+        paths.push(Path::new(&home).join(".gitconfig"));
+    }
+    paths
+}
+
+fn split_key(key: &str) -> Option<(String, Option<&str>, String)> {
+    let (section, rest) = key.split_once('.')?;
+    let (subsection, name) = match rest.rsplit_once('.') {
+        Some((subsection, name)) => (Some(subsection), name),
+        None => (None, rest),
+    };
+    Some((
+        section.to_ascii_lowercase(),
+        subsection,
+        name.to_ascii_lowercase(),
+    ))
+}
+
+// `section]`, `section "sub"]`, `section.sub]`を解析し、残りの文字列も返す
+fn parse_section_header(header: &str) -> Option<((String, Option<String>), &str)> {
+    let end = header.find([']', '"', ' ', '\t'])?;
+    let name = &header[..end];
+    let rest = header[end..].trim_start();
+
+    if let Some(rest) = rest.strip_prefix(']') {
+        return Some(match name.split_once('.') {
+            Some((section, sub)) => (
+                (section.to_ascii_lowercase(), Some(sub.to_ascii_lowercase())),
+                rest,
+            ),
+            None => ((name.to_ascii_lowercase(), None), rest),
+        });
+    }
+
+    let mut chars = rest.strip_prefix('"')?.char_indices();
+    let mut subsection = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => subsection.push(chars.next()?.1),
+            '"' => {
+                let rest = rest[i + 2..].strip_prefix(']')?;
+                return Some(((name.to_ascii_lowercase(), Some(subsection)), rest));
+            }
+            c => subsection.push(c),
+        }
+    }
+    None
+}
+
+fn parse_value(raw: &str) -> Option<String> {
+    let mut value = String::new();
+    let mut quoted = false;
+    // 引用符の外の末尾の空白は取り除く
+    let mut trailing_space = 0;
+    let mut chars = raw.trim_start().chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                trailing_space = 0;
+                continue;
+            }
+            '#' | ';' if !quoted => break,
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'b' => {
+                    value.pop();
+                }
+                c @ ('\\' | '"') => value.push(c),
+                _ => return None,
+            },
+            c if c.is_whitespace() && !quoted => {
+                value.push(c);
+                trailing_space += c.len_utf8();
+                continue;
+            }
+            c => value.push(c),
+        }
+        trailing_space = 0;
+    }
+    if quoted {
+        return None;
+    }
+    value.truncate(value.len() - trailing_space);
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(indoc::indoc! {r#"
+            # comment
+            [core]
+                bare = false
+                FileMode = yes ; trailing comment
+                symlinks
+            [remote "origin"]
+                url = "https://example.com/a b.git"
+                fetch = +refs/heads/*:refs/remotes/origin/*
+            [user]
+                name = Jane \"JD\" Doe
+        "#})
+        .unwrap();
+        assert_eq!(config.get_bool("core.bare"), Some(false));
+        assert_eq!(config.get_bool("core.filemode"), Some(true));
+        assert_eq!(config.get("core.symlinks"), Some("true"));
+        assert_eq!(
+            config.get("remote.origin.url"),
+            Some("https://example.com/a b.git")
+        );
+        assert_eq!(config.get("remote.Origin.url"), None);
+        assert_eq!(config.get("user.name"), Some("Jane \"JD\" Doe"));
+    }
+
+    #[test]
+    fn test_quote_value_roundtrip() {
+        for value in ["plain", " padded ", "a # b", "say \"hi\"", "back\\slash"] {
+            let text = format!("[x]\n\tkey = {}\n", quote_value(value));
+            assert_eq!(Config::parse(&text).unwrap().get("x.key"), Some(value));
+        }
+    }
+}
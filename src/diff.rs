@@ -0,0 +1,1019 @@
+//! Line diffs and unified hunks.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io::{self, Write};
+use std::ops::Range;
+
+pub mod combined;
+pub mod patch;
+pub mod rename;
+pub mod stat;
+pub mod tree;
+
+/// One step of an edit script turning the old lines into the new ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    /// Old line `.0` is kept as new line `.1`.
+    Equal(usize, usize),
+    /// Old line is removed.
+    Delete(usize),
+    /// New line is inserted.
+    Insert(usize),
+}
+
+/// Split `data` into lines, keeping the line terminators.
+pub fn lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|&b| b == b'\n').collect()
+}
+
+/// Edit script from `old` to `new` with Myers' O(ND) algorithm, done the
+/// way xdiff does it.
+///
+/// Lines found only on one side are set aside first, the middle of the
+/// script is searched for from both ends in linear space, and past a cost of
+/// about the square root of the number of lines the search settles for a
+/// good enough split instead of the shortest script. Finally changes are
+/// slid to where git shows them.
+pub fn myers<T: Eq + Hash>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let (removed, added) = myers_changes(old, new);
+    script(old, new, removed, added)
+}
+
+// myersで見つけた、oldで削除された行とnewで追加された行の印
+fn myers_changes<T: Eq + Hash>(old: &[T], new: &[T]) -> (Vec<bool>, Vec<bool>) {
+    // 行を番号に置き換え、それぞれの側での数を数える
+    let mut ids: HashMap<&T, usize> = HashMap::new();
+    let [old_ids, new_ids] = [old, new].map(|lines| {
+        lines
+            .iter()
+            .map(|line| {
+                let next = ids.len();
+                *ids.entry(line).or_insert(next)
+            })
+            .collect::<Vec<_>>()
+    });
+    let mut counts = vec![[0, 0]; ids.len()];
+    for (side, lines) in [&old_ids, &new_ids].into_iter().enumerate() {
+        for &id in lines {
+            counts[id][side] += 1;
+        }
+    }
+
+    let prefix = old_ids
+        .iter()
+        .zip(&new_ids)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old_ids[prefix..]
+        .iter()
+        .rev()
+        .zip(new_ids[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    // 相手側にない行などは比べずに変更とする
+    let old_kept = keep(&old_ids, prefix..old.len() - suffix, |id| counts[id][1]);
+    let new_kept = keep(&new_ids, prefix..new.len() - suffix, |id| counts[id][0]);
+
+    let lines = |ids: &[usize], kept: &[usize]| kept.iter().map(|&i| ids[i]).collect::<Vec<_>>();
+    let (old_lines, new_lines) = (lines(&old_ids, &old_kept), lines(&new_ids, &new_kept));
+    let size = old_lines.len() + new_lines.len() + 3;
+    let mut search = Search {
+        old: &old_lines,
+        new: &new_lines,
+        forward: vec![0; size],
+        backward: vec![0; size],
+        max_cost: bogosqrt(size).max(MIN_COST),
+        removed: vec![false; old_lines.len()],
+        added: vec![false; new_lines.len()],
+    };
+    search.compare(0..old_lines.len(), 0..new_lines.len(), false);
+    let mut removed = vec![false; old.len()];
+    let mut added = vec![false; new.len()];
+    for (changed, kept, found, range) in [
+        (
+            &mut removed,
+            &old_kept,
+            &search.removed,
+            prefix..old.len() - suffix,
+        ),
+        (
+            &mut added,
+            &new_kept,
+            &search.added,
+            prefix..new.len() - suffix,
+        ),
+    ] {
+        changed[range].fill(true);
+        for (&i, &found) in kept.iter().zip(found) {
+            changed[i] = found;
+        }
+    }
+    (removed, added)
+}
+
+// 変更の印を、gitと同じ位置へずらしてから編集列にする。削除を挿入より先に並べる
+fn script<T: PartialEq>(
+    old: &[T],
+    new: &[T],
+    mut removed: Vec<bool>,
+    mut added: Vec<bool>,
+) -> Vec<Edit> {
+    compact(old, &mut removed, &added);
+    compact(new, &mut added, &removed);
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && removed[i] {
+            edits.push(Edit::Delete(i));
+            i += 1;
+        } else if j < new.len() && added[j] {
+            edits.push(Edit::Insert(j));
+            j += 1;
+        } else {
+            edits.push(Edit::Equal(i, j));
+            i += 1;
+            j += 1;
+        }
+    }
+    edits
+}
+
+// xdiffの定数。最低でもこれだけの費用までは最短の編集列を探し、
+// その先ではこれだけ続く一致を見つけたらそこで分ける
+const MIN_COST: isize = 256;
+const SNAKE_LENGTH: isize = 20;
+
+// xdiffのxdl_bogosqrt。だいたいの平方根
+fn bogosqrt(mut n: usize) -> isize {
+    let mut root = 1;
+    while n > 0 {
+        root <<= 1;
+        n >>= 2;
+    }
+    root
+}
+
+// gitのxdl_cleanup_recordsと同じく、`range`のうち比べる行の位置。相手側にない行と、
+// 相手側に多すぎて周りも比べない行ばかりの行は、比べるまでもなく変更とする
+fn keep(ids: &[usize], range: Range<usize>, matches: impl Fn(usize) -> usize) -> Vec<usize> {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Matches {
+        None,
+        Few,
+        Many,
+    }
+    // 比べる範囲を見るのは前後これだけ
+    const WINDOW: usize = 100;
+
+    let limit = usize::try_from(bogosqrt(ids.len())).map_or(1024, |l| l.min(1024));
+    let found: Vec<Matches> = ids[range.clone()]
+        .iter()
+        .map(|&id| match matches(id) {
+            0 => Matches::None,
+            n if n >= limit => Matches::Many,
+            _ => Matches::Few,
+        })
+        .collect();
+    // 前後に続く、相手側にない行と多すぎる行の数
+    let run = |lines: &mut dyn Iterator<Item = &Matches>| {
+        let (mut none, mut many) = (0, 1);
+        for &m in lines.take(WINDOW) {
+            match m {
+                Matches::None => none += 1,
+                Matches::Many => many += 1,
+                Matches::Few => break,
+            }
+        }
+        (none, many)
+    };
+    let surrounded = |i: usize| {
+        let (before_none, before_many) = run(&mut found[..i].iter().rev());
+        let (after_none, after_many) = run(&mut found[i + 1..].iter());
+        let many = before_many + after_many;
+        before_none > 0 && after_none > 0 && many * 4 < many + before_none + after_none
+    };
+    (0..found.len())
+        .filter(|&i| found[i] == Matches::Few || (found[i] == Matches::Many && !surrounded(i)))
+        .map(|i| i + range.start)
+        .collect()
+}
+
+// xdiffのxdl_recs_cmp/xdl_splitと同じく、両端から探して真ん中で分ける
+struct Search<'a> {
+    old: &'a [usize],
+    new: &'a [usize],
+    // 対角線ごとに、前と後ろから到達したx
+    forward: Vec<isize>,
+    backward: Vec<isize>,
+    max_cost: isize,
+    removed: Vec<bool>,
+    added: Vec<bool>,
+}
+
+impl Search<'_> {
+    // `minimal`なら費用にかかわらず最短の編集列を探す
+    fn compare(&mut self, mut old_range: Range<usize>, mut new_range: Range<usize>, minimal: bool) {
+        while !old_range.is_empty()
+            && !new_range.is_empty()
+            && self.old[old_range.start] == self.new[new_range.start]
+        {
+            old_range.start += 1;
+            new_range.start += 1;
+        }
+        while !old_range.is_empty()
+            && !new_range.is_empty()
+            && self.old[old_range.end - 1] == self.new[new_range.end - 1]
+        {
+            old_range.end -= 1;
+            new_range.end -= 1;
+        }
+        if old_range.is_empty() {
+            self.added[new_range].fill(true);
+        } else if new_range.is_empty() {
+            self.removed[old_range].fill(true);
+        } else {
+            let (x, y, minimal_low, minimal_high) = self.split(&old_range, &new_range, minimal);
+            self.compare(old_range.start..x, new_range.start..y, minimal_low);
+            self.compare(x..old_range.end, y..new_range.end, minimal_high);
+        }
+    }
+
+    // 分ける点(x, y)と、前後の半分をそれぞれ最短で探すべきか。
+    // 後ろからの探索は範囲の外まで進むことがあるので、位置は符号付きで扱う
+    #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+    fn split(
+        &mut self,
+        old_range: &Range<usize>,
+        new_range: &Range<usize>,
+        minimal: bool,
+    ) -> (usize, usize, bool, bool) {
+        let (off1, lim1) = (old_range.start as isize, old_range.end as isize);
+        let (off2, lim2) = (new_range.start as isize, new_range.end as isize);
+        // 対角線k = x - yの位置
+        let shift = self.new.len() as isize + 1;
+        let at = |k: isize| (k + shift) as usize;
+        let (lowest, highest) = (off1 - lim2, lim1 - off2);
+        let odd = (old_range.len() + new_range.len()) % 2 == 1;
+        let mut front = (off1 - off2, off1 - off2);
+        let mut back = (lim1 - lim2, lim1 - lim2);
+        self.forward[at(front.0)] = off1;
+        self.backward[at(back.0)] = lim1;
+
+        for cost in 1.. {
+            let mut snake = false;
+            // 広げた外側の対角線からは来られない
+            front = extend(front, lowest, highest, |k| self.forward[at(k)] = -1);
+            for k in (front.0..=front.1).rev().step_by(2) {
+                let (left, right) = (self.forward[at(k - 1)], self.forward[at(k + 1)]);
+                let (mut x, start) = if left >= right {
+                    (left + 1, left + 1)
+                } else {
+                    (right, right)
+                };
+                let mut y = x - k;
+                while x < lim1 && y < lim2 && self.old[x as usize] == self.new[y as usize] {
+                    x += 1;
+                    y += 1;
+                }
+                snake |= x - start > SNAKE_LENGTH;
+                self.forward[at(k)] = x;
+                if odd && back.0 <= k && k <= back.1 && self.backward[at(k)] <= x {
+                    return (x as usize, y as usize, true, true);
+                }
+            }
+
+            back = extend(back, lowest, highest, |k| self.backward[at(k)] = isize::MAX);
+            for k in (back.0..=back.1).rev().step_by(2) {
+                let (left, right) = (self.backward[at(k - 1)], self.backward[at(k + 1)]);
+                let (mut x, start) = if left < right {
+                    (left, left)
+                } else {
+                    (right - 1, right - 1)
+                };
+                let mut y = x - k;
+                while x > off1 && y > off2 && self.old[x as usize - 1] == self.new[y as usize - 1] {
+                    x -= 1;
+                    y -= 1;
+                }
+                snake |= start - x > SNAKE_LENGTH;
+                self.backward[at(k)] = x;
+                if !odd && front.0 <= k && k <= front.1 && x <= self.forward[at(k)] {
+                    return (x as usize, y as usize, true, true);
+                }
+            }
+
+            if minimal {
+                continue;
+            }
+            if snake && cost > MIN_COST {
+                if let Some(split) = self.long_snake((off1, lim1), (off2, lim2), front, back, cost)
+                {
+                    return split;
+                }
+            }
+            if cost >= self.max_cost {
+                let (x, y, forward) = self.furthest((off1, lim1), (off2, lim2), front, back);
+                return (x as usize, y as usize, forward, !forward);
+            }
+        }
+        unreachable!("the search ends once the two ends meet")
+    }
+
+    // 費用がかかってきたら、十分に進んだ先で長く一致が続くところで分ける
+    #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+    fn long_snake(
+        &self,
+        (off1, lim1): (isize, isize),
+        (off2, lim2): (isize, isize),
+        front: (isize, isize),
+        back: (isize, isize),
+        cost: isize,
+    ) -> Option<(usize, usize, bool, bool)> {
+        let at = |k: isize| (k + self.new.len() as isize + 1) as usize;
+        let same = |x: isize, y: isize| self.old[x as usize] == self.new[y as usize];
+        let mut best: Option<(isize, isize, isize)> = None;
+        let middle = off1 - off2;
+        for k in (front.0..=front.1).rev().step_by(2) {
+            let x = self.forward[at(k)];
+            let y = x - k;
+            let progress = (x - off1) + (y - off2) - (k - middle).abs();
+            if progress > 4 * cost
+                && best.map_or(true, |(best, ..)| progress > best)
+                && off1 + SNAKE_LENGTH <= x
+                && x < lim1
+                && off2 + SNAKE_LENGTH <= y
+                && y < lim2
+                && (1..=SNAKE_LENGTH).all(|n| same(x - n, y - n))
+            {
+                best = Some((progress, x, y));
+            }
+        }
+        if let Some((_, x, y)) = best {
+            return Some((x as usize, y as usize, true, false));
+        }
+        let middle = lim1 - lim2;
+        for k in (back.0..=back.1).rev().step_by(2) {
+            let x = self.backward[at(k)];
+            let y = x - k;
+            let progress = (lim1 - x) + (lim2 - y) - (k - middle).abs();
+            if progress > 4 * cost
+                && best.map_or(true, |(best, ..)| progress > best)
+                && off1 < x
+                && x <= lim1 - SNAKE_LENGTH
+                && off2 < y
+                && y <= lim2 - SNAKE_LENGTH
+                && (0..SNAKE_LENGTH).all(|n| same(x + n, y + n))
+            {
+                best = Some((progress, x, y));
+            }
+        }
+        best.map(|(_, x, y)| (x as usize, y as usize, false, true))
+    }
+
+    // 費用がかかりすぎるときは、前と後ろのうち遠くまで進んだ点で分ける。
+    // 前から進んだ点ならtrueを返す
+    #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+    fn furthest(
+        &self,
+        (off1, lim1): (isize, isize),
+        (off2, lim2): (isize, isize),
+        front: (isize, isize),
+        back: (isize, isize),
+    ) -> (isize, isize, bool) {
+        let at = |k: isize| (k + self.new.len() as isize + 1) as usize;
+        // (x + y, x)
+        let mut forward_best = (-1, -1);
+        for k in (front.0..=front.1).rev().step_by(2) {
+            let mut x = self.forward[at(k)].min(lim1);
+            if x - k > lim2 {
+                x = lim2 + k;
+            }
+            if forward_best.0 < 2 * x - k {
+                forward_best = (2 * x - k, x);
+            }
+        }
+        let mut backward_best = (isize::MAX, isize::MAX);
+        for k in (back.0..=back.1).rev().step_by(2) {
+            let mut x = self.backward[at(k)].max(off1);
+            if x - k < off2 {
+                x = off2 + k;
+            }
+            if 2 * x - k < backward_best.0 {
+                backward_best = (2 * x - k, x);
+            }
+        }
+        if (lim1 + lim2) - backward_best.0 < forward_best.0 - (off1 + off2) {
+            let (sum, x) = forward_best;
+            (x, sum - x, true)
+        } else {
+            let (sum, x) = backward_best;
+            (x, sum - x, false)
+        }
+    }
+}
+
+// 探す対角線の範囲を1つ広げ、その外側の対角線を`block`する。
+// 端に着いたら、その側は1つ狭める
+fn extend(
+    (low, high): (isize, isize),
+    lowest: isize,
+    highest: isize,
+    mut block: impl FnMut(isize),
+) -> (isize, isize) {
+    let low = if low > lowest {
+        block(low - 2);
+        low - 1
+    } else {
+        low + 1
+    };
+    let high = if high < highest {
+        block(high + 2);
+        high + 1
+    } else {
+        high - 1
+    };
+    (low, high)
+}
+
+// gitのxdl_change_compactと同じく、変更された行のまとまりをできるだけ後ろへずらし、
+// 反対側の変更と並ぶ位置があればそこに合わせる。`changed`のまとまりと`other`の
+// まとまりは、変更されていない行を挟んで1つずつ対応する
+fn compact<T: PartialEq>(lines: &[T], changed: &mut [bool], other: &[bool]) {
+    let mut group = Group::first(changed);
+    let mut other_group = Group::first(other);
+    loop {
+        if !group.is_empty() {
+            let (earliest_end, end_matching_other) = loop {
+                let size = group.len();
+                while group.slide_up(lines, changed) {
+                    other_group.previous(other);
+                }
+                let earliest_end = group.end;
+                let mut end_matching_other = (!other_group.is_empty()).then_some(group.end);
+                while group.slide_down(lines, changed) {
+                    other_group.next(other);
+                    if !other_group.is_empty() {
+                        end_matching_other = Some(group.end);
+                    }
+                }
+                // ずらした先で隣のまとまりとつながったら、もう一度やり直す
+                if size == group.len() {
+                    break (earliest_end, end_matching_other);
+                }
+            };
+            if group.end != earliest_end && end_matching_other.is_some() {
+                while other_group.is_empty() {
+                    group.slide_up(lines, changed);
+                    other_group.previous(other);
+                }
+            }
+        }
+        if !group.next(changed) {
+            break;
+        }
+        other_group.next(other);
+    }
+}
+
+// 変更された行のまとまり。変更されていない行の直前ごとに1つあり、空のこともある
+struct Group {
+    start: usize,
+    end: usize,
+}
+
+impl Group {
+    fn first(changed: &[bool]) -> Self {
+        let end = changed.iter().take_while(|&&c| c).count();
+        Self { start: 0, end }
+    }
+
+    fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    fn next(&mut self, changed: &[bool]) -> bool {
+        if self.end == changed.len() {
+            return false;
+        }
+        self.start = self.end + 1;
+        self.end = self.start + changed[self.start..].iter().take_while(|&&c| c).count();
+        true
+    }
+
+    fn previous(&mut self, changed: &[bool]) -> bool {
+        if self.start == 0 {
+            return false;
+        }
+        self.end = self.start - 1;
+        self.start = self.end - changed[..self.end].iter().rev().take_while(|&&c| c).count();
+        true
+    }
+
+    // 後ろの変更されていない行が先頭の行と同じなら、1行後ろへずらす
+    fn slide_down<T: PartialEq>(&mut self, lines: &[T], changed: &mut [bool]) -> bool {
+        if self.end == lines.len() || lines[self.start] != lines[self.end] {
+            return false;
+        }
+        changed[self.start] = false;
+        changed[self.end] = true;
+        self.start += 1;
+        self.end += 1 + changed[self.end + 1..].iter().take_while(|&&c| c).count();
+        true
+    }
+
+    fn slide_up<T: PartialEq>(&mut self, lines: &[T], changed: &mut [bool]) -> bool {
+        if self.start == 0 || lines[self.start - 1] != lines[self.end - 1] {
+            return false;
+        }
+        self.start -= 1;
+        self.end -= 1;
+        changed[self.start] = true;
+        changed[self.end] = false;
+        self.start -= changed[..self.start]
+            .iter()
+            .rev()
+            .take_while(|&&c| c)
+            .count();
+        true
+    }
+}
+
+/// How lines of the two sides are matched up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Algorithm {
+    /// The shortest edit script.
+    #[default]
+    Myers,
+    /// Anchor on lines that occur exactly once on both sides.
+    Patience,
+    /// Anchor on the rarest common lines; like patience, but also handles
+    /// lines that are not unique.
+    Histogram,
+}
+
+impl Algorithm {
+    /// Parse a `--diff-algorithm`/`diff.algorithm` value.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "myers" | "default" | "minimal" => Some(Self::Myers),
+            "patience" => Some(Self::Patience),
+            "histogram" => Some(Self::Histogram),
+            _ => None,
+        }
+    }
+}
+
+/// Edit script from `old` to `new` using `algorithm`.
+pub fn diff<T: Eq + Hash>(algorithm: Algorithm, old: &[T], new: &[T]) -> Vec<Edit> {
+    match algorithm {
+        Algorithm::Myers => myers(old, new),
+        Algorithm::Patience => {
+            let mut edits = Vec::new();
+            patience(old, new, 0..old.len(), 0..new.len(), &mut edits);
+            edits
+        }
+        Algorithm::Histogram => {
+            let mut removed = vec![false; old.len()];
+            let mut added = vec![false; new.len()];
+            histogram(
+                old,
+                new,
+                0..old.len(),
+                0..new.len(),
+                (&mut removed, &mut added),
+            );
+            script(old, new, removed, added)
+        }
+    }
+}
+
+// 両側で1回だけ現れる行を目印に分割して再帰的に比べる
+fn patience<T: Eq + Hash>(
+    old: &[T],
+    new: &[T],
+    mut old_range: Range<usize>,
+    mut new_range: Range<usize>,
+    edits: &mut Vec<Edit>,
+) {
+    // 先頭と末尾の共通部分は目印を探すまでもない
+    while !old_range.is_empty()
+        && !new_range.is_empty()
+        && old[old_range.start] == new[new_range.start]
+    {
+        edits.push(Edit::Equal(old_range.start, new_range.start));
+        old_range.start += 1;
+        new_range.start += 1;
+    }
+    let mut suffix = 0;
+    while !old_range.is_empty()
+        && !new_range.is_empty()
+        && old[old_range.end - 1] == new[new_range.end - 1]
+    {
+        old_range.end -= 1;
+        new_range.end -= 1;
+        suffix += 1;
+    }
+
+    let anchors = unique_common_lines(old, new, &old_range, &new_range);
+    if anchors.is_empty() {
+        let offset = |edit: Edit| match edit {
+            Edit::Equal(i, j) => Edit::Equal(i + old_range.start, j + new_range.start),
+            Edit::Delete(i) => Edit::Delete(i + old_range.start),
+            Edit::Insert(j) => Edit::Insert(j + new_range.start),
+        };
+        let script = myers(&old[old_range.clone()], &new[new_range.clone()]);
+        edits.extend(script.into_iter().map(offset));
+    } else {
+        let (mut old_start, mut new_start) = (old_range.start, new_range.start);
+        for (i, j) in anchors {
+            patience(old, new, old_start..i, new_start..j, edits);
+            edits.push(Edit::Equal(i, j));
+            old_start = i + 1;
+            new_start = j + 1;
+        }
+        patience(
+            old,
+            new,
+            old_start..old_range.end,
+            new_start..new_range.end,
+            edits,
+        );
+    }
+
+    for k in 0..suffix {
+        edits.push(Edit::Equal(old_range.end + k, new_range.end + k));
+    }
+}
+
+// 両側で1回だけ現れる行のうち、順序を保てる最長の列
+fn unique_common_lines<T: Eq + Hash>(
+    old: &[T],
+    new: &[T],
+    old_range: &Range<usize>,
+    new_range: &Range<usize>,
+) -> Vec<(usize, usize)> {
+    // 行 -> (oldでの回数, oldでの位置, newでの回数, newでの位置)
+    let mut counts: HashMap<&T, (usize, usize, usize, usize)> = HashMap::new();
+    for i in old_range.clone() {
+        let count = counts.entry(&old[i]).or_default();
+        count.0 += 1;
+        count.1 = i;
+    }
+    for j in new_range.clone() {
+        if let Some(count) = counts.get_mut(&new[j]) {
+            count.2 += 1;
+            count.3 = j;
+        }
+    }
+    let mut pairs: Vec<(usize, usize)> = counts
+        .into_values()
+        .filter(|&(old_count, _, new_count, _)| old_count == 1 && new_count == 1)
+        .map(|(_, i, _, j)| (i, j))
+        .collect();
+    pairs.sort_unstable();
+
+    // newでの位置が増加する最長の部分列をpatience sortingで求める
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous = vec![None; pairs.len()];
+    for (k, &(_, j)) in pairs.iter().enumerate() {
+        let pile = tails.partition_point(|&t| pairs[t].1 < j);
+        if pile > 0 {
+            previous[k] = Some(tails[pile - 1]);
+        }
+        if pile == tails.len() {
+            tails.push(k);
+        } else {
+            tails[pile] = k;
+        }
+    }
+    let mut result = Vec::new();
+    let mut current = tails.last().copied();
+    while let Some(k) = current {
+        result.push(pairs[k]);
+        current = previous[k];
+    }
+    result.reverse();
+    result
+}
+
+// gitのxhistogram.cと同じく、oldで最も出現回数の少ない行を含む一致区間で分けて
+// 再帰的に比べ、削除と追加の印をつける
+fn histogram<T: Eq + Hash>(
+    old: &[T],
+    new: &[T],
+    mut old_range: Range<usize>,
+    mut new_range: Range<usize>,
+    (removed, added): (&mut [bool], &mut [bool]),
+) {
+    loop {
+        if old_range.is_empty() || new_range.is_empty() {
+            removed[old_range].fill(true);
+            added[new_range].fill(true);
+            return;
+        }
+        match rarest_region(old, new, &old_range, &new_range) {
+            Region::Found(old_match, new_match) => {
+                histogram(
+                    old,
+                    new,
+                    old_range.start..old_match.start,
+                    new_range.start..new_match.start,
+                    (removed, added),
+                );
+                // 後ろの半分は繰り返しで比べる
+                old_range.start = old_match.end;
+                new_range.start = new_match.end;
+            }
+            Region::None => {
+                removed[old_range].fill(true);
+                added[new_range].fill(true);
+                return;
+            }
+            Region::TooCommon => {
+                let (old_changes, new_changes) =
+                    myers_changes(&old[old_range.clone()], &new[new_range.clone()]);
+                removed[old_range].copy_from_slice(&old_changes);
+                added[new_range].copy_from_slice(&new_changes);
+                return;
+            }
+        }
+    }
+}
+
+// histogramで分ける目印になる一致区間
+enum Region {
+    Found(Range<usize>, Range<usize>),
+    // 共通の行がない
+    None,
+    // 共通の行はあるが、どれも出現回数が多すぎる
+    TooCommon,
+}
+
+// 出現回数が最も少ない行を含む一致区間。gitと同じく、回数が同じなら長いものを選ぶ
+fn rarest_region<T: Eq + Hash>(
+    old: &[T],
+    new: &[T],
+    old_range: &Range<usize>,
+    new_range: &Range<usize>,
+) -> Region {
+    // gitと同じく、出現回数が多すぎる行は目印にしない
+    const MAX_OCCURRENCES: usize = 64;
+
+    let mut positions: HashMap<&T, Vec<usize>> = HashMap::new();
+    for i in old_range.clone() {
+        positions.entry(&old[i]).or_default().push(i);
+    }
+    let count = |i: usize| positions[&old[i]].len();
+    let mut limit = MAX_OCCURRENCES + 1;
+    let mut common = false;
+    let mut best: Option<(Range<usize>, Range<usize>)> = None;
+    let mut j = new_range.start;
+    while j < new_range.end {
+        let mut next = j + 1;
+        let occurrences = positions.get(&new[j]).map_or(&[][..], Vec::as_slice);
+        common |= !occurrences.is_empty();
+        let mut start = occurrences.first().filter(|_| occurrences.len() <= limit);
+        while let Some(&i) = start {
+            let (mut old_start, mut new_start) = (i, j);
+            let (mut old_end, mut new_end) = (i + 1, j + 1);
+            // 広げながら、区間の中で最も少ない出現回数を数える
+            let mut rarity = occurrences.len();
+            while old_start > old_range.start
+                && new_start > new_range.start
+                && old[old_start - 1] == new[new_start - 1]
+            {
+                old_start -= 1;
+                new_start -= 1;
+                if rarity > 1 {
+                    rarity = rarity.min(count(old_start));
+                }
+            }
+            while old_end < old_range.end && new_end < new_range.end && old[old_end] == new[new_end]
+            {
+                if rarity > 1 {
+                    rarity = rarity.min(count(old_end));
+                }
+                old_end += 1;
+                new_end += 1;
+            }
+            // 一致した区間の中からは探し直さない
+            next = next.max(new_end);
+            let longer =
+                best.as_ref().map_or(1, |(old_match, _)| old_match.len()) < old_end - old_start;
+            if longer || rarity < limit {
+                best = Some((old_start..old_end, new_start..new_end));
+                limit = rarity;
+            }
+            start = occurrences.iter().find(|&&p| p >= old_end);
+        }
+        j = next;
+    }
+    match best {
+        _ if common && limit > MAX_OCCURRENCES => Region::TooCommon,
+        Some((old_match, new_match)) => Region::Found(old_match, new_match),
+        None => Region::None,
+    }
+}
+
+/// A group of nearby changes with surrounding context.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// 0-based first old line and number of old lines.
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+    /// The part of the edit script covered by the hunk.
+    pub edits: Range<usize>,
+}
+
+/// Group the changes of `edits` into hunks with `context` unchanged lines
+/// around them, merging hunks whose context would overlap.
+pub fn hunks(edits: &[Edit], context: usize) -> Vec<Hunk> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (i, edit) in edits.iter().enumerate() {
+        if matches!(edit, Edit::Equal(..)) {
+            continue;
+        }
+        let start = i.saturating_sub(context);
+        let end = (i + 1 + context).min(edits.len());
+        match ranges.last_mut() {
+            Some(last) if start <= last.end => last.end = end,
+            _ => ranges.push(start..end),
+        }
+    }
+
+    ranges
+        .into_iter()
+        .map(|range| {
+            let (old_start, new_start) = position(edits, range.start);
+            let (old_end, new_end) = position(edits, range.end);
+            Hunk {
+                old: old_start..old_end,
+                new: new_start..new_end,
+                edits: range,
+            }
+        })
+        .collect()
+}
+
+// 編集列のi番目の直前の、古い行と新しい行の位置
+fn position(edits: &[Edit], i: usize) -> (usize, usize) {
+    edits[..i]
+        .iter()
+        .fold((0, 0), |(old, new), edit| match edit {
+            Edit::Equal(..) => (old + 1, new + 1),
+            Edit::Delete(_) => (old + 1, new),
+            Edit::Insert(_) => (old, new + 1),
+        })
+}
+
+impl Hunk {
+    /// The `@@ -l,s +l,s @@` line, without a newline.
+    pub fn header(&self) -> String {
+        format!(
+            "@@ -{} +{} @@",
+            range_text(&self.old),
+            range_text(&self.new)
+        )
+    }
+
+    /// The text git shows after the header: the closest line before the
+    /// hunk that starts with a letter, `_` or `$`.
+    pub fn function_name<'a>(&self, old: &[&'a [u8]]) -> Option<&'a [u8]> {
+        // gitと同じく末尾の空白を除いて80byteまでにする
+        const MAX_LENGTH: usize = 80;
+        let line = old[..self.old.start].iter().rev().find(|line| {
+            line.first()
+                .is_some_and(|&b| b.is_ascii_alphabetic() || b == b'_' || b == b'$')
+        })?;
+        let end = line
+            .iter()
+            .rposition(|b| !b.is_ascii_whitespace())
+            .map_or(0, |i| i + 1);
+        Some(&line[..end.min(MAX_LENGTH)])
+    }
+
+    /// Write the hunk in unified format.
+    pub fn write_to(
+        &self,
+        out: &mut impl Write,
+        edits: &[Edit],
+        old: &[&[u8]],
+        new: &[&[u8]],
+    ) -> io::Result<()> {
+        write!(out, "{}", self.header())?;
+        if let Some(name) = self.function_name(old) {
+            out.write_all(b" ")?;
+            out.write_all(name)?;
+        }
+        writeln!(out)?;
+        for edit in &edits[self.edits.clone()] {
+            let (sign, line) = match *edit {
+                Edit::Equal(i, _) => (b' ', old[i]),
+                Edit::Delete(i) => (b'-', old[i]),
+                Edit::Insert(i) => (b'+', new[i]),
+            };
+            out.write_all(&[sign])?;
+            out.write_all(line)?;
+            if !line.ends_with(b"\n") {
+                out.write_all(b"\n\\ No newline at end of file\n")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// gitと同じく、1行なら長さを省略し、空なら直前の行番号を使う
+fn range_text(range: &Range<usize>) -> String {
+    match range.len() {
+        0 => format!("{},0", range.start),
+        1 => format!("{}", range.start + 1),
+        len => format!("{},{len}", range.start + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 編集列を適用して新しい内容を復元できるか
+    fn apply<'a>(edits: &[Edit], old: &[&'a str], new: &[&'a str]) -> Vec<&'a str> {
+        edits
+            .iter()
+            .filter_map(|edit| match *edit {
+                Edit::Equal(i, _) => Some(old[i]),
+                Edit::Delete(_) => None,
+                Edit::Insert(j) => Some(new[j]),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_myers() {
+        let old = ["a", "b", "c", "a", "b", "b", "a"];
+        let new = ["c", "b", "a", "b", "a", "c"];
+        let edits = myers(&old, &new);
+        assert_eq!(apply(&edits, &old, &new), new);
+        let changes = edits
+            .iter()
+            .filter(|e| !matches!(e, Edit::Equal(..)))
+            .count();
+        assert_eq!(changes, 5);
+
+        assert!(myers::<&str>(&[], &[]).is_empty());
+        assert_eq!(myers(&["a"], &[]), vec![Edit::Delete(0)]);
+        assert_eq!(myers(&[], &["a"]), vec![Edit::Insert(0)]);
+
+        // gitと同じく、挿入は後ろへずらし、削除は挿入より先に置く
+        let edits = myers(&["a", "b", "c"], &["a", "b", "a", "b", "c"]);
+        assert_eq!(edits[2..4], [Edit::Insert(2), Edit::Insert(3)]);
+        let edits = myers(&["a", "b"], &["c", "d"]);
+        assert_eq!(
+            edits,
+            [
+                Edit::Delete(0),
+                Edit::Delete(1),
+                Edit::Insert(0),
+                Edit::Insert(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_anchored() {
+        let old = ["{", "a", "}", "", "{", "b", "}", "x", "x"];
+        let new = ["{", "b", "}", "", "{", "a", "}", "x", "y"];
+        for algorithm in [Algorithm::Patience, Algorithm::Histogram] {
+            let edits = diff(algorithm, &old, &new);
+            assert_eq!(apply(&edits, &old, &new), new);
+        }
+        assert!(diff::<&str>(Algorithm::Histogram, &[], &[]).is_empty());
+
+        // どの行も多すぎるときはmyersで比べる
+        let old: Vec<&str> = (0..300).map(|i| ["a", "b"][i % 2]).collect();
+        let new: Vec<&str> = (0..300).map(|i| ["a", "b", "b"][i % 3]).collect();
+        let edits = diff(Algorithm::Histogram, &old, &new);
+        assert_eq!(apply(&edits, &old, &new), new);
+        assert_eq!(edits, myers(&old, &new));
+        assert_eq!(Algorithm::parse("minimal"), Some(Algorithm::Myers));
+    }
+
+    #[test]
+    fn test_hunks() {
+        let old: Vec<String> = (1..=20).map(|i| i.to_string()).collect();
+        let mut new = old.clone();
+        new[1] = "x".to_string();
+        new[17] = "y".to_string();
+        let edits = myers(&old, &new);
+        let result = hunks(&edits, 3);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].header(), "@@ -1,5 +1,5 @@");
+        assert_eq!(result[1].header(), "@@ -15,6 +15,6 @@");
+        assert_eq!(hunks(&edits, 8).len(), 1);
+    }
+}
@@ -0,0 +1,481 @@
+//! Combined diffs (`diff --cc`): a result compared with several parents at
+//! once, as shown for unmerged files and merge commits.
+
+use std::io::{self, Write};
+
+use super::patch::{is_binary, Options, Palette, Side, COLORS, NO_COLORS};
+use super::{diff, lines, Edit};
+
+/// A line removed from some of the parents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Lost<'a> {
+    text: &'a [u8],
+    /// Bit `n` is set when parent `n` had the line.
+    parents: u64,
+}
+
+// 結果の1行。最後の1つは末尾で消えた行をぶら下げるための番兵
+#[derive(Debug, Clone, Default)]
+struct Line<'a> {
+    text: &'a [u8],
+    /// Bit `n` is set when the line is not in parent `n`.
+    added: u64,
+    /// Lines removed just before this one.
+    lost: Vec<Lost<'a>>,
+    /// The line number in each parent where a hunk starting here starts.
+    parent_lines: Vec<usize>,
+    /// Whether the line is shown, as a change or as context.
+    shown: bool,
+    /// Whether the line is leading context, whose lost lines belong to the
+    /// part before the hunk.
+    no_pre_delete: bool,
+}
+
+/// Write the dense combined diff of the file `result` against `parents`,
+/// where a parent or the result with mode 0 does not have the file.
+///
+/// Hunks in which the result matches one of the parents are left out.
+pub fn write(
+    out: &mut impl Write,
+    parents: &[Side],
+    result: &Side,
+    options: &Options,
+) -> io::Result<()> {
+    let colors = if options.color { &COLORS } else { &NO_COLORS };
+    write_header(out, parents, result, colors)?;
+    if parents.iter().any(|p| is_binary(&p.data)) || is_binary(&result.data) {
+        return writeln!(out, "Binary files differ");
+    }
+    let added = parents.iter().all(|p| p.mode == 0);
+    let old_name = if added {
+        "/dev/null".to_string()
+    } else {
+        format!("a/{}", result.path)
+    };
+    let new_name = if result.mode == 0 {
+        "/dev/null".to_string()
+    } else {
+        format!("b/{}", result.path)
+    };
+    writeln!(out, "{}--- {old_name}{}", colors.meta, colors.reset)?;
+    writeln!(out, "{}+++ {new_name}{}", colors.meta, colors.reset)?;
+    if result.mode == 0 {
+        return Ok(());
+    }
+
+    let (mut sline, ends) = compare(parents, result, options);
+    mark_hunks(&mut sline, parents.len(), options.context);
+    write_hunks(out, &sline, &ends, colors)
+}
+
+fn write_header(
+    out: &mut impl Write,
+    parents: &[Side],
+    result: &Side,
+    colors: &Palette,
+) -> io::Result<()> {
+    let (meta, reset) = (colors.meta, colors.reset);
+    writeln!(out, "{meta}diff --cc {}{reset}", result.path)?;
+    let abbrev = |side: &Side| side.oid[..7].to_string();
+    let parent_oids: Vec<String> = parents.iter().map(abbrev).collect();
+    writeln!(
+        out,
+        "{meta}index {}..{}{reset}",
+        parent_oids.join(","),
+        abbrev(result)
+    )?;
+    if parents.iter().all(|p| p.mode == result.mode) {
+        return Ok(());
+    }
+    // どの親にもなければ追加、結果になければ削除
+    if parents.iter().all(|p| p.mode == 0) {
+        return writeln!(out, "{meta}new file mode {:06o}{reset}", result.mode);
+    }
+    let deleted = if result.mode == 0 {
+        "deleted file "
+    } else {
+        ""
+    };
+    let modes: Vec<String> = parents.iter().map(|p| format!("{:06o}", p.mode)).collect();
+    write!(out, "{meta}{deleted}mode {}", modes.join(","))?;
+    if result.mode != 0 {
+        write!(out, "..{:06o}", result.mode)?;
+    }
+    writeln!(out, "{reset}")
+}
+
+// 親ごとに結果と比べ、結果の各行に、どの親にない行か、直前でどの親から
+// 消えた行があるかを記録する。各親の最後の行の次の行番号も返す
+fn compare<'a>(
+    parents: &'a [Side],
+    result: &'a Side,
+    options: &Options,
+) -> (Vec<Line<'a>>, Vec<usize>) {
+    let result_lines = lines(&result.data);
+    let mut sline: Vec<Line> = result_lines
+        .iter()
+        .map(|text| Line {
+            text,
+            ..Line::default()
+        })
+        .collect();
+    sline.push(Line::default());
+    let count = result_lines.len();
+    let mut ends = Vec::new();
+
+    for (n, parent) in parents.iter().enumerate() {
+        let bit = 1 << n;
+        let parent_lines = lines(&parent.data);
+        let edits = diff(options.algorithm, &parent_lines, &result_lines);
+        // この親から消えた行は、変更のまとまりが始まる結果の行にぶら下げる
+        let mut lost: Vec<Vec<&[u8]>> = vec![Vec::new(); count + 1];
+        let (mut position, mut change_start) = (0, None);
+        for edit in &edits {
+            match *edit {
+                Edit::Equal(_, j) => {
+                    position = j + 1;
+                    change_start = None;
+                }
+                Edit::Insert(j) => {
+                    change_start.get_or_insert(position);
+                    sline[j].added |= bit;
+                    position = j + 1;
+                }
+                Edit::Delete(i) => {
+                    lost[*change_start.get_or_insert(position)].push(parent_lines[i]);
+                }
+            }
+        }
+        let mut parent_line = 1;
+        for (line, lost) in sline.iter_mut().zip(lost) {
+            line.parent_lines.push(parent_line);
+            coalesce(&mut line.lost, &lost, bit);
+            parent_line += line.lost.iter().filter(|l| l.parents & bit != 0).count();
+            if !line.text.is_empty() && line.added & bit == 0 {
+                parent_line += 1;
+            }
+        }
+        ends.push(parent_line);
+    }
+    (sline, ends)
+}
+
+// gitと同じく、すでにある消えた行と新しい親の消えた行の最長共通部分列を
+// とり、共通の行はまとめる
+fn coalesce<'a>(base: &mut Vec<Lost<'a>>, new: &[&'a [u8]], bit: u64) {
+    if new.is_empty() {
+        return;
+    }
+    let (rows, cols) = (base.len(), new.len());
+    let mut lcs = vec![vec![0usize; cols + 1]; rows + 1];
+    for i in 1..=rows {
+        for j in 1..=cols {
+            lcs[i][j] = if base[i - 1].text == new[j - 1] {
+                lcs[i - 1][j - 1] + 1
+            } else {
+                lcs[i][j - 1].max(lcs[i - 1][j])
+            };
+        }
+    }
+    let mut merged = Vec::with_capacity(rows + cols);
+    let (mut i, mut j) = (rows, cols);
+    while i > 0 || j > 0 {
+        if i > 0 && j > 0 && base[i - 1].text == new[j - 1] {
+            let mut line = base[i - 1].clone();
+            line.parents |= bit;
+            merged.push(line);
+            i -= 1;
+            j -= 1;
+        } else if j > 0 && (i == 0 || lcs[i][j - 1] >= lcs[i - 1][j]) {
+            merged.push(Lost {
+                text: new[j - 1],
+                parents: bit,
+            });
+            j -= 1;
+        } else {
+            merged.push(base[i - 1].clone());
+            i -= 1;
+        }
+    }
+    merged.reverse();
+    *base = merged;
+}
+
+fn is_changed(line: &Line, all: u64) -> bool {
+    line.added & all != 0 || !line.lost.is_empty()
+}
+
+// 表示する行に印を付ける。結果が親のどれかと同じになっている塊は省く
+fn mark_hunks(sline: &mut [Line], parents: usize, context: usize) {
+    let all = (1 << parents) - 1;
+    for line in sline.iter_mut() {
+        line.shown = is_changed(line, all);
+    }
+    let last = sline.len() - 1;
+    let mut i = 0;
+    while i <= last {
+        while i <= last && !sline[i].shown {
+            i += 1;
+        }
+        if i > last {
+            break;
+        }
+        let begin = i;
+        let mut j = i + 1;
+        while j <= last {
+            if !sline[j].shown {
+                // 文脈の範囲内に次の変更があれば、同じ塊として続ける
+                let tail = adjust_tail(sline, all, begin, j);
+                let mut lookahead = (tail + context).min(last + 1);
+                let mut next = None;
+                while lookahead > j {
+                    lookahead -= 1;
+                    if sline[lookahead].shown {
+                        next = Some(lookahead);
+                        break;
+                    }
+                }
+                match next {
+                    Some(next) => j = next,
+                    None => break,
+                }
+            }
+            j += 1;
+        }
+        let end = j;
+
+        // 変更がすべて同じ親の組に対するもので、それがすべての親でなければ、
+        // 結果はどれかの親と同じなので省く
+        let mut same = 0;
+        let mut interesting = false;
+        'lines: for line in &sline[begin..end] {
+            let diffs =
+                std::iter::once(line.added & all).chain(line.lost.iter().map(|lost| lost.parents));
+            for diff in diffs.filter(|&diff| diff != 0) {
+                if same == 0 {
+                    same = diff;
+                } else if same != diff {
+                    interesting = true;
+                    break 'lines;
+                }
+            }
+        }
+        if !interesting && same != all {
+            for line in &mut sline[begin..end] {
+                line.shown = false;
+            }
+        }
+        i = end;
+    }
+    give_context(sline, all, context);
+}
+
+// 塊の最後の行が、その前で消えた行のためだけに含まれているなら、それは
+// 文脈の1行として数える
+fn adjust_tail(sline: &[Line], all: u64, begin: usize, end: usize) -> usize {
+    if begin < end && sline[end - 1].added & all == 0 {
+        end - 1
+    } else {
+        end
+    }
+}
+
+fn find_next(sline: &[Line], from: usize, shown: bool) -> usize {
+    (from..sline.len())
+        .find(|&i| sline[i].shown == shown)
+        .unwrap_or(sline.len())
+}
+
+// 表示する行の前後に文脈を付け、近い塊はつなげる
+fn give_context(sline: &mut [Line], all: u64, context: usize) {
+    let end = sline.len();
+    let mut i = find_next(sline, 0, true);
+    while i < end {
+        for line in &mut sline[i.saturating_sub(context)..i] {
+            if !line.shown {
+                line.no_pre_delete = true;
+            }
+            line.shown = true;
+        }
+        loop {
+            let mut j = find_next(sline, i, false);
+            if j >= end {
+                return;
+            }
+            let next = find_next(sline, j, true);
+            j = adjust_tail(sline, all, i, j);
+            if next < j + context {
+                for line in &mut sline[j..next] {
+                    line.shown = true;
+                }
+                i = next;
+                continue;
+            }
+            for line in &mut sline[j..(j + context).min(end)] {
+                line.shown = true;
+            }
+            i = next;
+            break;
+        }
+    }
+}
+
+fn write_hunks(
+    out: &mut impl Write,
+    sline: &[Line],
+    ends: &[usize],
+    colors: &Palette,
+) -> io::Result<()> {
+    let parents = ends.len();
+    let count = sline.len() - 1;
+    let marker = "@".repeat(parents + 1);
+    let mut lno = 0;
+    loop {
+        let mut comment = None;
+        while lno <= count && !sline[lno].shown {
+            if is_function_line(sline[lno].text) {
+                comment = Some(sline[lno].text);
+            }
+            lno += 1;
+        }
+        if lno > count {
+            return Ok(());
+        }
+        let hunk_end = (lno + 1..=count)
+            .find(|&i| !sline[i].shown)
+            .unwrap_or(count + 1);
+        let mut result_lines = hunk_end - lno;
+        if hunk_end > count {
+            result_lines -= 1;
+        }
+
+        write!(out, "{}{marker}", colors.frag)?;
+        for (n, &parent_end) in ends.iter().enumerate() {
+            let start = sline[lno].parent_lines[n];
+            let end = sline
+                .get(hunk_end)
+                .map_or(parent_end, |line| line.parent_lines[n]);
+            write!(out, " -{start},{}", end - start)?;
+        }
+        write!(out, " +{},{result_lines} {marker}{}", lno + 1, colors.reset)?;
+        if let Some(comment) = comment {
+            write_comment(out, comment, colors)?;
+        }
+        writeln!(out)?;
+
+        for line in &sline[lno..hunk_end] {
+            if !line.no_pre_delete {
+                for lost in &line.lost {
+                    let signs: String = (0..parents)
+                        .map(|n| {
+                            if lost.parents & (1 << n) != 0 {
+                                '-'
+                            } else {
+                                ' '
+                            }
+                        })
+                        .collect();
+                    write!(out, "{}{signs}", colors.old)?;
+                    write_text(out, lost.text, colors)?;
+                }
+            }
+            if line.text.is_empty() {
+                break;
+            }
+            let signs: String = (0..parents)
+                .map(|n| if line.added & (1 << n) != 0 { '+' } else { ' ' })
+                .collect();
+            let color = if line.added == 0 { "" } else { colors.new };
+            write!(out, "{color}{signs}")?;
+            write_text(out, line.text, colors)?;
+        }
+        lno = hunk_end;
+    }
+}
+
+fn write_text(out: &mut impl Write, text: &[u8], colors: &Palette) -> io::Result<()> {
+    out.write_all(text.strip_suffix(b"\n").unwrap_or(text))?;
+    writeln!(out, "{}", colors.reset)
+}
+
+fn is_function_line(text: &[u8]) -> bool {
+    text.first()
+        .is_some_and(|&b| b.is_ascii_alphabetic() || b == b'_' || b == b'$')
+}
+
+// gitと同じく、先頭の40byteのうち最後の空白でない文字の手前までを書く
+fn write_comment(out: &mut impl Write, text: &[u8], colors: &Palette) -> io::Result<()> {
+    let line = text.split(|&b| b == b'\n').next().unwrap_or_default();
+    let end = line[..line.len().min(40)]
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .unwrap_or(0);
+    if end == 0 {
+        return Ok(());
+    }
+    write!(out, "{} {}", colors.reset, colors.reset)?;
+    out.write_all(&line[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn side(mode: u32, data: &str) -> Side {
+        Side::new("f", mode, "0".repeat(40), data.as_bytes().to_vec())
+    }
+
+    fn combined(parents: &[Side], result: &Side) -> String {
+        let mut out = Vec::new();
+        write(&mut out, parents, result, &Options::default()).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_conflict() {
+        let ours = side(0o100_644, "1\nX\n3\n");
+        let theirs = side(0o100_644, "1\nZ\n3\n");
+        let result = side(
+            0o100_644,
+            "1\n<<<<<<< HEAD\nX\n=======\nZ\n>>>>>>> side\n3\n",
+        );
+        let text = combined(&[ours, theirs], &result);
+        let hunk = text.split_once("+++ b/f\n").unwrap().1;
+        assert_eq!(
+            hunk,
+            "@@@ -1,3 -1,3 +1,7 @@@\n  1\n++<<<<<<< HEAD\n +X\n++=======\n+ Z\n++>>>>>>> side\n  3\n"
+        );
+    }
+
+    #[test]
+    fn test_same_as_parent() {
+        // 結果がどちらかの親と同じ塊は表示しない
+        let ours = side(0o100_644, "1\n2\n3\n");
+        let theirs = side(0o100_644, "1\nY\n3\n");
+        let result = side(0o100_644, "1\nY\n3\n");
+        let text = combined(&[ours, theirs], &result);
+        assert!(text.ends_with("+++ b/f\n"));
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let mut base = vec![Lost {
+            text: b"a\n",
+            parents: 1,
+        }];
+        coalesce(&mut base, &[b"a\n", b"b\n"], 2);
+        assert_eq!(
+            base,
+            vec![
+                Lost {
+                    text: b"a\n",
+                    parents: 3
+                },
+                Lost {
+                    text: b"b\n",
+                    parents: 2
+                }
+            ]
+        );
+    }
+}
@@ -0,0 +1,559 @@
+//! Patches: the `diff --git` output for pairs of files.
+
+use std::io::{self, Write};
+
+use super::{diff, hunks, lines, Algorithm, Edit, Hunk};
+use crate::object::tree::GITLINK_MODE;
+
+const NULL_OID: &str = "0000000";
+
+/// One side of a file pair.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Side {
+    pub path: String,
+    pub mode: u32,
+    pub oid: String,
+    /// The content; for a submodule, its `Subproject commit <oid>` line.
+    pub data: Vec<u8>,
+}
+
+impl Side {
+    pub fn new(path: impl Into<String>, mode: u32, oid: impl Into<String>, data: Vec<u8>) -> Self {
+        let oid = oid.into();
+        let data = if mode == GITLINK_MODE {
+            format!("Subproject commit {oid}\n").into_bytes()
+        } else {
+            data
+        };
+        Self {
+            path: path.into(),
+            mode,
+            oid,
+            data,
+        }
+    }
+}
+
+/// A file that differs between two sides; a side is `None` where the file
+/// does not exist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePair {
+    pub old: Option<Side>,
+    pub new: Option<Side>,
+    /// Similarity in percent when the new file was found to be a rename or
+    /// copy of the old one, which then has a different path.
+    pub similarity: Option<u32>,
+    /// Whether the old file is kept, making this a copy rather than a rename.
+    pub copy: bool,
+    /// Whether the path is unmerged in the index, which is all that is shown
+    /// of it.
+    pub unmerged: bool,
+}
+
+impl FilePair {
+    pub fn new(old: Option<Side>, new: Option<Side>) -> Self {
+        Self {
+            old,
+            new,
+            similarity: None,
+            copy: false,
+            unmerged: false,
+        }
+    }
+
+    /// The pair for a path that is unmerged in the index.
+    pub fn unmerged(path: &str) -> Self {
+        let side = Side::new(path, 0, "0".repeat(40), Vec::new());
+        Self {
+            unmerged: true,
+            ..Self::new(Some(side.clone()), Some(side))
+        }
+    }
+
+    /// The path of the file, on the new side when it exists.
+    pub fn path(&self) -> &str {
+        self.new
+            .as_ref()
+            .or(self.old.as_ref())
+            .map_or("", |side| side.path.as_str())
+    }
+
+    /// The path on the old side, which differs for renames and copies.
+    pub fn old_path(&self) -> &str {
+        self.old
+            .as_ref()
+            .map_or(self.path(), |side| side.path.as_str())
+    }
+
+    /// The name shown in `--stat` and `--numstat` output; renames are shown
+    /// as `dir/{old => new}`.
+    pub fn display_name(&self) -> String {
+        if self.similarity.is_some() {
+            rename_name(self.old_path(), self.path())
+        } else {
+            self.path().to_string()
+        }
+    }
+
+    /// The `--name-status` letter: `A`, `D`, `M` or `T` (type change), or
+    /// `R`/`C` followed by the similarity, like `R090`.
+    pub fn status(&self) -> String {
+        if self.unmerged {
+            return "U".to_string();
+        }
+        if let Some(similarity) = self.similarity {
+            let letter = if self.copy { 'C' } else { 'R' };
+            return format!("{letter}{similarity:03}");
+        }
+        match (&self.old, &self.new) {
+            (None, _) => "A",
+            (_, None) => "D",
+            (Some(old), Some(new)) if old.mode & 0o17_0000 != new.mode & 0o17_0000 => "T",
+            _ => "M",
+        }
+        .to_string()
+    }
+
+    /// Lines added and removed, or `None` for binary files.
+    pub fn line_counts(&self, algorithm: Algorithm) -> Option<(usize, usize)> {
+        let (old, new) = (self.old_data(), self.new_data());
+        if is_binary(old) || is_binary(new) {
+            return None;
+        }
+        let edits = diff(algorithm, &lines(old), &lines(new));
+        let added = edits
+            .iter()
+            .filter(|e| matches!(e, Edit::Insert(_)))
+            .count();
+        let removed = edits
+            .iter()
+            .filter(|e| matches!(e, Edit::Delete(_)))
+            .count();
+        Some((added, removed))
+    }
+
+    pub(crate) fn old_data(&self) -> &[u8] {
+        self.old.as_ref().map_or(&[], |side| &side.data)
+    }
+
+    pub(crate) fn new_data(&self) -> &[u8] {
+        self.new.as_ref().map_or(&[], |side| &side.data)
+    }
+}
+
+/// `--word-diff` modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WordDiff {
+    /// `[-removed-]{+added+}`
+    Plain,
+    /// Removed and added words in color, without markers.
+    Color,
+}
+
+/// How patches are written.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Number of unchanged lines around each change.
+    pub context: usize,
+    pub algorithm: Algorithm,
+    pub word_diff: Option<WordDiff>,
+    pub color: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            context: 3,
+            algorithm: Algorithm::default(),
+            word_diff: None,
+            color: false,
+        }
+    }
+}
+
+// gitの既定の色
+pub(super) struct Palette {
+    pub(super) meta: &'static str,
+    pub(super) frag: &'static str,
+    pub(super) old: &'static str,
+    pub(super) new: &'static str,
+    pub(super) whitespace: &'static str,
+    pub(super) reset: &'static str,
+}
+
+pub(super) const COLORS: Palette = Palette {
+    meta: "\x1b[1m",
+    frag: "\x1b[36m",
+    old: "\x1b[31m",
+    new: "\x1b[32m",
+    whitespace: "\x1b[41m",
+    reset: "\x1b[m",
+};
+
+pub(super) const NO_COLORS: Palette = Palette {
+    meta: "",
+    frag: "",
+    old: "",
+    new: "",
+    whitespace: "",
+    reset: "",
+};
+
+// gitのpprint_rename: 共通のディレクトリ部分を括弧の外に出す
+fn rename_name(old: &str, new: &str) -> String {
+    let (old_bytes, new_bytes) = (old.as_bytes(), new.as_bytes());
+    let prefix = old_bytes
+        .iter()
+        .zip(new_bytes)
+        .take_while(|(old_byte, new_byte)| old_byte == new_byte)
+        .enumerate()
+        .filter(|(_, (&old_byte, _))| old_byte == b'/')
+        .last()
+        .map_or(0, |(i, _)| i + 1);
+    // 接頭辞の"/"は接尾辞とも共有できる
+    let floor = prefix.saturating_sub(1);
+    let mut suffix = 0;
+    let (mut i, mut j) = (old_bytes.len(), new_bytes.len());
+    loop {
+        let old_byte = old_bytes.get(i).copied().unwrap_or(0);
+        let new_byte = new_bytes.get(j).copied().unwrap_or(0);
+        if old_byte != new_byte {
+            break;
+        }
+        if old_byte == b'/' {
+            suffix = old_bytes.len() - i;
+        }
+        if i <= floor || j <= floor {
+            break;
+        }
+        i -= 1;
+        j -= 1;
+    }
+    let old_middle =
+        &old[prefix.min(old_bytes.len())..old_bytes.len().saturating_sub(suffix).max(prefix)];
+    let new_middle =
+        &new[prefix.min(new_bytes.len())..new_bytes.len().saturating_sub(suffix).max(prefix)];
+    if prefix + suffix == 0 {
+        format!("{old_middle} => {new_middle}")
+    } else {
+        format!(
+            "{}{{{old_middle} => {new_middle}}}{}",
+            &old[..prefix],
+            &old[old_bytes.len() - suffix..]
+        )
+    }
+}
+
+/// Whether `data` looks binary: git's check for a NUL byte near the start.
+pub fn is_binary(data: &[u8]) -> bool {
+    data.iter().take(8000).any(|&b| b == 0)
+}
+
+/// Write the patch of `pair`, with its `diff --git` header.
+pub fn write(out: &mut impl Write, pair: &FilePair, options: &Options) -> io::Result<()> {
+    if pair.unmerged {
+        return writeln!(out, "* Unmerged path {}", pair.path());
+    }
+    // 種類が変わった場合は削除と追加の2つに分ける
+    if pair.status() == "T" {
+        let deleted = FilePair::new(pair.old.clone(), None);
+        let added = FilePair::new(None, pair.new.clone());
+        write(out, &deleted, options)?;
+        return write(out, &added, options);
+    }
+
+    // --word-diff=colorは色付けを伴う
+    let color = options.color || options.word_diff == Some(WordDiff::Color);
+    let colors = if color { &COLORS } else { &NO_COLORS };
+    let meta = |out: &mut dyn Write, line: &str| -> io::Result<()> {
+        writeln!(out, "{}{line}{}", colors.meta, colors.reset)
+    };
+    let old_path = pair.old_path();
+    let new_path = pair.path();
+    meta(out, &format!("diff --git a/{old_path} b/{new_path}"))?;
+
+    let abbrev =
+        |side: Option<&Side>| side.map_or(NULL_OID.to_string(), |s| s.oid[..7].to_string());
+    let (old, new) = (pair.old.as_ref(), pair.new.as_ref());
+    let content_changed = old.map(|s| &s.oid) != new.map(|s| &s.oid);
+    match (old, new) {
+        (None, Some(new)) => meta(out, &format!("new file mode {:o}", new.mode))?,
+        (Some(old), None) => meta(out, &format!("deleted file mode {:o}", old.mode))?,
+        (Some(old), Some(new)) if old.mode != new.mode => {
+            meta(out, &format!("old mode {:o}", old.mode))?;
+            meta(out, &format!("new mode {:o}", new.mode))?;
+        }
+        _ => {}
+    }
+    if let Some(similarity) = pair.similarity {
+        let kind = if pair.copy { "copy" } else { "rename" };
+        meta(out, &format!("similarity index {similarity}%"))?;
+        meta(out, &format!("{kind} from {old_path}"))?;
+        meta(out, &format!("{kind} to {new_path}"))?;
+    }
+    if content_changed {
+        let mode = match (old, new) {
+            (Some(old), Some(new)) if old.mode == new.mode => format!(" {:o}", old.mode),
+            _ => String::new(),
+        };
+        meta(
+            out,
+            &format!("index {}..{}{mode}", abbrev(old), abbrev(new)),
+        )?;
+    }
+    if !content_changed || (pair.old_data().is_empty() && pair.new_data().is_empty()) {
+        return Ok(());
+    }
+
+    let old_name = old.map_or("/dev/null".to_string(), |_| format!("a/{old_path}"));
+    let new_name = new.map_or("/dev/null".to_string(), |_| format!("b/{new_path}"));
+    if is_binary(pair.old_data()) || is_binary(pair.new_data()) {
+        return writeln!(out, "Binary files {old_name} and {new_name} differ");
+    }
+    meta(out, &format!("--- {old_name}"))?;
+    meta(out, &format!("+++ {new_name}"))?;
+
+    let old_lines = lines(pair.old_data());
+    let new_lines = lines(pair.new_data());
+    let edits = diff(options.algorithm, &old_lines, &new_lines);
+    for hunk in hunks(&edits, options.context) {
+        write!(out, "{}{}{}", colors.frag, hunk.header(), colors.reset)?;
+        if let Some(name) = hunk.function_name(&old_lines) {
+            write!(out, " {}", colors.reset)?;
+            out.write_all(name)?;
+            write!(out, "{}", colors.reset)?;
+        }
+        writeln!(out)?;
+        match options.word_diff {
+            Some(mode) => {
+                let lines = (old_lines.as_slice(), new_lines.as_slice());
+                write_word_hunk(out, &hunk, &edits, lines, mode, colors)?;
+            }
+            None => write_hunk_lines(out, &hunk, &edits, &old_lines, &new_lines, colors)?,
+        }
+    }
+    Ok(())
+}
+
+fn write_hunk_lines(
+    out: &mut impl Write,
+    hunk: &Hunk,
+    edits: &[Edit],
+    old: &[&[u8]],
+    new: &[&[u8]],
+    colors: &Palette,
+) -> io::Result<()> {
+    for edit in &edits[hunk.edits.clone()] {
+        let line = match *edit {
+            Edit::Equal(i, _) => {
+                let line = old[i];
+                out.write_all(b" ")?;
+                out.write_all(line.strip_suffix(b"\n").unwrap_or(line))?;
+                write!(out, "{}", colors.reset)?;
+                line
+            }
+            Edit::Delete(i) => {
+                let line = old[i];
+                write!(out, "{}-", colors.old)?;
+                out.write_all(line.strip_suffix(b"\n").unwrap_or(line))?;
+                write!(out, "{}", colors.reset)?;
+                line
+            }
+            Edit::Insert(i) => {
+                let line = new[i];
+                let text = line.strip_suffix(b"\n").unwrap_or(line);
+                // 追加された行の末尾の空白は強調する
+                let trimmed = trim_end_whitespace(text);
+                write!(out, "{}+{}", colors.new, colors.reset)?;
+                if !trimmed.is_empty() {
+                    write!(out, "{}", colors.new)?;
+                    out.write_all(trimmed)?;
+                    write!(out, "{}", colors.reset)?;
+                }
+                if trimmed.len() < text.len() {
+                    write!(out, "{}", colors.whitespace)?;
+                    out.write_all(&text[trimmed.len()..])?;
+                    write!(out, "{}", colors.reset)?;
+                }
+                line
+            }
+        };
+        writeln!(out)?;
+        if !line.ends_with(b"\n") {
+            writeln!(out, "\\ No newline at end of file{}", colors.reset)?;
+        }
+    }
+    Ok(())
+}
+
+fn trim_end_whitespace(text: &[u8]) -> &[u8] {
+    let end = text
+        .iter()
+        .rposition(|b| !matches!(b, b' ' | b'\t' | b'\r'))
+        .map_or(0, |i| i + 1);
+    &text[..end]
+}
+
+// 変更された行をまとめて単語単位で比べ、変更のない行はそのまま書く
+fn write_word_hunk(
+    out: &mut impl Write,
+    hunk: &Hunk,
+    edits: &[Edit],
+    (old, new): (&[&[u8]], &[&[u8]]),
+    mode: WordDiff,
+    colors: &Palette,
+) -> io::Result<()> {
+    let mut minus = Vec::new();
+    let mut plus = Vec::new();
+    for edit in &edits[hunk.edits.clone()] {
+        match *edit {
+            Edit::Delete(i) => minus.extend_from_slice(old[i]),
+            Edit::Insert(i) => plus.extend_from_slice(new[i]),
+            Edit::Equal(i, _) => {
+                write_words(out, &minus, &plus, mode, colors)?;
+                minus.clear();
+                plus.clear();
+                out.write_all(old[i].strip_suffix(b"\n").unwrap_or(old[i]))?;
+                writeln!(out, "{}", colors.reset)?;
+            }
+        }
+    }
+    write_words(out, &minus, &plus, mode, colors)
+}
+
+/// Write `plus` with the words changed from `minus` marked.
+fn write_words(
+    out: &mut impl Write,
+    minus: &[u8],
+    plus: &[u8],
+    mode: WordDiff,
+    colors: &Palette,
+) -> io::Result<()> {
+    if minus.is_empty() && plus.is_empty() {
+        return Ok(());
+    }
+    let old_words = words(minus);
+    let new_words = words(plus);
+    let old_text: Vec<&[u8]> = old_words.iter().map(|r| &minus[r.clone()]).collect();
+    let new_text: Vec<&[u8]> = new_words.iter().map(|r| &plus[r.clone()]).collect();
+    let edits = super::myers(&old_text, &new_text);
+
+    let mut written = Vec::new();
+    let mut current = 0;
+    let mut i = 0;
+    // 直前の一致した単語の終わり(newの中の位置)
+    let mut previous_end = 0;
+    while i < edits.len() {
+        if let Edit::Equal(_, j) = edits[i] {
+            previous_end = new_words[j].end;
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < edits.len() && !matches!(edits[i], Edit::Equal(..)) {
+            i += 1;
+        }
+        let group = &edits[start..i];
+        let deleted: Vec<usize> = group
+            .iter()
+            .filter_map(|e| {
+                if let Edit::Delete(k) = e {
+                    Some(*k)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        let inserted: Vec<usize> = group
+            .iter()
+            .filter_map(|e| {
+                if let Edit::Insert(k) = e {
+                    Some(*k)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        let (plus_begin, plus_end) = match (inserted.first(), inserted.last()) {
+            (Some(&first), Some(&last)) => (new_words[first].start, new_words[last].end),
+            _ => (previous_end, previous_end),
+        };
+        written.extend_from_slice(&plus[current..plus_begin]);
+        if let (Some(&first), Some(&last)) = (deleted.first(), deleted.last()) {
+            let text = &minus[old_words[first].start..old_words[last].end];
+            mark(&mut written, text, mode, true, colors);
+        }
+        if !inserted.is_empty() {
+            mark(
+                &mut written,
+                &plus[plus_begin..plus_end],
+                mode,
+                false,
+                colors,
+            );
+        }
+        current = plus_end;
+        previous_end = plus_end;
+    }
+    written.extend_from_slice(&plus[current..]);
+    if !written.ends_with(b"\n") {
+        written.push(b'\n');
+    }
+    out.write_all(&written)
+}
+
+// 空白以外の連続を単語とする
+fn words(text: &[u8]) -> Vec<std::ops::Range<usize>> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, b) in text.iter().enumerate() {
+        match (b.is_ascii_whitespace(), start) {
+            (false, None) => start = Some(i),
+            (true, Some(s)) => {
+                words.push(s..i);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        words.push(s..text.len());
+    }
+    words
+}
+
+// 改行をまたぐ場合は行ごとに印を付ける
+// 色付けするときは印の外側を色で囲む
+fn mark(out: &mut Vec<u8>, text: &[u8], mode: WordDiff, removed: bool, colors: &Palette) {
+    let (color, (open, close)) = match (mode, removed) {
+        (WordDiff::Plain, true) => (colors.old, ("[-", "-]")),
+        (WordDiff::Plain, false) => (colors.new, ("{+", "+}")),
+        (WordDiff::Color, true) => (colors.old, ("", "")),
+        (WordDiff::Color, false) => (colors.new, ("", "")),
+    };
+    let open = format!("{color}{open}");
+    let close = format!("{close}{}", colors.reset);
+    for (i, segment) in text.split(|&b| b == b'\n').enumerate() {
+        if i > 0 {
+            out.push(b'\n');
+        }
+        if !segment.is_empty() {
+            out.extend_from_slice(open.as_bytes());
+            out.extend_from_slice(segment);
+            out.extend_from_slice(close.as_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rename_name() {
+        assert_eq!(rename_name("d/x", "d/y"), "d/{x => y}");
+        assert_eq!(rename_name("a", "e/b"), "a => e/b");
+        assert_eq!(rename_name("src/a/x.rs", "src/b/x.rs"), "src/{a => b}/x.rs");
+        assert_eq!(rename_name("a/x", "x"), "a/x => x");
+    }
+}
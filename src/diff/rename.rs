@@ -0,0 +1,353 @@
+//! Rename and copy detection between deleted and added files.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use super::patch::{FilePair, Side};
+use crate::object::tree::{BLOB_MODE, EXECUTABLE_MODE, SYMLINK_MODE};
+
+/// How renames are looked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    /// Also look for copies of files that were modified or deleted.
+    pub copies: bool,
+    /// Minimum similarity in percent.
+    pub threshold: u32,
+    /// Most files compared by content on each side, like
+    /// `diff.renameLimit`; 0 means git's upper bound of 32767.
+    pub limit: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            copies: false,
+            threshold: 50,
+            limit: 1000,
+        }
+    }
+}
+
+/// Parse the `<n>` of `-M<n>`: a percentage like `90%`, or digits read as
+/// a decimal fraction like git, so that `9` and `90` both mean 90%.
+pub fn parse_score(value: &str) -> Option<u32> {
+    if let Some(percent) = value.strip_suffix('%') {
+        return percent.parse().ok().filter(|&p| p <= 100);
+    }
+    let digits = value.strip_prefix("0.").unwrap_or(value);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    // 小数点以下の桁として読む
+    let scaled = format!("{digits:0<2}");
+    scaled[..2].parse().ok()
+}
+
+/// How much of `new` is made of content of `old`, in percent.
+///
+/// Both sides are split into lines (at most 64 bytes each, like git) and
+/// the bytes of lines they share are counted against the larger side.
+pub fn similarity(old: &[u8], new: &[u8]) -> u32 {
+    score(&chunks(old), &chunks(new), old.len().max(new.len()))
+}
+
+// `max`は大きい方のバイト数
+fn score(old: &HashMap<u64, usize>, new: &HashMap<u64, usize>, max: usize) -> u32 {
+    if max == 0 {
+        return 100;
+    }
+    let copied: usize = new
+        .iter()
+        .map(|(hash, &size)| old.get(hash).map_or(0, |&s| s.min(size)))
+        .sum();
+    u32::try_from(copied * 100 / max).unwrap_or(100)
+}
+
+// 行ごとのハッシュ -> その行のバイト数の合計
+fn chunks(data: &[u8]) -> HashMap<u64, usize> {
+    let mut chunks = HashMap::new();
+    let mut start = 0;
+    for (i, &b) in data.iter().enumerate() {
+        if b == b'\n' || i + 1 - start == 64 || i + 1 == data.len() {
+            let mut hasher = DefaultHasher::new();
+            data[start..=i].hash(&mut hasher);
+            *chunks.entry(hasher.finish()).or_insert(0) += i + 1 - start;
+            start = i + 1;
+        }
+    }
+    chunks
+}
+
+/// Pair added files in `pairs` with deleted ones (and, with
+/// [`Options::copies`], modified ones) they are similar enough to.
+///
+/// A paired deletion disappears and the addition becomes a rename or copy
+/// from it. When copies are looked for, a deleted file may be the source of
+/// several files; the last of them in path order is the rename.
+pub fn detect(pairs: Vec<FilePair>, options: Options) -> Vec<FilePair> {
+    detect_limited(pairs, options).0
+}
+
+/// Like [`detect`], and also return the [`Options::limit`] that would have
+/// been needed when files were too many to compare their contents.
+///
+/// Files with the same content are paired either way.
+pub fn detect_limited(pairs: Vec<FilePair>, options: Options) -> (Vec<FilePair>, Option<usize>) {
+    let mut matching = Matching::new(&pairs, options);
+    if matching.sources.is_empty() || matching.destinations.is_empty() {
+        return (pairs, None);
+    }
+    let candidates = matching.exact_candidates();
+    matching.assign(candidates);
+    let needed = match matching.similar_candidates(options) {
+        Ok(candidates) => {
+            matching.assign(candidates);
+            None
+        }
+        Err(needed) => Some(needed),
+    };
+
+    // 組になったもの: 追加された位置 -> (元の位置, 似ている度合い)
+    let mut removed = vec![false; pairs.len()];
+    let mut paired: HashMap<usize, (usize, u32)> = HashMap::new();
+    for (d, matched) in matching.matched.iter().enumerate() {
+        if let Some((s, score)) = *matched {
+            let source = matching.sources[s].0;
+            paired.insert(matching.destinations[d].0, (source, score));
+            removed[source] |= matching.deleted[s];
+        }
+    }
+    let mut uses: HashMap<usize, usize> = matching
+        .sources
+        .iter()
+        .zip(&matching.uses)
+        .map(|(&(source, _), &uses)| (source, uses))
+        .collect();
+    let originals: HashMap<usize, FilePair> = paired
+        .values()
+        .map(|&(source, _)| (source, pairs[source].clone()))
+        .collect();
+    let mut result = Vec::new();
+    for (i, mut pair) in pairs.into_iter().enumerate() {
+        if let Some(&(source, score)) = paired.get(&i) {
+            let uses = uses.entry(source).or_default();
+            *uses -= 1;
+            let original = &originals[&source];
+            pair.old = original.old.clone();
+            pair.similarity = Some(score);
+            pair.copy = original.new.is_some() || *uses > 0;
+        } else if removed[i] {
+            continue;
+        }
+        result.push(pair);
+    }
+    (result, needed)
+}
+
+// 組にする途中の状態。候補は(似ている度合い, 追加の番号, 元の番号)で表す
+struct Matching<'a> {
+    // pairsでの位置と古い版
+    sources: Vec<(usize, &'a Side)>,
+    // pairsでの位置と新しい版
+    destinations: Vec<(usize, &'a Side)>,
+    deleted: Vec<bool>,
+    matched: Vec<Option<(usize, u32)>>,
+    uses: Vec<usize>,
+    copies: bool,
+}
+
+impl<'a> Matching<'a> {
+    fn new(pairs: &'a [FilePair], options: Options) -> Self {
+        let mut sources = Vec::new();
+        let mut deleted = Vec::new();
+        let mut destinations = Vec::new();
+        for (i, pair) in pairs.iter().enumerate() {
+            match (&pair.old, &pair.new) {
+                (Some(old), None) if renamable(old.mode) => {
+                    sources.push((i, old));
+                    deleted.push(true);
+                }
+                (Some(old), Some(new))
+                    if options.copies && renamable(old.mode) && old.mode == new.mode =>
+                {
+                    sources.push((i, old));
+                    deleted.push(false);
+                }
+                (None, Some(new)) if renamable(new.mode) => destinations.push((i, new)),
+                _ => {}
+            }
+        }
+        Self {
+            matched: vec![None; destinations.len()],
+            uses: vec![0; sources.len()],
+            sources,
+            destinations,
+            deleted,
+            copies: options.copies,
+        }
+    }
+
+    // 内容が同じもの。名前も同じものを優先する
+    fn exact_candidates(&self) -> Vec<(u32, usize, usize)> {
+        let mut by_oid: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (s, (_, old)) in self.sources.iter().enumerate() {
+            by_oid.entry(old.oid.as_str()).or_default().push(s);
+        }
+        let mut candidates = Vec::new();
+        for (d, (_, new)) in self.destinations.iter().enumerate() {
+            for &s in by_oid.get(new.oid.as_str()).into_iter().flatten() {
+                let old = self.sources[s].1;
+                if (old.mode == SYMLINK_MODE) == (new.mode == SYMLINK_MODE) {
+                    let score = 200 + u32::from(basename(&old.path) == basename(&new.path));
+                    candidates.push((score, d, s));
+                }
+            }
+        }
+        candidates
+    }
+
+    // まだ組になっていないものの中身を比べる。symlinkは内容が同じものとしか
+    // 組にしない。多すぎて比べなかったときは、必要だったlimitを返す
+    fn similar_candidates(&self, options: Options) -> Result<Vec<(u32, usize, usize)>, usize> {
+        let rest: Vec<usize> = (0..self.destinations.len())
+            .filter(|&d| self.matched[d].is_none() && self.destinations[d].1.mode != SYMLINK_MODE)
+            .collect();
+        let available: Vec<usize> = (0..self.sources.len())
+            .filter(|&s| self.sources[s].1.mode != SYMLINK_MODE)
+            .filter(|&s| self.copies || !self.deleted[s] || self.uses[s] == 0)
+            .collect();
+        if let Some(needed) = too_many(available.len(), rest.len(), options.limit) {
+            return Err(needed);
+        }
+        // 行の切り分けはファイルごとに一度だけ
+        let source_chunks: Vec<_> = available
+            .iter()
+            .map(|&s| chunks(&self.sources[s].1.data))
+            .collect();
+        let mut candidates = Vec::new();
+        for &d in &rest {
+            let new = self.destinations[d].1;
+            let mut new_chunks = None;
+            for (&s, old_chunks) in available.iter().zip(&source_chunks) {
+                let old = self.sources[s].1;
+                if too_different(&old.data, &new.data, options.threshold) {
+                    continue;
+                }
+                let new_chunks = new_chunks.get_or_insert_with(|| chunks(&new.data));
+                let score = score(old_chunks, new_chunks, old.data.len().max(new.data.len()));
+                if score >= options.threshold {
+                    candidates.push((score, d, s));
+                }
+            }
+        }
+        Ok(candidates)
+    }
+
+    // 候補を似ている順に並べ、似ているものから割り当てる
+    fn assign(&mut self, mut candidates: Vec<(u32, usize, usize)>) {
+        candidates.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+        for (score, d, s) in candidates {
+            if self.matched[d].is_some() || (self.deleted[s] && self.uses[s] > 0 && !self.copies) {
+                continue;
+            }
+            self.matched[d] = Some((s, score.min(100)));
+            self.uses[s] += 1;
+        }
+    }
+}
+
+// gitと同じく、組み合わせの数がlimitの2乗を超えたら比べない。
+// 比べるのに必要だったlimitを返す
+fn too_many(sources: usize, destinations: usize, limit: usize) -> Option<usize> {
+    let limit = if limit == 0 { 32767 } else { limit };
+    let fits = (sources <= limit || destinations <= limit)
+        && sources.saturating_mul(destinations) <= limit.saturating_mul(limit);
+    (!fits).then(|| sources.max(destinations))
+}
+
+fn renamable(mode: u32) -> bool {
+    matches!(mode, BLOB_MODE | EXECUTABLE_MODE | SYMLINK_MODE)
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+// 大きさが違いすぎるものは比べるまでもない
+fn too_different(old: &[u8], new: &[u8], threshold: u32) -> bool {
+    let max = old.len().max(new.len());
+    let delta = max - old.len().min(new.len());
+    max * (100 - threshold.min(100) as usize) < delta * 100
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_score() {
+        assert_eq!(parse_score("90%"), Some(90));
+        assert_eq!(parse_score("9"), Some(90));
+        assert_eq!(parse_score("75"), Some(75));
+        assert_eq!(parse_score("0.5"), Some(50));
+        assert_eq!(parse_score("x"), None);
+    }
+
+    #[test]
+    fn test_similarity() {
+        let old = b"1\n2\n3\n4\n";
+        assert_eq!(similarity(old, old), 100);
+        assert_eq!(similarity(old, b"1\n2\n3\n5\n"), 75);
+        assert_eq!(similarity(old, b"a\nb\n"), 0);
+    }
+
+    fn pair(before: Option<(&str, &str)>, after: Option<(&str, &str)>) -> FilePair {
+        let side = |(path, data): (&str, &str)| {
+            let mut hasher = DefaultHasher::new();
+            data.hash(&mut hasher);
+            let oid = format!("{:040x}", hasher.finish());
+            Side::new(path, BLOB_MODE, oid, data.as_bytes().to_vec())
+        };
+        FilePair::new(before.map(side), after.map(side))
+    }
+
+    #[test]
+    fn test_detect_limited() {
+        let pairs = vec![
+            pair(Some(("a", "1\n2\n3\n4\n")), None),
+            pair(Some(("b", "5\n6\n7\n8\n")), None),
+            pair(Some(("c", "same\n")), None),
+            pair(None, Some(("x", "1\n2\n3\n0\n"))),
+            pair(None, Some(("y", "5\n6\n7\n0\n"))),
+            pair(None, Some(("z", "same\n"))),
+        ];
+        let renames = |pairs: &[FilePair]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .filter_map(|pair| {
+                    Some((
+                        pair.old.as_ref()?.path.clone(),
+                        pair.new.as_ref()?.path.clone(),
+                    ))
+                })
+                .collect()
+        };
+        let (detected, needed) = detect_limited(pairs.clone(), Options::default());
+        assert_eq!(needed, None);
+        assert_eq!(
+            renames(&detected),
+            [("a", "x"), ("b", "y"), ("c", "z")].map(|(a, b)| (a.to_string(), b.to_string()))
+        );
+
+        // 数が多すぎても、内容が同じものは組にする
+        let options = Options {
+            limit: 1,
+            ..Options::default()
+        };
+        let (detected, needed) = detect_limited(pairs, options);
+        assert_eq!(needed, Some(2));
+        assert_eq!(renames(&detected), [("c".to_string(), "z".to_string())]);
+        assert_eq!(detected.len(), 5);
+    }
+}
@@ -0,0 +1,203 @@
+//! `--stat` and `--numstat` summaries of file pairs.
+
+use std::io::{self, Write};
+
+use super::patch::FilePair;
+use super::Algorithm;
+
+// gitが端末でないときに使う幅
+const DEFAULT_WIDTH: usize = 80;
+
+struct Line {
+    name: String,
+    /// Lines added and removed, or the old and new sizes of binary files.
+    counts: Result<(usize, usize), (usize, usize)>,
+    unmerged: bool,
+}
+
+/// Write the `--stat` diffstat of `pairs`, with `+`/`-` graphs scaled to
+/// fit 80 columns.
+pub fn write(
+    out: &mut impl Write,
+    pairs: &[FilePair],
+    algorithm: Algorithm,
+    color: bool,
+) -> io::Result<()> {
+    let lines: Vec<Line> = pairs
+        .iter()
+        .map(|pair| Line {
+            name: pair.display_name(),
+            counts: pair
+                .line_counts(algorithm)
+                .ok_or_else(|| (pair.old_data().len(), pair.new_data().len())),
+            unmerged: pair.unmerged,
+        })
+        .collect();
+    if lines.is_empty() {
+        return Ok(());
+    }
+
+    let max_change = lines
+        .iter()
+        .filter_map(|l| l.counts.ok().map(|(a, d)| a + d))
+        .max()
+        .unwrap_or(0);
+    let mut number_width = max_change.to_string().len();
+    if lines.iter().any(|l| l.counts.is_err()) {
+        number_width = number_width.max(3);
+    }
+    let max_name = lines
+        .iter()
+        .map(|l| l.name.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    // gitと同じ配分: 収まらなければグラフを3/8まで、残りを名前に
+    let width = DEFAULT_WIDTH;
+    let mut graph_width = max_change;
+    let mut name_width = max_name;
+    if name_width + number_width + 6 + graph_width > width {
+        let graph_limit = (width * 3 / 8).saturating_sub(number_width + 6).max(6);
+        if graph_width > graph_limit {
+            graph_width = graph_limit;
+        }
+        if name_width > width.saturating_sub(number_width + 6 + graph_width) {
+            name_width = width.saturating_sub(number_width + 6 + graph_width);
+        } else {
+            graph_width = width.saturating_sub(number_width + 6 + name_width);
+        }
+    }
+
+    let (green, red, reset) = if color {
+        ("\x1b[32m", "\x1b[31m", "\x1b[m")
+    } else {
+        ("", "", "")
+    };
+    let (mut insertions, mut deletions) = (0, 0);
+    let mut files = 0;
+    for line in &lines {
+        let name = fit_name(&line.name, name_width);
+        // 衝突しているパスは数えない
+        if line.unmerged {
+            writeln!(out, " {name:<name_width$} | Unmerged")?;
+            continue;
+        }
+        files += 1;
+        match line.counts {
+            Ok((added, removed)) => {
+                insertions += added;
+                deletions += removed;
+                let total = added + removed;
+                let (added, removed) = if max_change > graph_width {
+                    (
+                        scale(added, graph_width, max_change),
+                        scale(removed, graph_width, max_change),
+                    )
+                } else {
+                    (added, removed)
+                };
+                // 空の部分には色を付けない
+                let graph = |sign: &str, count: usize, color: &str| {
+                    if count == 0 {
+                        String::new()
+                    } else {
+                        format!("{color}{}{reset}", sign.repeat(count))
+                    }
+                };
+                writeln!(
+                    out,
+                    " {name:<name_width$} | {total:>number_width$}{}{}{}",
+                    if total > 0 { " " } else { "" },
+                    graph("+", added, green),
+                    graph("-", removed, red),
+                )?;
+            }
+            Err((old_size, new_size)) => writeln!(
+                out,
+                " {name:<name_width$} | {:>number_width$} {red}{old_size}{reset} -> {green}{new_size}{reset} bytes",
+                "Bin"
+            )?,
+        }
+    }
+    write_summary(out, files, insertions, deletions)
+}
+
+/// Write the `N files changed, X insertions(+), Y deletions(-)` line.
+pub fn write_summary(
+    out: &mut impl Write,
+    files: usize,
+    insertions: usize,
+    deletions: usize,
+) -> io::Result<()> {
+    let plural = |n: usize| if n == 1 { "" } else { "s" };
+    write!(out, " {files} file{} changed", plural(files))?;
+    if insertions > 0 || deletions == 0 {
+        write!(out, ", {insertions} insertion{}(+)", plural(insertions))?;
+    }
+    if deletions > 0 || insertions == 0 {
+        write!(out, ", {deletions} deletion{}(-)", plural(deletions))?;
+    }
+    writeln!(out)
+}
+
+/// Write `--numstat` lines: added, removed and the path, tab separated.
+pub fn write_numstat(
+    out: &mut impl Write,
+    pairs: &[FilePair],
+    algorithm: Algorithm,
+) -> io::Result<()> {
+    for pair in pairs {
+        match pair.line_counts(algorithm) {
+            Some((added, removed)) => {
+                writeln!(out, "{added}\t{removed}\t{}", pair.display_name())?;
+            }
+            None => writeln!(out, "-\t-\t{}", pair.display_name())?,
+        }
+    }
+    Ok(())
+}
+
+// gitのscale_linear: 0でなければ少なくとも1文字
+fn scale(value: usize, width: usize, max: usize) -> usize {
+    if value == 0 {
+        0
+    } else {
+        1 + value * (width - 1) / max
+    }
+}
+
+// 長すぎる名前は先頭を"..."で省略する
+fn fit_name(name: &str, width: usize) -> String {
+    let length = name.chars().count();
+    if length <= width {
+        return name.to_string();
+    }
+    let keep = width.saturating_sub(3);
+    let tail: String = name.chars().skip(length - keep).collect();
+    // ディレクトリの区切りから始まるように切る
+    let start = tail.find('/').unwrap_or(0);
+    format!("...{}", &tail[start..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let summary = |files, insertions, deletions| {
+            let mut out = Vec::new();
+            write_summary(&mut out, files, insertions, deletions).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(summary(1, 1, 0), " 1 file changed, 1 insertion(+)\n");
+        assert_eq!(summary(2, 0, 3), " 2 files changed, 3 deletions(-)\n");
+        assert_eq!(
+            summary(1, 0, 0),
+            " 1 file changed, 0 insertions(+), 0 deletions(-)\n"
+        );
+        assert_eq!(scale(1, 10, 100), 1);
+        assert_eq!(scale(100, 10, 100), 10);
+        assert_eq!(fit_name("src/very/long/name.rs", 12), ".../name.rs");
+    }
+}
@@ -0,0 +1,96 @@
+//! Structural comparison of two tree objects.
+
+use std::collections::BTreeMap;
+
+use crate::error::Result;
+use crate::object::tree::{Node, NodeType};
+use crate::Repository;
+
+/// A file whose entry differs between two trees.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// Slash-separated path from the top of the tree.
+    pub path: String,
+    /// `(mode, oid)` on each side; `None` where the file does not exist.
+    pub old: Option<(u32, String)>,
+    pub new: Option<(u32, String)>,
+}
+
+/// Compare the trees `old` and `new` (`None` for an empty tree; commits
+/// stand for their root trees), reading only subtrees whose hashes differ.
+///
+/// The changes are in path order. A file replaced by a directory shows up as
+/// the file deleted and the files of the directory added.
+pub fn changes(repo: &Repository, old: Option<&str>, new: Option<&str>) -> Result<Vec<Change>> {
+    let mut changes = Vec::new();
+    compare(repo, "", read(repo, old)?, read(repo, new)?, &mut changes)?;
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(changes)
+}
+
+// treeの直下のentryだけをNodeとして読む
+fn read(repo: &Repository, tree: Option<&str>) -> Result<Vec<Node>> {
+    let Some(tree) = tree else {
+        return Ok(Vec::new());
+    };
+    Ok(repo
+        .find_tree(tree)?
+        .entries
+        .into_iter()
+        .map(Node::from)
+        .collect())
+}
+
+fn compare(
+    repo: &Repository,
+    prefix: &str,
+    old: Vec<Node>,
+    new: Vec<Node>,
+    changes: &mut Vec<Change>,
+) -> Result<()> {
+    let mut nodes: BTreeMap<String, (Option<Node>, Option<Node>)> = BTreeMap::new();
+    for node in old {
+        let name = node.name.clone();
+        nodes.entry(name).or_default().0 = Some(node);
+    }
+    for node in new {
+        let name = node.name.clone();
+        nodes.entry(name).or_default().1 = Some(node);
+    }
+
+    for (name, (old, new)) in nodes {
+        if let (Some(old), Some(new)) = (&old, &new) {
+            // hashが同じなら中を見るまでもない
+            if old.hash == new.hash && old.mode == new.mode {
+                continue;
+            }
+        }
+        let path = format!("{prefix}{name}");
+        let is_tree =
+            |node: &Option<Node>| node.as_ref().is_some_and(|n| n.r#type == NodeType::Tree);
+        // ディレクトリの側は中身を再帰的に比べ、ファイルの側はそのまま記録する
+        let (old_tree, new_tree) = (is_tree(&old), is_tree(&new));
+        if old_tree || new_tree {
+            let subtree = |node: &Option<Node>, tree: bool| -> Result<Vec<Node>> {
+                match node {
+                    Some(node) if tree => read(repo, Some(&node.hash)),
+                    _ => Ok(Vec::new()),
+                }
+            };
+            compare(
+                repo,
+                &format!("{path}/"),
+                subtree(&old, old_tree)?,
+                subtree(&new, new_tree)?,
+                changes,
+            )?;
+        }
+        let file =
+            |node: Option<Node>, tree: bool| node.filter(|_| !tree).map(|n| (n.mode, n.hash));
+        let (old, new) = (file(old, old_tree), file(new, new_tree));
+        if old.is_some() || new.is_some() {
+            changes.push(Change { path, old, new });
+        }
+    }
+    Ok(())
+}
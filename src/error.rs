@@ -0,0 +1,127 @@
+//! Errors returned by the library.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::object::Kind;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// No repository was found, or the path is not a valid git directory.
+    NotARepository(String),
+    /// The operation needs a working tree but the repository is bare.
+    BareRepository,
+    ObjectNotFound(String),
+    RefNotFound(String),
+    /// A revision or object name that does not name any object.
+    InvalidObjectName(String),
+    WrongObjectType {
+        oid: String,
+        expected: Kind,
+        actual: Kind,
+    },
+    CorruptObject(String),
+    CorruptIndex(String),
+    CorruptRef(String),
+    InvalidRefName(String),
+    /// A path that must not be checked out or recorded in the index, such
+    /// as one with a `..` component or inside `.git`.
+    InvalidPath(String),
+    Config(String),
+    /// A malformed pathspec, or one outside the working tree.
+    InvalidPathspec(String),
+    /// Another process holds `<path>.lock`.
+    LockContention(PathBuf),
+    /// The operation stopped because of conflicts in these paths.
+    Conflict(Vec<String>),
+    /// Updating the working tree would lose uncommitted changes to these paths.
+    LocalChanges(Vec<String>),
+    /// Updating the working tree would overwrite these untracked files.
+    UntrackedOverwritten(Vec<String>),
+    /// A failed file operation, on `path` when it is known.
+    Io {
+        path: Option<PathBuf>,
+        error: io::Error,
+    },
+}
+
+impl Error {
+    /// Wrap an error from a file operation on `path`, for `map_err`.
+    pub fn io(path: impl AsRef<Path>) -> impl FnOnce(io::Error) -> Self {
+        let path = path.as_ref().to_path_buf();
+        move |error| Self::Io {
+            path: Some(path),
+            error,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotARepository(message)
+            | Self::Config(message)
+            | Self::InvalidPathspec(message) => f.write_str(message),
+            Self::BareRepository => f.write_str("this operation must be run in a work tree"),
+            Self::ObjectNotFound(oid) => write!(f, "object {oid} not found"),
+            Self::RefNotFound(name) => write!(f, "ref {name} not found"),
+            Self::InvalidObjectName(name) => write!(f, "not a valid object name: '{name}'"),
+            Self::WrongObjectType {
+                oid,
+                expected,
+                actual,
+            } => write!(f, "object {oid} is a {actual}, not a {expected}"),
+            Self::CorruptObject(message) => write!(f, "corrupt object {message}"),
+            Self::CorruptIndex(message) => write!(f, "index file corrupt: {message}"),
+            Self::CorruptRef(message) => write!(f, "bad ref {message}"),
+            Self::InvalidRefName(name) => write!(f, "'{name}' is not a valid ref name"),
+            Self::InvalidPath(path) => write!(f, "invalid path '{path}'"),
+            Self::LockContention(path) => write!(
+                f,
+                "Unable to create '{}': File exists.\n\n\
+                 Another git process seems to be running in this repository.",
+                path.display()
+            ),
+            Self::Conflict(paths) => {
+                write!(f, "conflicts in {} path(s)", paths.len())?;
+                write_paths(f, paths)
+            }
+            Self::LocalChanges(paths) => {
+                f.write_str("Your local changes to the following files would be overwritten:")?;
+                write_paths(f, paths)?;
+                f.write_str(
+                    "\nPlease commit your changes or stash them before you switch branches.",
+                )
+            }
+            Self::UntrackedOverwritten(paths) => {
+                f.write_str("The following untracked working tree files would be overwritten:")?;
+                write_paths(f, paths)?;
+                f.write_str("\nPlease move or remove them before you switch branches.")
+            }
+            Self::Io {
+                path: Some(path),
+                error,
+            } => write!(f, "{}: {error}", path.display()),
+            Self::Io { path: None, error } => error.fmt(f),
+        }
+    }
+}
+
+fn write_paths(f: &mut fmt::Formatter<'_>, paths: &[String]) -> fmt::Result {
+    for path in paths {
+        write!(f, "\n\t{path}")?;
+    }
+    Ok(())
+}
+
+// 元のエラーはDisplayに含めるので、sourceとしては返さない
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Self::Io { path: None, error }
+    }
+}
@@ -0,0 +1,255 @@
+//! `.gitignore`, `info/exclude` and `core.excludesFile` rules.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+use crate::repository::Repository;
+use crate::util::wildmatch::wildmatch;
+
+/// One line of an ignore file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    /// The line as written, e.g. `!/build/`.
+    pub original: String,
+    /// File the pattern comes from, as shown by `check-ignore -v`.
+    pub source: String,
+    /// 1-based line number in `source`.
+    pub line: usize,
+    /// `!pattern`: re-include paths excluded by an earlier pattern.
+    pub negative: bool,
+    pattern: String,
+    dir_only: bool,
+    // "/"を含むパターンは`base`からの相対パスに、含まないものはbasenameにマッチする
+    anchored: bool,
+    // パターンが書かれた.gitignoreのあるディレクトリ(worktreeのトップは"")
+    base: String,
+}
+
+impl Pattern {
+    /// Parse a line of an ignore file found in directory `base`.
+    pub fn parse(line: &str, base: &str, source: &str, line_number: usize) -> Option<Self> {
+        let mut text = trim_trailing_spaces(line);
+        if text.is_empty() || text.starts_with('#') {
+            return None;
+        }
+        let original = text.to_string();
+        let negative = text.starts_with('!');
+        if negative {
+            text = &text[1..];
+        }
+        let dir_only = text.ends_with('/');
+        if dir_only {
+            text = &text[..text.len() - 1];
+        }
+        let anchored = text.contains('/');
+        let pattern = text.strip_prefix('/').unwrap_or(text);
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(Self {
+            original,
+            source: source.to_string(),
+            line: line_number,
+            negative,
+            pattern: pattern.to_string(),
+            dir_only,
+            anchored,
+            base: base.to_string(),
+        })
+    }
+
+    /// Whether the pattern matches `path`, relative to the top of the working tree.
+    pub fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let relative = if self.base.is_empty() {
+            path
+        } else {
+            match path
+                .strip_prefix(self.base.as_str())
+                .and_then(|p| p.strip_prefix('/'))
+            {
+                Some(relative) => relative,
+                None => return false,
+            }
+        };
+        if self.anchored {
+            wildmatch(&self.pattern, relative, true)
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            wildmatch(&self.pattern, name, true)
+        }
+    }
+}
+
+// 末尾の空白は"\"でエスケープされていなければ無視する
+fn trim_trailing_spaces(line: &str) -> &str {
+    let line = line.trim_end_matches(['\n', '\r']);
+    let mut end = line.len();
+    while end > 0 && line.as_bytes()[end - 1] == b' ' {
+        if end >= 2 && line.as_bytes()[end - 2] == b'\\' {
+            break;
+        }
+        end -= 1;
+    }
+    &line[..end]
+}
+
+/// Decides which paths of a working tree are ignored.
+///
+/// `.gitignore` files are read lazily as directories are queried. Patterns
+/// in deeper directories take precedence over shallower ones, then come
+/// `info/exclude` and `core.excludesFile`; within a file, the last matching
+/// pattern wins.
+#[derive(Debug)]
+pub struct Ignore {
+    work_tree: PathBuf,
+    per_directory: HashMap<String, Vec<Pattern>>,
+    global: Vec<Pattern>,
+}
+
+impl Ignore {
+    pub fn new(repo: &Repository) -> Result<Self> {
+        let work_tree = repo.require_work_tree()?.to_path_buf();
+        let mut global = Vec::new();
+
+        let excludes_file = match repo.config().get("core.excludesFile") {
+            Some(path) => Some(expand_home(path)),
+            None => env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+                .map(|config| config.join("git/ignore")),
+        };
+        if let Some(path) = excludes_file {
+            global.extend(read_patterns(&path, "", &path.display().to_string())?);
+        }
+
+        let info_exclude = repo.git_dir().join("info/exclude");
+        let source = match info_exclude.strip_prefix(&work_tree) {
+            Ok(relative) => relative.display().to_string(),
+            Err(_) => info_exclude.display().to_string(),
+        };
+        // 優先度の高いinfo/excludeを後ろに置く
+        global.extend(read_patterns(&info_exclude, "", &source)?);
+
+        Ok(Self {
+            work_tree,
+            per_directory: HashMap::new(),
+            global,
+        })
+    }
+
+    /// The pattern deciding whether `path` is ignored, if any.
+    ///
+    /// A path inside an ignored directory is ignored by that directory's
+    /// pattern, since git never looks inside excluded directories. A returned
+    /// negative pattern means the path was explicitly re-included.
+    pub fn check(&mut self, path: &str, is_dir: bool) -> Result<Option<Pattern>> {
+        let mut end = 0;
+        while let Some(slash) = path[end..].find('/') {
+            end += slash;
+            if let Some(pattern) = self.find(&path[..end], true)? {
+                if !pattern.negative {
+                    return Ok(Some(pattern));
+                }
+            }
+            end += 1;
+        }
+        self.find(path, is_dir)
+    }
+
+    pub fn is_ignored(&mut self, path: &str, is_dir: bool) -> Result<bool> {
+        Ok(self
+            .check(path, is_dir)?
+            .is_some_and(|pattern| !pattern.negative))
+    }
+
+    fn find(&mut self, path: &str, is_dir: bool) -> Result<Option<Pattern>> {
+        // 深いディレクトリの.gitignoreから順に見る
+        let mut dir = path;
+        while let Some((parent, _)) = dir.rsplit_once('/') {
+            dir = parent;
+            if let Some(pattern) = self.find_in(dir, path, is_dir)? {
+                return Ok(Some(pattern));
+            }
+        }
+        if let Some(pattern) = self.find_in("", path, is_dir)? {
+            return Ok(Some(pattern));
+        }
+        Ok(self
+            .global
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(path, is_dir))
+            .cloned())
+    }
+
+    fn find_in(&mut self, dir: &str, path: &str, is_dir: bool) -> Result<Option<Pattern>> {
+        if !self.per_directory.contains_key(dir) {
+            let source = if dir.is_empty() {
+                ".gitignore".to_string()
+            } else {
+                format!("{dir}/.gitignore")
+            };
+            let patterns = read_patterns(&self.work_tree.join(&source), dir, &source)?;
+            self.per_directory.insert(dir.to_string(), patterns);
+        }
+        Ok(self.per_directory[dir]
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(path, is_dir))
+            .cloned())
+    }
+}
+
+fn read_patterns(path: &Path, base: &str, source: &str) -> Result<Vec<Pattern>> {
+    let text = match fs::read(path) {
+        Ok(text) => String::from_utf8_lossy(&text).into_owned(),
+        // 途中がファイルの場合(ENOTDIR)も無いものとして扱う
+        Err(_) if !path.exists() => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(text
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| Pattern::parse(line, base, source, i + 1))
+        .collect())
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(line: &str, base: &str) -> Pattern {
+        Pattern::parse(line, base, ".gitignore", 1).unwrap()
+    }
+
+    #[test]
+    fn test_pattern_matches() {
+        assert!(pattern("*.o", "").matches("a/b/c.o", false));
+        assert!(pattern("/build", "").matches("build", true));
+        assert!(!pattern("/build", "").matches("src/build", true));
+        assert!(pattern("target/", "").matches("a/target", true));
+        assert!(!pattern("target/", "").matches("a/target", false));
+        assert!(pattern("doc/*.txt", "").matches("doc/a.txt", false));
+        assert!(!pattern("doc/*.txt", "").matches("doc/x/a.txt", false));
+        assert!(pattern("**/logs", "").matches("x/y/logs", true));
+        assert!(pattern("*.tmp", "sub").matches("sub/x/a.tmp", false));
+        assert!(!pattern("*.tmp", "sub").matches("other/a.tmp", false));
+        assert!(pattern("!keep.o", "").negative);
+        assert!(pattern("trailing\\ ", "").matches("trailing ", false));
+        assert!(Pattern::parse("# comment", "", ".gitignore", 1).is_none());
+        assert!(Pattern::parse("   ", "", ".gitignore", 1).is_none());
+    }
+}
@@ -0,0 +1,449 @@
+//! The staging area (`.git/index`), versions 2 and 3.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::ops::Range;
+use std::os::linux::fs::MetadataExt;
+use std::path::Path;
+
+use byteorder::{BigEndian, ByteOrder};
+use sha1::{Digest, Sha1};
+
+use crate::error::{Error, Result};
+use crate::object::tree::{normalize_mode, Node};
+use crate::odb::ObjectDatabase;
+use crate::util::lockfile::LockFile;
+
+const SIGNATURE: &[u8] = b"DIRC";
+
+const FLAG_ASSUME_VALID: u16 = 0x8000;
+const FLAG_EXTENDED: u16 = 0x4000;
+const FLAG_STAGE_MASK: u16 = 0x3000;
+const FLAG_NAME_MASK: u16 = 0x0fff;
+const EXTENDED_SKIP_WORKTREE: u16 = 0x4000;
+const EXTENDED_INTENT_TO_ADD: u16 = 0x2000;
+
+/// One staged path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Entry {
+    pub ctime: (u32, u32),
+    pub mtime: (u32, u32),
+    pub dev: u32,
+    pub ino: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+    pub oid: String,
+    /// 0 for normal entries, 1-3 for the base/ours/theirs sides of a conflict.
+    pub stage: u8,
+    pub assume_valid: bool,
+    pub skip_worktree: bool,
+    pub intent_to_add: bool,
+    /// Slash-separated path relative to the top of the working tree.
+    pub path: String,
+}
+
+impl Entry {
+    /// Build an entry for `path` from its file system metadata.
+    // indexの各フィールドは32bitなので上位bitは切り捨てる
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn from_metadata(
+        path: impl Into<String>,
+        oid: impl Into<String>,
+        metadata: &fs::Metadata,
+    ) -> Self {
+        Self {
+            ctime: (metadata.st_ctime() as u32, metadata.st_ctime_nsec() as u32),
+            mtime: (metadata.st_mtime() as u32, metadata.st_mtime_nsec() as u32),
+            dev: metadata.st_dev() as u32,
+            ino: metadata.st_ino() as u32,
+            mode: normalize_mode(metadata.st_mode()),
+            uid: metadata.st_uid(),
+            gid: metadata.st_gid(),
+            size: metadata.st_size() as u32,
+            oid: oid.into(),
+            path: path.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Whether `metadata` still matches the recorded stat data, meaning the
+    /// file can be assumed unchanged without hashing it.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn is_stat_clean(&self, metadata: &fs::Metadata) -> bool {
+        self.mtime == (metadata.st_mtime() as u32, metadata.st_mtime_nsec() as u32)
+            && self.size == metadata.st_size() as u32
+            && self.ino == metadata.st_ino() as u32
+            && self.mode == normalize_mode(metadata.st_mode())
+    }
+
+    fn is_extended(&self) -> bool {
+        self.skip_worktree || self.intent_to_add
+    }
+
+    fn encode(&self, contents: &mut Vec<u8>) {
+        let start = contents.len();
+        for value in [
+            self.ctime.0,
+            self.ctime.1,
+            self.mtime.0,
+            self.mtime.1,
+            self.dev,
+            self.ino,
+            self.mode,
+            self.uid,
+            self.gid,
+            self.size,
+        ] {
+            contents.extend(value.to_be_bytes());
+        }
+        contents.extend(hex::decode(&self.oid).unwrap_or_else(|_| vec![0; 20]));
+
+        let name_length =
+            u16::try_from(self.path.len()).map_or(FLAG_NAME_MASK, |l| l.min(FLAG_NAME_MASK));
+        let mut flags = name_length | (u16::from(self.stage) << 12);
+        if self.assume_valid {
+            flags |= FLAG_ASSUME_VALID;
+        }
+        if self.is_extended() {
+            flags |= FLAG_EXTENDED;
+        }
+        contents.extend(flags.to_be_bytes());
+        if self.is_extended() {
+            let mut extended = 0;
+            if self.skip_worktree {
+                extended |= EXTENDED_SKIP_WORKTREE;
+            }
+            if self.intent_to_add {
+                extended |= EXTENDED_INTENT_TO_ADD;
+            }
+            contents.extend(u16::to_be_bytes(extended));
+        }
+        contents.extend(self.path.as_bytes());
+
+        // entryの長さが8の倍数になるように1~8byteのNULで埋める
+        let length = contents.len() - start;
+        let padding = 8 - length % 8;
+        contents.resize(contents.len() + padding, 0);
+    }
+
+    // (消費したbyte数, entry)を返す
+    fn decode(entry: &[u8]) -> Option<(usize, Self)> {
+        let field = |i: usize| BigEndian::read_u32(&entry[i * 4..i * 4 + 4]);
+        if entry.len() < 62 {
+            return None;
+        }
+        let flags = BigEndian::read_u16(&entry[60..62]);
+        let (extended, path_start) = if flags & FLAG_EXTENDED == 0 {
+            (0, 62)
+        } else {
+            (BigEndian::read_u16(entry.get(62..64)?), 64)
+        };
+        let path_length = entry[path_start..].iter().position(|&b| b == 0)?;
+        let path_end = path_start + path_length;
+        let next_byte = path_end + 8 - (path_end % 8);
+
+        let result = Self {
+            ctime: (field(0), field(1)),
+            mtime: (field(2), field(3)),
+            dev: field(4),
+            ino: field(5),
+            // 古い実装が書いた生のst_modeも正規化して読む
+            mode: normalize_mode(field(6)),
+            uid: field(7),
+            gid: field(8),
+            size: field(9),
+            oid: hex::encode(&entry[40..60]),
+            stage: u8::try_from((flags & FLAG_STAGE_MASK) >> 12).ok()?,
+            assume_valid: flags & FLAG_ASSUME_VALID != 0,
+            skip_worktree: extended & EXTENDED_SKIP_WORKTREE != 0,
+            intent_to_add: extended & EXTENDED_INTENT_TO_ADD != 0,
+            path: String::from_utf8_lossy(&entry[path_start..path_end]).into_owned(),
+        };
+        Some((next_byte.min(entry.len()), result))
+    }
+}
+
+/// The parsed index file, with entries kept sorted by path and stage.
+#[derive(Debug, Clone, Default)]
+pub struct Index {
+    entries: Vec<Entry>,
+}
+
+impl Index {
+    /// Read the index at `path`; a missing file is an empty index.
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(Error::io(path)(e)),
+        };
+        Self::decode(&content)
+    }
+
+    fn decode(content: &[u8]) -> Result<Self> {
+        let corrupt = |what: &str| Error::CorruptIndex(what.to_string());
+        if content.len() < 32 || &content[0..4] != SIGNATURE {
+            return Err(corrupt("bad signature"));
+        }
+        let (body, checksum) = content.split_at(content.len() - 20);
+        if Sha1::digest(body).as_slice() != checksum {
+            return Err(corrupt("bad checksum"));
+        }
+        let version = BigEndian::read_u32(&content[4..8]);
+        if !(2..=3).contains(&version) {
+            return Err(corrupt(&format!("unsupported version {version}")));
+        }
+
+        // entriesを上から1 entryずつ消費していく
+        let entry_count = BigEndian::read_u32(&content[8..12]);
+        let mut entries = Vec::new();
+        let mut rest = &body[12..];
+        for _ in 0..entry_count {
+            let (next_byte, entry) =
+                Entry::decode(rest).ok_or_else(|| corrupt("truncated entry"))?;
+            entries.push(entry);
+            rest = &rest[next_byte..];
+        }
+        // 残りはTREEなどのextension。キャッシュなので読み飛ばすが、必須のものは扱えない
+        while rest.len() >= 8 {
+            let signature = &rest[0..4];
+            if signature[0].is_ascii_uppercase() {
+                let size = BigEndian::read_u32(&rest[4..8]) as usize;
+                rest = rest
+                    .get(8 + size..)
+                    .ok_or_else(|| corrupt("truncated extension"))?;
+            } else {
+                return Err(corrupt(&format!(
+                    "unsupported extension {}",
+                    String::from_utf8_lossy(signature)
+                )));
+            }
+        }
+
+        let mut index = Self { entries };
+        index.sort();
+        Ok(index)
+    }
+
+    /// Write the index to `path`, going through `<path>.lock` so readers never
+    /// see a partial file.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let version: u32 = if self.entries.iter().any(Entry::is_extended) {
+            3
+        } else {
+            2
+        };
+        let entry_count = u32::try_from(self.entries.len())
+            .map_err(|_| Error::CorruptIndex("too many entries".to_string()))?;
+
+        let mut contents: Vec<u8> = SIGNATURE.to_vec();
+        contents.extend(version.to_be_bytes());
+        contents.extend(entry_count.to_be_bytes());
+        for entry in &self.entries {
+            entry.encode(&mut contents);
+        }
+        let checksum = Sha1::digest(&contents);
+        contents.extend(checksum.as_slice());
+
+        let mut lock = LockFile::acquire(path)?;
+        lock.write_all(&contents)?;
+        lock.commit()
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entry for `path` at `stage`.
+    pub fn get(&self, path: &str, stage: u8) -> Option<&Entry> {
+        self.position(path, stage).ok().map(|i| &self.entries[i])
+    }
+
+    /// Add `entry`, replacing an existing entry with the same path and stage.
+    ///
+    /// A stage 0 entry resolves a conflict at its path, dropping the entries
+    /// at the other stages, and a conflict stage replaces stage 0.
+    pub fn add(&mut self, entry: Entry) {
+        if entry.stage == 0 {
+            // stage 0は残して置き換え、conflictのstageだけを消す
+            let mut conflicts = self.stages(&entry.path);
+            if !conflicts.is_empty() && self.entries[conflicts.start].stage == 0 {
+                conflicts.start += 1;
+            }
+            self.entries.drain(conflicts);
+        } else {
+            self.remove_stage(&entry.path, 0);
+        }
+        match self.position(&entry.path, entry.stage) {
+            Ok(i) => self.entries[i] = entry,
+            Err(i) => self.entries.insert(i, entry),
+        }
+    }
+
+    /// Remove the entries a file at `path` replaces: files at its leading
+    /// directories, and files inside `path` as a directory.
+    pub fn remove_in_the_way(&mut self, path: &str) {
+        let dir = format!("{path}/");
+        let start = self
+            .entries
+            .partition_point(|e| e.path.as_bytes() < dir.as_bytes());
+        let end = start
+            + self.entries[start..]
+                .iter()
+                .take_while(|e| e.path.starts_with(&dir))
+                .count();
+        self.entries.drain(start..end);
+        for (i, _) in path.match_indices('/') {
+            self.remove(&path[..i]);
+        }
+    }
+
+    /// Remove every stage of `path`, returning whether anything was removed.
+    pub fn remove(&mut self, path: &str) -> bool {
+        let stages = self.stages(path);
+        let removed = !stages.is_empty();
+        self.entries.drain(stages);
+        removed
+    }
+
+    /// Remove only the entry of `path` at `stage`, returning whether there
+    /// was one.
+    pub fn remove_stage(&mut self, path: &str, stage: u8) -> bool {
+        match self.position(path, stage) {
+            Ok(i) => {
+                self.entries.remove(i);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    // pathのすべてのstageのentryの範囲
+    fn stages(&self, path: &str) -> Range<usize> {
+        let start = self
+            .entries
+            .partition_point(|e| e.path.as_bytes() < path.as_bytes());
+        let count = self.entries[start..]
+            .iter()
+            .take_while(|e| e.path == path)
+            .count();
+        start..start + count
+    }
+
+    fn position(&self, path: &str, stage: u8) -> Result<usize, usize> {
+        self.entries
+            .binary_search_by(|e| (e.path.as_bytes(), e.stage).cmp(&(path.as_bytes(), stage)))
+    }
+
+    fn sort(&mut self) {
+        self.entries
+            .sort_by(|a, b| (a.path.as_bytes(), a.stage).cmp(&(b.path.as_bytes(), b.stage)));
+    }
+
+    /// Write tree objects for the stage 0 entries and return the root tree hash.
+    pub fn write_tree(&self, odb: &ObjectDatabase) -> Result<String> {
+        let mut index_tree = Node::root();
+        for entry in &self.entries {
+            if entry.stage != 0 {
+                return Err(Error::Conflict(vec![entry.path.clone()]));
+            }
+            if entry.intent_to_add {
+                continue;
+            }
+            index_tree.insert(&entry.path, entry.mode, entry.oid.clone());
+        }
+        index_tree.write(odb)
+    }
+}
+
+/// Whether `path` may be recorded in the index and checked out: it has no
+/// empty, `.` or `..` components and no `.git` component in any case.
+pub fn verify_path(path: &str) -> bool {
+    path.split('/').all(|component| {
+        !component.is_empty()
+            && component != "."
+            && component != ".."
+            && !component.eq_ignore_ascii_case(".git")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(index: &Index) -> Vec<&str> {
+        index.entries().iter().map(|e| e.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_remove_in_the_way() {
+        let mut index = Index::default();
+        for path in ["d", "d-x", "e/f", "e/g/h", "ee"] {
+            index.add(Entry {
+                path: path.to_string(),
+                ..Entry::default()
+            });
+        }
+        index.remove_in_the_way("d/x");
+        assert_eq!(paths(&index), ["d-x", "e/f", "e/g/h", "ee"]);
+        index.remove_in_the_way("e");
+        assert_eq!(paths(&index), ["d-x", "ee"]);
+    }
+
+    #[test]
+    fn test_add_stages() {
+        let entry = |path: &str, stage: u8| Entry {
+            path: path.to_string(),
+            stage,
+            ..Entry::default()
+        };
+        let stages = |index: &Index| -> Vec<(String, u8)> {
+            let entries = index.entries().iter();
+            entries.map(|e| (e.path.clone(), e.stage)).collect()
+        };
+        let mut index = Index::default();
+        for (path, stage) in [("a", 0), ("b", 1), ("b", 2), ("b", 3), ("c", 0)] {
+            index.add(entry(path, stage));
+        }
+        // conflictのstageはstage 0を消し、stage 0はconflictのstageを消す
+        index.add(entry("a", 2));
+        index.add(entry("b", 0));
+        let expected = [
+            ("a".to_string(), 2),
+            ("b".to_string(), 0),
+            ("c".to_string(), 0),
+        ];
+        assert_eq!(stages(&index), expected);
+
+        assert!(index.remove("b"));
+        assert!(!index.remove("b"));
+        assert_eq!(paths(&index), ["a", "c"]);
+    }
+
+    #[test]
+    fn test_verify_path() {
+        for path in ["a", "a/b", ".gitignore", "a/.github/x", "..a", "a.."] {
+            assert!(verify_path(path), "{path}");
+        }
+        for path in [
+            "", "/a", "a/", "a//b", "./a", "a/./b", "../a", "a/../b", "a/..",
+        ] {
+            assert!(!verify_path(path), "{path}");
+        }
+        for path in [
+            ".git",
+            ".git/hooks/x",
+            "sub/.git/config",
+            ".GIT/x",
+            "a/.Git",
+        ] {
+            assert!(!verify_path(path), "{path}");
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! A small implementation of git.
+//!
+//! [`Repository`] is the entry point: open or discover one, then read and
+//! write objects through [`odb`], the staging area through [`index`],
+//! branches through [`refs`], history through [`revwalk`] and files of the
+//! working tree through [`worktree`].
+
+// testing.rsをコマンドのテストと共有するため、自身をgitとして参照できるようにする
+#[cfg(test)]
+extern crate self as git;
+
+pub mod blame;
+pub mod config;
+pub mod diff;
+pub mod error;
+pub mod ignore;
+pub mod index;
+pub mod merge;
+pub mod message;
+pub mod object;
+pub mod odb;
+pub mod pathspec;
+pub mod reflog;
+pub mod refs;
+pub mod repository;
+pub mod revision;
+pub mod revwalk;
+pub mod status;
+#[cfg(test)]
+mod testing;
+pub mod todo;
+mod util;
+pub mod worktree;
+
+pub use error::{Error, Result};
+pub use repository::Repository;
@@ -1,102 +1,214 @@
-use std::{env, path::PathBuf};
+use std::env;
+use std::process::ExitCode;
 
+use cli::{Command, Options};
+use git::repository::{find_git_root, Repository};
+
+mod cli;
 mod command;
-mod object;
-mod util;
+#[cfg(test)]
+mod testing;
 
-#[derive(Debug, Clone)]
-enum Command {
-    Init,
-    Add { files: Vec<PathBuf> },
-    Commit { message: String },
-    Branch { name: String, delete: bool },
-    Checkout { name: String, new_branch: bool },
-    Log,
+fn main() -> ExitCode {
+    // gitと同じく、読み手が閉じたパイプに書いたらSIGPIPEで黙って終わる。
+    // Rustの既定では無視され、書き込みのエラーになってしまう
+    #[cfg(unix)]
+    // SAFETY: ほかのスレッドを作る前に、既定の動作に戻すだけ
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
+    let args = cli::options();
+    match run(args) {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("fatal: {error:#}");
+            ExitCode::from(exit_code(&error))
+        }
+    }
 }
 
-fn options() -> Command {
-    use bpaf::{construct, positional, pure, short, Parser};
-
-    let init = pure(Command::Init)
-        .to_options()
-        .command("init")
-        .help("Initialize new git repository");
-
-    let add = {
-        let files = positional("FILE").help("File to add").many();
-        construct!(Command::Add { files })
-            .to_options()
-            .command("add")
-            .help("Add a file")
-    };
-
-    let commit = {
-        let message = short('m')
-            .long("message")
-            .help("Commit changes")
-            .argument("MESSAGE");
-        construct!(Command::Commit { message })
-            .to_options()
-            .command("commit")
-            .help("Commit changes")
-    };
-
-    let branch = {
-        let delete = short('d')
-            .long("delete")
-            .help("Delete the branch?")
-            .switch();
-        let name = positional("BRANCH").help("Branch name");
-        construct!(Command::Branch { delete, name })
-            .to_options()
-            .command("branch")
-    };
-
-    let checkout = {
-        let new_branch = short('b').help("Create new branch?").switch();
-        let name = positional("BRANCH").help("Branch name");
-        construct!(Command::Checkout { name, new_branch })
-            .to_options()
-            .command("checkout")
-    };
-
-    let log = pure(Command::Log).to_options().command("log");
-
-    construct!([init, add, commit, branch, checkout, log])
-        .to_options()
-        .version(env!("CARGO_PKG_VERSION"))
-        .fallback_to_usage()
-        .run()
+// gitと同じく、致命的なエラーは128、コンフリクトや変更の保護で止まった場合は1で終了する
+fn exit_code(error: &anyhow::Error) -> u8 {
+    match error.downcast_ref::<git::Error>() {
+        Some(
+            git::Error::Conflict(_)
+            | git::Error::LocalChanges(_)
+            | git::Error::UntrackedOverwritten(_),
+        ) => 1,
+        _ => 128,
+    }
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = options();
-    println!("Args: {args:?}");
+// コマンドが増えるほど長くなる振り分けなので、長さは気にしない
+#[allow(clippy::too_many_lines)]
+fn run(args: Options) -> anyhow::Result<ExitCode> {
+    // -Cは指定された順に相対パスとして解決する
+    for directory in &args.directory {
+        env::set_current_dir(directory)?;
+    }
 
-    if !matches!(args, Command::Init) {
-        util::path::find_git_root()?;
+    let cwd = env::current_dir()?;
+    if let Command::Init { directory, options } = &args.command {
+        let path = match directory {
+            Some(directory) => cwd.join(directory),
+            None => cwd,
+        };
+        command::init::init(&path, options)?;
+        return Ok(ExitCode::SUCCESS);
     }
 
-    match args {
-        Command::Init => command::init::init()?,
-        Command::Add { files } => command::add::add(&files)?,
-        Command::Commit { message } => command::commit::commit(message)?,
-        Command::Branch { name, delete } => {
-            if delete {
-                command::branch::delete(&name)?;
-            } else {
-                // show branches
-                todo!();
-            }
-        }
-        Command::Checkout { name, new_branch } => {
-            if new_branch {
-                command::branch::create(&name)?;
-            } else {
-                command::branch::checkout(&name)?;
-            }
-        }
-        Command::Log => todo!(),
+    let root = find_git_root(&cwd, args.git_dir.as_deref(), args.work_tree.as_deref())?;
+    let repo = Repository::open(&root.git_dir, root.work_tree.clone())?;
+
+    match args.command {
+        Command::Init { .. } => unreachable!(),
+        Command::Add { options, pathspec } => {
+            if !command::add::add(&repo, &root.prefix, &pathspec, &options)? {
+                return Ok(ExitCode::from(1));
+            }
+        }
+        Command::Rm { options, pathspec } => {
+            if !command::rm::rm(&repo, &root.prefix, &pathspec, &options)? {
+                return Ok(ExitCode::from(1));
+            }
+        }
+        Command::Mv { options, paths } => command::mv::mv(&repo, &root, &paths, &options)?,
+        Command::Diff { options, args } => {
+            command::diff::diff(&repo, &root.prefix, &args, &options)?;
+        }
+        Command::HashObject {
+            write,
+            stdin,
+            paths,
+        } => command::hash_object::hash_object(&repo, &paths, stdin, write)?,
+        Command::WriteTree => command::write_tree::write_tree(&repo)?,
+        Command::CommitTree {
+            parents,
+            messages,
+            tree,
+        } => command::commit_tree::commit_tree(&repo, &tree, &parents, &messages)?,
+        Command::LsTree {
+            options,
+            tree,
+            paths,
+        } => command::ls_tree::ls_tree(&repo, &root.prefix, &tree, &paths, &options)?,
+        Command::LsFiles { options, paths } => {
+            command::ls_files::ls_files(&repo, &root.prefix, &paths, &options)?;
+        }
+        Command::ReadTree { options, trees } => {
+            command::read_tree::read_tree(&repo, &trees, &options)?;
+        }
+        Command::Switch { options, args } => command::switch::switch(&repo, &args, &options)?,
+        Command::Restore { options, paths } => {
+            if !command::restore::restore(&repo, &root.prefix, &paths, &options)? {
+                return Ok(ExitCode::from(1));
+            }
+        }
+        Command::Merge { options, args } => {
+            let code = command::merge::merge(&repo, &root.prefix, &args, &options)?;
+            if code != 0 {
+                return Ok(ExitCode::from(code));
+            }
+        }
+        Command::CherryPick { options, args } => {
+            if !command::sequencer::cherry_pick(&repo, &root.prefix, &args, &options)? {
+                return Ok(ExitCode::from(1));
+            }
+        }
+        Command::Revert { options, args } => {
+            if !command::sequencer::revert(&repo, &root.prefix, &args, &options)? {
+                return Ok(ExitCode::from(1));
+            }
+        }
+        Command::Rebase { options, args } => {
+            if !command::rebase::rebase(&repo, &root.prefix, &args, &options)? {
+                return Ok(ExitCode::from(1));
+            }
+        }
+        Command::Stash {
+            subcommand,
+            options,
+            args,
+        } => {
+            if !command::stash::stash(&repo, &root.prefix, subcommand, &args, &options)? {
+                return Ok(ExitCode::from(1));
+            }
+        }
+        Command::MergeBase { options, args } => {
+            if !command::merge_base::merge_base(&repo, &args, &options)? {
+                return Ok(ExitCode::from(1));
+            }
+        }
+        Command::Clean { options, pathspec } => {
+            if !command::clean::clean(&repo, &root.prefix, &pathspec, &options)? {
+                return Ok(ExitCode::from(1));
+            }
+        }
+        Command::Blame {
+            options,
+            args,
+            paths,
+        } => command::blame::blame(&repo, &root, &args, &paths, &options)?,
+        Command::Mergetool { options, paths } => {
+            if !command::mergetool::mergetool(&repo, &root.prefix, &paths, &options)? {
+                return Ok(ExitCode::from(1));
+            }
+        }
+        Command::Reset {
+            options,
+            args,
+            paths,
+        } => command::reset::reset(&repo, &root.prefix, &args, &paths, &options)?,
+        Command::UpdateIndex { options, paths } => {
+            if !command::update_index::update_index(&repo, &root, &paths, &options)? {
+                return Ok(ExitCode::from(1));
+            }
+        }
+        Command::Show { options, args } => {
+            command::show::show(&repo, &root.prefix, &args, &options)?;
+        }
+        Command::Commit { options } => {
+            if !command::commit::commit(&repo, &root.prefix, &options)? {
+                return Ok(ExitCode::from(1));
+            }
+        }
+        Command::Branch { options, args } => {
+            if !command::branch::branch(&repo, &args, &options)? {
+                return Ok(ExitCode::from(1));
+            }
+        }
+        Command::Checkout {
+            options,
+            args,
+            paths,
+        } => {
+            if !command::checkout::checkout(&repo, &root.prefix, &args, &paths, &options)? {
+                return Ok(ExitCode::from(1));
+            }
+        }
+        Command::Log => command::log::log(&repo)?,
+        Command::Status { short, ignored } => {
+            command::status::status(&repo, &root.prefix, short, ignored)?;
+        }
+        Command::CheckIgnore {
+            verbose,
+            non_matching,
+            no_index,
+            paths,
+        } => {
+            let ignored = command::check_ignore::check_ignore(
+                &repo,
+                &root,
+                &paths,
+                verbose,
+                non_matching,
+                no_index,
+            )?;
+            // 何も無視されていなければエラーメッセージなしで1を返す
+            if !ignored {
+                return Ok(ExitCode::from(1));
+            }
+        }
     };
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
@@ -0,0 +1,830 @@
+//! Three-way merges of files and trees.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::diff::patch::{is_binary, FilePair, Side};
+use crate::diff::{lines, myers, rename, Edit};
+use crate::error::Result;
+use crate::index::{Entry, Index};
+use crate::object::tree::{BLOB_MODE, EXECUTABLE_MODE};
+use crate::object::Kind;
+use crate::repository::Repository;
+use crate::revwalk;
+
+// 衝突の印の長さ
+const MARKER_SIZE: usize = 7;
+
+/// How conflicts are shown in merged files, as chosen by
+/// `merge.conflictStyle`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictStyle {
+    /// Our and their side, with the lines both sides agree on moved out of
+    /// the conflict.
+    #[default]
+    Merge,
+    /// Our side, the base and their side, as they are.
+    Diff3,
+    /// Like `Diff3`, but with the lines both sides agree on at the start and
+    /// the end moved out of the conflict.
+    Zdiff3,
+}
+
+impl ConflictStyle {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "merge" => Some(Self::Merge),
+            "diff3" => Some(Self::Diff3),
+            "zdiff3" => Some(Self::Zdiff3),
+            _ => None,
+        }
+    }
+}
+
+/// The names written after the conflict markers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Labels {
+    pub ours: String,
+    pub base: String,
+    pub theirs: String,
+}
+
+/// Merge the changes from `base` to `ours` and from `base` to `theirs`.
+///
+/// Returns the merged content, with conflict markers around the parts both
+/// sides changed differently, and whether there were such conflicts.
+pub fn file(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    style: ConflictStyle,
+    labels: &Labels,
+) -> (Vec<u8>, bool) {
+    let (base, ours, theirs) = (lines(base), lines(ours), lines(theirs));
+    let ours_matches = matches(&base, &ours);
+    let theirs_matches = matches(&base, &theirs);
+
+    let mut out = Vec::new();
+    let mut conflict = false;
+    let (mut i, mut j, mut k) = (0, 0, 0);
+    loop {
+        // 両側とも変えていない行はそのまま残す
+        if i < base.len() && ours_matches[i] == Some(j) && theirs_matches[i] == Some(k) {
+            out.extend_from_slice(base[i]);
+            (i, j, k) = (i + 1, j + 1, k + 1);
+            continue;
+        }
+        // 次に両側がbaseと一致する行までが、どちらかが変えた部分
+        let end = (i..=base.len())
+            .find(|&e| ours_matches[e].is_some() && theirs_matches[e].is_some())
+            .unwrap_or(base.len());
+        let ours_end = ours_matches[end].unwrap_or(ours.len());
+        let theirs_end = theirs_matches[end].unwrap_or(theirs.len());
+        let chunk = Chunk {
+            base: &base[i..end],
+            ours: &ours[j..ours_end],
+            theirs: &theirs[k..theirs_end],
+        };
+        conflict |= chunk.write(&mut out, style, labels);
+        if end == base.len() {
+            break;
+        }
+        (i, j, k) = (end, ours_end, theirs_end);
+    }
+    (out, conflict)
+}
+
+// baseの各行に一致するもう一方の行。末尾には番兵として両方の終わりを置く
+fn matches(base: &[&[u8]], other: &[&[u8]]) -> Vec<Option<usize>> {
+    let mut result = vec![None; base.len()];
+    for edit in myers(base, other) {
+        if let Edit::Equal(i, j) = edit {
+            result[i] = Some(j);
+        }
+    }
+    result.push(Some(other.len()));
+    result
+}
+
+// 少なくとも一方が変えた部分
+struct Chunk<'a, 'b> {
+    base: &'b [&'a [u8]],
+    ours: &'b [&'a [u8]],
+    theirs: &'b [&'a [u8]],
+}
+
+impl Chunk<'_, '_> {
+    // 片側だけの変更や同じ変更はそのまま取り込み、衝突なら印を付けてtrueを返す
+    fn write(&self, out: &mut Vec<u8>, style: ConflictStyle, labels: &Labels) -> bool {
+        let resolved = if self.ours == self.base {
+            self.theirs
+        } else if self.theirs == self.base || self.ours == self.theirs {
+            self.ours
+        } else {
+            self.write_conflict(out, style, labels);
+            return true;
+        };
+        out.extend(resolved.iter().copied().flatten());
+        false
+    }
+
+    fn write_conflict(&self, out: &mut Vec<u8>, style: ConflictStyle, labels: &Labels) {
+        match style {
+            ConflictStyle::Diff3 => {
+                write_markers(out, self.ours, Some(self.base), self.theirs, labels);
+            }
+            ConflictStyle::Zdiff3 => {
+                let prefix = common_prefix(self.ours, self.theirs);
+                let suffix = common_prefix(
+                    self.ours[prefix..].iter().rev(),
+                    self.theirs[prefix..].iter().rev(),
+                );
+                let ours = prefix..self.ours.len() - suffix;
+                let theirs = prefix..self.theirs.len() - suffix;
+                out.extend(self.ours[..prefix].iter().copied().flatten());
+                write_markers(
+                    out,
+                    &self.ours[ours.clone()],
+                    Some(self.base),
+                    &self.theirs[theirs],
+                    labels,
+                );
+                out.extend(self.ours[ours.end..].iter().copied().flatten());
+            }
+            ConflictStyle::Merge => {
+                // 両側の差分をとり、一致する行で衝突を細かく分ける
+                let (mut i, mut j) = (0, 0);
+                for edit in myers(self.ours, self.theirs) {
+                    let Edit::Equal(next_i, next_j) = edit else {
+                        continue;
+                    };
+                    if i < next_i || j < next_j {
+                        let (ours, theirs) = (&self.ours[i..next_i], &self.theirs[j..next_j]);
+                        write_markers(out, ours, None, theirs, labels);
+                    }
+                    out.extend_from_slice(self.ours[next_i]);
+                    (i, j) = (next_i + 1, next_j + 1);
+                }
+                if i < self.ours.len() || j < self.theirs.len() {
+                    let (ours, theirs) = (&self.ours[i..], &self.theirs[j..]);
+                    write_markers(out, ours, None, theirs, labels);
+                }
+            }
+        }
+    }
+}
+
+fn common_prefix<'a, T: PartialEq + 'a>(
+    a: impl IntoIterator<Item = T>,
+    b: impl IntoIterator<Item = T>,
+) -> usize {
+    a.into_iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+fn write_markers(
+    out: &mut Vec<u8>,
+    ours: &[&[u8]],
+    base: Option<&[&[u8]]>,
+    theirs: &[&[u8]],
+    labels: &Labels,
+) {
+    write_marker(out, b'<', &labels.ours);
+    write_side(out, ours);
+    if let Some(base) = base {
+        write_marker(out, b'|', &labels.base);
+        write_side(out, base);
+    }
+    write_marker(out, b'=', "");
+    write_side(out, theirs);
+    write_marker(out, b'>', &labels.theirs);
+}
+
+fn write_marker(out: &mut Vec<u8>, marker: u8, label: &str) {
+    out.extend(std::iter::repeat(marker).take(MARKER_SIZE));
+    if !label.is_empty() {
+        out.push(b' ');
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(b'\n');
+}
+
+// 印が行の途中に来ないよう、改行のない最後の行には改行を足す
+fn write_side(out: &mut Vec<u8>, lines: &[&[u8]]) {
+    out.extend(lines.iter().copied().flatten());
+    if lines.last().is_some_and(|line| !line.ends_with(b"\n")) {
+        out.push(b'\n');
+    }
+}
+
+/// The result of merging two trees.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Outcome {
+    /// The merged index: stage 0 entries for merged paths and stages 1-3
+    /// (base, ours, theirs) for conflicted ones, without stat data.
+    pub entries: Vec<Entry>,
+    /// Every file to leave in the working tree as path -> `(mode, oid)`;
+    /// conflicted files have their conflict markers.
+    pub files: BTreeMap<String, (u32, String)>,
+    /// The paths that could not be merged.
+    pub conflicts: Vec<String>,
+    /// `Auto-merging <path>` and `CONFLICT (<kind>): ...` lines, in path
+    /// order.
+    pub messages: Vec<String>,
+}
+
+impl Outcome {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+
+    /// Write the merged files as a tree, conflicted files with their
+    /// conflict markers, and return its hash.
+    pub fn write_tree(&self, repo: &Repository) -> Result<String> {
+        let mut index = Index::default();
+        for (path, (mode, oid)) in &self.files {
+            index.add(Entry {
+                mode: *mode,
+                oid: oid.clone(),
+                path: path.clone(),
+                ..Entry::default()
+            });
+        }
+        index.write_tree(repo.odb())
+    }
+}
+
+// path -> (mode, oid)
+type Files = BTreeMap<String, (u32, String)>;
+
+/// Merge the commits `ours` and `theirs`, the changes since their merge
+/// base. Several merge bases are first merged into one, like git's `ort`
+/// strategy does; without any, both sides count as added.
+///
+/// `labels.base` is replaced by the merge base's abbreviated hash.
+pub fn commits(
+    repo: &Repository,
+    ours: &str,
+    theirs: &str,
+    style: ConflictStyle,
+    labels: &Labels,
+) -> Result<Outcome> {
+    let bases = revwalk::merge_bases(repo, ours, theirs)?;
+    let labels = Labels {
+        base: match bases.as_slice() {
+            [base] => base[..7].to_string(),
+            _ => "merged common ancestors".to_string(),
+        },
+        ..labels.clone()
+    };
+    let base = virtual_base(repo, &bases, style)?;
+    trees(repo, base.as_deref(), ours, theirs, style, &labels)
+}
+
+// 複数のmerge baseを順にマージして1つのtreeにする
+fn virtual_base(
+    repo: &Repository,
+    bases: &[String],
+    style: ConflictStyle,
+) -> Result<Option<String>> {
+    let Some((first, rest)) = bases.split_first() else {
+        return Ok(None);
+    };
+    let mut tree = repo.find_commit(first)?.tree;
+    let labels = Labels {
+        ours: "Temporary merge branch 1".to_string(),
+        base: "merged common ancestors".to_string(),
+        theirs: "Temporary merge branch 2".to_string(),
+    };
+    for other in rest {
+        let inner = revwalk::merge_bases(repo, first, other)?;
+        let base = virtual_base(repo, &inner, style)?;
+        let outcome = trees(repo, base.as_deref(), &tree, other, style, &labels)?;
+        tree = outcome.write_tree(repo)?;
+    }
+    Ok(Some(tree))
+}
+
+/// Merge the changes from the tree `base` (`None` for an empty tree) to
+/// `ours` and to `theirs`; commits stand for their root trees.
+pub fn trees(
+    repo: &Repository,
+    base: Option<&str>,
+    ours: &str,
+    theirs: &str,
+    style: ConflictStyle,
+    labels: &Labels,
+) -> Result<Outcome> {
+    let read = |tree: Option<&str>| -> Result<Files> {
+        let Some(tree) = tree else {
+            return Ok(Files::new());
+        };
+        Ok(repo
+            .tree_files(tree)?
+            .into_iter()
+            .map(|e| (e.name, (e.mode, e.oid)))
+            .collect())
+    };
+    let (mut base, mut ours, mut theirs) = (read(base)?, read(Some(ours))?, read(Some(theirs))?);
+    let mut outcome = Outcome::default();
+    let mut moved = BTreeMap::new();
+    if detects_renames(repo) {
+        let renames = [renames(repo, &base, &ours)?, renames(repo, &base, &theirs)?];
+        moved = pair_renames(
+            &mut outcome,
+            &mut base,
+            [&mut ours, &mut theirs],
+            &renames,
+            labels,
+        );
+    }
+    let paths: BTreeSet<&String> = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .collect();
+
+    for path in paths {
+        let sides = [base.get(path), ours.get(path), theirs.get(path)];
+        match sides {
+            // 片側だけの変更か、両側の同じ変更
+            [_, o, t] if o == t => outcome.take(path, o),
+            [b, o, t] if b == o => outcome.take(path, t),
+            [b, o, t] if b == t => outcome.take(path, o),
+            [_, Some(o), Some(t)] => {
+                // 名前を変えたファイルは、印にそれぞれの側でのパスを添える
+                let labels = match moved.get(path) {
+                    Some([b, o, t]) => Labels {
+                        ours: format!("{}:{o}", labels.ours),
+                        base: format!("{}:{b}", labels.base),
+                        theirs: format!("{}:{t}", labels.theirs),
+                    },
+                    None => labels.clone(),
+                };
+                merge_contents(repo, &mut outcome, path, sides[0], o, t, style, &labels)?;
+            }
+            [_, o, t] => {
+                // 片側が消し、もう一方が変えた
+                let (deleted_in, modified_in, left) = match o {
+                    None => (&labels.ours, &labels.theirs, t),
+                    Some(_) => (&labels.theirs, &labels.ours, o),
+                };
+                outcome.messages.push(format!(
+                    "CONFLICT (modify/delete): {path} deleted in {deleted_in} and modified in \
+                     {modified_in}.  Version {modified_in} of {path} left in tree."
+                ));
+                outcome.conflict(path, sides);
+                if let Some(left) = left {
+                    outcome.files.insert(path.clone(), left.clone());
+                }
+            }
+        }
+    }
+    outcome.move_files_in_the_way(&ours, labels);
+    Ok(outcome)
+}
+
+// merge.renames(なければdiff.renames)が偽でなければ名前の変更を探す
+fn detects_renames(repo: &Repository) -> bool {
+    let config = repo.config();
+    let value = config
+        .get("merge.renames")
+        .or_else(|| config.get("diff.renames"));
+    !matches!(value, Some("false" | "no" | "off" | "0"))
+}
+
+// baseから`side`で名前が変わったファイル: 元のパス -> 新しいパス
+fn renames(repo: &Repository, base: &Files, side: &Files) -> Result<BTreeMap<String, String>> {
+    let mut pairs = Vec::new();
+    for (path, (mode, oid)) in base.iter().filter(|(path, _)| !side.contains_key(*path)) {
+        let old = Side::new(path.as_str(), *mode, oid.as_str(), repo.find_blob(oid)?);
+        pairs.push(FilePair::new(Some(old), None));
+    }
+    for (path, (mode, oid)) in side.iter().filter(|(path, _)| !base.contains_key(*path)) {
+        let new = Side::new(path.as_str(), *mode, oid.as_str(), repo.find_blob(oid)?);
+        pairs.push(FilePair::new(None, Some(new)));
+    }
+    Ok(rename::detect(pairs, rename::Options::default())
+        .into_iter()
+        .filter_map(|pair| Some((pair.old?.path, pair.new?.path)))
+        .collect())
+}
+
+// 名前を変えた側に合わせて、baseともう一方の側のファイルを新しいパスに移し、
+// 移したパス -> (base, ours, theirs)での元のパスを返す。
+// 両側が別の名前にしたものと、もう一方が消したものは衝突にして取り除く
+fn pair_renames(
+    outcome: &mut Outcome,
+    base: &mut Files,
+    sides: [&mut Files; 2],
+    renames: &[BTreeMap<String, String>; 2],
+    labels: &Labels,
+) -> BTreeMap<String, [String; 3]> {
+    let mut moved = BTreeMap::new();
+    let [ours, theirs] = sides;
+    let sources: BTreeSet<&String> = renames[0].keys().chain(renames[1].keys()).collect();
+    for source in sources {
+        let Some(original) = base.remove(source) else {
+            continue;
+        };
+        match (renames[0].get(source), renames[1].get(source)) {
+            (Some(o), Some(t)) if o == t => {
+                base.insert(o.clone(), original);
+            }
+            (Some(o), Some(t)) => {
+                outcome.messages.push(format!(
+                    "CONFLICT (rename/rename): {source} renamed to {o} in {} and to {t} in {}.",
+                    labels.ours, labels.theirs
+                ));
+                let (o_side, t_side) = (ours.remove(o), theirs.remove(t));
+                outcome.conflict(source, [Some(&original), None, None]);
+                outcome.conflict(o, [None, o_side.as_ref(), None]);
+                outcome.conflict(t, [None, None, t_side.as_ref()]);
+                outcome.files.extend(o_side.map(|side| (o.clone(), side)));
+                outcome.files.extend(t_side.map(|side| (t.clone(), side)));
+            }
+            (renamed_in_ours, _) => {
+                let (target, kept, other, renamed_by, other_label) = match renamed_in_ours {
+                    Some(o) => (o, &mut *ours, &mut *theirs, &labels.ours, &labels.theirs),
+                    None => (
+                        &renames[1][source],
+                        &mut *theirs,
+                        &mut *ours,
+                        &labels.theirs,
+                        &labels.ours,
+                    ),
+                };
+                if other.contains_key(target) {
+                    // もう一方も同じパスにファイルを置いたなら、名前の変更とは扱わない
+                    base.insert(source.clone(), original);
+                    continue;
+                }
+                if let Some(file) = other.remove(source) {
+                    other.insert(target.clone(), file);
+                    base.insert(target.clone(), original);
+                    let paths = if renamed_in_ours.is_some() {
+                        [source.clone(), target.clone(), source.clone()]
+                    } else {
+                        [source.clone(), source.clone(), target.clone()]
+                    };
+                    moved.insert(target.clone(), paths);
+                    continue;
+                }
+                outcome.messages.push(format!(
+                    "CONFLICT (rename/delete): {source} renamed to {target} in {renamed_by}, \
+                     but deleted in {other_label}."
+                ));
+                let side = kept.remove(target);
+                let sides = if renamed_in_ours.is_some() {
+                    [Some(&original), side.as_ref(), None]
+                } else {
+                    [Some(&original), None, side.as_ref()]
+                };
+                outcome.conflict(target, sides);
+                outcome
+                    .files
+                    .extend(side.map(|side| (target.clone(), side)));
+            }
+        }
+    }
+    moved
+}
+
+impl Outcome {
+    // ディレクトリと同じパスにあるファイルは`<path>~<side>`に移して衝突にする
+    fn move_files_in_the_way(&mut self, ours: &Files, labels: &Labels) {
+        let in_the_way: Vec<String> = self
+            .files
+            .keys()
+            .filter(|path| {
+                let dir = format!("{path}/");
+                self.files
+                    .range(dir.clone()..)
+                    .next()
+                    .is_some_and(|(next, _)| next.starts_with(&dir))
+            })
+            .cloned()
+            .collect();
+        for path in &in_the_way {
+            let (label, stage) = if ours.contains_key(path) {
+                (&labels.ours, 2)
+            } else {
+                (&labels.theirs, 3)
+            };
+            let moved = format!("{path}~{}", label.replace('/', "_"));
+            self.messages.push(format!(
+                "CONFLICT (file/directory): directory in the way of {path} from {label}; \
+                 moving it to {moved} instead."
+            ));
+            for entry in self.entries.iter_mut().filter(|e| &e.path == path) {
+                entry.path = moved.clone();
+                if entry.stage == 0 {
+                    entry.stage = stage;
+                }
+            }
+            self.conflicts.retain(|conflict| conflict != path);
+            self.conflicts.push(moved.clone());
+            if let Some(file) = self.files.remove(path) {
+                self.files.insert(moved, file);
+            }
+        }
+    }
+
+    fn take(&mut self, path: &str, side: Option<&(u32, String)>) {
+        if let Some((mode, oid)) = side {
+            self.entries.push(Entry {
+                mode: *mode,
+                oid: oid.clone(),
+                path: path.to_string(),
+                ..Entry::default()
+            });
+            self.files.insert(path.to_string(), (*mode, oid.clone()));
+        }
+    }
+
+    fn conflict(&mut self, path: &str, sides: [Option<&(u32, String)>; 3]) {
+        for (stage, side) in (1..).zip(sides) {
+            if let Some((mode, oid)) = side {
+                self.entries.push(Entry {
+                    mode: *mode,
+                    oid: oid.clone(),
+                    path: path.to_string(),
+                    stage,
+                    ..Entry::default()
+                });
+            }
+        }
+        self.conflicts.push(path.to_string());
+    }
+}
+
+// 両側が変えたファイルの中身を行単位でマージする
+#[allow(clippy::too_many_arguments)]
+fn merge_contents(
+    repo: &Repository,
+    outcome: &mut Outcome,
+    path: &str,
+    base: Option<&(u32, String)>,
+    ours: &(u32, String),
+    theirs: &(u32, String),
+    style: ConflictStyle,
+    labels: &Labels,
+) -> Result<()> {
+    outcome.messages.push(format!("Auto-merging {path}"));
+    let kind = if base.is_some() { "content" } else { "add/add" };
+    let is_file = |mode: u32| mode == BLOB_MODE || mode == EXECUTABLE_MODE;
+    let base_data = match base {
+        Some((_, oid)) if is_file(base.map_or(BLOB_MODE, |b| b.0)) => repo.find_blob(oid)?,
+        _ => Vec::new(),
+    };
+    let (ours_data, theirs_data) = if is_file(ours.0) && is_file(theirs.0) {
+        (repo.find_blob(&ours.1)?, repo.find_blob(&theirs.1)?)
+    } else {
+        (Vec::new(), Vec::new())
+    };
+    // 実行bitは変えた側に合わせる
+    let mode = match base {
+        Some((mode, _)) if *mode == ours.0 => theirs.0,
+        _ => ours.0,
+    };
+
+    if !is_file(ours.0)
+        || !is_file(theirs.0)
+        || is_binary(&base_data)
+        || is_binary(&ours_data)
+        || is_binary(&theirs_data)
+    {
+        outcome.messages.push(format!(
+            "warning: Cannot merge binary files: {path} ({} vs. {})",
+            labels.ours, labels.theirs
+        ));
+        outcome
+            .messages
+            .push(format!("CONFLICT ({kind}): Merge conflict in {path}"));
+        outcome.conflict(path, [base, Some(ours), Some(theirs)]);
+        outcome.files.insert(path.to_string(), ours.clone());
+        return Ok(());
+    }
+
+    let (data, conflict) = file(&base_data, &ours_data, &theirs_data, style, labels);
+    let oid = repo.odb().write(Kind::Blob, &data)?;
+    if conflict {
+        outcome
+            .messages
+            .push(format!("CONFLICT ({kind}): Merge conflict in {path}"));
+        outcome.conflict(path, [base, Some(ours), Some(theirs)]);
+        outcome.files.insert(path.to_string(), (mode, oid));
+    } else {
+        outcome.take(path, Some(&(mode, oid)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempRepo;
+
+    fn merge(base: &str, ours: &str, theirs: &str, style: ConflictStyle) -> (String, bool) {
+        let labels = Labels {
+            ours: "HEAD".to_string(),
+            base: "base".to_string(),
+            theirs: "side".to_string(),
+        };
+        let (data, conflict) = file(
+            base.as_bytes(),
+            ours.as_bytes(),
+            theirs.as_bytes(),
+            style,
+            &labels,
+        );
+        (String::from_utf8(data).unwrap(), conflict)
+    }
+
+    #[test]
+    fn test_file_clean() {
+        let base = "1\n2\n3\n4\n5\n";
+        assert_eq!(
+            merge(
+                base,
+                "1\nX\n3\n4\n5\n",
+                "1\n2\n3\n4\nY\n",
+                ConflictStyle::Merge
+            ),
+            ("1\nX\n3\n4\nY\n".to_string(), false)
+        );
+        assert_eq!(
+            merge(
+                base,
+                "0\n1\n2\n3\n4\n5\n",
+                "1\n2\n3\n4\n5\n6\n",
+                ConflictStyle::Merge
+            ),
+            ("0\n1\n2\n3\n4\n5\n6\n".to_string(), false)
+        );
+        assert_eq!(
+            merge(
+                base,
+                "1\nX\n3\n4\n5\n",
+                "1\nX\n3\n4\n5\n",
+                ConflictStyle::Merge
+            ),
+            ("1\nX\n3\n4\n5\n".to_string(), false)
+        );
+        assert_eq!(
+            merge("", "a\n", "", ConflictStyle::Merge),
+            ("a\n".to_string(), false)
+        );
+    }
+
+    #[test]
+    fn test_file_conflict() {
+        let base = "1\n2\n3\n";
+        let (ours, theirs) = ("1\nX\nsame\n3\n", "1\nY\nsame\n3\n");
+        assert_eq!(
+            merge(base, ours, theirs, ConflictStyle::Merge),
+            (
+                "1\n<<<<<<< HEAD\nX\n=======\nY\n>>>>>>> side\nsame\n3\n".to_string(),
+                true
+            )
+        );
+        assert_eq!(
+            merge(base, ours, theirs, ConflictStyle::Diff3),
+            (
+                "1\n<<<<<<< HEAD\nX\nsame\n||||||| base\n2\n=======\nY\nsame\n>>>>>>> side\n3\n"
+                    .to_string(),
+                true
+            )
+        );
+        assert_eq!(
+            merge(base, ours, theirs, ConflictStyle::Zdiff3),
+            (
+                "1\n<<<<<<< HEAD\nX\n||||||| base\n2\n=======\nY\n>>>>>>> side\nsame\n3\n"
+                    .to_string(),
+                true
+            )
+        );
+        // 改行のない最後の行の後にも印は行の頭に来る
+        assert_eq!(
+            merge("a", "b", "c", ConflictStyle::Merge),
+            (
+                "<<<<<<< HEAD\nb\n=======\nc\n>>>>>>> side\n".to_string(),
+                true
+            )
+        );
+    }
+
+    fn labels() -> Labels {
+        Labels {
+            ours: "HEAD".to_string(),
+            base: "base".to_string(),
+            theirs: "side".to_string(),
+        }
+    }
+
+    fn blob(test: &TempRepo, outcome: &Outcome, path: &str) -> String {
+        let (_, oid) = &outcome.files[path];
+        String::from_utf8(test.repo.find_blob(oid).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_trees_rename() {
+        let test = TempRepo::new();
+        let lines: String = (1..=30).map(|n| n.to_string() + "\n").collect();
+        let edited = lines.replace("20\n", "twenty\n");
+        let base = test.commit(&[("a", &lines)], &[]);
+        let renamed = test.commit(&[("b", &lines)], &[&base]);
+        let modified = test.commit(&[("a", &edited)], &[&base]);
+
+        // 片側の名前の変更と、もう一方の変更は新しいパスでまとまる
+        for (ours, theirs) in [(&modified, &renamed), (&renamed, &modified)] {
+            let outcome = trees(
+                &test.repo,
+                Some(&base),
+                ours,
+                theirs,
+                ConflictStyle::Merge,
+                &labels(),
+            )
+            .unwrap();
+            assert!(outcome.is_clean());
+            assert_eq!(outcome.files.keys().collect::<Vec<_>>(), ["b"]);
+            assert_eq!(blob(&test, &outcome, "b"), edited);
+        }
+
+        let deleted = test.commit(&[], &[&base]);
+        let outcome = trees(
+            &test.repo,
+            Some(&base),
+            &deleted,
+            &renamed,
+            ConflictStyle::Merge,
+            &labels(),
+        )
+        .unwrap();
+        assert_eq!(outcome.conflicts, ["b"]);
+        assert_eq!(
+            outcome.messages,
+            ["CONFLICT (rename/delete): a renamed to b in side, but deleted in HEAD."]
+        );
+
+        let other = test.commit(&[("c", &lines)], &[&base]);
+        let outcome = trees(
+            &test.repo,
+            Some(&base),
+            &other,
+            &renamed,
+            ConflictStyle::Merge,
+            &labels(),
+        )
+        .unwrap();
+        assert_eq!(outcome.conflicts, ["a", "c", "b"]);
+        assert_eq!(outcome.files.keys().collect::<Vec<_>>(), ["b", "c"]);
+    }
+
+    #[test]
+    fn test_trees_file_directory() {
+        let test = TempRepo::new();
+        let base = test.commit(&[("k", "keep\n")], &[]);
+        let file = test.commit(&[("k", "keep\n"), ("d", "f\n")], &[&base]);
+        let dir = test.commit(&[("k", "keep\n"), ("d/x", "x\n")], &[&base]);
+
+        let outcome = trees(
+            &test.repo,
+            Some(&base),
+            &file,
+            &dir,
+            ConflictStyle::Merge,
+            &labels(),
+        )
+        .unwrap();
+        assert_eq!(outcome.conflicts, ["d~HEAD"]);
+        assert_eq!(
+            outcome.files.keys().collect::<Vec<_>>(),
+            ["d/x", "d~HEAD", "k"]
+        );
+        let moved: Vec<_> = outcome
+            .entries
+            .iter()
+            .filter(|e| e.path == "d~HEAD")
+            .map(|e| e.stage)
+            .collect();
+        assert_eq!(moved, [2]);
+
+        let outcome = trees(
+            &test.repo,
+            Some(&base),
+            &dir,
+            &file,
+            ConflictStyle::Merge,
+            &labels(),
+        )
+        .unwrap();
+        assert_eq!(outcome.conflicts, ["d~side"]);
+        assert_eq!(
+            outcome.messages,
+            [
+                "CONFLICT (file/directory): directory in the way of d from side; \
+              moving it to d~side instead."
+            ]
+        );
+    }
+}
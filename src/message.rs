@@ -0,0 +1,237 @@
+//! Tidying up commit messages and adding trailers to them.
+
+/// The line below which `scissors` cleanup drops everything.
+pub const SCISSORS: &str = "# ------------------------ >8 ------------------------";
+
+/// How a commit message is cleaned up, as chosen by `--cleanup` or
+/// `commit.cleanup`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Cleanup {
+    /// `strip` when the message was edited, `whitespace` otherwise.
+    #[default]
+    Default,
+    /// Drop `#` comment lines, then clean up whitespace.
+    Strip,
+    /// Strip trailing whitespace and collapse runs of blank lines.
+    Whitespace,
+    /// Keep the message as it is.
+    Verbatim,
+    /// Like `whitespace`, but an edited message is cut at the [`SCISSORS`] line.
+    Scissors,
+}
+
+impl Cleanup {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Self::Default),
+            "strip" => Some(Self::Strip),
+            "whitespace" => Some(Self::Whitespace),
+            "verbatim" => Some(Self::Verbatim),
+            "scissors" => Some(Self::Scissors),
+            _ => None,
+        }
+    }
+
+    /// The mode actually applied to a message that was opened in an editor
+    /// (`edited`) or not; `Default` is never returned.
+    #[must_use]
+    pub fn resolve(self, edited: bool) -> Self {
+        match self {
+            Self::Default if edited => Self::Strip,
+            Self::Default => Self::Whitespace,
+            mode => mode,
+        }
+    }
+}
+
+/// Clean up `message` with `mode`. The result is empty or ends with a
+/// newline unless `mode` is `Verbatim`.
+pub fn cleanup(message: &str, mode: Cleanup, edited: bool) -> String {
+    match mode.resolve(edited) {
+        Cleanup::Verbatim => message.to_string(),
+        Cleanup::Strip => stripspace(message, true),
+        Cleanup::Scissors if edited => {
+            let kept: Vec<&str> = message
+                .lines()
+                .take_while(|line| *line != SCISSORS)
+                .collect();
+            stripspace(&kept.join("\n"), false)
+        }
+        _ => stripspace(message, false),
+    }
+}
+
+/// Remove trailing whitespace from every line, blank lines at both ends and
+/// runs of blank lines, and `#` comment lines with `strip_comments`.
+pub fn stripspace(text: &str, strip_comments: bool) -> String {
+    let mut result = String::new();
+    let mut blank = false;
+    for line in text.lines() {
+        if strip_comments && line.starts_with('#') {
+            continue;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank = true;
+            continue;
+        }
+        // 段落の間の空行は1行にまとめ、先頭の空行は捨てる
+        if blank && !result.is_empty() {
+            result.push('\n');
+        }
+        blank = false;
+        result.push_str(line);
+        result.push('\n');
+    }
+    result
+}
+
+/// Append `trailers`, each `<token>: <value>` or `<token>=<value>`, to
+/// `message`, which must already be cleaned up.
+///
+/// They join the trailer block closing the message if there is one, or
+/// start a new paragraph. A trailer equal to the one just before it is not
+/// added again.
+pub fn add_trailers(message: &str, trailers: &[String]) -> String {
+    if trailers.is_empty() {
+        return message.to_string();
+    }
+    let mut lines: Vec<String> = message.lines().map(ToString::to_string).collect();
+    if !has_trailer_block(&lines) {
+        lines.push(String::new());
+    }
+    for trailer in trailers {
+        let (token, value) = trailer
+            .split_once([':', '='])
+            .unwrap_or((trailer.as_str(), ""));
+        let trailer = format!("{}: {}", token.trim(), value.trim());
+        let same_as_last = lines
+            .last()
+            .and_then(|last| parse_trailer(last))
+            .is_some_and(|(last_token, last_value)| {
+                last_token.eq_ignore_ascii_case(token.trim()) && last_value == value.trim()
+            });
+        if !same_as_last {
+            lines.push(trailer);
+        }
+    }
+    let mut result = lines.join("\n");
+    result.push('\n');
+    result
+}
+
+/// Append the `(cherry picked from commit <oid>)` line of `cherry-pick -x`
+/// to `message`, in the trailer block closing it if there is one.
+pub fn add_cherry_picked(message: &str, oid: &str) -> String {
+    let mut lines: Vec<String> = message.lines().map(ToString::to_string).collect();
+    if !has_trailer_block(&lines) {
+        lines.push(String::new());
+    }
+    lines.push(format!("(cherry picked from commit {oid})"));
+    let mut result = lines.join("\n");
+    result.push('\n');
+    result
+}
+
+// 件名以外の最後の段落がtrailerのまとまりかどうか。gitと同じく、すべてがtrailerか、
+// gitが付けるtrailerを含んで4分の1以上がtrailerなら認める
+fn has_trailer_block(lines: &[String]) -> bool {
+    let start = lines
+        .iter()
+        .rposition(String::is_empty)
+        .map_or(lines.len(), |i| i + 1);
+    // 件名だけの段落はtrailerではない
+    if start == 0 || start == lines.len() {
+        return false;
+    }
+    let (mut trailers, mut others, mut recognized) = (0, 0, false);
+    for line in &lines[start..] {
+        if line.starts_with([' ', '\t']) {
+            // 前の行の続き
+            continue;
+        }
+        if line.starts_with("Signed-off-by: ") || line.starts_with("(cherry picked from commit ") {
+            recognized = true;
+            trailers += 1;
+        } else if parse_trailer(line).is_some() {
+            trailers += 1;
+        } else {
+            others += 1;
+        }
+    }
+    trailers > 0 && (others == 0 || (recognized && trailers * 3 >= others))
+}
+
+// "<token>: <value>"。tokenは英数字と'-'だけ
+fn parse_trailer(line: &str) -> Option<(&str, &str)> {
+    let (token, value) = line.split_once(':')?;
+    let valid = !token.is_empty()
+        && token
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-');
+    valid.then(|| (token, value.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cleanup() {
+        let message = "\n\n  subject  \n\n\n# comment\nbody\t\n\n";
+        assert_eq!(
+            cleanup(message, Cleanup::Default, true),
+            "  subject\n\nbody\n"
+        );
+        assert_eq!(
+            cleanup(message, Cleanup::Default, false),
+            "  subject\n\n# comment\nbody\n"
+        );
+        assert_eq!(cleanup(message, Cleanup::Verbatim, true), message);
+        assert_eq!(cleanup("# only\n\n", Cleanup::Strip, false), "");
+
+        let message = format!("a\n{SCISSORS}\nb\n");
+        assert_eq!(cleanup(&message, Cleanup::Scissors, true), "a\n");
+        assert_eq!(cleanup(&message, Cleanup::Scissors, false), message);
+    }
+
+    #[test]
+    fn test_add_cherry_picked() {
+        assert_eq!(
+            add_cherry_picked("subj\n", "abc"),
+            "subj\n\n(cherry picked from commit abc)\n"
+        );
+        assert_eq!(
+            add_cherry_picked("subj\n\nSigned-off-by: x <y>\n", "abc"),
+            "subj\n\nSigned-off-by: x <y>\n(cherry picked from commit abc)\n"
+        );
+    }
+
+    #[test]
+    fn test_add_trailers() {
+        let trailers = [
+            "Signed-off-by=x <y>".to_string(),
+            "Reviewed-by: r".to_string(),
+        ];
+        assert_eq!(
+            add_trailers("subj\n", &trailers),
+            "subj\n\nSigned-off-by: x <y>\nReviewed-by: r\n"
+        );
+        assert_eq!(
+            add_trailers("subj\n\nSigned-off-by: x <y>\n", &trailers),
+            "subj\n\nSigned-off-by: x <y>\nReviewed-by: r\n"
+        );
+        assert_eq!(
+            add_trailers("subj\n\nbody\nSigned-off-by: x <y>\n", &trailers),
+            "subj\n\nbody\nSigned-off-by: x <y>\nReviewed-by: r\n"
+        );
+        assert_eq!(
+            add_trailers("subj\n\nSome thing: x\nAck: y\n", &trailers[1..]),
+            "subj\n\nSome thing: x\nAck: y\n\nReviewed-by: r\n"
+        );
+        assert_eq!(
+            add_trailers("subj\n", &["foo".to_string()]),
+            "subj\n\nfoo: \n"
+        );
+    }
+}
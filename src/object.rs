@@ -1 +1,93 @@
+//! Object model: blobs, trees, commits and tags.
+
+use std::fmt;
+use std::str::FromStr;
+
+use self::commit::Commit;
+use self::tag::Tag;
+use self::tree::Tree;
+
+use crate::error::Result;
+
 pub mod commit;
+pub mod tag;
+pub mod tree;
+
+/// Type of an object, as written in its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Blob,
+    Tree,
+    Commit,
+    Tag,
+}
+
+impl Kind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Blob => "blob",
+            Self::Tree => "tree",
+            Self::Commit => "commit",
+            Self::Tag => "tag",
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blob" => Ok(Self::Blob),
+            "tree" => Ok(Self::Tree),
+            "commit" => Ok(Self::Commit),
+            "tag" => Ok(Self::Tag),
+            _ => Err(format!("invalid object type \"{s}\"")),
+        }
+    }
+}
+
+/// A parsed object of any type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Object {
+    Blob(Vec<u8>),
+    Tree(Tree),
+    Commit(Commit),
+    Tag(Tag),
+}
+
+impl Object {
+    pub fn parse(kind: Kind, data: Vec<u8>) -> Result<Self> {
+        Ok(match kind {
+            Kind::Blob => Self::Blob(data),
+            Kind::Tree => Self::Tree(Tree::parse(&data)?),
+            Kind::Commit => Self::Commit(Commit::parse(&data)?),
+            Kind::Tag => Self::Tag(Tag::parse(&data)?),
+        })
+    }
+
+    pub fn kind(&self) -> Kind {
+        match self {
+            Self::Blob(_) => Kind::Blob,
+            Self::Tree(_) => Kind::Tree,
+            Self::Commit(_) => Kind::Commit,
+            Self::Tag(_) => Kind::Tag,
+        }
+    }
+
+    /// Object content without the header.
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Self::Blob(data) => data.clone(),
+            Self::Tree(tree) => tree.encode(),
+            Self::Commit(commit) => commit.encode(),
+            Self::Tag(tag) => tag.encode(),
+        }
+    }
+}
@@ -1,21 +1,60 @@
 use std::fmt;
 
-use chrono::{self, DateTime, Local};
+use chrono::{self, DateTime, FixedOffset, Local, TimeZone};
 
+use crate::error::{Error, Result};
+
+/// A commit object.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Commit {
-    pub hash: String,
-    pub size: usize,
     pub tree: String,
     pub parents: Vec<String>,
     pub author: Sign,
-    pub commiter: Sign,
+    pub committer: Sign,
+    /// Headers other than the ones above (`encoding`, `gpgsig`, `mergetag`, ...),
+    /// kept so that re-encoding a parsed commit reproduces the same object.
+    pub extra_headers: Vec<(String, String)>,
+    /// The message, including its trailing newline.
     pub message: String,
 }
 
+/// Identity and time stamp of an author, committer or tagger.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Sign {
     pub name: String,
     pub email: String,
-    pub time_stamp: DateTime<Local>,
+    pub time_stamp: DateTime<FixedOffset>,
+}
+
+impl Sign {
+    /// A signature stamped with the current local time.
+    pub fn now(name: impl Into<String>, email: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            email: email.into(),
+            time_stamp: Local::now().fixed_offset(),
+        }
+    }
+
+    /// Parse `Name <email> 1700000000 +0900`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (name, rest) = s.split_once('<')?;
+        let (email, rest) = rest.split_once('>')?;
+        let mut fields = rest.split_whitespace();
+        let seconds: i64 = fields.next()?.parse().ok()?;
+        let timezone = fields.next().unwrap_or("+0000");
+
+        let sign = if timezone.starts_with('-') { -1 } else { 1 };
+        let digits: i32 = timezone.trim_start_matches(['+', '-']).parse().ok()?;
+        let offset = FixedOffset::east_opt(sign * ((digits / 100) * 3600 + (digits % 100) * 60))?;
+        let time_stamp = offset.timestamp_opt(seconds, 0).single()?;
+
+        Some(Self {
+            name: name.trim_end().to_string(),
+            email: email.to_string(),
+            time_stamp,
+        })
+    }
 }
 
 impl fmt::Display for Sign {
@@ -30,3 +69,78 @@ impl fmt::Display for Sign {
         write!(f, "{name} <{email}> {timestamp} {timezone}")
     }
 }
+
+impl Commit {
+    /// Parse the content of a commit object (without the object header).
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let text = String::from_utf8_lossy(data);
+        let corrupt = |what: &str| Error::CorruptObject(format!("commit: {what}"));
+
+        let (headers, message) = text.split_once("\n\n").unwrap_or((&text, ""));
+        let mut tree = None;
+        let mut parents = Vec::new();
+        let mut author = None;
+        let mut committer = None;
+        let mut extra_headers: Vec<(String, String)> = Vec::new();
+
+        for line in headers.lines() {
+            // 空白で始まる行は直前のheaderの続き(gpgsigなど)
+            if let Some(continuation) = line.strip_prefix(' ') {
+                let (_, value) = extra_headers
+                    .last_mut()
+                    .ok_or_else(|| corrupt("unexpected continuation line"))?;
+                value.push('\n');
+                value.push_str(continuation);
+                continue;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "tree" => tree = Some(value.to_string()),
+                "parent" => parents.push(value.to_string()),
+                "author" => author = Some(Sign::parse(value).ok_or_else(|| corrupt("bad author"))?),
+                "committer" => {
+                    committer = Some(Sign::parse(value).ok_or_else(|| corrupt("bad committer"))?);
+                }
+                _ => extra_headers.push((key.to_string(), value.to_string())),
+            }
+        }
+
+        Ok(Self {
+            tree: tree.ok_or_else(|| corrupt("missing tree"))?,
+            parents,
+            author: author.ok_or_else(|| corrupt("missing author"))?,
+            committer: committer.ok_or_else(|| corrupt("missing committer"))?,
+            extra_headers,
+            message: message.to_string(),
+        })
+    }
+
+    /// Encode the commit as object content (without the object header).
+    pub fn encode(&self) -> Vec<u8> {
+        let Self {
+            tree,
+            parents,
+            author,
+            committer,
+            extra_headers,
+            message,
+        } = self;
+
+        let mut content = format!("tree {tree}\n");
+        for parent in parents {
+            content.push_str(&format!("parent {parent}\n"));
+        }
+        content.push_str(&format!("author {author}\ncommitter {committer}\n"));
+        for (key, value) in extra_headers {
+            content.push_str(&format!("{key} {}\n", value.replace('\n', "\n ")));
+        }
+        content.push('\n');
+        content.push_str(message);
+        content.into_bytes()
+    }
+
+    /// The first line of the message.
+    pub fn summary(&self) -> &str {
+        self.message.lines().next().unwrap_or_default()
+    }
+}
@@ -0,0 +1,64 @@
+use crate::error::{Error, Result};
+use crate::object::commit::Sign;
+use crate::object::Kind;
+
+/// An annotated tag object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub object: String,
+    pub kind: Kind,
+    pub name: String,
+    pub tagger: Option<Sign>,
+    /// The annotation, including its trailing newline and any signature.
+    pub message: String,
+}
+
+impl Tag {
+    /// Parse the content of a tag object (without the object header).
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let text = String::from_utf8_lossy(data);
+        let corrupt = |what: &str| Error::CorruptObject(format!("tag: {what}"));
+
+        let (headers, message) = text.split_once("\n\n").unwrap_or((&text, ""));
+        let mut object = None;
+        let mut kind = None;
+        let mut name = None;
+        let mut tagger = None;
+        for line in headers.lines() {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "object" => object = Some(value.to_string()),
+                "type" => kind = Some(value.parse().map_err(|_| corrupt("bad type"))?),
+                "tag" => name = Some(value.to_string()),
+                "tagger" => tagger = Some(Sign::parse(value).ok_or_else(|| corrupt("bad tagger"))?),
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            object: object.ok_or_else(|| corrupt("missing object"))?,
+            kind: kind.ok_or_else(|| corrupt("missing type"))?,
+            name: name.ok_or_else(|| corrupt("missing tag name"))?,
+            tagger,
+            message: message.to_string(),
+        })
+    }
+
+    /// Encode the tag as object content (without the object header).
+    pub fn encode(&self) -> Vec<u8> {
+        let Self {
+            object,
+            kind,
+            name,
+            tagger,
+            message,
+        } = self;
+        let mut content = format!("object {object}\ntype {kind}\ntag {name}\n");
+        if let Some(tagger) = tagger {
+            content.push_str(&format!("tagger {tagger}\n"));
+        }
+        content.push('\n');
+        content.push_str(message);
+        content.into_bytes()
+    }
+}
@@ -0,0 +1,221 @@
+use std::cmp::Ordering;
+
+use crate::error::{Error, Result};
+use crate::object::Kind;
+use crate::odb::ObjectDatabase;
+
+/// Mode of a subtree entry.
+pub const TREE_MODE: u32 = 0o04_0000;
+/// Mode of a regular file.
+pub const BLOB_MODE: u32 = 0o10_0644;
+/// Mode of an executable file.
+pub const EXECUTABLE_MODE: u32 = 0o10_0755;
+/// Mode of a symbolic link, whose blob holds the link target.
+pub const SYMLINK_MODE: u32 = 0o12_0000;
+/// Mode of a submodule, whose oid is a commit of another repository.
+pub const GITLINK_MODE: u32 = 0o16_0000;
+
+/// Turn a raw `st_mode` into one of the modes git records for files.
+///
+/// Permission bits other than the owner's execute bit are dropped, and a
+/// directory stands for an embedded repository.
+pub fn normalize_mode(st_mode: u32) -> u32 {
+    match st_mode & 0o17_0000 {
+        0o12_0000 => SYMLINK_MODE,
+        0o04_0000 | 0o16_0000 => GITLINK_MODE,
+        _ if st_mode & 0o100 != 0 => EXECUTABLE_MODE,
+        _ => BLOB_MODE,
+    }
+}
+
+/// A single `<mode> <name>\0<oid>` record of a tree object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub mode: u32,
+    pub name: String,
+    pub oid: String,
+}
+
+impl Entry {
+    pub fn is_tree(&self) -> bool {
+        self.mode == TREE_MODE
+    }
+}
+
+/// A tree object.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tree {
+    pub entries: Vec<Entry>,
+}
+
+impl Tree {
+    /// Parse the content of a tree object (without the object header).
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let corrupt = || Error::CorruptObject("tree: bad entry".to_string());
+        let mut entries = Vec::new();
+        let mut rest = data;
+        while !rest.is_empty() {
+            let space = rest.iter().position(|&b| b == b' ').ok_or_else(corrupt)?;
+            let mode = std::str::from_utf8(&rest[..space]).map_err(|_| corrupt())?;
+            let mode = u32::from_str_radix(mode, 8).map_err(|_| corrupt())?;
+            rest = &rest[space + 1..];
+
+            let nul = rest.iter().position(|&b| b == 0).ok_or_else(corrupt)?;
+            let name = String::from_utf8_lossy(&rest[..nul]).into_owned();
+            rest = &rest[nul + 1..];
+
+            if rest.len() < 20 {
+                return Err(corrupt());
+            }
+            let oid = hex::encode(&rest[..20]);
+            rest = &rest[20..];
+
+            entries.push(Entry { mode, name, oid });
+        }
+        Ok(Self { entries })
+    }
+
+    /// Encode the tree as object content, sorting entries the way git does.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        entries.sort_by(|a, b| compare_entry_names(a, b));
+
+        let mut contents = Vec::new();
+        for entry in entries {
+            contents.extend(format!("{:o} {}\0", entry.mode, entry.name).as_bytes());
+            contents.extend(hex::decode(&entry.oid).unwrap_or_default());
+        }
+        contents
+    }
+}
+
+// treeはnameの末尾に"/"があるものとして比較する
+fn compare_entry_names(a: &Entry, b: &Entry) -> Ordering {
+    let a_name = a.name.bytes().chain(a.is_tree().then_some(b'/'));
+    let b_name = b.name.bytes().chain(b.is_tree().then_some(b'/'));
+    a_name.cmp(b_name)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NodeType {
+    Blob,
+    Tree,
+}
+
+/// In-memory tree used to build tree objects from flat paths, such as index
+/// entries, one directory level per node.
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub r#type: NodeType,
+    pub mode: u32,
+    pub name: String,
+    pub hash: String,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    /// An empty root directory.
+    pub fn root() -> Self {
+        Self {
+            r#type: NodeType::Tree,
+            mode: 0,
+            name: "root".to_string(),
+            hash: String::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Insert a blob at the slash-separated `file_path`, creating directories as needed.
+    pub fn insert(&mut self, file_path: &str, mode: u32, hash: String) {
+        let path: Vec<_> = file_path.split('/').collect();
+        travel_tree(self, &path, mode, hash);
+    }
+
+    /// Write tree objects for this node and all of its subdirectories,
+    /// filling in their `hash`, and return the hash of this node.
+    pub fn write(&mut self, odb: &ObjectDatabase) -> Result<String> {
+        // childrenを左から探索していく深さ優先探索
+        let mut tree = Tree::default();
+        for child in &mut self.children {
+            if child.r#type == NodeType::Tree {
+                child.write(odb)?;
+            }
+            tree.entries.push(Entry {
+                mode: child.mode,
+                name: child.name.clone(),
+                oid: child.hash.clone(),
+            });
+        }
+        self.hash = odb.write(Kind::Tree, &tree.encode())?;
+        Ok(self.hash.clone())
+    }
+}
+
+impl From<Entry> for Node {
+    /// A node for one entry of a tree object; the children of a subtree are
+    /// not read.
+    fn from(entry: Entry) -> Self {
+        let r#type = if entry.is_tree() {
+            NodeType::Tree
+        } else {
+            NodeType::Blob
+        };
+        Self {
+            r#type,
+            mode: entry.mode,
+            name: entry.name,
+            hash: entry.oid,
+            children: Vec::new(),
+        }
+    }
+}
+
+fn travel_tree(node: &mut Node, path: &[&str], children_mode: u32, hash: String) {
+    if path.len() == 1 {
+        let new_node = Node {
+            r#type: NodeType::Blob,
+            mode: children_mode,
+            name: path[0].to_string(),
+            hash,
+            children: Vec::new(),
+        };
+        node.children.push(new_node);
+        return;
+    }
+
+    let Some((first, rest)) = path.split_first() else {
+        return;
+    };
+
+    if let Some(child_node) = node.children.iter_mut().find(|child| child.name == *first) {
+        // childrenにディレクトリがある場合はそのまま移動
+        travel_tree(child_node, rest, children_mode, hash);
+    } else {
+        // ない場合は作成して追加して移動
+        let mut new_node = Node {
+            r#type: NodeType::Tree,
+            mode: TREE_MODE,
+            name: (*first).to_string(),
+            hash: String::new(),
+            children: Vec::new(),
+        };
+        travel_tree(&mut new_node, rest, children_mode, hash);
+        node.children.push(new_node);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_mode() {
+        assert_eq!(normalize_mode(0o100_644), BLOB_MODE);
+        assert_eq!(normalize_mode(0o100_664), BLOB_MODE);
+        assert_eq!(normalize_mode(0o100_755), EXECUTABLE_MODE);
+        assert_eq!(normalize_mode(0o100_744), EXECUTABLE_MODE);
+        assert_eq!(normalize_mode(0o120_777), SYMLINK_MODE);
+        assert_eq!(normalize_mode(0o040_755), GITLINK_MODE);
+        assert_eq!(normalize_mode(0o160_000), GITLINK_MODE);
+    }
+}
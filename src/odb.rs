@@ -0,0 +1,181 @@
+//! Object storage: loose objects and packfiles.
+
+mod pack;
+
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::error::{Error, Result};
+use crate::object::Kind;
+use crate::util;
+
+use pack::{Pack, Packed};
+
+/// Object storage under `<git_dir>/objects`. Objects are written loose and
+/// read either loose or from the packs in `objects/pack`.
+#[derive(Debug, Clone)]
+pub struct ObjectDatabase {
+    path: PathBuf,
+    // packのindexは最初に必要になったときに読む
+    packs: OnceLock<Vec<Pack>>,
+}
+
+impl ObjectDatabase {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            packs: OnceLock::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn object_path(&self, hash: &str) -> PathBuf {
+        self.path.join(&hash[0..2]).join(&hash[2..])
+    }
+
+    fn packs(&self) -> &[Pack] {
+        self.packs.get_or_init(|| {
+            let Ok(dir) = fs::read_dir(self.path.join("pack")) else {
+                return Vec::new();
+            };
+            let mut paths: Vec<PathBuf> = dir
+                .filter_map(|entry| Some(entry.ok()?.path()))
+                .filter(|path| path.extension().is_some_and(|e| e == "idx"))
+                .collect();
+            paths.sort();
+            // gitと同じく、読めないpackは無視する
+            paths
+                .iter()
+                .filter(|path| path.with_extension("pack").is_file())
+                .filter_map(|path| Pack::open(path).ok().flatten())
+                .collect()
+        })
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        is_full_hash(hash)
+            && (self.object_path(hash).is_file()
+                || self.packs().iter().any(|pack| pack.find(hash).is_some()))
+    }
+
+    /// Hash `content` as an object of `kind` without storing it.
+    pub fn hash(kind: Kind, content: &[u8]) -> String {
+        let header = format!("{kind} {}\0", content.len());
+        util::compress::hash(&[header.as_bytes(), content].concat())
+    }
+
+    /// Store `content` as an object of `kind` and return its hash.
+    pub fn write(&self, kind: Kind, content: &[u8]) -> Result<String> {
+        let header = format!("{kind} {}\0", content.len());
+        let full_contents = [header.as_bytes(), content].concat();
+        let hash = util::compress::hash(&full_contents);
+
+        // 同じ内容のobjectは既に存在するので書き込まない
+        if self.contains(&hash) {
+            return Ok(hash);
+        }
+        let file_path = self.object_path(&hash);
+
+        // 書き込み途中のobjectが見えないように一時ファイルからrenameする
+        let compressed_contents = util::compress::with_zlib(&full_contents)?;
+        let temp_path = file_path.with_extension("tmp");
+        let mut file = util::path::create_nested_file(&temp_path)?;
+        file.write_all(&compressed_contents)?;
+        fs::rename(temp_path, file_path)?;
+        Ok(hash)
+    }
+
+    /// Read an object, returning its type and content without the header.
+    pub fn read(&self, hash: &str) -> Result<(Kind, Vec<u8>)> {
+        if !is_full_hash(hash) {
+            return Err(Error::InvalidObjectName(hash.to_string()));
+        }
+        let compressed = match fs::read(self.object_path(hash)) {
+            Ok(compressed) => compressed,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                for pack in self.packs() {
+                    if let Some(offset) = pack.find(hash) {
+                        return self.read_packed(hash, pack, offset);
+                    }
+                }
+                return Err(Error::ObjectNotFound(hash.to_string()));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let corrupt = || Error::CorruptObject(hash.to_string());
+        let data = util::compress::from_zlib(&compressed).map_err(|_| corrupt())?;
+
+        let header_end = data.iter().position(|&b| b == 0).ok_or_else(corrupt)?;
+        let header = std::str::from_utf8(&data[..header_end]).map_err(|_| corrupt())?;
+        let (kind, size) = header.split_once(' ').ok_or_else(corrupt)?;
+        let kind: Kind = kind.parse().map_err(|_| corrupt())?;
+        let content = data[header_end + 1..].to_vec();
+        if size.parse::<usize>().ok() != Some(content.len()) {
+            return Err(corrupt());
+        }
+        Ok((kind, content))
+    }
+
+    // packの中のobjectを、差分をたどって組み立てる
+    fn read_packed(&self, hash: &str, pack: &Pack, offset: u64) -> Result<(Kind, Vec<u8>)> {
+        let mut deltas = Vec::new();
+        let mut offset = offset;
+        let (kind, mut data) = loop {
+            match pack.read(offset)? {
+                Packed::Whole(kind, data) => break (kind, data),
+                Packed::OffsetDelta(base, delta) => {
+                    deltas.push(delta);
+                    offset = base;
+                }
+                Packed::RefDelta(base, delta) => {
+                    deltas.push(delta);
+                    break self.read(&base)?;
+                }
+            }
+        };
+        for delta in deltas.iter().rev() {
+            data = pack::apply_delta(&data, delta)
+                .ok_or_else(|| Error::CorruptObject(hash.to_string()))?;
+        }
+        Ok((kind, data))
+    }
+
+    /// Every stored object whose hash starts with the hex `prefix`.
+    pub fn find_by_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        let prefix = prefix.to_ascii_lowercase();
+        if prefix.len() < 2 || !prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Ok(Vec::new());
+        }
+        let mut found: Vec<String> = self
+            .packs()
+            .iter()
+            .flat_map(|pack| pack.find_by_prefix(&prefix))
+            .collect();
+        match fs::read_dir(self.path.join(&prefix[..2])) {
+            Ok(dir) => {
+                for entry in dir {
+                    let name = entry?.file_name();
+                    let hash = format!("{}{}", &prefix[..2], name.to_string_lossy());
+                    if is_full_hash(&hash) && hash.starts_with(&prefix) {
+                        found.push(hash);
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        found.sort();
+        found.dedup();
+        Ok(found)
+    }
+}
+
+/// Whether `hash` is a 40-digit lowercase hex object name.
+pub fn is_full_hash(hash: &str) -> bool {
+    hash.len() == 40 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
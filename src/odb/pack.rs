@@ -0,0 +1,411 @@
+//! Packfiles: `objects/pack/*.pack` with their `.idx`, versions 1 and 2.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use byteorder::{BigEndian, ByteOrder};
+use flate2::bufread::ZlibDecoder;
+
+use crate::error::{Error, Result};
+use crate::object::Kind;
+
+const INDEX_MAGIC: &[u8] = b"\xfftOc";
+const FANOUT_SIZE: usize = 256 * 4;
+
+const OFS_DELTA: u8 = 6;
+const REF_DELTA: u8 = 7;
+
+/// One packfile, looked up through its index.
+#[derive(Clone)]
+pub struct Pack {
+    path: PathBuf,
+    index: Vec<u8>,
+    version: u32,
+    count: usize,
+}
+
+/// An object stored in a pack, either whole or as a delta.
+pub enum Packed {
+    Whole(Kind, Vec<u8>),
+    /// A delta against the object at an earlier offset of the same pack.
+    OffsetDelta(u64, Vec<u8>),
+    /// A delta against the object with this hash.
+    RefDelta(String, Vec<u8>),
+}
+
+impl fmt::Debug for Pack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pack")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Pack {
+    /// Open the pack of the index at `index_path`, or `None` if the index
+    /// is not one git would use.
+    pub fn open(index_path: &Path) -> Result<Option<Self>> {
+        let index = fs::read(index_path)?;
+        let (version, fanout) = if index.starts_with(INDEX_MAGIC) {
+            if index.len() < 8 || BigEndian::read_u32(&index[4..8]) != 2 {
+                return Ok(None);
+            }
+            (2, 8)
+        } else {
+            (1, 0)
+        };
+        if index.len() < fanout + FANOUT_SIZE {
+            return Ok(None);
+        }
+        let count = BigEndian::read_u32(&index[fanout + FANOUT_SIZE - 4..]) as usize;
+        // 各entryの大きさと、末尾にあるpackとindexのchecksum
+        let size = if version == 2 {
+            fanout + FANOUT_SIZE + count * 28 + 40
+        } else {
+            FANOUT_SIZE + count * 24 + 40
+        };
+        if index.len() < size {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            path: index_path.with_extension("pack"),
+            index,
+            version,
+            count,
+        }))
+    }
+
+    fn fanout(&self, byte: u8) -> usize {
+        let start = if self.version == 2 { 8 } else { 0 };
+        let at = start + usize::from(byte) * 4;
+        BigEndian::read_u32(&self.index[at..at + 4]) as usize
+    }
+
+    fn hash_at(&self, n: usize) -> &[u8] {
+        let at = if self.version == 2 {
+            8 + FANOUT_SIZE + n * 20
+        } else {
+            FANOUT_SIZE + n * 24 + 4
+        };
+        &self.index[at..at + 20]
+    }
+
+    fn offset_at(&self, n: usize) -> u64 {
+        if self.version == 1 {
+            let at = FANOUT_SIZE + n * 24;
+            return u64::from(BigEndian::read_u32(&self.index[at..]));
+        }
+        let offsets = 8 + FANOUT_SIZE + self.count * 24;
+        let offset = BigEndian::read_u32(&self.index[offsets + n * 4..]);
+        if offset & 0x8000_0000 == 0 {
+            return u64::from(offset);
+        }
+        // 2GiBを超える位置は8バイトの表に入っている
+        let at = offsets + self.count * 4 + (offset & 0x7fff_ffff) as usize * 8;
+        self.index
+            .get(at..at + 8)
+            .map_or(u64::MAX, BigEndian::read_u64)
+    }
+
+    // 先頭のバイトがbyteであるhashの番号の範囲
+    fn bucket(&self, byte: u8) -> std::ops::Range<usize> {
+        let start = if byte == 0 { 0 } else { self.fanout(byte - 1) };
+        start..self.fanout(byte).max(start).min(self.count)
+    }
+
+    /// The offset in the pack of the object `hash`.
+    pub fn find(&self, hash: &str) -> Option<u64> {
+        let hash = hex::decode(hash).ok()?;
+        let bucket = self.bucket(hash[0]);
+        let (mut low, mut high) = (bucket.start, bucket.end);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            match self.hash_at(middle).cmp(&hash) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return Some(self.offset_at(middle)),
+            }
+        }
+        None
+    }
+
+    /// Every hash in the pack starting with the lowercase hex `prefix`,
+    /// which has at least two digits.
+    pub fn find_by_prefix(&self, prefix: &str) -> Vec<String> {
+        let Ok(first) = u8::from_str_radix(&prefix[..2], 16) else {
+            return Vec::new();
+        };
+        self.bucket(first)
+            .map(|n| hex::encode(self.hash_at(n)))
+            .filter(|hash| hash.starts_with(prefix))
+            .collect()
+    }
+
+    /// Read the entry at `offset`, leaving deltas unresolved.
+    pub fn read(&self, offset: u64) -> Result<Packed> {
+        let corrupt = || Error::CorruptObject(format!("at {offset} in {}", self.path.display()));
+        let file = File::open(&self.path).map_err(Error::io(&self.path))?;
+        let mut reader = BufReader::new(file);
+        reader.seek(SeekFrom::Start(offset))?;
+
+        // 種類と展開後の大きさ。大きさは下位から7ビットずつ続く
+        let mut c = read_byte(&mut reader).ok_or_else(corrupt)?;
+        let kind = (c >> 4) & 7;
+        let mut size = u64::from(c & 0x0f);
+        let mut shift = 4;
+        while c & 0x80 != 0 {
+            c = read_byte(&mut reader).ok_or_else(corrupt)?;
+            if shift > 57 {
+                return Err(corrupt());
+            }
+            size |= u64::from(c & 0x7f) << shift;
+            shift += 7;
+        }
+        let base = match kind {
+            OFS_DELTA => {
+                // 上位から7ビットずつ。続くたびに1を足して表せる範囲を詰めている
+                let mut c = read_byte(&mut reader).ok_or_else(corrupt)?;
+                let mut distance = u64::from(c & 0x7f);
+                while c & 0x80 != 0 {
+                    c = read_byte(&mut reader).ok_or_else(corrupt)?;
+                    distance = distance
+                        .checked_add(1)
+                        .and_then(|d| d.checked_mul(128))
+                        .ok_or_else(corrupt)?
+                        | u64::from(c & 0x7f);
+                }
+                if distance == 0 {
+                    return Err(corrupt());
+                }
+                Some(offset.checked_sub(distance).ok_or_else(corrupt)?)
+            }
+            REF_DELTA => {
+                let mut hash = [0; 20];
+                for b in &mut hash {
+                    *b = read_byte(&mut reader).ok_or_else(corrupt)?;
+                }
+                return Ok(Packed::RefDelta(
+                    hex::encode(hash),
+                    inflate(&mut reader, size).ok_or_else(corrupt)?,
+                ));
+            }
+            _ => None,
+        };
+        let data = inflate(&mut reader, size).ok_or_else(corrupt)?;
+        Ok(match (base, kind) {
+            (Some(base), _) => Packed::OffsetDelta(base, data),
+            (None, 1) => Packed::Whole(Kind::Commit, data),
+            (None, 2) => Packed::Whole(Kind::Tree, data),
+            (None, 3) => Packed::Whole(Kind::Blob, data),
+            (None, 4) => Packed::Whole(Kind::Tag, data),
+            _ => return Err(corrupt()),
+        })
+    }
+}
+
+fn read_byte(reader: &mut impl Read) -> Option<u8> {
+    let mut buffer = [0];
+    reader.read_exact(&mut buffer).ok()?;
+    Some(buffer[0])
+}
+
+// zlibで圧縮されたsizeバイトを展開する
+fn inflate(reader: &mut impl std::io::BufRead, size: u64) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    ZlibDecoder::new(reader)
+        .take(size + 1)
+        .read_to_end(&mut data)
+        .ok()?;
+    (data.len() as u64 == size).then_some(data)
+}
+
+/// Rebuild an object from its `base` and a `delta` of copy and insert
+/// instructions, or `None` if the delta does not fit the base.
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Option<Vec<u8>> {
+    let mut bytes = delta.iter().copied();
+    if varint(&mut bytes)? != base.len() {
+        return None;
+    }
+    let size = varint(&mut bytes)?;
+    let mut result = Vec::with_capacity(size);
+    while let Some(c) = bytes.next() {
+        if c & 0x80 != 0 {
+            // 下位4ビットは位置の、続く3ビットは大きさの、どのバイトが続くか
+            let mut offset = 0usize;
+            let mut length = 0usize;
+            for bit in 0..4 {
+                if c & (1 << bit) != 0 {
+                    offset |= usize::from(bytes.next()?) << (bit * 8);
+                }
+            }
+            for bit in 0..3 {
+                if c & (0x10 << bit) != 0 {
+                    length |= usize::from(bytes.next()?) << (bit * 8);
+                }
+            }
+            if length == 0 {
+                length = 0x10000;
+            }
+            result.extend_from_slice(base.get(offset..offset.checked_add(length)?)?);
+        } else if c != 0 {
+            for _ in 0..c {
+                result.push(bytes.next()?);
+            }
+        } else {
+            return None;
+        }
+    }
+    (result.len() == size).then_some(result)
+}
+
+// 下位から7ビットずつ、最上位ビットが立っていれば続く数
+fn varint(bytes: &mut impl Iterator<Item = u8>) -> Option<usize> {
+    let mut value = 0usize;
+    let mut shift = 0;
+    loop {
+        let c = bytes.next()?;
+        if shift >= usize::BITS {
+            return None;
+        }
+        value |= usize::from(c & 0x7f) << shift;
+        shift += 7;
+        if c & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+}
+
+// テストで作るpackは小さいので、大きさを切り詰めることはない
+#[cfg(test)]
+#[allow(clippy::cast_possible_truncation)]
+mod tests {
+    use sha1::{Digest, Sha1};
+
+    use super::*;
+    use crate::odb::ObjectDatabase;
+    use crate::testing::TempRepo;
+    use crate::util::compress::with_zlib;
+
+    fn header(kind: u8, size: usize) -> Vec<u8> {
+        let mut bytes = vec![(kind << 4) | (size & 0x0f) as u8];
+        let mut rest = size >> 4;
+        while rest > 0 {
+            *bytes.last_mut().unwrap() |= 0x80;
+            bytes.push((rest & 0x7f) as u8);
+            rest >>= 7;
+        }
+        bytes
+    }
+
+    // objectsのpack/にpackとversion 2のindexを書く
+    fn write_pack(objects: &Path, entries: &[(&str, Vec<u8>)]) {
+        let mut pack = b"PACK\0\0\0\x02".to_vec();
+        pack.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+        let mut offsets = Vec::new();
+        for (hash, entry) in entries {
+            offsets.push((hex::decode(hash).unwrap(), pack.len() as u32));
+            pack.extend_from_slice(entry);
+        }
+        let checksum = Sha1::digest(&pack);
+        pack.extend_from_slice(&checksum);
+
+        offsets.sort();
+        let mut index = INDEX_MAGIC.to_vec();
+        index.extend_from_slice(&2u32.to_be_bytes());
+        for byte in 0..=255 {
+            let count = offsets.iter().filter(|(hash, _)| hash[0] <= byte).count();
+            index.extend_from_slice(&(count as u32).to_be_bytes());
+        }
+        for (hash, _) in &offsets {
+            index.extend_from_slice(hash);
+        }
+        index.extend(std::iter::repeat(0).take(offsets.len() * 4));
+        for (_, offset) in &offsets {
+            index.extend_from_slice(&offset.to_be_bytes());
+        }
+        index.extend_from_slice(&checksum);
+        index.extend_from_slice(&Sha1::digest(&index));
+        fs::create_dir_all(objects.join("pack")).unwrap();
+        fs::write(objects.join("pack/pack-test.pack"), pack).unwrap();
+        fs::write(objects.join("pack/pack-test.idx"), index).unwrap();
+    }
+
+    #[test]
+    fn test_apply_delta() {
+        let base = b"hello world\n";
+        // "world"を写し、", hello\n"を足す
+        let mut delta = vec![12, 13, 0x91, 6, 5, 8];
+        delta.extend_from_slice(b", hello\n");
+        assert_eq!(
+            apply_delta(base, &delta).as_deref(),
+            Some(&b"world, hello\n"[..])
+        );
+        // 元の大きさや写す範囲が合わないもの
+        assert_eq!(apply_delta(b"hello", &delta), None);
+        assert_eq!(apply_delta(base, &[12, 5, 0x91, 10, 5]), None);
+        assert_eq!(apply_delta(base, &[12, 1, 0]), None);
+    }
+
+    #[test]
+    fn test_read_packed() {
+        let test = TempRepo::new();
+        let objects = test.repo.git_dir().join("objects");
+        let base = b"hello world\n";
+        let mut delta = vec![12, 13, 0x91, 6, 5, 8];
+        delta.extend_from_slice(b", hello\n");
+        let mut again = vec![13, 18, 0x90, 13, 5];
+        again.extend_from_slice(b"again");
+        let hashes = [
+            &b"hello world\n"[..],
+            b"world, hello\n",
+            b"world, hello\nagain",
+        ]
+        .map(|content| ObjectDatabase::hash(Kind::Blob, content));
+
+        let whole = [header(3, base.len()), with_zlib(base).unwrap()].concat();
+        // 2番目は1番目からの位置で、3番目は2番目のhashで差分の元を指す
+        let offset_delta = [
+            header(OFS_DELTA, delta.len()),
+            vec![whole.len() as u8],
+            with_zlib(&delta).unwrap(),
+        ]
+        .concat();
+        let ref_delta = [
+            header(REF_DELTA, again.len()),
+            hex::decode(&hashes[1]).unwrap(),
+            with_zlib(&again).unwrap(),
+        ]
+        .concat();
+        write_pack(
+            &objects,
+            &[
+                (&hashes[0], whole),
+                (&hashes[1], offset_delta),
+                (&hashes[2], ref_delta),
+            ],
+        );
+
+        let odb = ObjectDatabase::new(&objects);
+        for (hash, content) in
+            hashes
+                .iter()
+                .zip(["hello world\n", "world, hello\n", "world, hello\nagain"])
+        {
+            assert!(odb.contains(hash));
+            assert_eq!(
+                odb.read(hash).unwrap(),
+                (Kind::Blob, content.as_bytes().to_vec())
+            );
+            assert_eq!(odb.find_by_prefix(&hash[..6]).unwrap(), [hash.clone()]);
+        }
+        // packにあるobjectはloose objectとして書かない
+        odb.write(Kind::Blob, base).unwrap();
+        assert!(!odb.object_path(&hashes[0]).exists());
+        assert!(matches!(
+            odb.read(&"0".repeat(40)),
+            Err(Error::ObjectNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,216 @@
+//! Pathspecs: the path arguments of commands, with git's pattern magic.
+
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::util::path::normalize;
+use crate::util::wildmatch::wildmatch;
+
+/// One parsed pathspec argument.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    /// The argument as given on the command line.
+    pub original: String,
+    /// Pattern relative to the top of the working tree.
+    pub pattern: String,
+    /// `:(exclude)` / `:!`: remove matches of the other items.
+    pub exclude: bool,
+    /// `:(glob)`: `*` does not match `/`, `**` matches directories.
+    pub glob: bool,
+    /// `:(literal)`: no wildcards at all.
+    pub literal: bool,
+    /// `:(icase)`: case-insensitive matching.
+    pub icase: bool,
+}
+
+impl Item {
+    fn parse(arg: &str, prefix: &str) -> Result<Self> {
+        let invalid = |what: &str| Error::InvalidPathspec(format!("{what} in '{arg}'"));
+        let mut item = Self {
+            original: arg.to_string(),
+            pattern: String::new(),
+            exclude: false,
+            glob: false,
+            literal: false,
+            icase: false,
+        };
+        let mut top = false;
+
+        let pattern = if let Some(rest) = arg.strip_prefix(":(") {
+            let (magic, pattern) = rest
+                .split_once(')')
+                .ok_or_else(|| invalid("Missing ')' at the end of pathspec magic"))?;
+            for word in magic.split(',').filter(|w| !w.is_empty()) {
+                match word {
+                    "top" => top = true,
+                    "exclude" => item.exclude = true,
+                    "glob" => item.glob = true,
+                    "literal" => item.literal = true,
+                    "icase" => item.icase = true,
+                    _ => return Err(invalid(&format!("Invalid pathspec magic '{word}'"))),
+                }
+            }
+            pattern
+        } else if let Some(rest) = arg.strip_prefix(':') {
+            // 短い形式: ":/", ":!", ":^"を組み合わせ、":"で終わることもある
+            let end = rest
+                .find(|c| !matches!(c, '/' | '!' | '^'))
+                .unwrap_or(rest.len());
+            for c in rest[..end].chars() {
+                match c {
+                    '/' => top = true,
+                    _ => item.exclude = true,
+                }
+            }
+            let pattern = &rest[end..];
+            pattern.strip_prefix(':').unwrap_or(pattern)
+        } else {
+            arg
+        };
+        if item.glob && item.literal {
+            return Err(invalid("'literal' and 'glob' are incompatible"));
+        }
+
+        let base = if top { "" } else { prefix };
+        let joined = normalize(Path::new(base).join(pattern));
+        let joined = joined.to_string_lossy();
+        if joined == ".." || joined.starts_with("../") {
+            return Err(Error::InvalidPathspec(format!(
+                "{arg}: '{arg}' is outside repository"
+            )));
+        }
+        // "dir/"のような末尾の"/"はディレクトリそのものを表す
+        item.pattern = match joined.trim_end_matches('/') {
+            "." => String::new(),
+            pattern => pattern.to_string(),
+        };
+        Ok(item)
+    }
+
+    fn has_wildcard(&self) -> bool {
+        self.wildcard_start().is_some()
+    }
+
+    /// Where the first wildcard of the pattern is, if it has any.
+    pub fn wildcard_start(&self) -> Option<usize> {
+        if self.literal {
+            return None;
+        }
+        self.pattern.find(['*', '?', '[', '\\'])
+    }
+
+    /// Whether `path` (relative to the top, slash-separated) is matched,
+    /// either directly or because a leading directory of it is.
+    pub fn matches(&self, path: &str) -> bool {
+        let (pattern, path) = if self.icase {
+            (self.pattern.to_lowercase(), path.to_lowercase())
+        } else {
+            (self.pattern.clone(), path.to_string())
+        };
+        if pattern.is_empty()
+            || path == pattern
+            || path
+                .strip_prefix(pattern.as_str())
+                .is_some_and(|rest| rest.starts_with('/'))
+        {
+            return true;
+        }
+        if !self.has_wildcard() {
+            return false;
+        }
+        if wildmatch(&pattern, &path, self.glob) {
+            return true;
+        }
+        path.match_indices('/')
+            .any(|(i, _)| wildmatch(&pattern, &path[..i], self.glob))
+    }
+}
+
+/// A list of pathspec items; an empty list matches every path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pathspec {
+    pub items: Vec<Item>,
+}
+
+impl Pathspec {
+    /// Parse command line `args`, given relative to the directory `prefix`
+    /// (itself relative to the top of the working tree).
+    pub fn parse<S: AsRef<str>>(args: &[S], prefix: &Path) -> Result<Self> {
+        let prefix = prefix.to_string_lossy();
+        let items = args
+            .iter()
+            .map(|arg| Item::parse(arg.as_ref(), &prefix))
+            .collect::<Result<_>>()?;
+        Ok(Self { items })
+    }
+
+    /// Whether no positive item was given, so every path matches.
+    pub fn is_empty(&self) -> bool {
+        self.items.iter().all(|item| item.exclude)
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        if self
+            .items
+            .iter()
+            .any(|item| item.exclude && item.matches(path))
+        {
+            return false;
+        }
+        self.is_empty()
+            || self
+                .items
+                .iter()
+                .any(|item| !item.exclude && item.matches(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(args: &[&str], prefix: &str) -> Pathspec {
+        Pathspec::parse(args, Path::new(prefix)).unwrap()
+    }
+
+    #[test]
+    fn test_pathspec() {
+        let p = spec(&["*.rs"], "");
+        assert!(p.matches("main.rs"));
+        assert!(p.matches("src/main.rs"));
+        assert!(!p.matches("README.md"));
+
+        let p = spec(&[":(glob)*.rs"], "");
+        assert!(p.matches("main.rs"));
+        assert!(!p.matches("src/main.rs"));
+        assert!(spec(&[":(glob)**/*.rs"], "").matches("src/a/main.rs"));
+
+        let p = spec(&["src"], "");
+        assert!(p.matches("src/a/b.rs"));
+        assert!(!p.matches("srcx"));
+
+        let p = spec(&[".", ":!*.md"], "");
+        assert!(p.matches("a.rs"));
+        assert!(!p.matches("doc/a.md"));
+        assert!(spec(&[":(exclude)*.md"], "").matches("a.rs"));
+
+        let p = spec(&["a.txt"], "sub");
+        assert!(p.matches("sub/a.txt"));
+        assert!(!p.matches("a.txt"));
+        assert!(spec(&[":/a.txt"], "sub").matches("a.txt"));
+        assert!(spec(&["../a.txt"], "sub").matches("a.txt"));
+        assert!(spec(&[":/"], "sub").matches("x/y"));
+
+        assert!(!spec(&[":(literal)*.rs"], "").matches("a.rs"));
+        assert_eq!(spec(&["src/*.rs"], "").items[0].wildcard_start(), Some(4));
+        assert_eq!(
+            spec(&[":(literal)*.rs"], "").items[0].wildcard_start(),
+            None
+        );
+        assert!(spec(&[":(icase)README"], "").matches("readme"));
+
+        assert!(Pathspec::parse(&[":(bogus)x"], Path::new("")).is_err());
+        assert!(Pathspec::parse(&["../x"], Path::new("")).is_err());
+    }
+}
@@ -0,0 +1,80 @@
+//! Reference logs: the values `HEAD` and branches had, kept under
+//! `.git/logs` one line per update.
+
+use std::fmt;
+
+use crate::object::commit::Sign;
+
+/// The hash written for a reference that did not exist yet.
+pub const NULL_OID: &str = "0000000000000000000000000000000000000000";
+
+/// One update of a reference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// `None` when the reference was created.
+    pub old: Option<String>,
+    pub new: String,
+    /// Who made the update, and when.
+    pub committer: Sign,
+    /// What caused the update, e.g. `commit: <subject>`.
+    pub message: String,
+}
+
+impl Entry {
+    pub fn new(old: Option<&str>, new: &str, committer: Sign, message: &str) -> Self {
+        // gitと同じく、改行などの空白は1つの空白にまとめる
+        let message = message.split_whitespace().collect::<Vec<_>>().join(" ");
+        Self {
+            old: old.map(ToString::to_string),
+            new: new.to_string(),
+            committer,
+            message,
+        }
+    }
+
+    /// Parse `<old> <new> <name> <<email>> <time> <zone>\t<message>`.
+    pub fn parse(line: &str) -> Option<Self> {
+        let (head, message) = line.split_once('\t').unwrap_or((line, ""));
+        let (old, rest) = head.split_once(' ')?;
+        let (new, committer) = rest.split_once(' ')?;
+        Some(Self {
+            old: (old != NULL_OID).then(|| old.to_string()),
+            new: new.to_string(),
+            committer: Sign::parse(committer)?,
+            message: message.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let old = self.old.as_deref().unwrap_or(NULL_OID);
+        write!(f, "{old} {} {}", self.new, self.committer)?;
+        if !self.message.is_empty() {
+            write!(f, "\t{}", self.message)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry() {
+        let line = format!(
+            "{NULL_OID} 1111111111111111111111111111111111111111 A U Thor <a@example.com> \
+             1700000000 +0900\tcommit (initial): first"
+        );
+        let entry = Entry::parse(&line).unwrap();
+        assert_eq!(entry.old, None);
+        assert_eq!(entry.committer.name, "A U Thor");
+        assert_eq!(entry.message, "commit (initial): first");
+        assert_eq!(entry.to_string(), line);
+
+        let sign = Sign::parse("A <a@example.com> 1700000000 +0000").unwrap();
+        let entry = Entry::new(Some("2"), "3", sign, "reset:  moving\nto HEAD~ ");
+        assert_eq!(entry.message, "reset: moving to HEAD~");
+    }
+}
@@ -0,0 +1,344 @@
+//! References: `HEAD`, branches and tags, loose or packed.
+
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::object::commit::Sign;
+use crate::odb;
+use crate::reflog;
+use crate::util::lockfile::LockFile;
+
+/// What a reference points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// An object hash.
+    Direct(String),
+    /// Another reference, e.g. `HEAD` -> `refs/heads/main`.
+    Symbolic(String),
+}
+
+/// Reference storage of a repository.
+#[derive(Debug, Clone)]
+pub struct Refs {
+    git_dir: PathBuf,
+}
+
+impl Refs {
+    pub fn new(git_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            git_dir: git_dir.into(),
+        }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.git_dir.join(name)
+    }
+
+    /// Read `name` without following symbolic references.
+    pub fn read(&self, name: &str) -> Result<Option<Target>> {
+        match fs::read_to_string(self.path(name)) {
+            Ok(content) => {
+                let content = content.trim_end();
+                if let Some(target) = content.strip_prefix("ref:") {
+                    return Ok(Some(Target::Symbolic(target.trim().to_string())));
+                }
+                if !odb::is_full_hash(content) {
+                    return Err(Error::CorruptRef(format!("{name}: {content:?}")));
+                }
+                Ok(Some(Target::Direct(content.to_string())))
+            }
+            // refs/heads/aがディレクトリの場合(refs/heads/a/bがある)もpacked-refsを探す
+            Err(e) if e.kind() == ErrorKind::NotFound || self.path(name).is_dir() => Ok(self
+                .packed()?
+                .into_iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, oid)| Target::Direct(oid))),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Follow symbolic references starting at `name`, returning the name of
+    /// the last reference in the chain and the hash it points at, if any.
+    pub fn resolve_name(&self, name: &str) -> Result<(String, Option<String>)> {
+        let mut name = name.to_string();
+        // 循環参照を避けるため、gitと同じく5段までしか辿らない
+        for _ in 0..5 {
+            match self.read(&name)? {
+                Some(Target::Symbolic(target)) => name = target,
+                Some(Target::Direct(oid)) => return Ok((name, Some(oid))),
+                None => return Ok((name, None)),
+            }
+        }
+        Err(Error::CorruptRef(format!(
+            "{name}: too many levels of symbolic refs"
+        )))
+    }
+
+    /// The hash `name` ultimately points at.
+    pub fn resolve(&self, name: &str) -> Result<Option<String>> {
+        Ok(self.resolve_name(name)?.1)
+    }
+
+    /// The commit `HEAD` points at; `None` on an unborn branch.
+    pub fn head(&self) -> Result<Option<String>> {
+        self.resolve("HEAD")
+    }
+
+    /// Full name of the checked out branch (`refs/heads/...`), or `None` when
+    /// `HEAD` is detached.
+    pub fn current_branch(&self) -> Result<Option<String>> {
+        match self.read("HEAD")? {
+            Some(Target::Symbolic(target)) => Ok(Some(target)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Point `name` directly at `oid`, replacing what it pointed at.
+    pub fn update(&self, name: &str, oid: &str) -> Result<()> {
+        if !is_valid_name(name) {
+            return Err(Error::InvalidRefName(name.to_string()));
+        }
+        let mut lock = LockFile::acquire(self.path(name))?;
+        lock.write_all(format!("{oid}\n").as_bytes())?;
+        lock.commit()
+    }
+
+    /// Update the reference `HEAD` resolves to (the current branch, or `HEAD`
+    /// itself when detached).
+    pub fn update_head(&self, oid: &str) -> Result<()> {
+        let (name, _) = self.resolve_name("HEAD")?;
+        self.update(&name, oid)
+    }
+
+    /// Point `name` at `oid` like [`Refs::update`], recording the update in
+    /// its reflog, and in the one of `HEAD` when `name` is checked out.
+    pub fn update_logged(
+        &self,
+        name: &str,
+        oid: &str,
+        committer: &Sign,
+        message: &str,
+    ) -> Result<()> {
+        let previous = self.resolve(name)?;
+        let entry = reflog::Entry::new(previous.as_deref(), oid, committer.clone(), message);
+        // gitと同じく、値が変わらなければHEADのreflogにだけ記録する
+        if previous.as_deref() != Some(oid) {
+            self.update(name, oid)?;
+            self.append_log(name, &entry)?;
+        }
+        if name != "HEAD" && self.current_branch()?.as_deref() == Some(name) {
+            self.append_log("HEAD", &entry)?;
+        }
+        Ok(())
+    }
+
+    /// [`Refs::update_head`] that also records the update in the reflogs.
+    pub fn update_head_logged(&self, oid: &str, committer: &Sign, message: &str) -> Result<()> {
+        let (name, _) = self.resolve_name("HEAD")?;
+        self.update_logged(&name, oid, committer, message)
+    }
+
+    /// Add `entry` to the reflog of `name`. As with git's default
+    /// `core.logAllRefUpdates`, a new reflog is only started for `HEAD`,
+    /// branches and `refs/stash`.
+    pub fn append_log(&self, name: &str, entry: &reflog::Entry) -> Result<()> {
+        let path = self.git_dir.join("logs").join(name);
+        // stashの一覧はreflogそのものなので、常に記録する
+        let logged = name == "HEAD"
+            || name == "refs/stash"
+            || ["refs/heads/", "refs/remotes/", "refs/notes/"]
+                .iter()
+                .any(|prefix| name.starts_with(prefix));
+        if !logged && !path.exists() {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{entry}")?;
+        Ok(())
+    }
+
+    /// The reflog of `name`, oldest update first.
+    pub fn log(&self, name: &str) -> Result<Vec<reflog::Entry>> {
+        match fs::read_to_string(self.git_dir.join("logs").join(name)) {
+            Ok(content) => Ok(content.lines().filter_map(reflog::Entry::parse).collect()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replace the reflog of `name` with `entries`, oldest first.
+    pub fn write_log(&self, name: &str, entries: &[reflog::Entry]) -> Result<()> {
+        let mut content = String::new();
+        for entry in entries {
+            content.push_str(&entry.to_string());
+            content.push('\n');
+        }
+        let mut lock = LockFile::acquire(self.git_dir.join("logs").join(name))?;
+        lock.write_all(content.as_bytes())?;
+        lock.commit()
+    }
+
+    /// Make `name` a symbolic reference to `target`.
+    pub fn set_symbolic(&self, name: &str, target: &str) -> Result<()> {
+        if !is_valid_name(target) {
+            return Err(Error::InvalidRefName(target.to_string()));
+        }
+        let mut lock = LockFile::acquire(self.path(name))?;
+        lock.write_all(format!("ref: {target}\n").as_bytes())?;
+        lock.commit()
+    }
+
+    /// Delete `name`, both loose and packed.
+    pub fn delete(&self, name: &str) -> Result<()> {
+        let packed = self.packed()?;
+        if packed.iter().any(|(n, _)| n == name) {
+            let path = self.git_dir.join("packed-refs");
+            let content = fs::read_to_string(&path).map_err(Error::io(&path))?;
+            let mut lines: Vec<&str> = Vec::new();
+            let mut skipping = false;
+            for line in content.lines() {
+                // 削除するrefの直後の"^"(peeled tag)の行も取り除く
+                if skipping && line.starts_with('^') {
+                    continue;
+                }
+                skipping = line.split_once(' ').is_some_and(|(_, n)| n == name);
+                if !skipping {
+                    lines.push(line);
+                }
+            }
+            let mut lock = LockFile::acquire(&path)?;
+            lock.write_all((lines.join("\n") + "\n").as_bytes())?;
+            lock.commit()?;
+        }
+
+        match fs::remove_file(self.path(name)) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                if !packed.iter().any(|(n, _)| n == name) {
+                    return Err(Error::RefNotFound(name.to_string()));
+                }
+            }
+            Err(e) => return Err(e.into()),
+        }
+        remove_empty_parents(&self.git_dir, name);
+        // reflogも一緒に消す
+        let logs = self.git_dir.join("logs");
+        if fs::remove_file(logs.join(name)).is_ok() {
+            remove_empty_parents(&logs, name);
+        }
+        Ok(())
+    }
+
+    /// All references under `prefix` (e.g. `refs/heads/`), sorted by name.
+    pub fn list(&self, prefix: &str) -> Result<Vec<(String, String)>> {
+        let mut refs = Vec::new();
+        collect_loose(&self.git_dir, &self.git_dir.join("refs"), &mut refs)?;
+        for (name, oid) in self.packed()? {
+            if !refs.iter().any(|(n, _)| *n == name) {
+                refs.push((name, oid));
+            }
+        }
+        refs.retain(|(name, _)| name.starts_with(prefix));
+        refs.sort();
+        Ok(refs)
+    }
+
+    fn packed(&self) -> Result<Vec<(String, String)>> {
+        let content = match fs::read_to_string(self.git_dir.join("packed-refs")) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(content
+            .lines()
+            .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+            .filter_map(|line| line.split_once(' '))
+            .map(|(oid, name)| (name.to_string(), oid.to_string()))
+            .collect())
+    }
+}
+
+// refs/heads/a/bのような階層のrefを消した後に空ディレクトリを残さない
+fn remove_empty_parents(base: &Path, name: &str) {
+    let mut dir = base.join(name);
+    while dir.pop()
+        && dir
+            .strip_prefix(base)
+            .is_ok_and(|d| d.components().count() > 2)
+    {
+        if fs::remove_dir(&dir).is_err() {
+            break;
+        }
+    }
+}
+
+fn collect_loose(git_dir: &Path, dir: &Path, refs: &mut Vec<(String, String)>) -> Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_loose(git_dir, &path, refs)?;
+            continue;
+        }
+        let Ok(content) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let content = content.trim_end();
+        let Some(name) = path.strip_prefix(git_dir).ok().and_then(Path::to_str) else {
+            continue;
+        };
+        if odb::is_full_hash(content) && is_valid_name(name) {
+            refs.push((name.to_string(), content.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Whether `name` is acceptable as a reference name (see `git check-ref-format`).
+pub fn is_valid_name(name: &str) -> bool {
+    if name.is_empty()
+        || name == "@"
+        || name.starts_with('/')
+        || name.ends_with('/')
+        || name.ends_with('.')
+        || name.contains("..")
+        || name.contains("@{")
+        || name.contains("//")
+    {
+        return false;
+    }
+    if name.chars().any(|c| {
+        c.is_ascii_control() || matches!(c, ' ' | '~' | '^' | ':' | '?' | '*' | '[' | '\\')
+    }) {
+        return false;
+    }
+    name.split('/')
+        .all(|component| !component.starts_with('.') && component.strip_suffix(".lock").is_none())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_name() {
+        assert!(is_valid_name("refs/heads/main"));
+        assert!(is_valid_name("refs/heads/feature/x-1"));
+        assert!(is_valid_name("HEAD"));
+        assert!(!is_valid_name("refs/heads/a..b"));
+        assert!(!is_valid_name("refs/heads/.hidden"));
+        assert!(!is_valid_name("refs/heads/x.lock"));
+        assert!(!is_valid_name("refs/heads/a b"));
+        assert!(!is_valid_name("refs/heads/a^"));
+        assert!(!is_valid_name("refs/heads/"));
+    }
+}
@@ -0,0 +1,444 @@
+//! Opening repositories and reaching their parts.
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::index::Index;
+use crate::object::commit::Commit;
+use crate::object::tree::{self, Tree};
+use crate::object::{Kind, Object};
+use crate::odb::ObjectDatabase;
+use crate::refs::{self, Refs};
+use crate::util;
+
+pub use crate::util::path::{find_git_root, GitRoot};
+
+const DEFAULT_BRANCH: &str = "main";
+
+const DESCRIPTION: &str =
+    "Unnamed repository; edit this file 'description' to name the repository.\n";
+
+const INFO_EXCLUDE: &str = "\
+# git ls-files --others --exclude-from=.git/info/exclude
+# Lines that start with '#' are comments.
+# For a project mostly in C, the following would be a good set of
+# exclude patterns (uncomment them if you want to use them):
+# *.[oa]
+# *~
+";
+
+/// Options of [`Repository::init`].
+#[derive(Debug, Clone, Default)]
+pub struct InitOptions {
+    /// Use the directory itself as the git directory, without a working tree.
+    pub bare: bool,
+    /// Branch `HEAD` points at; defaults to `init.defaultBranch`, then `main`.
+    pub initial_branch: Option<String>,
+    /// Directory whose files are copied into the new git directory, instead of
+    /// the built-in `description`, `hooks/` and `info/exclude`.
+    pub template: Option<PathBuf>,
+    pub shared: Option<SharedMode>,
+    /// Only `sha1` is supported.
+    pub object_format: Option<String>,
+}
+
+/// Value of `core.sharedRepository`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SharedMode {
+    /// Use the permissions given by umask.
+    Umask,
+    /// Group writable.
+    Group,
+    /// Group writable and readable by everybody.
+    All,
+    /// Exact permission bits of files, e.g. `0640`.
+    Perm(u32),
+}
+
+impl SharedMode {
+    /// Parse the value of `--shared` or `core.sharedRepository`.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "umask" | "false" | "no" | "off" | "0" => Some(Self::Umask),
+            "group" | "true" | "yes" | "on" | "1" => Some(Self::Group),
+            "all" | "world" | "everybody" | "2" => Some(Self::All),
+            _ => {
+                let perm = u32::from_str_radix(value, 8).ok()?;
+                // 所有者が読み書きできない設定は受け付けない
+                (perm & 0o600 == 0o600 && perm <= 0o777).then_some(Self::Perm(perm))
+            }
+        }
+    }
+
+    fn config_value(self) -> String {
+        match self {
+            Self::Umask => "0".to_string(),
+            Self::Group => "1".to_string(),
+            Self::All => "2".to_string(),
+            Self::Perm(perm) => format!("{perm:#05o}").replace("0o", "0"),
+        }
+    }
+
+    // umaskで作られたmodeに共有のためのbitを足す
+    fn apply(self, mode: u32, is_dir: bool) -> u32 {
+        let mode = mode & 0o777;
+        let mode = match self {
+            Self::Umask => return mode,
+            Self::Group => mode | (mode & 0o700) >> 3,
+            Self::All => mode | (mode & 0o700) >> 3 | (mode & 0o500) >> 6,
+            Self::Perm(perm) if is_dir => (perm & 0o444) >> 2 | perm,
+            Self::Perm(perm) => perm,
+        };
+        if is_dir {
+            // 新しく作られるファイルのgroupをディレクトリに揃える
+            mode | 0o2000
+        } else {
+            mode
+        }
+    }
+}
+
+/// An opened repository.
+///
+/// Commands receive this instead of relying on the process working directory,
+/// so several repositories can be used from the same process.
+#[derive(Debug, Clone)]
+pub struct Repository {
+    git_dir: PathBuf,
+    work_tree: Option<PathBuf>,
+    config: Config,
+    odb: ObjectDatabase,
+    refs: Refs,
+}
+
+impl Repository {
+    /// Open the repository whose git directory is `git_dir`.
+    pub fn open(git_dir: impl Into<PathBuf>, work_tree: Option<PathBuf>) -> Result<Self> {
+        let git_dir = git_dir.into();
+        let config = Config::load(&git_dir)?;
+        let work_tree = if config.get_bool("core.bare") == Some(true) {
+            None
+        } else {
+            work_tree
+        };
+        let odb = ObjectDatabase::new(git_dir.join("objects"));
+        let refs = Refs::new(&git_dir);
+        Ok(Self {
+            git_dir,
+            work_tree,
+            config,
+            odb,
+            refs,
+        })
+    }
+
+    /// Create a repository at `path`, or reinitialize the one already there.
+    ///
+    /// Reinitializing keeps `HEAD` and existing files, only adding what is
+    /// missing. Also returns whether the repository already existed.
+    pub fn init(path: &Path, options: &InitOptions) -> Result<(Self, bool)> {
+        match options.object_format.as_deref() {
+            None | Some("sha1") => {}
+            Some("sha256") => {
+                return Err(Error::Config(
+                    "object format 'sha256' is not supported".to_string(),
+                ))
+            }
+            Some(format) => {
+                return Err(Error::Config(format!("unknown hash algorithm '{format}'")))
+            }
+        }
+
+        let git_dir = if options.bare {
+            path.to_path_buf()
+        } else {
+            path.join(".git")
+        };
+        let reinit = util::path::is_git_dir(&git_dir);
+        let global = Config::load_global()?;
+        // 何かを作る前にブランチ名を確認する
+        let branch = options
+            .initial_branch
+            .as_deref()
+            .or_else(|| global.get("init.defaultBranch"))
+            .unwrap_or(DEFAULT_BRANCH);
+        let head = format!("refs/heads/{branch}");
+        if !reinit && !refs::is_valid_name(&head) {
+            return Err(Error::InvalidRefName(branch.to_string()));
+        }
+
+        let template = options
+            .template
+            .clone()
+            .or_else(|| std::env::var_os("GIT_TEMPLATE_DIR").map(PathBuf::from))
+            .or_else(|| global.get("init.templateDir").map(PathBuf::from));
+        // 失敗したら、作りかけのディレクトリを残さない
+        let created = path
+            .ancestors()
+            .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+            .last()
+            .map(Path::to_path_buf)
+            .or_else(|| (!git_dir.exists()).then(|| git_dir.clone()));
+        if let Err(e) = Self::create_git_dir(&git_dir, &head, template.as_deref(), reinit, options)
+        {
+            if let Some(created) = created {
+                let _ = fs::remove_dir_all(created);
+            }
+            return Err(e);
+        }
+
+        let work_tree = (!options.bare).then(|| path.to_path_buf());
+        Ok((Self::open(git_dir, work_tree)?, reinit))
+    }
+
+    // git_dirの中身を作る。再初期化なら、足りないものだけを足す
+    fn create_git_dir(
+        git_dir: &Path,
+        head: &str,
+        template: Option<&Path>,
+        reinit: bool,
+        options: &InitOptions,
+    ) -> Result<()> {
+        for dir in ["objects/info", "objects/pack", "refs/heads", "refs/tags"] {
+            let dir = git_dir.join(dir);
+            fs::create_dir_all(&dir).map_err(Error::io(&dir))?;
+        }
+        if let Some(template) = template {
+            copy_template(template, git_dir)?;
+        } else {
+            write_new_file(&git_dir.join("description"), DESCRIPTION)?;
+            fs::create_dir_all(git_dir.join("hooks"))?;
+            fs::create_dir_all(git_dir.join("info"))?;
+            write_new_file(&git_dir.join("info/exclude"), INFO_EXCLUDE)?;
+        }
+
+        if !reinit {
+            let head_path = git_dir.join("HEAD");
+            fs::write(&head_path, format!("ref: {head}\n")).map_err(Error::io(&head_path))?;
+
+            let config_path = git_dir.join("config");
+            Config::set_in_file(&config_path, "core.repositoryformatversion", "0")?;
+            Config::set_in_file(&config_path, "core.filemode", "true")?;
+            Config::set_in_file(&config_path, "core.bare", &options.bare.to_string())?;
+            if !options.bare {
+                Config::set_in_file(&config_path, "core.logallrefupdates", "true")?;
+            }
+        }
+
+        if let Some(shared) = options.shared {
+            if shared != SharedMode::Umask {
+                let config_path = git_dir.join("config");
+                Config::set_in_file(
+                    &config_path,
+                    "core.sharedRepository",
+                    &shared.config_value(),
+                )?;
+                set_shared_permissions(git_dir, shared)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Find and open the repository containing `start`, searching parent
+    /// directories like the command line does.
+    pub fn discover(start: &Path) -> Result<Self> {
+        let root = util::path::find_git_root(start, None, None)?;
+        Self::open(root.git_dir, root.work_tree)
+    }
+
+    pub fn git_dir(&self) -> &Path {
+        &self.git_dir
+    }
+
+    pub fn work_tree(&self) -> Option<&Path> {
+        self.work_tree.as_deref()
+    }
+
+    /// The working tree, or an error for bare repositories.
+    pub fn require_work_tree(&self) -> Result<&Path> {
+        self.work_tree().ok_or(Error::BareRepository)
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn odb(&self) -> &ObjectDatabase {
+        &self.odb
+    }
+
+    pub fn refs(&self) -> &Refs {
+        &self.refs
+    }
+
+    pub fn index_path(&self) -> PathBuf {
+        self.git_dir.join("index")
+    }
+
+    /// Read the index; a repository without one has an empty index.
+    pub fn index(&self) -> Result<Index> {
+        Index::read(&self.index_path())
+    }
+
+    pub fn write_index(&self, index: &Index) -> Result<()> {
+        index.write(&self.index_path())
+    }
+
+    pub fn read_object(&self, oid: &str) -> Result<Object> {
+        let (kind, data) = self.odb.read(oid)?;
+        Object::parse(kind, data).map_err(|e| match e {
+            Error::CorruptObject(reason) => Error::CorruptObject(format!("{oid}: {reason}")),
+            e => e,
+        })
+    }
+
+    pub fn write_object(&self, object: &Object) -> Result<String> {
+        self.odb.write(object.kind(), &object.encode())
+    }
+
+    pub fn find_commit(&self, oid: &str) -> Result<Commit> {
+        match self.read_object(oid)? {
+            Object::Commit(commit) => Ok(commit),
+            object => Err(wrong_kind(oid, object.kind(), Kind::Commit)),
+        }
+    }
+
+    /// Read a tree, peeling commits to their root tree.
+    pub fn find_tree(&self, oid: &str) -> Result<Tree> {
+        match self.read_object(oid)? {
+            Object::Tree(tree) => Ok(tree),
+            Object::Commit(commit) => self.find_tree(&commit.tree),
+            object => Err(wrong_kind(oid, object.kind(), Kind::Tree)),
+        }
+    }
+
+    /// Every non-tree entry under `tree`, with `name` set to its full
+    /// slash-separated path, in path order.
+    pub fn tree_files(&self, tree: &str) -> Result<Vec<tree::Entry>> {
+        let mut files = Vec::new();
+        self.collect_tree_files(tree, "", &mut files)?;
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(files)
+    }
+
+    fn collect_tree_files(
+        &self,
+        tree: &str,
+        prefix: &str,
+        files: &mut Vec<tree::Entry>,
+    ) -> Result<()> {
+        for entry in self.find_tree(tree)?.entries {
+            let path = format!("{prefix}{}", entry.name);
+            if entry.is_tree() {
+                self.collect_tree_files(&entry.oid, &format!("{path}/"), files)?;
+            } else {
+                files.push(tree::Entry {
+                    name: path,
+                    ..entry
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn find_blob(&self, oid: &str) -> Result<Vec<u8>> {
+        match self.read_object(oid)? {
+            Object::Blob(data) => Ok(data),
+            object => Err(wrong_kind(oid, object.kind(), Kind::Blob)),
+        }
+    }
+}
+
+fn wrong_kind(oid: &str, actual: Kind, expected: Kind) -> Error {
+    Error::WrongObjectType {
+        oid: oid.to_string(),
+        expected,
+        actual,
+    }
+}
+
+// 既存のファイルは上書きしない
+fn write_new_file(path: &Path, content: &str) -> Result<()> {
+    if !path.exists() {
+        fs::write(path, content).map_err(Error::io(path))?;
+    }
+    Ok(())
+}
+
+fn copy_template(template: &Path, dest: &Path) -> Result<()> {
+    let entries = match fs::read_dir(template) {
+        Ok(entries) => entries,
+        // gitと同じく、存在しないtemplateは無視する
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(Error::io(template)(e)),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let target = dest.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            fs::create_dir_all(&target).map_err(Error::io(&target))?;
+            copy_template(&entry.path(), &target)?;
+        } else if file_type.is_symlink() {
+            if target.symlink_metadata().is_err() {
+                std::os::unix::fs::symlink(fs::read_link(entry.path())?, &target)?;
+            }
+        } else if !target.exists() {
+            fs::copy(entry.path(), &target).map_err(Error::io(&target))?;
+        }
+    }
+    Ok(())
+}
+
+fn set_shared_permissions(path: &Path, shared: SharedMode) -> Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.file_type().is_symlink() {
+        return Ok(());
+    }
+    let mode = shared.apply(metadata.permissions().mode(), metadata.is_dir());
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)? {
+            set_shared_permissions(&entry?.path(), shared)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_mode() {
+        assert_eq!(SharedMode::parse("group"), Some(SharedMode::Group));
+        assert_eq!(SharedMode::parse("everybody"), Some(SharedMode::All));
+        assert_eq!(SharedMode::parse("0640"), Some(SharedMode::Perm(0o640)));
+        assert_eq!(SharedMode::parse("0044"), None);
+        assert_eq!(SharedMode::Perm(0o640).config_value(), "0640");
+        assert_eq!(SharedMode::Group.apply(0o755, true), 0o2775);
+        assert_eq!(SharedMode::All.apply(0o600, false), 0o664);
+        assert_eq!(SharedMode::Perm(0o640).apply(0o755, true), 0o2750);
+    }
+
+    #[test]
+    fn test_init_failure_removes_git_dir() {
+        let temp = crate::testing::TempRepo::new();
+        temp.write("template", "");
+        let options = InitOptions {
+            template: Some(temp.root.join("template")),
+            ..InitOptions::default()
+        };
+        let path = temp.root.join("new/repo");
+        let Err(e) = Repository::init(&path, &options) else {
+            panic!("init with a file as the template succeeded");
+        };
+        let message = e.to_string();
+        assert!(message.starts_with(&format!("{}: ", temp.root.join("template").display())));
+        assert!(std::error::Error::source(&e).is_none());
+        assert!(!temp.root.join("new").exists());
+    }
+}
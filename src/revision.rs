@@ -0,0 +1,236 @@
+//! Revision syntax: names such as `HEAD~2`, `main^2`, `v1.0^{tree}`, `main@{1}`,
+//! `@{-1}`, abbreviated hashes and `<rev>:<path>`.
+
+use crate::error::{Error, Result};
+use crate::object::{Kind, Object};
+use crate::odb::is_full_hash;
+use crate::repository::Repository;
+
+// 短いrefの名前を探す順番
+const REF_RULES: &[&str] = &[
+    "{}",
+    "refs/{}",
+    "refs/tags/{}",
+    "refs/heads/{}",
+    "refs/remotes/{}",
+    "refs/remotes/{}/HEAD",
+];
+
+/// Resolve `spec` to an object hash.
+pub fn resolve(repo: &Repository, spec: &str) -> Result<String> {
+    let invalid = || Error::InvalidObjectName(spec.to_string());
+
+    // "<rev>:<path>"はtreeの中のentryを、":<path>"はindexのentryを指す
+    if let Some((rev, path)) = spec.split_once(':') {
+        let path = path.trim_matches('/');
+        if rev.is_empty() {
+            let index = repo.index()?;
+            return index
+                .get(path, 0)
+                .map(|e| e.oid.clone())
+                .ok_or_else(invalid);
+        }
+        let tree = peel(repo, &resolve(repo, rev)?, Kind::Tree)?;
+        if path.is_empty() {
+            return Ok(tree);
+        }
+        return lookup_path(repo, &tree, path)?.ok_or_else(invalid);
+    }
+
+    let base_end = spec.find(['^', '~']).unwrap_or(spec.len());
+    let (base, mut suffix) = spec.split_at(base_end);
+    let mut oid = resolve_base(repo, base)?.ok_or_else(invalid)?;
+
+    while !suffix.is_empty() {
+        if let Some(rest) = suffix.strip_prefix("^{") {
+            let (kind, rest) = rest.split_once('}').ok_or_else(invalid)?;
+            oid = match kind {
+                "" => peel_tags(repo, &oid)?,
+                kind => peel(repo, &oid, kind.parse().map_err(|_| invalid())?)?,
+            };
+            suffix = rest;
+            continue;
+        }
+        let operator = suffix.as_bytes()[0];
+        let rest = &suffix[1..];
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let number = if digits == 0 {
+            1
+        } else {
+            rest[..digits].parse().map_err(|_| invalid())?
+        };
+        suffix = &rest[digits..];
+
+        let commit = peel(repo, &oid, Kind::Commit)?;
+        oid = if operator == b'^' {
+            if number == 0 {
+                commit
+            } else {
+                let parents = repo.find_commit(&commit)?.parents;
+                parents.get(number - 1).cloned().ok_or_else(invalid)?
+            }
+        } else {
+            let mut current = commit;
+            for _ in 0..number {
+                let parents = repo.find_commit(&current)?.parents;
+                current = parents.first().cloned().ok_or_else(invalid)?;
+            }
+            current
+        };
+    }
+    Ok(oid)
+}
+
+/// Resolve `spec` and peel it to a commit.
+pub fn resolve_commit(repo: &Repository, spec: &str) -> Result<String> {
+    let oid = resolve(repo, spec)?;
+    peel(repo, &oid, Kind::Commit)
+}
+
+// 演算子を除いた部分: refの名前か(短縮された)hash
+fn resolve_base(repo: &Repository, name: &str) -> Result<Option<String>> {
+    let name = if name == "@" { "HEAD" } else { name };
+    if name.is_empty() {
+        return Ok(None);
+    }
+    if let Some((name, selector)) = name.split_once("@{") {
+        let Some(selector) = selector.strip_suffix('}') else {
+            return Ok(None);
+        };
+        if let Some(n) = selector.strip_prefix('-').filter(|_| name.is_empty()) {
+            let previous = match n.parse() {
+                Ok(n) => previous_checkout(repo, n)?,
+                Err(_) => None,
+            };
+            return match previous {
+                Some(previous) => resolve_base(repo, &previous),
+                None => Ok(None),
+            };
+        }
+        return match selector.parse() {
+            Ok(n) => resolve_reflog(repo, name, n),
+            Err(_) => Ok(None),
+        };
+    }
+    if is_full_hash(name) && repo.odb().contains(name) {
+        return Ok(Some(name.to_string()));
+    }
+    if let Some(full_name) = full_ref_name(repo, name)? {
+        return repo.refs().resolve(&full_name);
+    }
+    if name.len() >= 4 {
+        let found = repo.odb().find_by_prefix(name)?;
+        match found.len() {
+            0 => {}
+            1 => return Ok(found.into_iter().next()),
+            _ => {
+                return Err(Error::InvalidObjectName(format!(
+                    "{name} (short object ID is ambiguous)"
+                )))
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// The full name of the reference `name` is short for, e.g.
+/// `refs/heads/main` for `main`.
+pub fn full_ref_name(repo: &Repository, name: &str) -> Result<Option<String>> {
+    for rule in REF_RULES {
+        let full_name = rule.replace("{}", name);
+        // git dir直下はHEADやORIG_HEADのような名前だけを探す
+        let pseudo_ref = name.bytes().all(|b| b.is_ascii_uppercase() || b == b'_');
+        if full_name == name && !pseudo_ref && !name.starts_with("refs/") {
+            continue;
+        }
+        if repo.refs().resolve(&full_name)?.is_some() {
+            return Ok(Some(full_name));
+        }
+    }
+    Ok(None)
+}
+
+/// The branch, or the commit when `HEAD` was detached, that was checked
+/// out before the `n`th last switch (`@{-<n>}`), found in the reflog of
+/// `HEAD`.
+pub fn previous_checkout(repo: &Repository, n: usize) -> Result<Option<String>> {
+    if n == 0 {
+        return Ok(None);
+    }
+    let log = repo.refs().log("HEAD")?;
+    Ok(log
+        .iter()
+        .rev()
+        .filter_map(|entry| entry.message.strip_prefix("checkout: moving from "))
+        .filter_map(|moved| moved.split_once(" to "))
+        .nth(n - 1)
+        .map(|(from, _)| from.to_string()))
+}
+
+// "<ref>@{<n>}": reflogでn回前の値。refを省略すると現在のブランチ
+fn resolve_reflog(repo: &Repository, name: &str, n: usize) -> Result<Option<String>> {
+    let full_name = if name.is_empty() {
+        repo.refs()
+            .current_branch()?
+            .unwrap_or_else(|| "HEAD".to_string())
+    } else {
+        match full_ref_name(repo, name)? {
+            Some(full_name) => full_name,
+            None => return Ok(None),
+        }
+    };
+    let log = repo.refs().log(&full_name)?;
+    if n < log.len() {
+        return Ok(Some(log[log.len() - 1 - n].new.clone()));
+    }
+    // 最も古い更新の前の値まで遡れる
+    Ok(log
+        .first()
+        .filter(|_| n == log.len())
+        .and_then(|e| e.old.clone()))
+}
+
+/// Follow tags starting at `oid` to the first object that is not a tag.
+pub fn peel_tags(repo: &Repository, oid: &str) -> Result<String> {
+    let mut oid = oid.to_string();
+    while let Object::Tag(tag) = repo.read_object(&oid)? {
+        oid = tag.object;
+    }
+    Ok(oid)
+}
+
+/// Peel `oid` through tags (and from a commit to its tree) until it is an
+/// object of type `kind`.
+pub fn peel(repo: &Repository, oid: &str, kind: Kind) -> Result<String> {
+    let mut oid = oid.to_string();
+    loop {
+        let object = repo.read_object(&oid)?;
+        if object.kind() == kind {
+            return Ok(oid);
+        }
+        oid = match object {
+            Object::Tag(tag) => tag.object,
+            Object::Commit(commit) if kind == Kind::Tree => commit.tree,
+            object => {
+                return Err(Error::WrongObjectType {
+                    oid,
+                    expected: kind,
+                    actual: object.kind(),
+                })
+            }
+        };
+    }
+}
+
+// treeの中からslash区切りのpathを探す
+fn lookup_path(repo: &Repository, tree: &str, path: &str) -> Result<Option<String>> {
+    let mut oid = tree.to_string();
+    for name in path.split('/') {
+        let tree = repo.find_tree(&oid)?;
+        match tree.entries.into_iter().find(|e| e.name == name) {
+            Some(entry) => oid = entry.oid,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(oid))
+}
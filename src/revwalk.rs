@@ -0,0 +1,323 @@
+//! Walking commit history.
+
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use crate::error::Result;
+use crate::object::commit::Commit;
+use crate::repository::Repository;
+
+/// Iterates over commits reachable from the pushed commits, newest first by
+/// committer date, skipping commits reachable from hidden ones.
+pub struct RevWalk<'r> {
+    repo: &'r Repository,
+    queue: BinaryHeap<(i64, String)>,
+    seen: HashSet<String>,
+    hidden: HashSet<String>,
+    first_parent: bool,
+}
+
+impl<'r> RevWalk<'r> {
+    pub fn new(repo: &'r Repository) -> Self {
+        Self {
+            repo,
+            queue: BinaryHeap::new(),
+            seen: HashSet::new(),
+            hidden: HashSet::new(),
+            first_parent: false,
+        }
+    }
+
+    /// Only follow the first parent of merge commits.
+    pub fn first_parent(&mut self, first_parent: bool) -> &mut Self {
+        self.first_parent = first_parent;
+        self
+    }
+
+    /// Start walking from `oid`.
+    pub fn push(&mut self, oid: &str) -> Result<()> {
+        if self.seen.insert(oid.to_string()) {
+            let commit = self.repo.find_commit(oid)?;
+            self.queue
+                .push((commit.committer.time_stamp.timestamp(), oid.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Exclude `oid` and all of its ancestors.
+    pub fn hide(&mut self, oid: &str) -> Result<()> {
+        let mut stack = vec![oid.to_string()];
+        while let Some(oid) = stack.pop() {
+            if !self.hidden.insert(oid.clone()) {
+                continue;
+            }
+            stack.extend(self.repo.find_commit(&oid)?.parents);
+        }
+        Ok(())
+    }
+
+    fn next_commit(&mut self) -> Result<Option<(String, Commit)>> {
+        while let Some((_, oid)) = self.queue.pop() {
+            if self.hidden.contains(&oid) {
+                continue;
+            }
+            let commit = self.repo.find_commit(&oid)?;
+            let parents = if self.first_parent {
+                &commit.parents[..commit.parents.len().min(1)]
+            } else {
+                &commit.parents[..]
+            };
+            for parent in parents {
+                self.push(parent)?;
+            }
+            return Ok(Some((oid, commit)));
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for RevWalk<'_> {
+    type Item = Result<(String, Commit)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_commit().transpose()
+    }
+}
+
+// merge-baseを探すときの印: どちらから届いたか、すでに共通の祖先の祖先か
+const FROM_ONE: u8 = 1;
+const FROM_TWO: u8 = 2;
+const STALE: u8 = 4;
+
+/// The best common ancestors of the commits `one` and `two`: those that are
+/// not ancestors of other common ancestors.
+pub fn merge_bases(repo: &Repository, one: &str, two: &str) -> Result<Vec<String>> {
+    merge_bases_many(repo, one, &[two.to_string()])
+}
+
+/// The best common ancestors of `one` and any of `others`, as if `others`
+/// were merged together first.
+pub fn merge_bases_many(repo: &Repository, one: &str, others: &[String]) -> Result<Vec<String>> {
+    if others.iter().any(|other| other == one) {
+        return Ok(vec![one.to_string()]);
+    }
+    let candidates = common_ancestors(repo, one, others)?;
+    remove_redundant(repo, candidates)
+}
+
+/// The best common ancestors of all of `commits`, for an octopus merge.
+pub fn octopus_merge_bases(repo: &Repository, commits: &[String]) -> Result<Vec<String>> {
+    let Some((first, rest)) = commits.split_first() else {
+        return Ok(Vec::new());
+    };
+    // gitと同じく、それまでの結果の各々と次のcommitとのmerge-baseを集める
+    let mut result = vec![first.clone()];
+    for commit in rest {
+        let mut next: Vec<String> = Vec::new();
+        for base in &result {
+            for oid in merge_bases(repo, base, commit)? {
+                if !next.contains(&oid) {
+                    next.push(oid);
+                }
+            }
+        }
+        result = next;
+    }
+    Ok(result)
+}
+
+/// Whether `ancestor` is reachable from `descendant`, including when they
+/// are the same commit.
+pub fn is_ancestor(repo: &Repository, ancestor: &str, descendant: &str) -> Result<bool> {
+    let mut seen = HashSet::new();
+    let mut stack = vec![descendant.to_string()];
+    while let Some(oid) = stack.pop() {
+        if oid == ancestor {
+            return Ok(true);
+        }
+        if !seen.insert(oid.clone()) {
+            continue;
+        }
+        stack.extend(repo.find_commit(&oid)?.parents);
+    }
+    Ok(false)
+}
+
+/// Where the history of `commit` forked from the branch `name`, taking the
+/// commits the branch pointed at according to its reflog into account, as
+/// the branch may have been rewound since.
+///
+/// Returns `None` when no unique such commit is found among them.
+pub fn fork_point(repo: &Repository, name: &str, commit: &str) -> Result<Option<String>> {
+    let mut tips: Vec<String> = repo.refs().resolve(name)?.into_iter().collect();
+    for entry in repo.refs().log(name)?.into_iter().rev() {
+        for oid in entry.old.into_iter().chain([entry.new]) {
+            if !tips.contains(&oid) && repo.find_commit(&oid).is_ok() {
+                tips.push(oid);
+            }
+        }
+    }
+    if tips.is_empty() {
+        return Ok(None);
+    }
+    let bases = remove_redundant(repo, common_ancestors(repo, commit, &tips)?)?;
+    // 共通の祖先はただ1つで、しかもブランチが指していたcommitでなければならない
+    match &bases[..] {
+        [base] if tips.contains(base) => Ok(Some(base.clone())),
+        _ => Ok(None),
+    }
+}
+
+// gitと同じく、両側から新しい順に印を付けながら降りていき、両方の印が付いた
+// commitを候補にする。候補の祖先は候補にならない
+fn common_ancestors(repo: &Repository, one: &str, others: &[String]) -> Result<Vec<String>> {
+    let mut flags: HashMap<String, u8> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    flags.insert(one.to_string(), FROM_ONE);
+    queue.push((commit_time(repo, one)?, one.to_string()));
+    for other in others {
+        *flags.entry(other.clone()).or_default() |= FROM_TWO;
+        queue.push((commit_time(repo, other)?, other.clone()));
+    }
+    let mut result: Vec<String> = Vec::new();
+    while queue.iter().any(|(_, oid)| flags[oid] & STALE == 0) {
+        let Some((_, oid)) = queue.pop() else { break };
+        let mut flag = flags[&oid];
+        if flag & (FROM_ONE | FROM_TWO) == FROM_ONE | FROM_TWO && flag & STALE == 0 {
+            if !result.contains(&oid) {
+                result.push(oid.clone());
+            }
+            flag |= STALE;
+        }
+        for parent in repo.find_commit(&oid)?.parents {
+            let parent_flag = flags.entry(parent.clone()).or_default();
+            if *parent_flag & flag == flag {
+                continue;
+            }
+            *parent_flag |= flag;
+            queue.push((commit_time(repo, &parent)?, parent));
+        }
+    }
+    // 後から別の候補の祖先だとわかったものは除く
+    result.retain(|oid| flags[oid] & STALE == 0);
+    Ok(result)
+}
+
+// 他の候補の祖先になっている候補を除く
+fn remove_redundant(repo: &Repository, candidates: Vec<String>) -> Result<Vec<String>> {
+    if candidates.len() < 2 {
+        return Ok(candidates);
+    }
+    let mut result = Vec::new();
+    for candidate in &candidates {
+        let mut redundant = false;
+        for other in candidates.iter().filter(|other| *other != candidate) {
+            if ancestors(repo, other)?.contains(candidate) {
+                redundant = true;
+                break;
+            }
+        }
+        if !redundant {
+            result.push(candidate.clone());
+        }
+    }
+    Ok(result)
+}
+
+// oidの(自分を除く)すべての祖先
+fn ancestors(repo: &Repository, oid: &str) -> Result<HashSet<String>> {
+    let mut seen = HashSet::new();
+    let mut stack = repo.find_commit(oid)?.parents;
+    while let Some(oid) = stack.pop() {
+        if seen.insert(oid.clone()) {
+            stack.extend(repo.find_commit(&oid)?.parents);
+        }
+    }
+    Ok(seen)
+}
+
+fn commit_time(repo: &Repository, oid: &str) -> Result<i64> {
+    Ok(repo.find_commit(oid)?.committer.time_stamp.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::commit::Sign;
+    use crate::testing::TempRepo;
+
+    fn sorted(mut oids: Vec<String>) -> Vec<String> {
+        oids.sort();
+        oids
+    }
+
+    #[test]
+    fn test_merge_bases_criss_cross() {
+        let test = TempRepo::new();
+        let root = test.commit(&[], &[]);
+        let a1 = test.commit(&[("a", "1\n")], &[&root]);
+        let b1 = test.commit(&[("b", "1\n")], &[&root]);
+        let a2 = test.commit(&[("a", "1\n"), ("b", "1\n")], &[&a1, &b1]);
+        let b2 = test.commit(&[("a", "1\n"), ("b", "1\n")], &[&b1, &a1]);
+
+        let bases = merge_bases(&test.repo, &a2, &b2).unwrap();
+        assert_eq!(sorted(bases), sorted(vec![a1.clone(), b1.clone()]));
+        let candidates = vec![root.clone(), a1.clone(), b1.clone()];
+        let kept = remove_redundant(&test.repo, candidates).unwrap();
+        assert_eq!(kept, [a1.clone(), b1.clone()]);
+        assert_eq!(merge_bases(&test.repo, &a2, &a1).unwrap(), [a1]);
+    }
+
+    #[test]
+    fn test_merge_bases_many_and_octopus() {
+        let test = TempRepo::new();
+        let root = test.commit(&[], &[]);
+        let a = test.commit(&[("a", "1\n")], &[&root]);
+        let b = test.commit(&[("b", "1\n")], &[&a]);
+        let c = test.commit(&[("c", "1\n")], &[&a]);
+        let d = test.commit(&[("d", "1\n")], &[&root]);
+
+        // cとdをマージしたものとの共通の祖先: rootはaの祖先なので残らない
+        let others = [c.clone(), d.clone()];
+        assert_eq!(
+            merge_bases_many(&test.repo, &b, &others).unwrap(),
+            [a.clone()]
+        );
+        let candidates = common_ancestors(&test.repo, &b, &others).unwrap();
+        assert_eq!(
+            remove_redundant(&test.repo, candidates).unwrap(),
+            [a.clone()]
+        );
+
+        let commits = [b.clone(), c.clone()];
+        assert_eq!(octopus_merge_bases(&test.repo, &commits).unwrap(), [a]);
+        let commits = [b, c, d];
+        assert_eq!(octopus_merge_bases(&test.repo, &commits).unwrap(), [root]);
+    }
+
+    #[test]
+    fn test_fork_point_after_rewind() {
+        let test = TempRepo::new();
+        let sign = Sign::parse("A <a@example.com> 1700000000 +0000").unwrap();
+        let refs = test.repo.refs();
+        let a = test.commit(&[("a", "1\n")], &[]);
+        let b = test.commit(&[("a", "2\n")], &[&a]);
+        let c = test.commit(&[("a", "3\n")], &[&b]);
+        for oid in [&a, &b, &c] {
+            refs.update_logged("refs/heads/up", oid, &sign, "commit")
+                .unwrap();
+        }
+        let topic = test.commit(&[("a", "3\n"), ("t", "1\n")], &[&c]);
+        // upstreamをbとcを書き直したものに置き換える
+        let rewritten = test.commit(&[("a", "4\n")], &[&a]);
+        refs.update_logged("refs/heads/up", &rewritten, &sign, "reset")
+            .unwrap();
+
+        assert_eq!(merge_bases(&test.repo, &rewritten, &topic).unwrap(), [a]);
+        let point = fork_point(&test.repo, "refs/heads/up", &topic).unwrap();
+        assert_eq!(point, Some(c));
+        assert_eq!(
+            fork_point(&test.repo, "refs/heads/none", &topic).unwrap(),
+            None
+        );
+    }
+}
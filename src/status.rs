@@ -0,0 +1,534 @@
+//! Comparing `HEAD`, the index and the working tree.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+use crate::diff::patch::{FilePair, Side};
+use crate::diff::rename;
+use crate::error::{Error, Result};
+use crate::ignore::Ignore;
+use crate::index::Index;
+use crate::object::tree::GITLINK_MODE;
+use crate::repository::Repository;
+use crate::worktree::Worktree;
+
+/// How a path differs between two sides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Modified,
+    Deleted,
+    /// Added as a rename of a deleted file, only found by
+    /// [`Status::find_renames`].
+    Renamed,
+}
+
+/// Which versions of an unmerged path the index has, named after what
+/// each side of the merge did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    BothDeleted,
+    AddedByUs,
+    DeletedByThem,
+    AddedByThem,
+    DeletedByUs,
+    BothAdded,
+    BothModified,
+}
+
+impl Conflict {
+    /// The conflict of a path with entries at the stages in `mask`, where
+    /// bits 0, 1 and 2 stand for the base, our and their versions.
+    pub fn from_stages(mask: u8) -> Self {
+        match mask {
+            1 => Self::BothDeleted,
+            2 => Self::AddedByUs,
+            3 => Self::DeletedByThem,
+            4 => Self::AddedByThem,
+            5 => Self::DeletedByUs,
+            6 => Self::BothAdded,
+            _ => Self::BothModified,
+        }
+    }
+
+    /// Whether one side deleted the file while the other modified it.
+    pub fn is_delete_modify(self) -> bool {
+        matches!(self, Self::DeletedByThem | Self::DeletedByUs)
+    }
+}
+
+/// State of the working tree, as shown by `status`.
+#[derive(Debug, Clone, Default)]
+pub struct Status {
+    /// Differences between `HEAD` and the index.
+    pub staged: Vec<(String, Change)>,
+    /// The original path of each renamed file in `staged`, by its new path.
+    pub renamed_from: HashMap<String, String>,
+    /// Differences between the index and the working tree.
+    pub unstaged: Vec<(String, Change)>,
+    /// Paths with conflicts in the index, left out of the changes above.
+    pub unmerged: Vec<(String, Conflict)>,
+    /// Files not in the index; directories without tracked files are
+    /// collapsed into one entry ending with `/`.
+    pub untracked: Vec<String>,
+    /// Ignored files and directories, only collected on request.
+    pub ignored: Vec<String>,
+}
+
+impl Status {
+    pub fn collect(repo: &Repository, show_ignored: bool) -> Result<Self> {
+        Self::collect_against(repo, repo.refs().head()?.as_deref(), show_ignored)
+    }
+
+    /// Like [`Status::collect`], but with the staged changes taken against
+    /// the commit `base` instead of `HEAD` (`None` for no commit).
+    pub fn collect_against(
+        repo: &Repository,
+        base: Option<&str>,
+        show_ignored: bool,
+    ) -> Result<Self> {
+        let index = repo.index()?;
+        let Untracked { untracked, ignored } = find_untracked(repo, &index, true, show_ignored)?;
+        Ok(Self {
+            staged: staged_changes(repo, &index, base)?,
+            renamed_from: HashMap::new(),
+            unstaged: unstaged_changes(repo, &index)?,
+            unmerged: unmerged_paths(&index),
+            untracked,
+            ignored,
+        })
+    }
+
+    /// Pair files deleted from `base` and added to the index in `staged`
+    /// into renames, unless `status.renames` (or `diff.renames`) is false.
+    /// Contents are compared only within `status.renameLimit`.
+    pub fn find_renames(&mut self, repo: &Repository, base: Option<&str>) -> Result<()> {
+        let config = repo.config();
+        let value = config
+            .get("status.renames")
+            .or_else(|| config.get("diff.renames"));
+        if matches!(value, Some("false" | "no" | "off" | "0")) {
+            return Ok(());
+        }
+        let head: HashMap<String, (u32, String)> = match base {
+            Some(oid) => repo
+                .tree_files(oid)?
+                .into_iter()
+                .map(|entry| (entry.name, (entry.mode, entry.oid)))
+                .collect(),
+            None => HashMap::new(),
+        };
+        let index = repo.index()?;
+        let mut pairs = Vec::new();
+        for (path, change) in &self.staged {
+            let (mode, oid) = match change {
+                Change::Deleted => match head.get(path) {
+                    Some((mode, oid)) => (*mode, oid.as_str()),
+                    None => continue,
+                },
+                Change::Added => match index.get(path, 0) {
+                    Some(entry) => (entry.mode, entry.oid.as_str()),
+                    None => continue,
+                },
+                _ => continue,
+            };
+            // submoduleのcommitはこのリポジトリにないので、名前の変更とは見ない
+            if mode == GITLINK_MODE {
+                continue;
+            }
+            let side = Side::new(path.as_str(), mode, oid, repo.find_blob(oid)?);
+            pairs.push(match change {
+                Change::Deleted => FilePair::new(Some(side), None),
+                _ => FilePair::new(None, Some(side)),
+            });
+        }
+        // status.renameLimitが多すぎて比べなかったときも、gitは何も言わない
+        let limit = config
+            .get("status.renameLimit")
+            .or_else(|| config.get("diff.renameLimit"))
+            .and_then(|n| n.parse().ok());
+        let options = rename::Options {
+            limit: limit.unwrap_or(rename::Options::default().limit),
+            ..rename::Options::default()
+        };
+        for pair in rename::detect(pairs, options) {
+            if let (Some(old), Some(new)) = (pair.old, pair.new) {
+                self.renamed_from.insert(new.path, old.path);
+            }
+        }
+        let sources: HashSet<&String> = self.renamed_from.values().collect();
+        let renamed_from = &self.renamed_from;
+        self.staged.retain(|(path, _)| !sources.contains(path));
+        for (path, change) in &mut self.staged {
+            if renamed_from.contains_key(path) {
+                *change = Change::Renamed;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the index differs from `HEAD`.
+    pub fn has_staged(&self) -> bool {
+        !self.staged.is_empty()
+    }
+
+    /// Whether nothing differs and there are no untracked files.
+    pub fn is_clean(&self) -> bool {
+        self.staged.is_empty()
+            && self.unstaged.is_empty()
+            && self.unmerged.is_empty()
+            && self.untracked.is_empty()
+    }
+}
+
+fn staged_changes(
+    repo: &Repository,
+    index: &Index,
+    base: Option<&str>,
+) -> Result<Vec<(String, Change)>> {
+    let head = match base {
+        Some(oid) => repo.tree_files(oid)?,
+        None => Vec::new(),
+    };
+    let head: BTreeMap<&str, (u32, &str)> = head
+        .iter()
+        .map(|entry| (entry.name.as_str(), (entry.mode, entry.oid.as_str())))
+        .collect();
+
+    let mut changes = BTreeMap::new();
+    for entry in index.entries() {
+        if entry.stage != 0 || entry.intent_to_add {
+            continue;
+        }
+        match head.get(entry.path.as_str()) {
+            None => {
+                changes.insert(entry.path.clone(), Change::Added);
+            }
+            Some(&(mode, oid)) if mode != entry.mode || oid != entry.oid => {
+                changes.insert(entry.path.clone(), Change::Modified);
+            }
+            Some(_) => {}
+        }
+    }
+    // 衝突しているパスはステージ0になくても削除ではない
+    let unmerged: HashSet<&str> = index
+        .entries()
+        .iter()
+        .filter(|e| e.stage != 0)
+        .map(|e| e.path.as_str())
+        .collect();
+    for path in head.keys() {
+        if index.get(path, 0).is_none() && !unmerged.contains(path) {
+            changes.insert((*path).to_string(), Change::Deleted);
+        }
+    }
+    Ok(changes.into_iter().collect())
+}
+
+fn unmerged_paths(index: &Index) -> Vec<(String, Conflict)> {
+    let mut stages: BTreeMap<&str, u8> = BTreeMap::new();
+    for entry in index.entries().iter().filter(|e| e.stage != 0) {
+        *stages.entry(&entry.path).or_default() |= 1 << (entry.stage - 1);
+    }
+    stages
+        .into_iter()
+        .map(|(path, mask)| (path.to_string(), Conflict::from_stages(mask)))
+        .collect()
+}
+
+fn unstaged_changes(repo: &Repository, index: &Index) -> Result<Vec<(String, Change)>> {
+    let work_tree = repo.require_work_tree()?;
+    let files = Worktree::new(repo)?;
+    let mut changes = Vec::new();
+    for entry in index.entries() {
+        if entry.stage != 0 || entry.assume_valid || entry.skip_worktree {
+            continue;
+        }
+        let path = work_tree.join(&entry.path);
+        let metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) if !metadata.is_dir() || entry.mode == GITLINK_MODE => metadata,
+            Ok(_) => {
+                changes.push((entry.path.clone(), Change::Deleted));
+                continue;
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound || !path.exists() => {
+                changes.push((entry.path.clone(), Change::Deleted));
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if entry.intent_to_add {
+            changes.push((entry.path.clone(), Change::Added));
+            continue;
+        }
+        // statが違っても内容が同じなら変更なし
+        if files.is_modified(entry, &metadata)? {
+            changes.push((entry.path.clone(), Change::Modified));
+        }
+    }
+    Ok(changes)
+}
+
+/// Paths of the working tree that are not in the index.
+#[derive(Debug, Clone, Default)]
+pub struct Untracked {
+    pub untracked: Vec<String>,
+    pub ignored: Vec<String>,
+}
+
+/// Walk the working tree for paths not in `index`.
+///
+/// With `collapse`, a directory without tracked files is reported as a single
+/// `dir/` entry instead of the files inside it. Ignored paths are only
+/// collected with `show_ignored`.
+pub fn find_untracked(
+    repo: &Repository,
+    index: &Index,
+    collapse: bool,
+    show_ignored: bool,
+) -> Result<Untracked> {
+    let tracked_dirs: HashSet<&str> = index
+        .entries()
+        .iter()
+        .flat_map(|entry| entry.path.match_indices('/').map(|(i, _)| &entry.path[..i]))
+        .collect();
+    let mut walker = Walker {
+        repo,
+        tracked: index
+            .entries()
+            .iter()
+            .map(|e| (e.path.as_str(), e.mode))
+            .collect(),
+        tracked_dirs,
+        ignore: Ignore::new(repo)?,
+        collapse,
+        show_ignored,
+    };
+    let mut result = Untracked::default();
+    walker.walk("", &mut result)?;
+    result.untracked.sort();
+    result.ignored.sort();
+    Ok(result)
+}
+
+struct Walker<'a> {
+    repo: &'a Repository,
+    // path -> mode
+    tracked: HashMap<&'a str, u32>,
+    tracked_dirs: HashSet<&'a str>,
+    ignore: Ignore,
+    collapse: bool,
+    show_ignored: bool,
+}
+
+impl Walker<'_> {
+    fn walk(&mut self, dir: &str, result: &mut Untracked) -> Result<()> {
+        let work_tree = self.repo.require_work_tree()?;
+        let entries = read_dir(&work_tree.join(dir))?;
+
+        for entry in entries {
+            let name = entry.file_name();
+            if name == ".git" {
+                continue;
+            }
+            let name = name.to_string_lossy();
+            let path = if dir.is_empty() {
+                name.into_owned()
+            } else {
+                format!("{dir}/{name}")
+            };
+            let is_dir = entry.file_type()?.is_dir();
+
+            if is_dir && self.tracked_dirs.contains(path.as_str()) {
+                self.walk(&path, result)?;
+                continue;
+            }
+            // ファイルだったパスがディレクトリになっていれば、中を調べる
+            match self.tracked.get(path.as_str()) {
+                Some(&mode) if !is_dir || mode == GITLINK_MODE => continue,
+                _ => {}
+            }
+            // 埋め込まれたリポジトリは中を見ずに1つのパスとして扱う
+            let is_dir = is_dir && !work_tree.join(&path).join(".git").exists();
+            let is_repo = entry.file_type()?.is_dir() && !is_dir;
+            if self.ignore.is_ignored(&path, is_dir)? {
+                if !self.show_ignored {
+                    continue;
+                }
+                if !is_dir {
+                    result.ignored.push(path);
+                } else if self.collapse {
+                    result.ignored.push(format!("{path}/"));
+                } else {
+                    list_files(work_tree, &path, &mut result.ignored)?;
+                }
+                continue;
+            }
+            if is_repo && self.collapse {
+                result.untracked.push(format!("{path}/"));
+            } else if !is_dir {
+                result.untracked.push(path);
+            } else if !self.collapse {
+                self.walk(&path, result)?;
+            } else if self.has_untracked(&path, result)? {
+                result.untracked.push(format!("{path}/"));
+            }
+        }
+        Ok(())
+    }
+
+    // 追跡されていないディレクトリに、無視されないファイルがあるか
+    // (無視されたファイルも表示する場合は、それらを集めながら全体を見る)
+    fn has_untracked(&mut self, dir: &str, result: &mut Untracked) -> Result<bool> {
+        let work_tree = self.repo.require_work_tree()?;
+        let entries = read_dir(&work_tree.join(dir))?;
+
+        let mut found = false;
+        for entry in entries {
+            let path = format!("{dir}/{}", entry.file_name().to_string_lossy());
+            let is_dir = entry.file_type()?.is_dir();
+            if self.ignore.is_ignored(&path, is_dir)? {
+                if self.show_ignored {
+                    result
+                        .ignored
+                        .push(if is_dir { format!("{path}/") } else { path });
+                }
+                continue;
+            }
+            found |= !is_dir || self.has_untracked(&path, result)?;
+            if found && !self.show_ignored {
+                break;
+            }
+        }
+        Ok(found)
+    }
+}
+
+// ディレクトリの中身を名前順に読む
+fn read_dir(dir: &Path) -> Result<Vec<fs::DirEntry>> {
+    let mut entries = fs::read_dir(dir)
+        .and_then(Iterator::collect::<io::Result<Vec<_>>>)
+        .map_err(Error::io(dir))?;
+    entries.sort_by_key(fs::DirEntry::file_name);
+    Ok(entries)
+}
+
+// 無視されたディレクトリの中身をすべて集める
+fn list_files(work_tree: &Path, dir: &str, files: &mut Vec<String>) -> Result<()> {
+    for entry in read_dir(&work_tree.join(dir))? {
+        let path = format!("{dir}/{}", entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            list_files(work_tree, &path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::index::Entry;
+    use crate::object::tree::BLOB_MODE;
+    use crate::object::Kind;
+    use crate::testing::TempRepo;
+
+    fn index(entries: &[(&str, u32)]) -> Index {
+        let mut index = Index::default();
+        for (path, mode) in entries {
+            index.add(Entry {
+                mode: *mode,
+                path: (*path).to_string(),
+                ..Entry::default()
+            });
+        }
+        index
+    }
+
+    #[test]
+    fn test_find_untracked() {
+        let test = TempRepo::new();
+        test.write("a", "a\n");
+        test.write("new/b", "b\n");
+        test.write("sub/c", "c\n");
+        let index = index(&[("a", BLOB_MODE), ("sub", GITLINK_MODE)]);
+
+        let found = find_untracked(&test.repo, &index, true, false).unwrap();
+        assert_eq!(found.untracked, ["new/"]);
+        let found = find_untracked(&test.repo, &index, false, false).unwrap();
+        assert_eq!(found.untracked, ["new/b"]);
+    }
+
+    #[test]
+    fn test_find_untracked_file_replaced_by_directory() {
+        let test = TempRepo::new();
+        test.write("d/x", "x\n");
+        let index = index(&[("d", BLOB_MODE)]);
+
+        let found = find_untracked(&test.repo, &index, true, false).unwrap();
+        assert_eq!(found.untracked, ["d/"]);
+        let found = find_untracked(&test.repo, &index, false, false).unwrap();
+        assert_eq!(found.untracked, ["d/x"]);
+    }
+
+    #[test]
+    fn test_find_renames() {
+        let test = TempRepo::new();
+        let lines: String = (1..=20).map(|n| n.to_string() + "\n").collect();
+        let base = test.commit(&[("a", &lines), ("c", "c\n")], &[]);
+        let mut index = Index::default();
+        for (path, content) in [("b", lines.clone() + "21\n"), ("c", "c\n".to_string())] {
+            let oid = test
+                .repo
+                .odb()
+                .write(Kind::Blob, content.as_bytes())
+                .unwrap();
+            index.add(Entry {
+                mode: BLOB_MODE,
+                oid,
+                path: path.to_string(),
+                ..Entry::default()
+            });
+        }
+        // submoduleのcommitはobjectとして読めない
+        index.add(Entry {
+            mode: GITLINK_MODE,
+            oid: "1".repeat(40),
+            path: "sub".to_string(),
+            ..Entry::default()
+        });
+        test.repo.write_index(&index).unwrap();
+
+        let mut status = Status::collect_against(&test.repo, Some(&base), false).unwrap();
+        assert_eq!(
+            status.staged,
+            [
+                ("a".to_string(), Change::Deleted),
+                ("b".to_string(), Change::Added),
+                ("sub".to_string(), Change::Added)
+            ]
+        );
+        status.find_renames(&test.repo, Some(&base)).unwrap();
+        assert_eq!(
+            status.staged,
+            [
+                ("b".to_string(), Change::Renamed),
+                ("sub".to_string(), Change::Added)
+            ]
+        );
+        assert_eq!(status.renamed_from["b"], "a");
+
+        let config = test.repo.git_dir().join("config");
+        Config::set_in_file(&config, "diff.renames", "false").unwrap();
+        let repo =
+            Repository::open(test.repo.git_dir().to_path_buf(), Some(test.root.clone())).unwrap();
+        let mut status = Status::collect_against(&repo, Some(&base), false).unwrap();
+        status.find_renames(&repo, Some(&base)).unwrap();
+        assert_eq!(status.staged.len(), 3);
+        assert!(status.renamed_from.is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+//! Repositories in temporary directories, for tests that need objects,
+//! refs or a working tree.
+//!
+//! Shared by the tests of the library and of the commands, so paths go
+//! through `git::` rather than `crate::`.
+
+use std::cell::Cell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chrono::{FixedOffset, TimeZone};
+
+use git::index::{Entry, Index};
+use git::object::commit::{Commit, Sign};
+use git::object::tree::BLOB_MODE;
+use git::object::{Kind, Object};
+use git::repository::{InitOptions, Repository};
+
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A repository removed again when dropped.
+pub struct TempRepo {
+    pub repo: Repository,
+    pub root: PathBuf,
+    // コミットごとに1秒ずつ進める時刻
+    time: Cell<i64>,
+}
+
+impl TempRepo {
+    pub fn new() -> Self {
+        let count = COUNT.fetch_add(1, Ordering::SeqCst);
+        let root = std::env::temp_dir().join(format!("git-test-{}-{count}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let (repo, _) = Repository::init(&root, &InitOptions::default()).unwrap();
+        Self {
+            repo,
+            root,
+            time: Cell::new(1_700_000_000),
+        }
+    }
+
+    /// Write a commit of `files` as `(path, content)` with `parents`.
+    pub fn commit(&self, files: &[(&str, &str)], parents: &[&str]) -> String {
+        let mut index = Index::default();
+        for (path, content) in files {
+            let oid = self
+                .repo
+                .odb()
+                .write(Kind::Blob, content.as_bytes())
+                .unwrap();
+            index.add(Entry {
+                mode: BLOB_MODE,
+                oid,
+                path: (*path).to_string(),
+                ..Entry::default()
+            });
+        }
+        let tree = index.write_tree(self.repo.odb()).unwrap();
+        self.time.set(self.time.get() + 1);
+        let time_stamp = FixedOffset::east_opt(0)
+            .unwrap()
+            .timestamp_opt(self.time.get(), 0)
+            .unwrap();
+        let sign = Sign {
+            name: "A".to_string(),
+            email: "a@example.com".to_string(),
+            time_stamp,
+        };
+        let commit = Commit {
+            tree,
+            parents: parents.iter().map(ToString::to_string).collect(),
+            author: sign.clone(),
+            committer: sign,
+            extra_headers: Vec::new(),
+            message: "message\n".to_string(),
+        };
+        self.repo.write_object(&Object::Commit(commit)).unwrap()
+    }
+
+    /// Write `content` to `path` in the working tree.
+    pub fn write(&self, path: &str, content: &str) {
+        let path = self.root.join(path);
+        fs::create_dir_all(path.parent().unwrap_or(Path::new("."))).unwrap();
+        fs::write(path, content).unwrap();
+    }
+}
+
+impl Drop for TempRepo {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
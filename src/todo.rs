@@ -0,0 +1,326 @@
+//! The todo list of `rebase`: the commands left to run, one per line.
+
+use std::collections::HashMap;
+
+/// What a line of the todo list does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Use the commit.
+    Pick,
+    /// Use the commit, editing its message.
+    Reword,
+    /// Use the commit, then stop to amend it.
+    Edit,
+    /// Meld the commit into the previous one, editing the messages of both.
+    Squash,
+    /// Meld the commit into the previous one, keeping only the message of
+    /// the previous one.
+    Fixup,
+    /// Run a shell command.
+    Exec,
+    /// Stop until `rebase --continue`.
+    Break,
+    /// Leave the commit out.
+    Drop,
+    /// Point a ref at the current commit when the rebase finishes.
+    UpdateRef,
+    /// Nothing; the whole list when there is nothing to do.
+    Noop,
+}
+
+impl Command {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Pick => "pick",
+            Self::Reword => "reword",
+            Self::Edit => "edit",
+            Self::Squash => "squash",
+            Self::Fixup => "fixup",
+            Self::Exec => "exec",
+            Self::Break => "break",
+            Self::Drop => "drop",
+            Self::UpdateRef => "update-ref",
+            Self::Noop => "noop",
+        }
+    }
+
+    // gitと同じく、1文字の略も受け付ける
+    fn parse(word: &str) -> Option<Self> {
+        match word {
+            "p" | "pick" => Some(Self::Pick),
+            "r" | "reword" => Some(Self::Reword),
+            "e" | "edit" => Some(Self::Edit),
+            "s" | "squash" => Some(Self::Squash),
+            "f" | "fixup" => Some(Self::Fixup),
+            "x" | "exec" => Some(Self::Exec),
+            "b" | "break" => Some(Self::Break),
+            "d" | "drop" => Some(Self::Drop),
+            "u" | "update-ref" => Some(Self::UpdateRef),
+            "noop" => Some(Self::Noop),
+            _ => None,
+        }
+    }
+
+    /// Whether the argument is a commit.
+    pub fn takes_commit(self) -> bool {
+        matches!(
+            self,
+            Self::Pick | Self::Reword | Self::Edit | Self::Squash | Self::Fixup | Self::Drop
+        )
+    }
+
+    /// Whether the commit is melded into the previous one.
+    pub fn is_fixup(self) -> bool {
+        matches!(self, Self::Squash | Self::Fixup)
+    }
+}
+
+/// One line of the todo list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    pub command: Command,
+    /// The commit, the shell command of `exec` or the ref of `update-ref`;
+    /// empty for `break` and `noop`.
+    pub arg: String,
+    /// What follows the commit, usually its subject.
+    pub rest: String,
+}
+
+// 元から空のcommitに付ける印
+const EMPTY_MARK: &str = " # empty";
+
+impl Item {
+    pub fn new(command: Command, arg: impl Into<String>, rest: impl Into<String>) -> Self {
+        Self {
+            command,
+            arg: arg.into(),
+            rest: rest.into(),
+        }
+    }
+
+    /// The line for a commit with `subject`, marked when the commit changes
+    /// nothing.
+    pub fn commit(command: Command, oid: &str, subject: &str, empty: bool) -> Self {
+        let mark = if empty { EMPTY_MARK } else { "" };
+        Self::new(command, oid, format!("{subject}{mark}"))
+    }
+
+    /// The subject of the commit, without the mark of an empty commit.
+    pub fn subject(&self) -> &str {
+        self.rest.strip_suffix(EMPTY_MARK).unwrap_or(&self.rest)
+    }
+
+    /// The line, with the commit cut to `abbrev` characters if given.
+    pub fn line(&self, abbrev: Option<usize>) -> String {
+        let mut line = self.command.name().to_string();
+        let arg = match abbrev {
+            Some(len) if self.command.takes_commit() => &self.arg[..len.min(self.arg.len())],
+            _ => &self.arg,
+        };
+        for part in [arg, &self.rest] {
+            if !part.is_empty() {
+                line.push(' ');
+                line.push_str(part);
+            }
+        }
+        line
+    }
+}
+
+/// Parse a todo list, skipping blank and `#` comment lines. A line that is
+/// not understood is returned with its number, from 1.
+pub fn parse(text: &str) -> Result<Vec<Item>, (usize, String)> {
+    let mut items = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let invalid = || (number + 1, line.to_string());
+        let (word, rest) = trimmed.split_once([' ', '\t']).unwrap_or((trimmed, ""));
+        let command = Command::parse(word).ok_or_else(invalid)?;
+        let rest = rest.trim_start();
+        let item = match command {
+            Command::Break | Command::Noop if rest.is_empty() => Item::new(command, "", ""),
+            Command::Exec | Command::UpdateRef if !rest.is_empty() => Item::new(command, rest, ""),
+            _ if command.takes_commit() && !rest.is_empty() => {
+                let (arg, rest) = rest.split_once([' ', '\t']).unwrap_or((rest, ""));
+                Item::new(command, arg, rest.trim_start())
+            }
+            _ => return Err(invalid()),
+        };
+        items.push(item);
+    }
+    Ok(items)
+}
+
+/// Write the todo list one item per line, with commits cut to `abbrev`
+/// characters if given. As with git, each `update-ref` but the last item
+/// is followed by a blank line.
+pub fn format(items: &[Item], abbrev: Option<usize>) -> String {
+    let mut text = String::new();
+    for (i, item) in items.iter().enumerate() {
+        text.push_str(&item.line(abbrev));
+        text.push('\n');
+        if item.command == Command::UpdateRef && i + 1 < items.len() {
+            text.push('\n');
+        }
+    }
+    text
+}
+
+/// Move the `pick`s of commits titled `fixup! <title>` or `squash! <title>`
+/// after the commit they are meant for, turning them into `fixup` or
+/// `squash`. The commit is found by its subject, by `resolve` giving the
+/// commit a name stands for, or by a subject starting with the title.
+pub fn autosquash(items: &mut Vec<Item>, resolve: impl Fn(&str) -> Option<String>) {
+    let count = items.len();
+    let mut next: Vec<Option<usize>> = vec![None; count];
+    let mut tail: Vec<Option<usize>> = vec![None; count];
+    let mut subjects: HashMap<String, usize> = HashMap::new();
+    let mut moved = false;
+    for i in 0..count {
+        if items[i].command != Command::Pick {
+            continue;
+        }
+        let subject = items[i].subject().to_string();
+        if let Some(title) = fixup_title(&subject) {
+            let target = subjects
+                .get(title)
+                .copied()
+                .or_else(|| {
+                    if title.contains(' ') {
+                        return None;
+                    }
+                    let oid = resolve(title)?;
+                    items[..i].iter().position(|item| item.arg == oid)
+                })
+                .or_else(|| {
+                    items[..i]
+                        .iter()
+                        .position(|item| item.subject().starts_with(title))
+                });
+            if let Some(target) = target {
+                items[i].command = if subject.starts_with("fixup!") {
+                    Command::Fixup
+                } else {
+                    Command::Squash
+                };
+                let last = tail[target].unwrap_or(target);
+                next[i] = next[last];
+                next[last] = Some(i);
+                tail[target] = Some(i);
+                moved = true;
+            }
+        }
+        subjects.entry(subject).or_insert(i);
+    }
+    if !moved {
+        return;
+    }
+    let mut sorted = Vec::with_capacity(count);
+    for i in 0..count {
+        if items[i].command.is_fixup() {
+            continue;
+        }
+        let mut current = Some(i);
+        while let Some(j) = current {
+            sorted.push(items[j].clone());
+            current = next[j];
+        }
+    }
+    *items = sorted;
+}
+
+// "fixup! "や"squash! "を繰り返し外した残り
+fn fixup_title(subject: &str) -> Option<&str> {
+    fn strip(s: &str) -> Option<&str> {
+        s.strip_prefix("fixup! ")
+            .or_else(|| s.strip_prefix("squash! "))
+    }
+    let mut title = strip(subject)?;
+    while let Some(rest) = strip(title) {
+        title = rest;
+    }
+    Some(title)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let text = "pick abc subj # empty\n\n# comment\nf def  other\nexec make test\nbreak\n\
+                    update-ref refs/heads/x\n";
+        let items = parse(text).unwrap();
+        assert_eq!(
+            items,
+            vec![
+                Item::new(Command::Pick, "abc", "subj # empty"),
+                Item::new(Command::Fixup, "def", "other"),
+                Item::new(Command::Exec, "make test", ""),
+                Item::new(Command::Break, "", ""),
+                Item::new(Command::UpdateRef, "refs/heads/x", ""),
+            ]
+        );
+        assert_eq!(items[0].subject(), "subj");
+        assert_eq!(items[1].line(None), "fixup def other");
+        assert_eq!(parse("pick\n"), Err((1, "pick".to_string())));
+        assert_eq!(parse("pick a\nfoo bar\n"), Err((2, "foo bar".to_string())));
+    }
+
+    #[test]
+    fn test_line() {
+        let item = Item::commit(Command::Pick, "0123456789abcdef", "subj", true);
+        assert_eq!(item.line(Some(7)), "pick 0123456 subj # empty");
+        assert_eq!(item.line(None), "pick 0123456789abcdef subj # empty");
+        let item = Item::new(Command::Exec, "echo hi", "");
+        assert_eq!(item.line(Some(7)), "exec echo hi");
+    }
+
+    #[test]
+    fn test_format() {
+        let items = [
+            Item::new(Command::Pick, "a1", "one"),
+            Item::new(Command::UpdateRef, "refs/heads/x", ""),
+            Item::new(Command::UpdateRef, "refs/heads/y", ""),
+            Item::new(Command::Pick, "b2", "two"),
+            Item::new(Command::UpdateRef, "refs/heads/z", ""),
+        ];
+        assert_eq!(
+            format(&items, Some(1)),
+            "pick a one\nupdate-ref refs/heads/x\n\nupdate-ref refs/heads/y\n\npick b two\n\
+             update-ref refs/heads/z\n"
+        );
+        assert_eq!(parse(&format(&items, None)).unwrap(), items);
+    }
+
+    #[test]
+    fn test_autosquash() {
+        let pick = |oid: &str, subject: &str| Item::new(Command::Pick, oid, subject);
+        let mut items = vec![
+            pick("a1", "one"),
+            pick("b2", "two"),
+            pick("c3", "fixup! one"),
+            pick("d4", "squash! fixup! two"),
+            pick("e5", "fixup! a1"),
+            pick("f6", "fixup! tw"),
+            pick("g7", "fixup! missing"),
+        ];
+        autosquash(&mut items, |name| (name == "a1").then(|| "a1".to_string()));
+        let lines: Vec<String> = items.iter().map(|item| item.line(None)).collect();
+        assert_eq!(
+            lines,
+            [
+                "pick a1 one",
+                "fixup c3 fixup! one",
+                "fixup e5 fixup! a1",
+                "pick b2 two",
+                "squash d4 squash! fixup! two",
+                "fixup f6 fixup! tw",
+                "pick g7 fixup! missing",
+            ]
+        );
+    }
+}
@@ -1,2 +1,4 @@
 pub mod compress;
+pub mod lockfile;
 pub mod path;
+pub mod wildmatch;
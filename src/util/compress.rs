@@ -1,5 +1,6 @@
-use std::io::Write;
+use std::io::{Read, Write};
 
+use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
 use sha1::{Digest, Sha1};
@@ -10,6 +11,13 @@ pub fn with_zlib(str: &[u8]) -> std::io::Result<Vec<u8>> {
     e.finish()
 }
 
+pub fn from_zlib(compressed: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut d = ZlibDecoder::new(compressed);
+    let mut result = Vec::new();
+    d.read_to_end(&mut result)?;
+    Ok(result)
+}
+
 pub fn hash(str: &[u8]) -> String {
     let mut hasher = Sha1::new();
     hasher.update(str);
@@ -28,6 +36,13 @@ mod tests {
         assert_eq!(result, vec![120, 156, 43, 73, 45, 46, 1, 0, 4, 93, 1, 193]);
     }
 
+    #[test]
+    fn test_from_zlib() {
+        let compressed = vec![120, 156, 43, 73, 45, 46, 1, 0, 4, 93, 1, 193];
+        let result = from_zlib(&compressed).unwrap();
+        assert_eq!(result, b"test");
+    }
+
     #[test]
     fn test_hash() {
         let str = "test".to_string();
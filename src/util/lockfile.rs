@@ -0,0 +1,63 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+
+/// A `<path>.lock` file that replaces `path` on `commit`, as git does for
+/// every file it rewrites. The lock is removed if dropped uncommitted.
+pub struct LockFile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    file: Option<File>,
+}
+
+impl LockFile {
+    pub fn acquire(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut lock_name = OsString::from(path.as_os_str());
+        lock_name.push(".lock");
+        let lock_path = PathBuf::from(lock_name);
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(Error::io(dir))?;
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+            .map_err(|e| match e.kind() {
+                ErrorKind::AlreadyExists => Error::LockContention(lock_path.clone()),
+                _ => Error::io(&lock_path)(e),
+            })?;
+        Ok(Self {
+            path,
+            lock_path,
+            file: Some(file),
+        })
+    }
+
+    pub fn write_all(&mut self, contents: &[u8]) -> Result<()> {
+        match &mut self.file {
+            Some(file) => Ok(file.write_all(contents)?),
+            None => Err(io::Error::new(ErrorKind::Other, "lock already released").into()),
+        }
+    }
+
+    /// Move the lock file over the target path.
+    pub fn commit(mut self) -> Result<()> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+        }
+        fs::rename(&self.lock_path, &self.path).map_err(Error::io(&self.path))
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
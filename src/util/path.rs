@@ -1,37 +1,175 @@
+use std::env;
 use std::fs;
-use std::io::{self, ErrorKind};
-use std::path::Path;
-
-pub fn find_git_root() -> Result<String, io::Error> {
-    let files = fs::read_dir(".").unwrap();
-    for file in files {
-        let file = file.unwrap();
-        if file.file_type().unwrap().is_dir() && file.file_name() == ".git" {
-            return Ok(file.path().to_str().unwrap().to_string());
+use std::io::ErrorKind;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::{Error, Result};
+
+/// Location of a repository as seen from a directory inside it.
+#[derive(Debug, Clone)]
+pub struct GitRoot {
+    /// The `.git` directory (or the repository itself when bare).
+    pub git_dir: PathBuf,
+    /// Top-level directory of the working tree; `None` for bare repositories.
+    pub work_tree: Option<PathBuf>,
+    /// The starting directory relative to `work_tree`, empty at the top level.
+    pub prefix: PathBuf,
+}
+
+impl GitRoot {
+    /// Turn `path`, relative to the starting directory, into a path relative
+    /// to the top of the working tree.
+    pub fn resolve(&self, path: impl AsRef<Path>) -> PathBuf {
+        normalize(self.prefix.join(path))
+    }
+}
+
+/// Find the repository `start` belongs to.
+///
+/// `git_dir` and `work_tree` are the `--git-dir`/`--work-tree` options; when
+/// absent, `GIT_DIR`/`GIT_WORK_TREE` are used. Without an explicit git dir,
+/// parent directories are searched for `.git` up to `GIT_CEILING_DIRECTORIES`
+/// or a filesystem boundary (unless `GIT_DISCOVERY_ACROSS_FILESYSTEM` is set).
+pub fn find_git_root(
+    start: &Path,
+    git_dir: Option<&Path>,
+    work_tree: Option<&Path>,
+) -> Result<GitRoot> {
+    let cwd = start.canonicalize()?;
+    let work_tree = work_tree
+        .map(Path::to_path_buf)
+        .or_else(|| env::var_os("GIT_WORK_TREE").map(PathBuf::from));
+    let git_dir = git_dir
+        .map(Path::to_path_buf)
+        .or_else(|| env::var_os("GIT_DIR").map(PathBuf::from));
+
+    let (git_dir, work_tree) = if let Some(git_dir) = git_dir {
+        let git_dir = resolve_git_dir(&cwd.join(&git_dir))?.ok_or_else(|| {
+            Error::NotARepository(format!("not a git repository: '{}'", git_dir.display()))
+        })?;
+        // GIT_DIRだけ指定された場合はカレントディレクトリがworktreeになる
+        (git_dir, Some(work_tree.unwrap_or_else(|| cwd.clone())))
+    } else {
+        let (git_dir, found_work_tree) = discover(&cwd)?;
+        (git_dir, work_tree.or(found_work_tree))
+    };
+
+    let work_tree = work_tree.map(|w| cwd.join(w).canonicalize()).transpose()?;
+    let prefix = work_tree
+        .as_ref()
+        .and_then(|w| cwd.strip_prefix(w).ok())
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+
+    Ok(GitRoot {
+        git_dir: git_dir.canonicalize()?,
+        work_tree,
+        prefix,
+    })
+}
+
+// cwdから親ディレクトリに向かって.gitを探す
+fn discover(cwd: &Path) -> Result<(PathBuf, Option<PathBuf>)> {
+    let ceilings = ceiling_directories();
+    let across_filesystem = env::var("GIT_DISCOVERY_ACROSS_FILESYSTEM")
+        .is_ok_and(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes" | "on"));
+
+    let mut dir = cwd.to_path_buf();
+    let device = fs::metadata(&dir)?.dev();
+    loop {
+        if let Some(git_dir) = resolve_git_dir(&dir.join(".git"))? {
+            return Ok((git_dir, Some(dir)));
+        }
+        if is_git_dir(&dir) {
+            return Ok((dir, None));
+        }
+
+        let Some(parent) = dir.parent() else {
+            break;
+        };
+        if ceilings.iter().any(|c| c == parent) {
+            break;
+        }
+        if !across_filesystem && fs::metadata(parent)?.dev() != device {
+            return Err(Error::NotARepository(format!(
+                "not a git repository (or any parent up to mount point {})\n\
+                     Stopping at filesystem boundary (GIT_DISCOVERY_ACROSS_FILESYSTEM not set).",
+                dir.display()
+            )));
         }
+        dir = parent.to_path_buf();
     }
-    Err(io::Error::new(
-        io::ErrorKind::NotFound,
-        "Not found .git directory",
+
+    Err(Error::NotARepository(
+        "not a git repository (or any of the parent directories): .git".to_string(),
     ))
 }
 
-pub fn get_head_ref() -> String {
-    let head = fs::read_to_string(".git/HEAD").unwrap();
-    let head: Vec<&str> = head.split(' ').collect();
+fn ceiling_directories() -> Vec<PathBuf> {
+    let Some(value) = env::var_os("GIT_CEILING_DIRECTORIES") else {
+        return Vec::new();
+    };
+    env::split_paths(&value)
+        .filter(|p| p.is_absolute())
+        .map(|p| p.canonicalize().unwrap_or(p))
+        .collect()
+}
+
+// .gitがディレクトリならそのまま、"gitdir: <path>"を含むファイルならその先を返す
+fn resolve_git_dir(path: &Path) -> Result<Option<PathBuf>> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if metadata.is_dir() {
+        return Ok(is_git_dir(path).then(|| path.to_path_buf()));
+    }
 
-    format!(".git/{}", head[1].to_string().trim_end())
+    let content = fs::read_to_string(path).map_err(Error::io(path))?;
+    let Some(target) = content.strip_prefix("gitdir:") else {
+        return Err(Error::NotARepository(format!(
+            "invalid gitfile format: {}",
+            path.display()
+        )));
+    };
+    let target = Path::new(target.trim());
+    let target = match path.parent() {
+        Some(base) if target.is_relative() => base.join(target),
+        _ => target.to_path_buf(),
+    };
+    if !is_git_dir(&target) {
+        return Err(Error::NotARepository(format!(
+            "not a git repository: {}",
+            target.display()
+        )));
+    }
+    Ok(Some(target))
 }
 
-pub fn get_head_commit_hash() -> Option<String> {
-    let head_ref = get_head_ref();
-    let head_commit = fs::read_to_string(head_ref);
-    match head_commit {
-        Ok(head_commit) if !head_commit.is_empty() => Some(head_commit),
-        Ok(_) => None,
-        Err(ref e) if e.kind() == ErrorKind::NotFound => None,
-        Err(e) => panic!("{}", e),
+pub(crate) fn is_git_dir(path: &Path) -> bool {
+    path.join("HEAD").is_file() && path.join("objects").is_dir() && path.join("refs").is_dir()
+}
+
+/// Lexically resolve `.` and `..` components without touching the filesystem.
+pub fn normalize(path: impl AsRef<Path>) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.as_ref().components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !result.pop() {
+                    result.push("..");
+                }
+            }
+            c => result.push(c),
+        }
     }
+    if result.as_os_str().is_empty() {
+        result.push(".");
+    }
+    result
 }
 
 pub fn create_nested_file(file_path: impl AsRef<Path>) -> std::io::Result<fs::File> {
@@ -42,3 +180,16 @@ pub fn create_nested_file(file_path: impl AsRef<Path>) -> std::io::Result<fs::Fi
 
     fs::File::create(file_path)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("sub/./a.txt"), PathBuf::from("sub/a.txt"));
+        assert_eq!(normalize("sub/deep/../b.txt"), PathBuf::from("sub/b.txt"));
+        assert_eq!(normalize("sub/.."), PathBuf::from("."));
+        assert_eq!(normalize("../a"), PathBuf::from("../a"));
+    }
+}
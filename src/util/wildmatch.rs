@@ -0,0 +1,174 @@
+//! Shell-style pattern matching with git's `wildmatch` semantics.
+
+/// Match `text` against `pattern`.
+///
+/// With `pathname`, `*`, `?` and `[...]` do not match `/`, and `**` between
+/// slashes matches any number of directories.
+pub fn wildmatch(pattern: &str, text: &str, pathname: bool) -> bool {
+    match_bytes(pattern.as_bytes(), text.as_bytes(), pathname)
+}
+
+fn match_bytes(pattern: &[u8], text: &[u8], pathname: bool) -> bool {
+    let mut p = 0;
+    let mut t = 0;
+    while p < pattern.len() {
+        match pattern[p] {
+            b'*' => {
+                let at_start = p == 0 || pattern[p - 1] == b'/';
+                let mut end = p + 1;
+                while end < pattern.len() && pattern[end] == b'*' {
+                    end += 1;
+                }
+                let double = end - p >= 2;
+                let rest = &pattern[end..];
+                if pathname && double && at_start && rest.is_empty() {
+                    // 末尾の"/**"は残りすべてにマッチする
+                    return true;
+                }
+                if pathname && double && at_start && rest[0] == b'/' {
+                    // "**/"は0個以上のディレクトリにマッチする
+                    let rest = &rest[1..];
+                    let mut start = t;
+                    loop {
+                        if match_bytes(rest, &text[start..], pathname) {
+                            return true;
+                        }
+                        match text[start..].iter().position(|&c| c == b'/') {
+                            Some(slash) => start += slash + 1,
+                            None => return false,
+                        }
+                    }
+                }
+                // それ以外の連続した"*"は"*"と同じ
+                for start in t..=text.len() {
+                    if match_bytes(rest, &text[start..], pathname) {
+                        return true;
+                    }
+                    if start < text.len() && text[start] == b'/' && pathname {
+                        return false;
+                    }
+                }
+                return false;
+            }
+            b'?' => {
+                if t >= text.len() || (pathname && text[t] == b'/') {
+                    return false;
+                }
+                p += 1;
+                t += 1;
+            }
+            b'[' => {
+                if t >= text.len() || (pathname && text[t] == b'/') {
+                    return false;
+                }
+                if let Some((matched, len)) = match_class(&pattern[p + 1..], text[t]) {
+                    if !matched {
+                        return false;
+                    }
+                    p += len + 1;
+                } else {
+                    // 閉じていない"["は文字として扱う
+                    if text[t] != b'[' {
+                        return false;
+                    }
+                    p += 1;
+                }
+                t += 1;
+            }
+            c => {
+                let (c, len) = if c == b'\\' && p + 1 < pattern.len() {
+                    (pattern[p + 1], 2)
+                } else {
+                    (c, 1)
+                };
+                if t >= text.len() || text[t] != c {
+                    return false;
+                }
+                p += len;
+                t += 1;
+            }
+        }
+    }
+    t == text.len()
+}
+
+// "["の後ろから解析し、マッチしたかどうかと"]"までの長さを返す
+fn match_class(class: &[u8], c: u8) -> Option<(bool, usize)> {
+    let mut i = 0;
+    let negated = matches!(class.first(), Some(b'!' | b'^'));
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let &start = class.get(i)?;
+        if start == b']' && !first {
+            break;
+        }
+        first = false;
+        if start == b'[' && class.get(i + 1) == Some(&b':') {
+            let close = class[i + 2..].windows(2).position(|w| w == b":]")?;
+            let name = &class[i + 2..i + 2 + close];
+            matched |= match name {
+                b"alnum" => c.is_ascii_alphanumeric(),
+                b"alpha" => c.is_ascii_alphabetic(),
+                b"blank" => c == b' ' || c == b'\t',
+                b"cntrl" => c.is_ascii_control(),
+                b"digit" => c.is_ascii_digit(),
+                b"graph" => c.is_ascii_graphic(),
+                b"lower" => c.is_ascii_lowercase(),
+                b"print" => c.is_ascii_graphic() || c == b' ',
+                b"punct" => c.is_ascii_punctuation(),
+                b"space" => c.is_ascii_whitespace(),
+                b"upper" => c.is_ascii_uppercase(),
+                b"xdigit" => c.is_ascii_hexdigit(),
+                _ => false,
+            };
+            i += close + 4;
+            continue;
+        }
+        let (low, len) = if start == b'\\' {
+            (*class.get(i + 1)?, 2)
+        } else {
+            (start, 1)
+        };
+        i += len;
+        if class.get(i) == Some(&b'-') && class.get(i + 1).is_some_and(|&end| end != b']') {
+            let (high, len) = if class[i + 1] == b'\\' {
+                (*class.get(i + 2)?, 2)
+            } else {
+                (class[i + 1], 1)
+            };
+            matched |= (low..=high).contains(&c);
+            i += len + 1;
+        } else {
+            matched |= low == c;
+        }
+    }
+    Some((matched != negated, i + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildmatch() {
+        assert!(wildmatch("*.rs", "main.rs", true));
+        assert!(!wildmatch("*.rs", "src/main.rs", true));
+        assert!(wildmatch("*.rs", "src/main.rs", false));
+        assert!(wildmatch("**/foo", "foo", true));
+        assert!(wildmatch("**/foo", "a/b/foo", true));
+        assert!(wildmatch("a/**/b", "a/b", true));
+        assert!(wildmatch("a/**/b", "a/x/y/b", true));
+        assert!(wildmatch("abc/**", "abc/x/y", true));
+        assert!(!wildmatch("abc/**", "abd/x", true));
+        assert!(wildmatch("f?o", "foo", true));
+        assert!(wildmatch("[a-c]x", "bx", true));
+        assert!(!wildmatch("[!a-c]x", "bx", true));
+        assert!(wildmatch("[[:digit:]]", "7", true));
+        assert!(wildmatch("\\*", "*", true));
+        assert!(!wildmatch("\\*", "a", true));
+    }
+}
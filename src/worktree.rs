@@ -0,0 +1,419 @@
+//! Files of the working tree: hashing them the way git records them, and
+//! writing tree entries back out.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::linux::fs::MetadataExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{symlink, OpenOptionsExt};
+use std::path::PathBuf;
+
+use crate::error::{Error, Result};
+use crate::index::{verify_path, Entry, Index};
+use crate::object::tree::{normalize_mode, BLOB_MODE, EXECUTABLE_MODE, GITLINK_MODE, SYMLINK_MODE};
+use crate::object::Kind;
+use crate::odb::ObjectDatabase;
+use crate::repository::Repository;
+
+/// The working tree of a repository, with the `core.fileMode` and
+/// `core.symlinks` settings that decide how files map to index entries.
+#[derive(Debug)]
+pub struct Worktree<'a> {
+    repo: &'a Repository,
+    root: PathBuf,
+    /// Whether the executable bit of files can be trusted.
+    file_mode: bool,
+    /// Whether symbolic links can be created; otherwise they are checked
+    /// out as plain files containing the link target.
+    symlinks: bool,
+}
+
+impl<'a> Worktree<'a> {
+    pub fn new(repo: &'a Repository) -> Result<Self> {
+        let config = repo.config();
+        Ok(Self {
+            repo,
+            root: repo.require_work_tree()?.to_path_buf(),
+            file_mode: config.get_bool("core.fileMode").unwrap_or(true),
+            symlinks: config.get_bool("core.symlinks").unwrap_or(true),
+        })
+    }
+
+    pub fn repository(&self) -> &'a Repository {
+        self.repo
+    }
+
+    /// The mode to record for a file with `metadata`, given the mode of its
+    /// current index entry.
+    pub fn mode(&self, metadata: &fs::Metadata, existing: Option<u32>) -> u32 {
+        let mode = normalize_mode(metadata.st_mode());
+        let is_file = matches!(mode, BLOB_MODE | EXECUTABLE_MODE);
+        match existing {
+            // リンクが普通のファイルとして書き出されている
+            Some(SYMLINK_MODE) if is_file && !self.symlinks => SYMLINK_MODE,
+            Some(existing @ (BLOB_MODE | EXECUTABLE_MODE)) if is_file && !self.file_mode => {
+                existing
+            }
+            None if is_file && !self.file_mode => BLOB_MODE,
+            _ => mode,
+        }
+    }
+
+    /// The content git stores for `path`: the target of a symbolic link, or
+    /// the file itself.
+    pub fn read(&self, path: &str, metadata: &fs::Metadata) -> Result<Vec<u8>> {
+        let full_path = self.root.join(path);
+        if metadata.file_type().is_symlink() {
+            let target = fs::read_link(&full_path).map_err(Error::io(&full_path))?;
+            Ok(target.as_os_str().as_bytes().to_vec())
+        } else {
+            fs::read(&full_path).map_err(Error::io(&full_path))
+        }
+    }
+
+    /// The oid to record for `path`: the checked out commit of an embedded
+    /// repository, or the blob of the file, which is also written to the
+    /// object database when `write` is set.
+    pub fn hash(&self, path: &str, metadata: &fs::Metadata, write: bool) -> Result<String> {
+        if metadata.is_dir() {
+            let sub = Repository::discover(&self.root.join(path))?;
+            return sub
+                .refs()
+                .head()?
+                .ok_or_else(|| Error::RefNotFound(format!("HEAD of '{path}'")));
+        }
+        let content = self.read(path, metadata)?;
+        if write {
+            self.repo.odb().write(Kind::Blob, &content)
+        } else {
+            Ok(ObjectDatabase::hash(Kind::Blob, &content))
+        }
+    }
+
+    /// An index entry for `path` with the stat data of `metadata`.
+    pub fn entry(
+        &self,
+        path: &str,
+        oid: impl Into<String>,
+        metadata: &fs::Metadata,
+        existing: Option<u32>,
+    ) -> Entry {
+        let mut entry = Entry::from_metadata(path, oid, metadata);
+        entry.mode = self.mode(metadata, existing);
+        entry
+    }
+
+    /// Whether the file at `entry.path`, described by `metadata`, differs
+    /// from the index entry.
+    pub fn is_modified(&self, entry: &Entry, metadata: &fs::Metadata) -> Result<bool> {
+        if entry.mode == GITLINK_MODE {
+            // 中身のない(初期化されていない)submoduleは変更なしとみなす
+            if !metadata.is_dir() {
+                return Ok(true);
+            }
+            let sub = self.root.join(&entry.path);
+            if !sub.join(".git").exists() {
+                return Ok(false);
+            }
+            return Ok(self.hash(&entry.path, metadata, false)? != entry.oid);
+        }
+        if metadata.is_dir() {
+            return Ok(true);
+        }
+        if entry.is_stat_clean(metadata) {
+            return Ok(false);
+        }
+        if self.mode(metadata, Some(entry.mode)) != entry.mode {
+            return Ok(true);
+        }
+        Ok(self.hash(&entry.path, metadata, false)? != entry.oid)
+    }
+
+    /// Write the object `oid` to `path` as a file of `mode`, replacing what
+    /// is there, and return the metadata of the new file.
+    ///
+    /// Paths that [`verify_path`] rejects and paths below a symbolic link
+    /// are refused, so that a tree cannot write outside the working tree or
+    /// into `.git`.
+    pub fn write(&self, path: &str, mode: u32, oid: &str) -> Result<fs::Metadata> {
+        if !verify_path(path) || self.has_symlink_leading_path(path) {
+            return Err(Error::InvalidPath(path.to_string()));
+        }
+        let full_path = self.root.join(path);
+        if let Some(parent) = full_path.parent() {
+            fs::create_dir_all(parent).map_err(Error::io(parent))?;
+        }
+        match fs::symlink_metadata(&full_path) {
+            Ok(metadata) if metadata.is_dir() => {
+                if mode != GITLINK_MODE {
+                    fs::remove_dir(&full_path)?;
+                }
+            }
+            Ok(_) => fs::remove_file(&full_path)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        match mode {
+            GITLINK_MODE => fs::create_dir_all(&full_path)?,
+            SYMLINK_MODE if self.symlinks => {
+                let target = self.repo.find_blob(oid)?;
+                symlink(OsStr::from_bytes(&target), &full_path)?;
+            }
+            _ => {
+                let content = self.repo.find_blob(oid)?;
+                // 実行bitだけを決め、残りはumaskに任せる
+                let permissions = if mode == EXECUTABLE_MODE {
+                    0o777
+                } else {
+                    0o666
+                };
+                let mut file = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(permissions)
+                    .open(&full_path)
+                    .map_err(Error::io(&full_path))?;
+                file.write_all(&content).map_err(Error::io(&full_path))?;
+            }
+        }
+        Ok(fs::symlink_metadata(&full_path)?)
+    }
+
+    /// Remove the file at `path` and the directories left empty by it.
+    pub fn remove(&self, path: &str) -> Result<()> {
+        // gitと同じく、シンボリックリンクの先にあるものは消さない
+        if !verify_path(path) || self.has_symlink_leading_path(path) {
+            return Ok(());
+        }
+        let full_path = self.root.join(path);
+        match fs::symlink_metadata(&full_path) {
+            // submoduleの中身は消さない
+            Ok(metadata) if metadata.is_dir() => {
+                let _ = fs::remove_dir(&full_path);
+            }
+            Ok(_) => fs::remove_file(&full_path)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        let mut dir = full_path.parent();
+        while let Some(parent) = dir {
+            if parent == self.root || fs::remove_dir(parent).is_err() {
+                break;
+            }
+            dir = parent.parent();
+        }
+        Ok(())
+    }
+
+    // pathの途中のディレクトリのどれかがシンボリックリンクか
+    fn has_symlink_leading_path(&self, path: &str) -> bool {
+        let mut dir = self.root.clone();
+        let Some((leading, _)) = path.rsplit_once('/') else {
+            return false;
+        };
+        leading.split('/').any(|component| {
+            dir.push(component);
+            fs::symlink_metadata(&dir).is_ok_and(|metadata| metadata.file_type().is_symlink())
+        })
+    }
+
+    /// Move the index and the working tree from the tree `old` to the tree
+    /// `new` (either may be a commit), keeping local changes to paths that
+    /// are the same in both.
+    ///
+    /// Without `force`, nothing is touched when a path that changes between
+    /// the trees has uncommitted changes or is an untracked file. With
+    /// `force`, the index and the working tree are reset to `new`.
+    pub fn checkout_tree(
+        &self,
+        index: &mut Index,
+        old: Option<&str>,
+        new: &str,
+        force: bool,
+    ) -> Result<()> {
+        let old = self.tree_map(old)?;
+        let new = self.tree_map(Some(new))?;
+        if let Some(path) = new.keys().find(|path| !verify_path(path)) {
+            return Err(Error::InvalidPath(path.clone()));
+        }
+        let mut paths: BTreeSet<String> = old.keys().chain(new.keys()).cloned().collect();
+        paths.extend(index.entries().iter().map(|e| e.path.clone()));
+
+        let unmerged_paths: BTreeSet<&str> = index
+            .entries()
+            .iter()
+            .filter(|e| e.stage != 0)
+            .map(|e| e.path.as_str())
+            .collect();
+
+        let mut updates = Vec::new();
+        let mut local_changes = Vec::new();
+        let mut untracked = Vec::new();
+        for path in paths {
+            let current = index
+                .get(&path, 0)
+                .filter(|e| !e.intent_to_add)
+                .map(|e| (e.mode, e.oid.clone()));
+            let unmerged = unmerged_paths.contains(path.as_str());
+            let (old, new) = (old.get(&path).cloned(), new.get(&path).cloned());
+
+            if force {
+                if unmerged || current != new || self.is_dirty(index, &path)? {
+                    updates.push((path, new));
+                }
+                continue;
+            }
+            if old == new || current == new {
+                continue;
+            }
+            if unmerged || current != old || self.is_dirty(index, &path)? {
+                local_changes.push(path);
+            } else if current.is_none()
+                && new.is_some()
+                && self.is_untracked(&path, new.as_ref())?
+            {
+                untracked.push(path);
+            } else {
+                updates.push((path, new));
+            }
+        }
+        if !local_changes.is_empty() {
+            return Err(Error::LocalChanges(local_changes));
+        }
+        if !untracked.is_empty() {
+            return Err(Error::UntrackedOverwritten(untracked));
+        }
+        self.update_files(index, updates)
+    }
+
+    /// Replace each path of `updates` in the index and the working tree with
+    /// its new `(mode, oid)`, or remove it where there is none.
+    pub fn update_files(
+        &self,
+        index: &mut Index,
+        updates: Vec<(String, Option<(u32, String)>)>,
+    ) -> Result<()> {
+        // 途中で止まらないよう、何かを変える前にすべてのパスを確かめる
+        if let Some((path, _)) = updates
+            .iter()
+            .find(|(path, new)| new.is_some() && !verify_path(path))
+        {
+            return Err(Error::InvalidPath(path.clone()));
+        }
+        // ファイルとディレクトリが入れ替わる場合に備えて、先に削除する
+        for (path, _) in updates.iter().filter(|(_, new)| new.is_none()) {
+            index.remove(path);
+            self.remove(path)?;
+        }
+        for (path, new) in updates {
+            let Some((mode, blob)) = new else { continue };
+            index.remove(&path);
+            let metadata = self.write(&path, mode, &blob)?;
+            let mut entry = Entry::from_metadata(path, blob, &metadata);
+            entry.mode = mode;
+            index.add(entry);
+        }
+        Ok(())
+    }
+
+    /// All files of `tree` (none for `None`) as path -> `(mode, oid)`.
+    pub fn tree_map(&self, tree: Option<&str>) -> Result<BTreeMap<String, (u32, String)>> {
+        let Some(tree) = tree else {
+            return Ok(BTreeMap::new());
+        };
+        Ok(self
+            .repo
+            .tree_files(tree)?
+            .into_iter()
+            .map(|e| (e.name, (e.mode, e.oid)))
+            .collect())
+    }
+
+    /// Whether the file at `path` differs from its stage 0 index entry,
+    /// including when it is missing; `false` for paths not in the index.
+    pub fn is_dirty(&self, index: &Index, path: &str) -> Result<bool> {
+        let metadata = match fs::symlink_metadata(self.root.join(path)) {
+            Ok(metadata) => Some(metadata),
+            Err(e) if e.kind() == ErrorKind::NotFound || !self.root.join(path).exists() => None,
+            Err(e) => return Err(e.into()),
+        };
+        match (index.get(path, 0), metadata) {
+            (Some(entry), Some(metadata)) => self.is_modified(entry, &metadata),
+            (Some(_), None) => Ok(true),
+            (None, _) => Ok(false),
+        }
+    }
+
+    /// Whether writing `new` to `path` would lose an untracked file or
+    /// directory that is there, i.e. one with other content.
+    pub fn is_untracked(&self, path: &str, new: Option<&(u32, String)>) -> Result<bool> {
+        let Ok(metadata) = fs::symlink_metadata(self.root.join(path)) else {
+            return Ok(false);
+        };
+        if metadata.is_dir() {
+            return Ok(fs::read_dir(self.root.join(path))?.next().is_some());
+        }
+        let Some((mode, oid)) = new else {
+            return Ok(true);
+        };
+        Ok(
+            self.mode(&metadata, Some(*mode)) != *mode
+                || self.hash(path, &metadata, false)? != *oid,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempRepo;
+
+    #[test]
+    fn test_write_refuses_invalid_paths() {
+        let test = TempRepo::new();
+        let oid = test.repo.odb().write(Kind::Blob, b"x\n").unwrap();
+        let files = Worktree::new(&test.repo).unwrap();
+        for path in [".git/hooks/post-checkout", ".GIT/config", "sub/../../x"] {
+            assert!(matches!(
+                files.write(path, EXECUTABLE_MODE, &oid),
+                Err(Error::InvalidPath(_))
+            ));
+        }
+        assert!(!test.repo.git_dir().join("hooks/post-checkout").exists());
+
+        // シンボリックリンクになったディレクトリの先には書かない
+        test.write("outside/keep", "keep\n");
+        symlink(test.root.join("outside"), test.root.join("link")).unwrap();
+        assert!(matches!(
+            files.write("link/x", BLOB_MODE, &oid),
+            Err(Error::InvalidPath(_))
+        ));
+        assert!(!test.root.join("outside/x").exists());
+        files.remove("link/keep").unwrap();
+        assert!(test.root.join("outside/keep").exists());
+
+        files.write("dir/x", BLOB_MODE, &oid).unwrap();
+        assert_eq!(fs::read(test.root.join("dir/x")).unwrap(), b"x\n");
+    }
+
+    #[test]
+    fn test_checkout_tree_refuses_invalid_paths() {
+        let test = TempRepo::new();
+        let good = test.commit(&[("a", "a\n")], &[]);
+        let evil = test.commit(
+            &[("a", "a\n"), (".git/hooks/post-checkout", "x\n")],
+            &[&good],
+        );
+        let files = Worktree::new(&test.repo).unwrap();
+        let mut index = Index::default();
+        files.checkout_tree(&mut index, None, &good, true).unwrap();
+        let result = files.checkout_tree(&mut index, Some(&good), &evil, true);
+        assert!(
+            matches!(result, Err(Error::InvalidPath(path)) if path == ".git/hooks/post-checkout")
+        );
+        assert!(!test.repo.git_dir().join("hooks/post-checkout").exists());
+    }
+}
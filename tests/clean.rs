@@ -0,0 +1,65 @@
+mod common;
+
+use common::TestRepo;
+
+fn setup() -> TestRepo {
+    let test = TestRepo::new();
+    test.write(".gitignore", "*.log\nbuild/\n");
+    test.write("tracked", "t\n");
+    test.git(&["add", ".gitignore", "tracked"]);
+    test.git(&["commit", "-m", "initial"]);
+    test.write("untracked", "u\n");
+    test.write("debug.log", "i\n");
+    test.write("dir/file", "x\n");
+    test.write("build/out", "o\n");
+    test.write("sub/tracked", "t\n");
+    test.write("sub/more.log", "i\n");
+    test.git(&["add", "sub/tracked"]);
+    test
+}
+
+fn would_remove(test: &TestRepo, flags: &[&str]) -> Vec<String> {
+    let args: Vec<&str> = ["clean", "-n"].iter().chain(flags).copied().collect();
+    test.git(&args)
+        .lines()
+        .map(|line| line.strip_prefix("Would remove ").unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn test_selection() {
+    let test = setup();
+    assert_eq!(would_remove(&test, &[]), ["untracked"]);
+    assert_eq!(would_remove(&test, &["-d"]), ["dir/", "untracked"]);
+    assert_eq!(
+        would_remove(&test, &["-x"]),
+        ["debug.log", "sub/more.log", "untracked"]
+    );
+    assert_eq!(
+        would_remove(&test, &["-x", "-d"]),
+        ["build/", "debug.log", "dir/", "sub/more.log", "untracked"]
+    );
+    assert_eq!(would_remove(&test, &["-X"]), ["debug.log", "sub/more.log"]);
+    assert_eq!(
+        would_remove(&test, &["-X", "-d"]),
+        ["build/", "debug.log", "sub/more.log"]
+    );
+    assert_eq!(would_remove(&test, &["-d", "dir", "build"]), ["dir/"]);
+}
+
+#[test]
+fn test_remove() {
+    let test = setup();
+    test.git(&["clean", "-f", "-d"]);
+    assert_eq!(test.read("untracked"), None);
+    assert!(!test.root.join("dir").exists());
+    assert_eq!(test.read("debug.log").as_deref(), Some("i\n"));
+    assert_eq!(test.read("build/out").as_deref(), Some("o\n"));
+    assert_eq!(test.read("sub/tracked").as_deref(), Some("t\n"));
+
+    test.git(&["clean", "-f", "-X"]);
+    assert_eq!(test.read("debug.log"), None);
+    assert_eq!(test.read("sub/more.log"), None);
+    assert_eq!(test.read("build/out").as_deref(), Some("o\n"));
+    assert_eq!(test.read("tracked").as_deref(), Some("t\n"));
+}
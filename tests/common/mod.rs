@@ -0,0 +1,93 @@
+//! Repositories in temporary directories for running the `git` binary.
+
+// テストごとに使うものが違うので、使われないものがあってもよい
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use git::Repository;
+
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A work tree removed again when dropped.
+pub struct TestRepo {
+    pub root: PathBuf,
+}
+
+impl TestRepo {
+    pub fn new() -> Self {
+        let count = COUNT.fetch_add(1, Ordering::SeqCst);
+        let root =
+            std::env::temp_dir().join(format!("git-cli-test-{}-{count}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let test = Self { root };
+        test.git(&["init", "-b", "main"]);
+        test
+    }
+
+    /// Run the binary in the work tree and return its standard output,
+    /// failing the test when it does not succeed.
+    pub fn git(&self, args: &[&str]) -> String {
+        // 利用者の設定に左右されないよう、HOMEも一時ディレクトリにする
+        let output = Command::new(env!("CARGO_BIN_EXE_git"))
+            .args(args)
+            .current_dir(&self.root)
+            .env("HOME", &self.root)
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("GIT_DIR")
+            .env_remove("GIT_WORK_TREE")
+            .env("GIT_AUTHOR_NAME", "A")
+            .env("GIT_AUTHOR_EMAIL", "a@example.com")
+            .env("GIT_COMMITTER_NAME", "A")
+            .env("GIT_COMMITTER_EMAIL", "a@example.com")
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "git {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Write `content` to `path` in the work tree.
+    pub fn write(&self, path: &str, content: &str) {
+        let path = self.root.join(path);
+        fs::create_dir_all(path.parent().unwrap_or(Path::new("."))).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    /// The content of `path` in the work tree, if it exists.
+    pub fn read(&self, path: &str) -> Option<String> {
+        fs::read_to_string(self.root.join(path)).ok()
+    }
+
+    pub fn repo(&self) -> Repository {
+        Repository::discover(&self.root).unwrap()
+    }
+
+    /// The files of the commit `oid` as `(path, content)`.
+    pub fn files(&self, oid: &str) -> Vec<(String, String)> {
+        let repo = self.repo();
+        let tree = repo.find_commit(oid).unwrap().tree;
+        repo.tree_files(&tree)
+            .unwrap()
+            .into_iter()
+            .map(|entry| {
+                let content = repo.find_blob(&entry.oid).unwrap();
+                (entry.name, String::from_utf8(content).unwrap())
+            })
+            .collect()
+    }
+}
+
+impl Drop for TestRepo {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
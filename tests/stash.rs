@@ -0,0 +1,115 @@
+mod common;
+
+use common::TestRepo;
+
+fn files(entries: &[(&str, &str)]) -> Vec<(String, String)> {
+    entries
+        .iter()
+        .map(|(path, content)| ((*path).to_string(), (*content).to_string()))
+        .collect()
+}
+
+fn setup() -> (TestRepo, String) {
+    let test = TestRepo::new();
+    test.write("a", "a\n");
+    test.write("b", "b\n");
+    test.git(&["add", "a", "b"]);
+    test.git(&["commit", "-m", "initial"]);
+    let head = test.repo().refs().head().unwrap().unwrap();
+    (test, head)
+}
+
+#[test]
+fn test_push_include_untracked() {
+    let (test, head) = setup();
+    test.write("a", "a2\n");
+    test.write("new", "new\n");
+    test.git(&["add", "new"]);
+    test.write("a", "a3\n");
+    test.write("untracked", "u\n");
+    test.git(&["stash", "push", "-u"]);
+
+    // 作業ツリーのcommitの親は、HEAD、インデックスのcommit、追跡していないファイルのcommit
+    let repo = test.repo();
+    let stash = repo.refs().resolve("refs/stash").unwrap().unwrap();
+    let commit = repo.find_commit(&stash).unwrap();
+    let [parent, index, untracked] = &commit.parents[..] else {
+        panic!("stash commit has parents {:?}", commit.parents);
+    };
+    assert_eq!(*parent, head);
+    assert_eq!(repo.find_commit(index).unwrap().parents, [head.clone()]);
+    assert!(repo.find_commit(untracked).unwrap().parents.is_empty());
+    assert_eq!(
+        test.files(&stash),
+        files(&[("a", "a3\n"), ("b", "b\n"), ("new", "new\n")])
+    );
+    assert_eq!(
+        test.files(index),
+        files(&[("a", "a\n"), ("b", "b\n"), ("new", "new\n")])
+    );
+    assert_eq!(test.files(untracked), files(&[("untracked", "u\n")]));
+
+    assert_eq!(test.read("a").as_deref(), Some("a\n"));
+    assert_eq!(test.read("new"), None);
+    assert_eq!(test.read("untracked"), None);
+    assert_eq!(test.git(&["status", "-s"]), "");
+
+    test.git(&["stash", "pop", "--index"]);
+    assert_eq!(test.read("a").as_deref(), Some("a3\n"));
+    assert_eq!(test.read("untracked").as_deref(), Some("u\n"));
+    assert_eq!(test.git(&["status", "-s"]), " M a\nA  new\n?? untracked\n");
+}
+
+#[test]
+fn test_push_without_untracked() {
+    let (test, head) = setup();
+    test.write("a", "a2\n");
+    test.write("untracked", "u\n");
+    test.git(&["stash"]);
+
+    let repo = test.repo();
+    let stash = repo.refs().resolve("refs/stash").unwrap().unwrap();
+    let commit = repo.find_commit(&stash).unwrap();
+    assert_eq!(commit.parents.len(), 2);
+    assert_eq!(commit.parents[0], head);
+    assert_eq!(test.read("untracked").as_deref(), Some("u\n"));
+}
+
+#[test]
+fn test_push_keep_index() {
+    let (test, _) = setup();
+    test.write("a", "staged\n");
+    test.git(&["add", "a"]);
+    test.write("a", "unstaged\n");
+    test.write("b", "b2\n");
+    test.git(&["stash", "push", "--keep-index"]);
+
+    // インデックスの変更は、インデックスにも作業ツリーにも残る
+    assert_eq!(test.read("a").as_deref(), Some("staged\n"));
+    assert_eq!(test.read("b").as_deref(), Some("b\n"));
+    assert_eq!(test.git(&["status", "-s"]), "M  a\n");
+    let stash = test.repo().refs().resolve("refs/stash").unwrap().unwrap();
+    assert_eq!(
+        test.files(&stash),
+        files(&[("a", "unstaged\n"), ("b", "b2\n")])
+    );
+}
+
+#[test]
+fn test_push_pathspec() {
+    let (test, head) = setup();
+    test.write("a", "a2\n");
+    test.write("b", "b2\n");
+    test.git(&["add", "b"]);
+    test.git(&["stash", "push", "--", "a"]);
+
+    // 指定したパスの変更だけをしまい、他はそのまま残す
+    assert_eq!(test.read("a").as_deref(), Some("a\n"));
+    assert_eq!(test.read("b").as_deref(), Some("b2\n"));
+    assert_eq!(test.git(&["status", "-s"]), "M  b\n");
+    let repo = test.repo();
+    let stash = repo.refs().resolve("refs/stash").unwrap().unwrap();
+    let commit = repo.find_commit(&stash).unwrap();
+    assert_eq!(commit.parents[0], head);
+    assert_eq!(test.files(&stash), files(&[("a", "a2\n"), ("b", "b2\n")]));
+}